@@ -0,0 +1,10 @@
+CREATE TABLE "supplier_tokens" (
+    "id" UUID PRIMARY KEY,
+    "supplier_id" UUID NOT NULL REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    "token_hash" TEXT NOT NULL UNIQUE,
+    "created_at" TIMESTAMPTZ NOT NULL,
+    "expires_at" TIMESTAMPTZ NOT NULL,
+    "revoked_at" TIMESTAMPTZ
+);
+
+CREATE INDEX "supplier_tokens_supplier_id_idx" ON "supplier_tokens"("supplier_id");
@@ -0,0 +1,8 @@
+CREATE TABLE "api_keys" (
+    "id" UUID PRIMARY KEY,
+    "name" TEXT NOT NULL,
+    "key_hash" TEXT NOT NULL UNIQUE,
+    "scopes" TEXT[] NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL,
+    "revoked_at" TIMESTAMPTZ
+);
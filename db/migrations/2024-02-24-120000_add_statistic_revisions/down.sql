@@ -0,0 +1 @@
+DROP TABLE "statistic_revisions";
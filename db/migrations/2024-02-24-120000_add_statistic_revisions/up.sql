@@ -0,0 +1,16 @@
+-- Append-only log of changes to "statistics"."value"
+CREATE TABLE "statistic_revisions" (
+    "id" UUID PRIMARY KEY,
+    "supplier_id" UUID NOT NULL REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    "period_id" UUID NOT NULL REFERENCES "periods"("id") ON DELETE CASCADE,
+    "statistic_type_id" UUID NOT NULL REFERENCES "statistic_types"("id") ON DELETE CASCADE,
+    "copy_id" UUID NOT NULL REFERENCES "copies"("id") ON DELETE CASCADE,
+    "old_value" INTEGER,
+    "new_value" INTEGER NOT NULL,
+    "changed_at" TIMESTAMPTZ NOT NULL,
+    "supplier_token_id" UUID REFERENCES "supplier_tokens"("id") ON DELETE SET NULL,
+    "user_agent" TEXT,
+    "forwarded_for" TEXT
+);
+
+CREATE INDEX "statistic_revisions_supplier_id_changed_at_idx" ON "statistic_revisions"("supplier_id", "changed_at");
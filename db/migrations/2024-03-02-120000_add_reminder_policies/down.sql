@@ -0,0 +1,2 @@
+DROP TABLE "reminder_rules";
+ALTER TABLE "statistics_collectors" DROP COLUMN "account_manager_mail";
@@ -0,0 +1,19 @@
+ALTER TABLE "statistics_collectors" ADD COLUMN "account_manager_mail" TEXT;
+
+CREATE TABLE "reminder_rules" (
+    "id" UUID PRIMARY KEY,
+    "statistics_collector_id" UUID NOT NULL REFERENCES "statistics_collectors"("id") ON DELETE CASCADE,
+    "days_after_end" INTEGER NOT NULL,
+    "hour" INTEGER NOT NULL CHECK ("hour" BETWEEN 0 AND 23),
+    "reminder_type" TEXT NOT NULL,
+    "recipients" TEXT[] NOT NULL
+);
+
+CREATE INDEX "reminder_rules_statistics_collector_id_idx" ON "reminder_rules"("statistics_collector_id");
+
+-- existing collectors keep the reminders which used to be hardcoded
+INSERT INTO "reminder_rules" ("id", "statistics_collector_id", "days_after_end", "hour", "reminder_type", "recipients")
+SELECT gen_random_uuid(), "id", 0, 8, 'FirstReminder', ARRAY['supplier'] FROM "statistics_collectors";
+
+INSERT INTO "reminder_rules" ("id", "statistics_collector_id", "days_after_end", "hour", "reminder_type", "recipients")
+SELECT gen_random_uuid(), "id", 0, 15, 'SecondReminder', ARRAY['supplier'] FROM "statistics_collectors";
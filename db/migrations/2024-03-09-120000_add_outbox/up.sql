@@ -0,0 +1,17 @@
+CREATE TABLE "outbox" (
+    "id" UUID PRIMARY KEY,
+    "statistics_collector_id" UUID NOT NULL REFERENCES "statistics_collectors"("id") ON DELETE CASCADE,
+    "supplier_id" UUID NOT NULL REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    "period_id" UUID REFERENCES "periods"("id") ON DELETE CASCADE,
+    "kind" TEXT NOT NULL,
+    "recipient" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "next_attempt_at" TIMESTAMPTZ NOT NULL,
+    "last_error" TEXT,
+    "created_at" TIMESTAMPTZ NOT NULL,
+    "sent_at" TIMESTAMPTZ
+);
+
+CREATE INDEX "outbox_status_next_attempt_at_idx" ON "outbox"("status", "next_attempt_at");
+CREATE INDEX "outbox_statistics_collector_id_idx" ON "outbox"("statistics_collector_id");
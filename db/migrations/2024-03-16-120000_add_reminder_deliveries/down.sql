@@ -0,0 +1,2 @@
+DROP INDEX "outbox_supplier_id_period_id_kind_idx";
+DROP TABLE "reminder_deliveries";
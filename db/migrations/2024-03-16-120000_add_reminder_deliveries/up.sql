@@ -0,0 +1,11 @@
+CREATE TABLE "reminder_deliveries" (
+    "id" UUID PRIMARY KEY,
+    "supplier_id" UUID NOT NULL REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    "period_id" UUID REFERENCES "periods"("id") ON DELETE CASCADE,
+    "reminder_type" TEXT NOT NULL,
+    "attempted_at" TIMESTAMPTZ NOT NULL,
+    "error" TEXT
+);
+
+CREATE INDEX "reminder_deliveries_supplier_id_idx" ON "reminder_deliveries"("supplier_id");
+CREATE INDEX "outbox_supplier_id_period_id_kind_idx" ON "outbox"("supplier_id", "period_id", "kind");
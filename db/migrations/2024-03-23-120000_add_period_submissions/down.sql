@@ -0,0 +1,11 @@
+ALTER TABLE "suppliers" ADD COLUMN "submitted_date" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_DATE;
+
+UPDATE "suppliers" SET "submitted_date" = "latest"."submitted_at"
+FROM (
+    SELECT "supplier_id", MAX("submitted_at") AS "submitted_at"
+    FROM "period_submissions"
+    GROUP BY "supplier_id"
+) "latest"
+WHERE "suppliers"."id" = "latest"."supplier_id";
+
+DROP TABLE "period_submissions";
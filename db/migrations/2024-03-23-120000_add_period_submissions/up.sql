@@ -0,0 +1,38 @@
+CREATE TABLE "period_submissions" (
+    "supplier_id" UUID NOT NULL REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    "period_id" UUID NOT NULL REFERENCES "periods"("id") ON DELETE CASCADE,
+    "submitted_at" TIMESTAMPTZ NOT NULL,
+    "complete" BOOLEAN NOT NULL,
+    PRIMARY KEY ("supplier_id", "period_id")
+);
+
+-- Values changed by suppliers are the only reliable trace of past submissions
+INSERT INTO "period_submissions" ("supplier_id", "period_id", "submitted_at", "complete")
+SELECT
+    "r"."supplier_id",
+    "r"."period_id",
+    MAX("r"."changed_at"),
+    NOT EXISTS (
+        SELECT 1 FROM "statistics" "s"
+        WHERE "s"."supplier_id" = "r"."supplier_id"
+            AND "s"."period_id" = "r"."period_id"
+            AND "s"."value" = 0
+    )
+FROM "statistic_revisions" "r"
+GROUP BY "r"."supplier_id", "r"."period_id";
+
+-- Periods filled in before revisions were recorded were last submitted at the supplier's submitted date,
+-- cells which weren't filled in are 0
+INSERT INTO "period_submissions" ("supplier_id", "period_id", "submitted_at", "complete")
+SELECT
+    "s"."supplier_id",
+    "s"."period_id",
+    "suppliers"."submitted_date",
+    NOT BOOL_OR("s"."value" = 0)
+FROM "statistics" "s"
+JOIN "suppliers" ON "suppliers"."id" = "s"."supplier_id"
+GROUP BY "s"."supplier_id", "s"."period_id", "suppliers"."submitted_date"
+HAVING BOOL_OR("s"."value" <> 0)
+ON CONFLICT ("supplier_id", "period_id") DO NOTHING;
+
+ALTER TABLE "suppliers" DROP COLUMN "submitted_date";
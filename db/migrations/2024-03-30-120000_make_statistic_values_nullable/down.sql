@@ -0,0 +1,5 @@
+UPDATE "statistic_revisions" SET "new_value" = 0 WHERE "new_value" IS NULL;
+ALTER TABLE "statistic_revisions" ALTER COLUMN "new_value" SET NOT NULL;
+
+UPDATE "statistics" SET "value" = 0 WHERE "value" IS NULL;
+ALTER TABLE "statistics" ALTER COLUMN "value" SET NOT NULL;
@@ -0,0 +1,3 @@
+-- Existing values are kept, cells which are filled in from now on can be empty
+ALTER TABLE "statistics" ALTER COLUMN "value" DROP NOT NULL;
+ALTER TABLE "statistic_revisions" ALTER COLUMN "new_value" DROP NOT NULL;
@@ -0,0 +1,7 @@
+ALTER TABLE "statistics_collectors" DROP CONSTRAINT "statistics_collectors_weekday_check";
+UPDATE "statistics_collectors" SET "weekday" = 'nie bylo ustawione' WHERE "weekday" IS NULL;
+ALTER TABLE "statistics_collectors" ALTER COLUMN "weekday" SET DEFAULT 'nie bylo ustawione';
+ALTER TABLE "statistics_collectors" ALTER COLUMN "weekday" SET NOT NULL;
+
+ALTER TABLE "statistics_collectors" DROP CONSTRAINT "statistics_collectors_periodicity_check";
+ALTER TABLE "statistics_collectors" ALTER COLUMN "periodicity" SET DEFAULT 'nie bylo ustawione';
@@ -0,0 +1,30 @@
+-- Values used to be free-form, anything unrecognized becomes "custom"
+UPDATE "statistics_collectors" SET "periodicity" = CASE LOWER("periodicity")
+    WHEN 'daily' THEN 'daily'
+    WHEN 'dziennie' THEN 'daily'
+    WHEN 'weekly' THEN 'weekly'
+    WHEN 'tygodniowo' THEN 'weekly'
+    WHEN 'biweekly' THEN 'biweekly'
+    WHEN 'dwutygodniowo' THEN 'biweekly'
+    WHEN 'monthly' THEN 'monthly'
+    WHEN 'miesięcznie' THEN 'monthly'
+    ELSE 'custom'
+END;
+
+ALTER TABLE "statistics_collectors" ALTER COLUMN "periodicity" DROP DEFAULT;
+ALTER TABLE "statistics_collectors" ADD CONSTRAINT "statistics_collectors_periodicity_check"
+    CHECK ("periodicity" IN ('daily', 'weekly', 'biweekly', 'monthly', 'custom'));
+
+ALTER TABLE "statistics_collectors" ALTER COLUMN "weekday" DROP NOT NULL;
+ALTER TABLE "statistics_collectors" ALTER COLUMN "weekday" DROP DEFAULT;
+UPDATE "statistics_collectors" SET "weekday" = CASE
+    WHEN LOWER("weekday") IN ('monday', 'tuesday', 'wednesday', 'thursday', 'friday', 'saturday', 'sunday')
+        THEN LOWER("weekday")
+    ELSE NULL
+END;
+ALTER TABLE "statistics_collectors" ADD CONSTRAINT "statistics_collectors_weekday_check"
+    CHECK ("weekday" IN ('monday', 'tuesday', 'wednesday', 'thursday', 'friday', 'saturday', 'sunday'));
+
+-- Weekly periods can't be generated without a weekday
+UPDATE "statistics_collectors" SET "periodicity" = 'custom'
+WHERE "periodicity" IN ('weekly', 'biweekly') AND "weekday" IS NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE "derived_statistic_types" (
+    "id" UUID PRIMARY KEY,
+    "statistics_collector_id" UUID NOT NULL REFERENCES "statistics_collectors"("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "formula" TEXT NOT NULL,
+    UNIQUE ("statistics_collector_id", "name")
+);
@@ -0,0 +1 @@
+ALTER TABLE "statistics_collectors" DROP COLUMN "created_at";
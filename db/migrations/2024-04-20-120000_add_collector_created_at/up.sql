@@ -0,0 +1,6 @@
+-- existing collectors are treated as created now
+ALTER TABLE "statistics_collectors" ADD COLUMN "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE "statistics_collectors" ALTER COLUMN "created_at" DROP DEFAULT;
+
+CREATE INDEX "statistics_collectors_created_at_idx" ON "statistics_collectors"("created_at", "id");
+CREATE INDEX "statistics_collectors_name_idx" ON "statistics_collectors"("name", "id");
@@ -0,0 +1,2 @@
+ALTER TABLE "statistics_collectors" DROP COLUMN "deleted_at";
+ALTER TABLE "statistics_collectors" DROP COLUMN "archived_at";
@@ -0,0 +1,5 @@
+-- deleted collectors are kept until they're purged after the retention period
+ALTER TABLE "statistics_collectors" ADD COLUMN "archived_at" TIMESTAMPTZ;
+ALTER TABLE "statistics_collectors" ADD COLUMN "deleted_at" TIMESTAMPTZ;
+
+CREATE INDEX "statistics_collectors_deleted_at_idx" ON "statistics_collectors"("deleted_at");
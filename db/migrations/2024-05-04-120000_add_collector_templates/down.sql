@@ -0,0 +1 @@
+DROP TABLE "collector_templates";
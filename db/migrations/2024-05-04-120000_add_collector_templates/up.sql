@@ -0,0 +1,7 @@
+CREATE TABLE "collector_templates" (
+    "id" UUID PRIMARY KEY,
+    "name" TEXT NOT NULL UNIQUE,
+    -- a `json::received::StatCollector`
+    "collector" JSONB NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL
+);
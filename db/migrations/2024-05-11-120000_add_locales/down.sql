@@ -0,0 +1,2 @@
+ALTER TABLE "suppliers" DROP COLUMN "locale";
+ALTER TABLE "statistics_collectors" DROP COLUMN "locale";
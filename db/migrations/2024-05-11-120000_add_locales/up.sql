@@ -0,0 +1,3 @@
+-- suppliers without a locale get the one of their collector
+ALTER TABLE "statistics_collectors" ADD COLUMN "locale" TEXT NOT NULL DEFAULT 'pl';
+ALTER TABLE "suppliers" ADD COLUMN "locale" TEXT;
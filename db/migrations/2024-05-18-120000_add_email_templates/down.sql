@@ -0,0 +1,2 @@
+DROP TABLE "email_images";
+DROP TABLE "email_templates";
@@ -0,0 +1,20 @@
+-- every change of a template is a new version, the highest one is sent
+CREATE TABLE "email_templates" (
+    "id" UUID PRIMARY KEY,
+    -- name of a `logic::outbox::OutboxKind`
+    "kind" TEXT NOT NULL,
+    -- name of a `logic::locale::Locale`
+    "locale" TEXT NOT NULL,
+    "version" INTEGER NOT NULL,
+    "subject" TEXT NOT NULL,
+    "body" TEXT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL,
+    UNIQUE ("kind", "locale", "version")
+);
+
+CREATE TABLE "email_images" (
+    "name" TEXT PRIMARY KEY,
+    "content_type" TEXT NOT NULL,
+    "data" BYTEA NOT NULL,
+    "updated_at" TIMESTAMPTZ NOT NULL
+);
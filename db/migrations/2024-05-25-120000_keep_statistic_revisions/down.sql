@@ -0,0 +1,28 @@
+-- revisions of removed rows can't refer to them again
+DELETE FROM "statistic_revisions"
+WHERE "supplier_id" IS NULL
+    OR "period_id" IS NULL
+    OR "statistic_type_id" IS NULL
+    OR "copy_id" IS NULL;
+
+DROP INDEX "statistic_revisions_statistics_collector_id_changed_at_idx";
+
+ALTER TABLE "statistic_revisions"
+    DROP CONSTRAINT "statistic_revisions_supplier_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_period_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_statistic_type_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_copy_id_fkey",
+    ADD FOREIGN KEY ("supplier_id") REFERENCES "suppliers"("id") ON DELETE CASCADE,
+    ADD FOREIGN KEY ("period_id") REFERENCES "periods"("id") ON DELETE CASCADE,
+    ADD FOREIGN KEY ("statistic_type_id") REFERENCES "statistic_types"("id") ON DELETE CASCADE,
+    ADD FOREIGN KEY ("copy_id") REFERENCES "copies"("id") ON DELETE CASCADE,
+    ALTER COLUMN "supplier_id" SET NOT NULL,
+    ALTER COLUMN "period_id" SET NOT NULL,
+    ALTER COLUMN "statistic_type_id" SET NOT NULL,
+    ALTER COLUMN "copy_id" SET NOT NULL,
+    DROP COLUMN "statistics_collector_id",
+    DROP COLUMN "supplier_name",
+    DROP COLUMN "placement_type_name",
+    DROP COLUMN "period_name",
+    DROP COLUMN "statistic_type_name",
+    DROP COLUMN "copy_name";
@@ -0,0 +1,45 @@
+-- revisions outlive the suppliers, periods, statistic types and copies they refer to,
+-- so they keep the names and the collector
+ALTER TABLE "statistic_revisions"
+    ADD COLUMN "statistics_collector_id" UUID REFERENCES "statistics_collectors"("id") ON DELETE CASCADE,
+    ADD COLUMN "supplier_name" TEXT,
+    ADD COLUMN "placement_type_name" TEXT,
+    ADD COLUMN "period_name" TEXT,
+    ADD COLUMN "statistic_type_name" TEXT,
+    ADD COLUMN "copy_name" TEXT;
+
+UPDATE "statistic_revisions" AS "revision"
+SET "statistics_collector_id" = "placement_types"."statistics_collector_id",
+    "supplier_name" = "suppliers"."name",
+    "placement_type_name" = "placement_types"."name",
+    "period_name" = "periods"."name",
+    "statistic_type_name" = "statistic_types"."name",
+    "copy_name" = "copies"."name"
+FROM "suppliers", "placement_types", "periods", "statistic_types", "copies"
+WHERE "suppliers"."id" = "revision"."supplier_id"
+    AND "placement_types"."id" = "suppliers"."placement_type_id"
+    AND "periods"."id" = "revision"."period_id"
+    AND "statistic_types"."id" = "revision"."statistic_type_id"
+    AND "copies"."id" = "revision"."copy_id";
+
+ALTER TABLE "statistic_revisions"
+    ALTER COLUMN "statistics_collector_id" SET NOT NULL,
+    ALTER COLUMN "supplier_name" SET NOT NULL,
+    ALTER COLUMN "placement_type_name" SET NOT NULL,
+    ALTER COLUMN "period_name" SET NOT NULL,
+    ALTER COLUMN "statistic_type_name" SET NOT NULL,
+    ALTER COLUMN "copy_name" SET NOT NULL,
+    ALTER COLUMN "supplier_id" DROP NOT NULL,
+    ALTER COLUMN "period_id" DROP NOT NULL,
+    ALTER COLUMN "statistic_type_id" DROP NOT NULL,
+    ALTER COLUMN "copy_id" DROP NOT NULL,
+    DROP CONSTRAINT "statistic_revisions_supplier_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_period_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_statistic_type_id_fkey",
+    DROP CONSTRAINT "statistic_revisions_copy_id_fkey",
+    ADD FOREIGN KEY ("supplier_id") REFERENCES "suppliers"("id") ON DELETE SET NULL,
+    ADD FOREIGN KEY ("period_id") REFERENCES "periods"("id") ON DELETE SET NULL,
+    ADD FOREIGN KEY ("statistic_type_id") REFERENCES "statistic_types"("id") ON DELETE SET NULL,
+    ADD FOREIGN KEY ("copy_id") REFERENCES "copies"("id") ON DELETE SET NULL;
+
+CREATE INDEX "statistic_revisions_statistics_collector_id_changed_at_idx" ON "statistic_revisions"("statistics_collector_id", "changed_at");
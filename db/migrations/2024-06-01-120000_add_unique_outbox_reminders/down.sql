@@ -0,0 +1,3 @@
+DROP INDEX "outbox_supplier_id_period_id_kind_idx";
+CREATE INDEX "outbox_supplier_id_period_id_kind_idx" ON "outbox"("supplier_id", "period_id", "kind");
+ALTER TABLE "outbox" DROP COLUMN "forced";
@@ -0,0 +1,18 @@
+-- forced reminders are queued even if the same one was queued before
+ALTER TABLE "outbox" ADD COLUMN "forced" BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- earlier duplicates count as forced
+UPDATE "outbox" SET "forced" = TRUE
+WHERE "id" IN (
+    SELECT "id" FROM (
+        SELECT "id", ROW_NUMBER() OVER (PARTITION BY "supplier_id", "period_id", "kind" ORDER BY "created_at") AS "n"
+        FROM "outbox"
+        WHERE "status" NOT IN ('failed', 'cancelled') AND "kind" <> 'Escalation'
+    ) AS "ranked"
+    WHERE "n" > 1
+);
+
+-- a reminder is queued at most once unless forced, even by concurrent requests
+DROP INDEX "outbox_supplier_id_period_id_kind_idx";
+CREATE UNIQUE INDEX "outbox_supplier_id_period_id_kind_idx" ON "outbox"("supplier_id", "period_id", "kind")
+WHERE "status" NOT IN ('failed', 'cancelled') AND "kind" <> 'Escalation' AND NOT "forced";
@@ -0,0 +1,4 @@
+DROP INDEX "outbox_supplier_id_period_id_kind_idx";
+CREATE UNIQUE INDEX "outbox_supplier_id_period_id_kind_idx" ON "outbox"("supplier_id", "period_id", "kind")
+WHERE "status" NOT IN ('failed', 'cancelled') AND "kind" <> 'Escalation' AND NOT "forced";
+UPDATE "outbox" SET "forced" = FALSE WHERE "kind" = 'Escalation';
@@ -0,0 +1,15 @@
+-- earlier duplicate escalations count as forced, like duplicate reminders
+UPDATE "outbox" SET "forced" = TRUE
+WHERE "id" IN (
+    SELECT "id" FROM (
+        SELECT "id", ROW_NUMBER() OVER (PARTITION BY "supplier_id", "period_id" ORDER BY "created_at") AS "n"
+        FROM "outbox"
+        WHERE "status" NOT IN ('failed', 'cancelled') AND "kind" = 'Escalation' AND NOT "forced"
+    ) AS "ranked"
+    WHERE "n" > 1
+);
+
+-- the account manager is told about a missing submission once, even if several rules escalate it
+DROP INDEX "outbox_supplier_id_period_id_kind_idx";
+CREATE UNIQUE INDEX "outbox_supplier_id_period_id_kind_idx" ON "outbox"("supplier_id", "period_id", "kind")
+WHERE "status" NOT IN ('failed', 'cancelled') AND NOT "forced";
@@ -0,0 +1,2 @@
+ALTER TABLE "outbox" DROP COLUMN "token";
+ALTER TABLE "outbox" DROP COLUMN "supplier_token_id";
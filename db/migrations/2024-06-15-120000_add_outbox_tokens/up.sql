@@ -0,0 +1,4 @@
+-- a reminder's link is issued once, retries send the same one
+ALTER TABLE "outbox" ADD COLUMN "supplier_token_id" UUID REFERENCES "supplier_tokens"("id") ON DELETE SET NULL;
+-- only kept until the message is sent or given up on, tokens are otherwise stored hashed
+ALTER TABLE "outbox" ADD COLUMN "token" TEXT;
@@ -150,3 +150,5 @@ BOOTSTRAP5 = {
 # integration
 STAT_COLLECTOR_INT_URL = os.getenv("STAT_COLLECTOR_INT_URL")
 STAT_COLLECTOR_EXT_URL = os.getenv("BASE_URL")
+# created with `stat-collector create-api-key django --scope read,write,send_email`
+STAT_COLLECTOR_API_KEY = os.getenv("STAT_COLLECTOR_API_KEY")
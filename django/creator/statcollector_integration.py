@@ -1,12 +1,13 @@
 from http import HTTPStatus
 import json
-import re
 import requests
 from uuid import UUID
 
 from django.conf import settings
 from django.http import HttpResponse
-from django.views.decorators.http import require_POST
+from django.shortcuts import get_object_or_404
+from django.urls import reverse
+from django.views.decorators.http import require_GET, require_POST
 
 from .serializers import StatCollectorSerializer
 from .models import StatCollector
@@ -14,24 +15,28 @@ from .models import StatCollector
 
 def get_base_url_headers():
     url = settings.STAT_COLLECTOR_INT_URL + "/statistics_collector"
-    headers = {"Content-Type": "application/json"}
+    headers = {
+        "Content-Type": "application/json",
+        "Authorization": f"Bearer {settings.STAT_COLLECTOR_API_KEY}",
+    }
     return url, headers
 
 
 def sync_statcollector(statcollector: StatCollector):
     if statcollector.external_id is not None:
-        ext_delete_statcollector(statcollector.external_id)
+        response = ext_update_statcollector(statcollector)
+        if response.status_code != HTTPStatus.NOT_FOUND:
+            return
         statcollector.external_id = None
         statcollector.save(no_sync=True)
 
     response = ext_create_statcollector(statcollector)
     # print("creating", response.status_code, response.text)
     if response.status_code == HTTPStatus.CONFLICT:
-        pattern = r"Conflict: statistics collector with name .+ and client .+ with id ([\w-]+) already exists"
-        match = re.match(pattern, response.text)
-        if match:
-            uuid_str = match.group(1)
-            ext_delete_statcollector(UUID(uuid_str))
+        # the error body gives the id of the existing collector
+        existing_id = response.json().get("id")
+        if existing_id:
+            ext_delete_statcollector(UUID(existing_id))
             response = ext_create_statcollector(statcollector)
 
     if response.status_code == HTTPStatus.OK:
@@ -52,9 +57,31 @@ def ext_create_statcollector(statcollector: StatCollector):
     return requests.post(url=url, data=body, headers=headers)
 
 
+def ext_update_statcollector(statcollector: StatCollector):
+    # updates in place, so statistics already filled in by suppliers are kept
+    body = json.dumps(StatCollectorSerializer(statcollector).data)
+    url, headers = get_base_url_headers()
+    url += f"/{str(statcollector.external_id)}"
+    return requests.put(url=url, data=body, headers=headers)
+
+
 def ext_url(statcollector: StatCollector):
-    url = settings.STAT_COLLECTOR_EXT_URL
-    return url + f"/statistics_collector/{statcollector.external_id}"
+    # the stats page needs the API key, so it's served through ext_page
+    return reverse("creator:ext_page", kwargs={"stat_id": statcollector.id})
+
+
+@require_GET
+def ext_page(request, stat_id):
+    statcollector = get_object_or_404(StatCollector, id=stat_id)
+    url, headers = get_base_url_headers()
+    url += f"/{statcollector.external_id}"
+    # the filters of the page are sent as query parameters
+    response = requests.get(url=url, headers=headers, params=dict(request.GET.lists()))
+    return HttpResponse(
+        content=response.content,
+        status=response.status_code,
+        content_type=response.headers.get("Content-Type"),
+    )
 
 
 def ext_read_stats(statcollector: StatCollector):
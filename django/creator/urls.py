@@ -1,7 +1,7 @@
 from django.urls import path
 
 from . import views
-from .statcollector_integration import ext_email_reminder
+from .statcollector_integration import ext_email_reminder, ext_page
 
 app_name = "creator"
 
@@ -35,5 +35,6 @@ urlpatterns = [
     ),
     path("<uuid:stat_id>/json/", views.StatCollectorListAPIView.as_view(), name="json"),
     path("<uuid:stat_id>/xls/", views.get_statistics, name="xls"),
+    path("<uuid:stat_id>/stats/", ext_page, name="ext_page"),
     path("email/", ext_email_reminder, name="send_email"),
 ]
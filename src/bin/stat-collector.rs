@@ -1,10 +1,15 @@
+use clap::{Parser, Subcommand};
 use dotenvy::dotenv;
 use lettre::message::Mailbox;
-use lettre::transport::smtp::authentication::Credentials;
-use stat_collector::build_app;
-use stat_collector::logic::email::AppMailer;
+use stat_collector::logic::api_key::{create_api_key, ApiScope};
+use stat_collector::logic::archive::DEFAULT_RETENTION_DAYS;
+use stat_collector::logic::email::{AppMailer, Mailer};
+use stat_collector::logic::outbox::DEFAULT_SEND_CONCURRENCY;
 use stat_collector::logic::scheduler::start_scheduler;
 use stat_collector::logic::time::AppClock;
+use stat_collector::logic::time::Clock;
+use stat_collector::logic::transport::TransportConfig;
+use stat_collector::{build_app, run_migrations};
 use std::env;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::{Arc, Mutex};
@@ -13,8 +18,31 @@ use tracing::warn;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+#[derive(Parser)]
+#[command(about = "Collects advertising statistics from suppliers")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the server, this is the default
+    Serve,
+    /// Creates a key for the /statistics_collector API and prints it
+    CreateApiKey {
+        /// Who or what the key is for
+        name: String,
+        /// Scopes of the key: read, write, send_email
+        #[arg(long = "scope", required = true, value_delimiter = ',')]
+        scopes: Vec<ApiScope>,
+    },
+}
+
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     if let Err(e) = dotenv() {
         warn!("Failed to load .env file: {}", e);
     }
@@ -29,33 +57,83 @@ async fn main() {
 
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
-    let smtp_name = env::var("SMTP_NAME").expect("SMTP_NAME must be set");
-    let smtp_username = env::var("SMTP_USERNAME").expect("SMTP_USERNAME must be set");
-    let smtp_password = env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD must be set");
-    let smtp_host = env::var("SMTP_HOST").expect("SMTP_HOST must be set");
+    // set up connection pool
+    let manager = deadpool_diesel::postgres::Manager::new(db_url, deadpool_diesel::Runtime::Tokio1);
+    let db_pool = deadpool_diesel::postgres::Pool::builder(manager)
+        .build()
+        .unwrap();
+
+    if let Some(Command::CreateApiKey { name, scopes }) = cli.command {
+        run_migrations(&db_pool).await;
+        let now = AppClock.now();
+        let conn = db_pool.get().await.unwrap();
+        let key = conn
+            .interact(move |conn| create_api_key(conn, &name, &scopes, now))
+            .await
+            .unwrap()
+            .expect("Failed to create the API key");
+        println!("{}", key);
+        return;
+    }
+
+    // the sender is the SMTP account unless another address is configured
+    let from_name = env::var("SMTP_NAME").ok();
+    let from_address = env::var("MAIL_FROM")
+        .or_else(|_| env::var("SMTP_USERNAME"))
+        .expect("MAIL_FROM or SMTP_USERNAME must be set");
+    let transport = TransportConfig::from_env(|name| env::var(name).ok())
+        .and_then(|config| config.build())
+        .unwrap_or_else(|e| panic!("Invalid mail transport configuration: {}", e));
     let base_url = env::var("BASE_URL").expect("BASE_URL must be set");
+    let retention_days = env::var("DELETED_RETENTION_DAYS")
+        .map(|days| {
+            days.parse()
+                .expect("DELETED_RETENTION_DAYS must be a number of days")
+        })
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    let send_concurrency = env::var("SEND_CONCURRENCY")
+        .map(|concurrency| {
+            concurrency
+                .parse()
+                .expect("SEND_CONCURRENCY must be a number of emails")
+        })
+        .unwrap_or(DEFAULT_SEND_CONCURRENCY);
 
     let mailer = AppMailer::new(
-        Mailbox::new(Some(smtp_name), smtp_username.parse().unwrap()),
-        &smtp_host,
-        587,
-        std::time::Duration::from_secs(15),
-        Credentials::new(smtp_username, smtp_password),
+        Mailbox::new(
+            from_name,
+            from_address
+                .parse()
+                .expect("MAIL_FROM must be an email address"),
+        ),
+        transport,
         &base_url,
     );
-    let mailer = Arc::new(Mutex::new(mailer));
+    // messages are only signed if a key is configured
+    let mailer = match env::var("DKIM_PRIVATE_KEY_FILE") {
+        Ok(key_file) => {
+            let selector = env::var("DKIM_SELECTOR").expect("DKIM_SELECTOR must be set");
+            let domain = env::var("DKIM_DOMAIN").expect("DKIM_DOMAIN must be set");
+            let key = std::fs::read_to_string(key_file).expect("Failed to read the DKIM key");
+            mailer
+                .with_dkim(&selector, &domain, &key)
+                .expect("DKIM_PRIVATE_KEY_FILE must be an RSA key in PKCS#1 PEM")
+        }
+        Err(_) => mailer,
+    };
+    let mailer: Arc<dyn Mailer> = Arc::new(mailer);
 
     let clock = Arc::new(Mutex::new(AppClock));
 
-    // set up connection pool
-    let manager = deadpool_diesel::postgres::Manager::new(db_url, deadpool_diesel::Runtime::Tokio1);
-    let db_pool = deadpool_diesel::postgres::Pool::builder(manager)
-        .build()
-        .unwrap();
-
-    start_scheduler(db_pool.clone(), clock.clone(), mailer.clone())
-        .await
-        .expect("Failed to start scheduler");
+    start_scheduler(
+        db_pool.clone(),
+        clock.clone(),
+        mailer.clone(),
+        chrono::Duration::days(retention_days),
+        send_concurrency,
+    )
+    .await
+    .expect("Failed to start scheduler");
 
     let app = build_app(db_pool, mailer, clock).await;
 
@@ -1,6 +1,7 @@
-#![allow(clippy::new_without_default)]
+#![allow(clippy::new_without_default, non_local_definitions)]
 
 use crate::json;
+use crate::logic::locale::Locale;
 use derive_more::Display;
 use diesel::prelude::*;
 use diesel_derive_newtype::DieselNewType;
@@ -50,8 +51,25 @@ pub struct StatisticsCollector {
     pub id: StatCollectorId,
     pub name: String,
     pub client: String,
+    /// Name of a `logic::periods::Periodicity`
     pub periodicity: String,
-    pub weekday: String,
+    /// Lowercase English name, only set for weekly periodicities
+    pub weekday: Option<String>,
+    /// Receives reminders of the collector's reminder policy sent to the account manager
+    pub account_manager_mail: Option<String>,
+    pub created_at: DateTime<Local>,
+    /// Archived collectors are hidden from the list and send no reminders
+    pub archived_at: Option<DateTime<Local>>,
+    /// Deleted collectors can be restored until they're purged
+    pub deleted_at: Option<DateTime<Local>>,
+    /// Name of a `logic::locale::Locale`, the default of the collector's suppliers
+    pub locale: String,
+}
+
+impl StatisticsCollector {
+    pub fn locale(&self) -> Locale {
+        self.locale.parse().unwrap_or_default()
+    }
 }
 
 #[repr(transparent)]
@@ -81,7 +99,9 @@ impl PeriodId {
     }
 }
 
-#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable)]
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
 #[diesel(belongs_to(StatisticsCollector))]
 #[diesel(table_name = periods)]
 pub struct Period {
@@ -169,7 +189,9 @@ impl SupplierId {
     }
 }
 
-#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable)]
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
 #[diesel(table_name = suppliers)]
 #[diesel(belongs_to(PlacementType))]
 pub struct Supplier {
@@ -177,18 +199,78 @@ pub struct Supplier {
     pub name: String,
     pub mail: String,
     pub placement_type_id: PlacementTypeId,
-    pub submitted_date: DateTime<Local>,
+    /// Name of a `logic::locale::Locale`, the collector's one is used if it's not set
+    pub locale: Option<String>,
 }
 
 impl Supplier {
-    pub fn as_json(&self, stats: Vec<Vec<Vec<i32>>>) -> json::sent::Supplier {
+    pub fn as_json(&self, stats: Vec<Vec<Vec<Option<i32>>>>) -> json::sent::Supplier {
         json::sent::Supplier {
             id: self.id,
             name: self.name.clone(),
             mail: self.mail.parse().unwrap(),
+            locale: self.locale(),
             stats,
         }
     }
+
+    pub fn locale(&self) -> Option<Locale> {
+        self.locale
+            .as_deref()
+            .and_then(|locale| locale.parse().ok())
+    }
+}
+
+/// The last submission of a supplier's statistics of a period, see `logic::submissions`
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
+#[diesel(table_name = period_submissions)]
+#[diesel(belongs_to(Period))]
+#[diesel(belongs_to(Supplier))]
+#[diesel(primary_key(supplier_id, period_id))]
+pub struct PeriodSubmission {
+    pub supplier_id: SupplierId,
+    pub period_id: PeriodId,
+    pub submitted_at: DateTime<Local>,
+    /// Whether all values of the period were filled in
+    pub complete: bool,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct SupplierTokenId(Uuid);
+
+impl SupplierTokenId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Grants access to the supplier page. Only a hash of the token is stored.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable)]
+#[diesel(table_name = supplier_tokens)]
+#[diesel(belongs_to(Supplier))]
+pub struct SupplierToken {
+    pub id: SupplierTokenId,
+    pub supplier_id: SupplierId,
+    pub token_hash: String,
+    pub created_at: DateTime<Local>,
+    pub expires_at: DateTime<Local>,
+    pub revoked_at: Option<DateTime<Local>>,
 }
 
 #[repr(transparent)]
@@ -287,5 +369,346 @@ pub struct Statistic {
     pub supplier_id: SupplierId,
     pub statistic_type_id: StatisticTypeId,
     pub copy_id: CopyId,
-    pub value: i32,
+    /// Empty until the supplier fills it in, or after they clear it
+    pub value: Option<i32>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct StatisticRevisionId(Uuid);
+
+impl StatisticRevisionId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// A change of a statistic's value. Revisions are never updated, only appended.
+/// They keep the names of what they refer to, the references are cleared when it is removed.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = statistic_revisions)]
+pub struct StatisticRevision {
+    pub id: StatisticRevisionId,
+    pub supplier_id: Option<SupplierId>,
+    pub period_id: Option<PeriodId>,
+    pub statistic_type_id: Option<StatisticTypeId>,
+    pub copy_id: Option<CopyId>,
+    /// `None` if the statistic didn't exist before
+    pub old_value: Option<i32>,
+    pub new_value: Option<i32>,
+    pub changed_at: DateTime<Local>,
+    /// Token from the link used to make the change
+    pub supplier_token_id: Option<SupplierTokenId>,
+    pub user_agent: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub statistics_collector_id: StatCollectorId,
+    pub supplier_name: String,
+    pub placement_type_name: String,
+    pub period_name: String,
+    pub statistic_type_name: String,
+    pub copy_name: String,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct ApiKeyId(Uuid);
+
+impl ApiKeyId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Grants access to the admin API. Only a hash of the key is stored.
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct ApiKey {
+    pub id: ApiKeyId,
+    pub name: String,
+    pub key_hash: String,
+    /// Names of `logic::api_key::ApiScope`s
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Local>,
+    pub revoked_at: Option<DateTime<Local>>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct ReminderRuleId(Uuid);
+
+impl ReminderRuleId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// A rule of the reminder policy of a collector, see `json::reminders::ReminderRule`
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
+#[diesel(table_name = reminder_rules)]
+#[diesel(belongs_to(StatisticsCollector))]
+pub struct ReminderRule {
+    pub id: ReminderRuleId,
+    pub statistics_collector_id: StatCollectorId,
+    pub days_after_end: i32,
+    pub hour: i32,
+    /// Name of a `logic::email::ReminderType`
+    pub reminder_type: String,
+    /// Names of `json::reminders::ReminderRecipient`s
+    pub recipients: Vec<String>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct OutboxMessageId(Uuid);
+
+impl OutboxMessageId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// An email waiting to be sent, or already sent, see `logic::outbox`
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
+#[diesel(table_name = outbox)]
+#[diesel(belongs_to(StatisticsCollector))]
+#[diesel(belongs_to(Supplier))]
+pub struct OutboxMessage {
+    pub id: OutboxMessageId,
+    pub statistics_collector_id: StatCollectorId,
+    pub supplier_id: SupplierId,
+    /// Only empty for reminders queued manually before they were tied to a period
+    pub period_id: Option<PeriodId>,
+    /// Name of a `logic::outbox::OutboxKind`
+    pub kind: String,
+    pub recipient: String,
+    /// Name of a `logic::outbox::OutboxStatus`
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Local>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Local>,
+    pub sent_at: Option<DateTime<Local>>,
+    /// Queued even though the same reminder or escalation was queued before
+    pub forced: bool,
+    /// Token of the link in a reminder, issued by the first attempt
+    pub supplier_token_id: Option<SupplierTokenId>,
+    /// The token in plain text, until the reminder is sent or has failed
+    pub token: Option<String>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct ReminderDeliveryId(Uuid);
+
+impl ReminderDeliveryId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// An attempt to send a reminder to a supplier
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
+#[diesel(table_name = reminder_deliveries)]
+#[diesel(belongs_to(Supplier))]
+pub struct ReminderDelivery {
+    pub id: ReminderDeliveryId,
+    pub supplier_id: SupplierId,
+    pub period_id: Option<PeriodId>,
+    /// Name of a `logic::email::ReminderType`
+    pub reminder_type: String,
+    pub attempted_at: DateTime<Local>,
+    /// Empty if the reminder was sent
+    pub error: Option<String>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct DerivedStatisticTypeId(Uuid);
+
+impl DerivedStatisticTypeId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// A statistic type computed from the others, see `json::derived::DerivedStatisticType`
+#[derive(
+    Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, Insertable, Clone,
+)]
+#[diesel(table_name = derived_statistic_types)]
+#[diesel(belongs_to(StatisticsCollector))]
+pub struct DerivedStatisticType {
+    pub id: DerivedStatisticTypeId,
+    pub statistics_collector_id: StatCollectorId,
+    pub name: String,
+    /// Parsed by `logic::formula::Formula`
+    pub formula: String,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct CollectorTemplateId(Uuid);
+
+impl CollectorTemplateId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Structure of a collector saved to create new collectors from
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Insertable, Clone)]
+#[diesel(table_name = collector_templates)]
+pub struct CollectorTemplate {
+    pub id: CollectorTemplateId,
+    pub name: String,
+    /// A `json::received::StatCollector`
+    pub collector: serde_json::Value,
+    pub created_at: DateTime<Local>,
+}
+
+#[repr(transparent)]
+#[derive(
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    DieselNewType,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Display,
+)]
+pub struct EmailTemplateId(Uuid);
+
+impl EmailTemplateId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// A version of the template of an email, see `logic::email_templates`
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Insertable, Clone)]
+#[diesel(table_name = email_templates)]
+pub struct EmailTemplate {
+    pub id: EmailTemplateId,
+    /// Name of a `logic::outbox::OutboxKind`
+    pub kind: String,
+    /// Name of a `logic::locale::Locale`
+    pub locale: String,
+    /// Starts at 1, 0 is the built-in template
+    pub version: i32,
+    pub subject: String,
+    pub body: String,
+    pub created_at: DateTime<Local>,
+}
+
+/// An image which email templates can show
+#[derive(Debug, PartialEq, Queryable, Selectable, Identifiable, Insertable, Clone)]
+#[diesel(table_name = email_images)]
+#[diesel(primary_key(name))]
+pub struct EmailImage {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub updated_at: DateTime<Local>,
 }
@@ -1,24 +1,102 @@
-use crate::logic::email::EmailAttachment;
+use crate::logic::outbox::OutboxKind;
 use maud::{html, Markup, PreEscaped, DOCTYPE};
+use rust_i18n::t;
 
-pub fn reminder(
-    reminder_text: PreEscaped<&str>,
-    header_img: &EmailAttachment,
-    body_img: &EmailAttachment,
-    footer_img: &EmailAttachment,
-    dont_print_img: &EmailAttachment,
-    link: &str,
-) -> Markup {
+static BODY_IMG: &[u8] = include_bytes!("../../assets/body.jpg");
+static FOOTER_IMG: &[u8] = include_bytes!("../../assets/footer.jpg");
+static HEADER_IMG: &[u8] = include_bytes!("../../assets/header.jpg");
+static HEADER_EXCLAMATION_IMG: &[u8] = include_bytes!("../../assets/header_exclamation.jpg");
+static DONT_PRINT_IMG: &[u8] = include_bytes!("../../assets/dont_print.jpg");
+
+/// Images of the built-in templates, all of them are JPEGs
+pub const BUILT_IN_IMAGES: [(&str, &[u8]); 5] = [
+    ("body", BODY_IMG),
+    ("footer", FOOTER_IMG),
+    ("header", HEADER_IMG),
+    ("header_exclamation", HEADER_EXCLAMATION_IMG),
+    ("dont_print", DONT_PRINT_IMG),
+];
+
+/// A placeholder in a template, see `logic::email_templates::Placeholders`
+fn placeholder(name: &str) -> String {
+    format!("{{{{{}}}}}", name)
+}
+
+fn image(name: &str) -> String {
+    placeholder(&format!("image:{}", name))
+}
+
+/// A translation with a line break in place of each newline
+fn lines(text: &str) -> Markup {
+    html! {
+        @for (i, line) in text.lines().enumerate() {
+            @if i > 0 {
+                br;
+            }
+            (line)
+        }
+    }
+}
+
+/// Subject and body of the template which is used until one is saved for the kind and language
+pub fn built_in_template(kind: OutboxKind, locale: &str) -> (String, String) {
+    let collector = placeholder("collector");
+    let client = placeholder("client");
+    match kind {
+        OutboxKind::FirstReminder => (
+            t!(
+                "email_first_reminder_subject",
+                locale = locale,
+                collector = collector,
+                client = client
+            )
+            .into_owned(),
+            reminder(
+                locale,
+                &t!("email_first_reminder_text", locale = locale),
+                "header",
+            )
+            .into_string(),
+        ),
+        OutboxKind::SecondReminder => (
+            t!(
+                "email_second_reminder_subject",
+                locale = locale,
+                collector = collector,
+                client = client
+            )
+            .into_owned(),
+            reminder(
+                locale,
+                &t!("email_second_reminder_text", locale = locale),
+                "header_exclamation",
+            )
+            .into_string(),
+        ),
+        OutboxKind::Escalation => (
+            t!(
+                "email_escalation_subject",
+                locale = locale,
+                supplier = placeholder("supplier"),
+                collector = collector,
+                client = client
+            )
+            .into_owned(),
+            escalation(locale).into_string(),
+        ),
+    }
+}
+
+/// `locale` is the name of the email's language, as `t!` takes it.
+/// Mail clients don't have to download anything, fonts fall back to the ones they have.
+fn reminder(locale: &str, reminder_text: &str, header_image: &str) -> Markup {
+    let link = placeholder("link");
     html! {
         (DOCTYPE)
         head {
             meta name="viewport" content="width=device-width, initial-scale=1.0";
             meta http-equiv="Content-Type" content="text/html; charset=utf-8";
             title { "" }
-            link rel="preconnect" href="https://fonts.googleapis.com";
-            link rel="preconnect" href="https://fonts.gstatic.com";
-            link href="https://fonts.googleapis.com/css2?family=League+Spartan:wght@700&display=swap" rel="stylesheet";
-            link href="https://fonts.googleapis.com/css2?family=League+Spartan&display=swap" rel="stylesheet";
             style type="text/css" {
                 {r#"
                     body {
@@ -35,14 +113,14 @@ pub fn reminder(
                         text-decoration: none !important;
                     }
                     .league-spartan-bold {
-                        font-family: "League Spartan", sans-serif;
+                        font-family: "League Spartan", Arial, Helvetica, sans-serif;
                         font-optical-sizing: auto;
                         font-weight: 700;
                         font-style: normal;
                         line-height: 1.4;
                     }
                     .league-spartan-regular {
-                        font-family: "League Spartan", sans-serif;
+                        font-family: "League Spartan", Arial, Helvetica, sans-serif;
                         font-optical-sizing: auto;
                         font-weight: 400;
                         font-style: normal;
@@ -75,25 +153,31 @@ pub fn reminder(
                                                     tr {
                                                         td {
                                                             a href=(link) target="_blank" {
-                                                                img style="display: block; border: 0;" border="0" class="img" src=(header_img.as_src()) width="600" height="235" alt="";
+                                                                img style="display: block; border: 0;" border="0" class="img" src=(image(header_image)) width="600" height="235" alt="";
                                                             }
                                                         }
                                                     }
-                                                    tr height="211px" background=(body_img.as_src()) {
-                                                        td style="padding-left: 10%; padding-bottom: 2%;" {
+                                                    tr {
+                                                        // Outlook ignores backgrounds of cells, it draws the VML rectangle behind the text instead
+                                                        td height="211" bgcolor="#ffffff" background=(image("body")) style="padding-left: 10%; padding-bottom: 2%;" {
+                                                            (PreEscaped(format!(
+                                                                r##"<!--[if gte mso 9]><v:rect xmlns:v="urn:schemas-microsoft-com:vml" fill="true" stroke="false" style="width: 450pt; height: 158pt;"><v:fill type="frame" src="{}" color="#ffffff" /><v:textbox inset="0,0,0,0"><![endif]-->"##,
+                                                                image("body")
+                                                            )))
                                                             span class="league-spartan-bold" style="font-size: 23px; line-height: 1.5;" {
-                                                                "Cześć,"
+                                                                (t!("email_greeting", locale = locale))
                                                                 br;
                                                             }
                                                             span class="league-spartan-regular" style="font-size: 23px; line-height: 1.3;" {
-                                                                (reminder_text)
+                                                                (lines(reminder_text))
                                                             }
+                                                            (PreEscaped("<!--[if gte mso 9]></v:textbox></v:rect><![endif]-->"))
                                                         }
                                                     }
                                                     tr {
                                                         td {
                                                             a href=(link) target="_blank" {
-                                                                img style="display: block; border: 0;" border="0" class="img" src=(footer_img.as_src()) width="600" height="auto" alt="";
+                                                                img style="display: block; border: 0;" border="0" class="img" src=(image("footer")) width="600" height="auto" alt="";
                                                             }
                                                         }
                                                     }
@@ -105,13 +189,7 @@ pub fn reminder(
                                                         td width="6%" {}
                                                         td width="94%" class="league-spartan-bold" style="font-size: 13px; padding-top: 2%;" {
                                                             span {
-                                                                "Daniel Statystyka"
-                                                                br;
-                                                                "Stats Collector"
-                                                                br;
-                                                                "SIGMA BIS S.A."
-                                                                br;
-                                                                "ul. Bielańska 12, 00-085 Warszawa"
+                                                                (lines(&t!("email_signature", locale = locale)))
                                                             }
                                                         }
                                                     }
@@ -125,17 +203,7 @@ pub fn reminder(
                                                             span {
                                                                 br;
                                                                 br;
-                                                                "Sigma BIS Spółka Akcyjna z siedzibą w Warszawie, ul. Bielańska 12, 00-085 Warszawa"
-                                                                br;
-                                                                "wpisaną do rejestru przedsiębiorców Krajowego Rejestru Sądowego, prowadzonego przez Sąd Rejonowym dla m. st. Warszawy w Warszawie, XII Wydział Gospodarczy Krajowego Rejestru Sądowego, pod numerem KRS: 0000534906, o kapitale zakładowym (w pełni opłaconym) w wysokości: 3.100.000,00 zł, nr NIP: 527-273-18-26"
-                                                                br;
-                                                                "UWAGA: Informacja zawarta w niniejszej wiadomości lub którymkolwiek z jej załączników podlega ochronie i jest objęta zakazem ujawniania. Jeśli czytelnik niniejszej wiadomości nie jest jej zamierzonym adresatem lub pośrednikiem upoważnionym do jej przekazania adresatowi, niniejszym informujemy, że wszelkie ujawnianie, w tym przekazanie osobom trzecim, rozprowadzanie, dystrybucja, powielanie niniejszej wiadomości lub jej załączników, bądź inne działanie o podobnym charakterze jest zabronione. Jeżeli otrzymałeś tę wiadomość omyłkowo, prosimy niezwłocznie zawiadomić nadawcę wysyłając odpowiedź na niniejszą wiadomość, a następnie usunąć ją z komputera bez otwierania załączników. Dziękujemy. Sigma BIS S.A."
-                                                                br;
-                                                                "Sigma BIS SA, a Polish company, with its registered office at ul. Bielańska 12, 00-085 Warszawa"
-                                                                br;
-                                                                "entered into the Register of Entrepreneurs kept by the District Court for Warsaw in Warsaw, XII Commercial Division of the National Court Register under the number KRS 0000534906 NIP: 527-273-18-26, share capital/paid up capital: 3.100.000,00 PLN"
-                                                                br;
-                                                                "NOTE: Information contained in this message or any attachments thereto may be protected and withheld from disclosure. Please be advised that if you are not the intended addressee or an authorised recipient of the addressee, any disclosure of this message, including forwarding it or any attachments thereto to third parties, dissemination, distribution, reproduction or any similar activity is prohibited. If you are not the intended recipient of this message, please promptly notify the sender by replying to this message, and then delete it from your computer without opening the attachments. Thank you Sigma BIS S.A."
+                                                                (lines(&t!("email_legal_footer", locale = locale)))
                                                             }
                                                         }
                                                         td width="6%" {}
@@ -148,7 +216,7 @@ pub fn reminder(
                                                         td width="6%" {}
                                                         td width="94%" {
                                                             br;
-                                                            img style="display: block; border: 0;" border="0" class="img" src=(dont_print_img.as_src()) width="210" height="75" alt="Oszczędność mamy w naturze - Sprawdź";
+                                                            img style="display: block; border: 0;" border="0" class="img" src=(image("dont_print")) width="210" height="75" alt=(t!("email_dont_print_alt", locale = locale));
                                                         }
                                                     }
                                                 }
@@ -164,3 +232,32 @@ pub fn reminder(
         }
     }
 }
+
+/// Escaped text in bold, to put in a translation
+fn bold(text: &str) -> String {
+    html! { b { (text) } }.into_string()
+}
+
+fn escalation(locale: &str) -> Markup {
+    html! {
+        (DOCTYPE)
+        head {
+            meta http-equiv="Content-Type" content="text/html; charset=utf-8";
+        }
+        body {
+            p { (t!("email_greeting", locale = locale)) }
+            p {
+                (PreEscaped(t!(
+                    "email_escalation_text",
+                    locale = locale,
+                    supplier = bold(&placeholder("supplier")),
+                    mail = placeholder("supplier_mail"),
+                    period = bold(&placeholder("period")),
+                    collector = bold(&placeholder("collector")),
+                    client = bold(&placeholder("client"))
+                )))
+            }
+            p { (t!("email_escalation_contact", locale = locale)) }
+        }
+    }
+}
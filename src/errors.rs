@@ -1,4 +1,16 @@
-use axum::response::IntoResponse;
+use crate::json::sent::{ErrorCode, ErrorResponse, ValidationProblem};
+use crate::logic::locale::{Locale, RequestedLocale};
+use crate::logic::render_html;
+use axum::body::Body;
+use axum::extract::rejection::{FormRejection, JsonRejection, PathRejection, QueryRejection};
+use axum::extract::Request;
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use itertools::Itertools;
+use maud::{html, Markup};
+use rust_i18n::t;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,6 +20,14 @@ pub enum AppError {
     NotFound { resource: String, id: String },
     #[error("Conflict: {resource} with id {id} already exists")]
     Conflict { resource: String, id: String },
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Invalid request: {}", .0.iter().map(|problem| format!("{}: {}", problem.path, problem.message)).join(", "))]
+    Validation(Vec<ValidationProblem>),
     #[error("Database error: {0}")]
     DbError(#[from] diesel::result::Error),
     #[error("Connection pool error: {0}")]
@@ -18,6 +38,10 @@ pub enum AppError {
     EmailError(#[from] lettre::error::Error),
     #[error("Email send error: {0}")]
     EmailSendError(#[from] lettre::transport::smtp::Error),
+    #[error("Email file error: {0}")]
+    EmailFileError(#[from] lettre::transport::file::Error),
+    #[error("Sendmail error: {0}")]
+    SendmailError(#[from] lettre::transport::sendmail::Error),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -37,19 +61,231 @@ impl AppError {
         }
     }
 
+    pub fn bad_request(message: impl ToString) -> Self {
+        Self::BadRequest(message.to_string())
+    }
+
+    pub fn unauthorized(message: impl ToString) -> Self {
+        Self::Unauthorized(message.to_string())
+    }
+
+    pub fn forbidden(message: impl ToString) -> Self {
+        Self::Forbidden(message.to_string())
+    }
+
+    /// A single problem with the part of the request body at `path`
+    pub fn validation(path: impl ToString, message: impl ToString) -> Self {
+        Self::Validation(vec![ValidationProblem {
+            path: path.to_string(),
+            message: message.to_string(),
+        }])
+    }
+
     pub fn other(error: impl Into<anyhow::Error>) -> Self {
         Self::Other(error.into())
     }
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> axum::http::Response<axum::body::Body> {
-        let status_code = match &self {
-            Self::NotFound { .. } => axum::http::StatusCode::NOT_FOUND,
-            Self::Conflict { .. } => axum::http::StatusCode::CONFLICT,
-            _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+/// Malformed JSON is a bad request, well-formed JSON which doesn't fit the expected type is invalid
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(error) => Self::validation("", error.body_text()),
+            rejection => Self::bad_request(rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl From<FormRejection> for AppError {
+    fn from(rejection: FormRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound { .. } => StatusCode::NOT_FOUND,
+            Self::Conflict { .. } => StatusCode::CONFLICT,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotFound { .. } => ErrorCode::NotFound,
+            Self::Conflict { .. } => ErrorCode::Conflict,
+            Self::BadRequest(_) => ErrorCode::BadRequest,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::Forbidden(_) => ErrorCode::Forbidden,
+            Self::Validation(_) => ErrorCode::ValidationFailed,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<AppError> for ErrorResponse {
+    fn from(error: AppError) -> Self {
+        let message = error.to_string();
+        let code = error.code();
+        let (resource, id, details) = match error {
+            AppError::NotFound { resource, id } | AppError::Conflict { resource, id } => {
+                (Some(resource), Some(id), vec![])
+            }
+            AppError::Validation(problems) => (None, None, problems),
+            _ => (None, None, vec![]),
         };
+        Self {
+            code,
+            message,
+            resource,
+            id,
+            details,
+        }
+    }
+}
+
+/// Responds with an `ErrorResponse`, which `negotiate_error_format` turns into a page for browsers
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status_code = self.status_code();
+        let unauthorized = matches!(self, Self::Unauthorized(_));
+        let body = ErrorResponse::from(self);
+
+        let mut response = (status_code, Json(body.clone())).into_response();
+        if unauthorized {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+/// Whether the client asks for HTML before JSON, as browsers do
+fn prefers_html(headers: &HeaderMap) -> bool {
+    let accept = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|media_type| media_type.split(';').next().unwrap_or_default().trim())
+        .collect::<Vec<_>>();
+    let position = |media_type| accept.iter().position(|accepted| *accepted == media_type);
+
+    match (position("text/html"), position("application/json")) {
+        (Some(html), Some(json)) => html < json,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+fn error_page(locale: &str, status_code: StatusCode, error: &ErrorResponse) -> Markup {
+    let description = match error.code {
+        ErrorCode::NotFound => t!("error_not_found", locale = locale),
+        ErrorCode::Conflict => t!("error_conflict", locale = locale),
+        ErrorCode::BadRequest => t!("error_bad_request", locale = locale),
+        ErrorCode::Unauthorized => t!("error_unauthorized", locale = locale),
+        ErrorCode::Forbidden => t!("error_forbidden", locale = locale),
+        ErrorCode::ValidationFailed => t!("error_validation_failed", locale = locale),
+        ErrorCode::Internal => t!("error_internal", locale = locale),
+    };
+    let title = t!(
+        "error_title",
+        locale = locale,
+        status = status_code.as_u16()
+    );
+
+    render_html::template(
+        locale,
+        &title,
+        html! {
+            div style="max-width: 40em; margin: 4em auto; padding: 0 1em; font-family: sans-serif" {
+                h1 { (title) }
+                p { (description) }
+                @if !error.details.is_empty() {
+                    ul {
+                        @for problem in &error.details {
+                            li { code { (problem.path) } ": " (problem.message) }
+                        }
+                    }
+                }
+                p style="color: #666; font-size: small" { (error.message) }
+            }
+        },
+    )
+}
+
+/// Middleware which renders error responses as a page in the requested language if the request prefers HTML
+pub async fn negotiate_error_format(request: Request, next: Next) -> Response {
+    let html = prefers_html(request.headers());
+    let locale = RequestedLocale::from_request(request.uri(), request.headers())
+        .resolve(None, Locale::default())
+        .as_str();
+    let mut response = next.run(request).await;
+
+    let Some(error) = response
+        .extensions_mut()
+        .remove::<ErrorResponse>()
+        .filter(|_| html)
+    else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    let page = error_page(locale, parts.status, &error);
+    Response::from_parts(parts, Body::from(page.into_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(ACCEPT, HeaderValue::from_static(value))])
+    }
+
+    #[test]
+    fn browsers_get_html() {
+        assert!(prefers_html(&accept(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+        )));
+        assert!(!prefers_html(&accept("application/json, text/html")));
+        assert!(!prefers_html(&accept("*/*")));
+        assert!(!prefers_html(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn errors_have_structured_bodies() {
+        let body = ErrorResponse::from(AppError::conflict("statistics collector", "abc"));
+        assert_eq!(body.code, ErrorCode::Conflict);
+        assert_eq!(body.resource.as_deref(), Some("statistics collector"));
+        assert_eq!(body.id.as_deref(), Some("abc"));
 
-        (status_code, self.to_string()).into_response()
+        let body = ErrorResponse::from(AppError::validation("/name", "name must not be empty"));
+        assert_eq!(body.code, ErrorCode::ValidationFailed);
+        assert_eq!(body.details[0].path, "/name");
     }
 }
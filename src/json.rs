@@ -1,11 +1,13 @@
+pub mod derived;
 pub mod received;
+pub mod reminders;
 pub mod sent;
 
-mod date_serde {
+pub(crate) mod date_serde {
     use chrono::NaiveDate;
     use serde::{Deserialize, Deserializer, Serializer};
 
-    const FORMAT: &str = "%Y.%m.%d";
+    pub(crate) const FORMAT: &str = "%Y.%m.%d";
 
     pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -22,6 +24,73 @@ mod date_serde {
         let s = String::deserialize(deserializer)?;
         NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
     }
+
+    /// Same format for optional dates, which are null if empty
+    pub(crate) mod option {
+        use chrono::NaiveDate;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S>(date: &Option<NaiveDate>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match date {
+                Some(date) => super::serialize(date, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|s| NaiveDate::parse_from_str(&s, super::FORMAT))
+                .transpose()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Weekdays are written as lowercase English names, an empty string or null means none
+pub(crate) mod weekday_serde {
+    use chrono::Weekday;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn name(weekday: Weekday) -> &'static str {
+        match weekday {
+            Weekday::Mon => "monday",
+            Weekday::Tue => "tuesday",
+            Weekday::Wed => "wednesday",
+            Weekday::Thu => "thursday",
+            Weekday::Fri => "friday",
+            Weekday::Sat => "saturday",
+            Weekday::Sun => "sunday",
+        }
+    }
+
+    pub fn serialize<S>(weekday: &Option<Weekday>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match weekday {
+            Some(weekday) => serializer.serialize_str(name(*weekday)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Weekday>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(s) if !s.is_empty() => s
+                .parse::<Weekday>()
+                .map(Some)
+                .map_err(|_| serde::de::Error::custom(format!("unknown weekday {}", s))),
+            _ => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -43,4 +112,26 @@ mod tests {
         let parsed_date: TestStruct = serde_json::from_str(&s).unwrap();
         assert_eq!(parsed_date, date);
     }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[repr(transparent)]
+    struct WeekdayStruct(#[serde(with = "weekday_serde")] Option<chrono::Weekday>);
+
+    #[test]
+    fn weekday_serde_works() {
+        let weekday = WeekdayStruct(Some(chrono::Weekday::Wed));
+        let s = serde_json::to_string(&weekday).unwrap();
+        assert_eq!(s, "\"wednesday\"");
+        assert_eq!(serde_json::from_str::<WeekdayStruct>(&s).unwrap(), weekday);
+        assert_eq!(
+            serde_json::from_str::<WeekdayStruct>("\"Wednesday\"").unwrap(),
+            weekday
+        );
+
+        assert_eq!(
+            serde_json::from_str::<WeekdayStruct>("\"\"").unwrap(),
+            WeekdayStruct(None)
+        );
+        assert!(serde_json::from_str::<WeekdayStruct>("\"someday\"").is_err());
+    }
 }
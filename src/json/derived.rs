@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A statistic type computed in summaries from the totals of the other statistic types of the collector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DerivedStatisticType {
+    #[schema(example = "CTR")]
+    pub name: String,
+    /// Arithmetic on statistic type names and numbers with `+ - * /` and parentheses.
+    /// Names containing these characters are written in square brackets, like `[Click-through]`.
+    #[schema(example = "Clicks / Displays")]
+    pub formula: String,
+}
@@ -1,9 +1,14 @@
-use crate::json::date_serde;
+use crate::db::{CopyId, PeriodId, PlacementTypeId, StatisticTypeId, SupplierId};
+use crate::json::derived::DerivedStatisticType;
+use crate::json::reminders::ReminderPolicy;
+use crate::json::{date_serde, weekday_serde};
+use crate::logic::locale::Locale;
+use crate::logic::periods::Periodicity;
 use lettre::Address;
 use serde::Deserialize;
 use serde::Serialize;
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Weekday};
 use utoipa::ToSchema;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
@@ -11,15 +16,134 @@ use utoipa::ToSchema;
 pub struct StatCollector {
     pub name: String,
     pub client: String,
+    /// Can be left out if `campaign` is given
+    #[serde(default)]
     pub periods: Vec<Period>,
     pub placement_types: Vec<PlacementType>,
-    pub periodicity: String,
-    pub weekday: String,
+    pub periodicity: Periodicity,
+    /// Required for weekly and biweekly periodicities
+    #[serde(default, with = "weekday_serde")]
+    #[schema(value_type = Option<String>, example = "wednesday")]
+    pub weekday: Option<Weekday>,
+    /// The periods are generated from the campaign if it's given
+    #[serde(default)]
+    pub campaign: Option<Campaign>,
+    /// New collectors get the reminders which used to be hardcoded if not given,
+    /// updates keep the stored ones
+    #[serde(default)]
+    pub reminder_policy: Option<ReminderPolicy>,
+    /// Updates keep the stored ones if not given
+    #[serde(default)]
+    pub derived_statistic_types: Option<Vec<DerivedStatisticType>>,
+    /// Language of the supplier pages and emails of suppliers without their own,
+    /// new collectors get the default one if not given, updates keep the stored one
+    #[serde(default)]
+    pub locale: Option<Locale>,
 }
 
+/// First and last day of the campaign
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Campaign {
+    #[serde(with = "date_serde")]
+    #[schema(example = "2021.01.01")]
+    pub start_date: NaiveDate,
+    #[serde(with = "date_serde")]
+    #[schema(example = "2021.12.25")]
+    pub end_date: NaiveDate,
+}
+
+/// A new collector with the structure of an existing one or of a template, without any statistics.
+/// The periods are the given ones, or generated from the campaign,
+/// or else the original periods moved by `periodOffsetDays`, or by `periodOffsetMonths`
+/// for monthly and custom periodicities.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CloneRequest {
+    pub name: String,
+    pub client: String,
+    #[serde(default)]
+    pub periods: Vec<Period>,
+    #[serde(default)]
+    pub campaign: Option<Campaign>,
+    /// Days to move the original periods by, weekly periods start on a correspondingly later weekday
+    #[serde(default)]
+    #[schema(example = 364)]
+    pub period_offset_days: i64,
+    /// Calendar months to move the original periods of monthly and custom periodicities by,
+    /// periods ending on the last day of a month still do
+    #[serde(default)]
+    #[schema(example = 12)]
+    pub period_offset_months: i32,
+}
+
+/// A supplier added to a placement type of an existing collector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSupplier {
+    pub placement_type_id: PlacementTypeId,
+    pub name: String,
+    pub mail: Address,
+    /// The collector's language is used if not given
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+/// Changes of a supplier of an existing collector, missing fields stay as they are.
+/// When moving, values are kept for the statistic types and copies of the same names
+/// in the new placement type, the others are dropped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplierChanges {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub mail: Option<Address>,
+    #[serde(default)]
+    pub placement_type_id: Option<PlacementTypeId>,
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+/// Saves the structure of a collector as a template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NewTemplate {
+    pub name: String,
+}
+
+/// A new version of an email template. Placeholders such as `{{collector}}` are replaced
+/// when the email is sent, see `logic::email_templates::Placeholders`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EmailTemplateContent {
+    #[schema(example = "Statystyki {{collector}} dla {{client}}")]
+    pub subject: String,
+    /// HTML, images are shown with `<img src="{{image:name}}">`
+    #[schema(
+        example = "<p>Prosimy o statystyki za {{period}}: <a href=\"{{link}}\">{{link}}</a></p>"
+    )]
+    pub body: String,
+}
+
+/// Renders an email template for a supplier without sending it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailPreviewRequest {
+    pub supplier_id: SupplierId,
+    /// The last period which has ended, or the first one, if not given
+    #[serde(default)]
+    pub period_id: Option<PeriodId>,
+    /// Content to preview instead of the current version of the template
+    #[serde(default)]
+    pub content: Option<EmailTemplateContent>,
+}
+
+/// `id` is only meaningful when updating an existing collector.
+/// Items without it are matched by name.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Period {
+    #[serde(default)]
+    pub id: Option<PeriodId>,
     pub name: String,
     #[serde(with = "date_serde")]
     #[schema(example = "2021.01.01")]
@@ -30,15 +154,30 @@ pub struct Period {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct PlacementType {
+    #[serde(default)]
+    pub id: Option<PlacementTypeId>,
     pub name: String,
     pub suppliers: Vec<Supplier>,
     pub statistics: Vec<String>,
     pub copies: Vec<String>,
+    /// Ids of `statistics` in the same order, `null` for new ones. Can be left out.
+    /// A statistic type with an id keeps its values when it's renamed.
+    #[serde(default)]
+    pub statistic_type_ids: Vec<Option<StatisticTypeId>>,
+    /// Ids of `copies` in the same order, `null` for new ones. Can be left out.
+    #[serde(default)]
+    pub copy_ids: Vec<Option<CopyId>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Supplier {
+    #[serde(default)]
+    pub id: Option<SupplierId>,
     pub name: String,
     pub mail: Address,
+    /// The collector's language is used if not given, updates keep the stored language
+    #[serde(default)]
+    pub locale: Option<Locale>,
 }
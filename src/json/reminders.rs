@@ -0,0 +1,85 @@
+use crate::logic::email::ReminderType;
+use lettre::Address;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// When and to whom reminders about each period of a collector are sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderPolicy {
+    /// Receives reminders sent to `accountManager`
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "manager@example.com")]
+    pub account_manager: Option<Address>,
+    pub rules: Vec<ReminderRule>,
+}
+
+/// Reminders which used to be hardcoded: on the day a period ends,
+/// the first one at 8:00 and the second one at 15:00
+impl Default for ReminderPolicy {
+    fn default() -> Self {
+        Self {
+            account_manager: None,
+            rules: vec![
+                ReminderRule {
+                    days_after_end: 0,
+                    hour: 8,
+                    reminder_type: ReminderType::FirstReminder,
+                    recipients: vec![ReminderRecipient::Supplier],
+                },
+                ReminderRule {
+                    days_after_end: 0,
+                    hour: 15,
+                    reminder_type: ReminderType::SecondReminder,
+                    recipients: vec![ReminderRecipient::Supplier],
+                },
+            ],
+        }
+    }
+}
+
+/// A reminder sent for every period, `days_after_end` days after the period ends, at `hour` local time.
+/// Second reminders, and all reminders sent to the account manager,
+/// are skipped for suppliers who have already submitted their statistics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderRule {
+    /// Can be negative to send a reminder before the period ends
+    #[schema(example = 2)]
+    pub days_after_end: i32,
+    /// 0-23
+    #[schema(example = 8)]
+    pub hour: u32,
+    pub reminder_type: ReminderType,
+    pub recipients: Vec<ReminderRecipient>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ReminderRecipient {
+    Supplier,
+    AccountManager,
+}
+
+impl Display for ReminderRecipient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Supplier => write!(f, "supplier"),
+            Self::AccountManager => write!(f, "accountManager"),
+        }
+    }
+}
+
+impl FromStr for ReminderRecipient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "supplier" => Ok(Self::Supplier),
+            "accountManager" => Ok(Self::AccountManager),
+            _ => Err(format!("unknown reminder recipient {}", s)),
+        }
+    }
+}
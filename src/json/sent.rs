@@ -1,9 +1,22 @@
-use crate::db::{PeriodId, PlacementTypeId, StatCollectorId, SupplierId};
-use crate::json::date_serde;
-use chrono::NaiveDate;
+use crate::db::{
+    CollectorTemplateId, CopyId, OutboxMessageId, PeriodId, PlacementTypeId, ReminderDeliveryId,
+    StatCollectorId, StatisticRevisionId, StatisticTypeId, SupplierId,
+};
+use crate::json;
+use crate::json::derived::DerivedStatisticType;
+use crate::json::reminders::ReminderPolicy;
+use crate::json::{date_serde, weekday_serde};
+use crate::logic::collector_list::CollectorStatus;
+use crate::logic::email::ReminderType;
+use crate::logic::locale::Locale;
+use crate::logic::outbox::{OutboxKind, OutboxStatus};
+use crate::logic::periods::Periodicity;
+use crate::logic::summary::Dimension;
+use chrono::{DateTime, Local, NaiveDate, Weekday};
 use lettre::Address;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use utoipa::{ToResponse, ToSchema};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, ToResponse)]
@@ -14,8 +27,15 @@ pub struct StatCollector {
     pub client: String,
     pub periods: Vec<Period>,
     pub placement_types: Vec<PlacementType>,
-    pub periodicity: String,
-    pub weekday: String,
+    pub periodicity: Periodicity,
+    #[serde(with = "weekday_serde")]
+    #[schema(value_type = Option<String>, example = "wednesday")]
+    pub weekday: Option<Weekday>,
+    pub reminder_policy: ReminderPolicy,
+    pub derived_statistic_types: Vec<DerivedStatisticType>,
+    pub locale: Locale,
+    /// Submissions of each period, sorted like `periods`
+    pub completion: Vec<PeriodCompletion>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
@@ -32,12 +52,17 @@ pub struct Period {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct PlacementType {
     pub id: PlacementTypeId,
     pub name: String,
     pub suppliers: Vec<Supplier>,
     pub statistics: Vec<String>,
     pub copies: Vec<String>,
+    /// Ids of `statistics` in the same order
+    pub statistic_type_ids: Vec<StatisticTypeId>,
+    /// Ids of `copies` in the same order
+    pub copy_ids: Vec<CopyId>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
@@ -45,6 +70,8 @@ pub struct Supplier {
     pub id: SupplierId,
     pub name: String,
     pub mail: Address,
+    /// Set if the supplier doesn't use the collector's language
+    pub locale: Option<Locale>,
     /// Outer index is stat type, middle index is copy inner index is date
     /// In other words, given stat types Display and Clicks, dates 1, 2, 3 and copies A, B:
     /// stats[0][0][0] is the number of displays for copy A on date 1
@@ -59,7 +86,262 @@ pub struct Supplier {
     /// stats[1][1][0] is the number of clicks for copy B on date 1
     /// stats[1][1][1] is the number of clicks for copy B on date 2
     /// etc.
-    pub stats: Vec<Vec<Vec<i32>>>,
+    pub stats: Vec<Vec<Vec<Option<i32>>>>,
+}
+
+/// A freshly issued access token of a supplier. It can't be retrieved again later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplierToken {
+    pub token: String,
+    /// Path of the supplier page, with the token included
+    #[schema(example = "/supplier/6e2c0b58-1a77-4b39-9b6b-0a4f2b3c1d2e?token=...")]
+    pub path: String,
+    #[schema(value_type = String, format = DateTime)]
+    pub expires_at: DateTime<Local>,
+}
+
+/// Result of `GET /statistics_collector`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectorPage {
+    pub items: Vec<CollectorListItem>,
+    /// Pass it as `cursor` to get the next page, empty on the last page
+    pub next_cursor: Option<String>,
+    /// Number of collectors matching the filters, on all pages
+    pub total: i64,
+}
+
+/// A statistics collector of the list, without its configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectorListItem {
+    pub id: StatCollectorId,
+    pub name: String,
+    pub client: String,
+    pub periodicity: Periodicity,
+    #[serde(with = "weekday_serde")]
+    #[schema(value_type = Option<String>, example = "wednesday")]
+    pub weekday: Option<Weekday>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Local>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub archived_at: Option<DateTime<Local>>,
+    /// Deleted collectors are only listed with `deleted=true`
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub deleted_at: Option<DateTime<Local>>,
+    /// Start of the first period, empty if there are no periods
+    #[serde(with = "date_serde::option")]
+    #[schema(value_type = Option<String>, example = "2021.01.01")]
+    pub start_date: Option<NaiveDate>,
+    /// End of the last period, empty if there are no periods
+    #[serde(with = "date_serde::option")]
+    #[schema(value_type = Option<String>, example = "2021.12.25")]
+    pub end_date: Option<NaiveDate>,
+    pub status: CollectorStatus,
+    pub suppliers: i64,
+    /// Percentage of the ended periods the suppliers filled in completely,
+    /// empty if no period has ended yet
+    #[schema(example = 87.5)]
+    pub completion: Option<f64>,
+}
+
+/// A saved structure of a collector, see `POST /collector_template/{id}/instantiate`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectorTemplate {
+    pub id: CollectorTemplateId,
+    pub name: String,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Local>,
+    /// Name, client and periods of the collector the template was saved from
+    pub collector: json::received::StatCollector,
+}
+
+/// A version of the template of an email
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailTemplate {
+    pub kind: OutboxKind,
+    pub locale: Locale,
+    /// 0 is the built-in template, which is used until the first version is saved
+    pub version: i32,
+    pub subject: String,
+    pub body: String,
+    /// Empty for the built-in template
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub created_at: Option<DateTime<Local>>,
+}
+
+/// An email rendered for a supplier, images are embedded as data URLs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EmailPreview {
+    /// Empty for escalations of collectors without an account manager
+    pub to: Option<String>,
+    pub subject: String,
+    pub html: String,
+    /// The plain text alternative sent with the HTML
+    pub text: String,
+}
+
+/// An image which email templates can show with `{{image:name}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailImage {
+    pub name: String,
+    pub content_type: String,
+    /// Bytes
+    pub size: usize,
+    /// Built-in images are used until an image with the same name is uploaded
+    pub built_in: bool,
+}
+
+/// How many suppliers submitted the statistics of a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodCompletion {
+    pub period_id: PeriodId,
+    pub suppliers: usize,
+    /// Suppliers who submitted the period, even if some values are missing
+    pub submitted: usize,
+    /// Suppliers who filled in all values of the period
+    pub complete: usize,
+    pub incomplete_suppliers: Vec<SupplierId>,
+}
+
+/// A change of a single value made by a supplier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticRevision {
+    pub id: StatisticRevisionId,
+    #[schema(value_type = String, format = DateTime)]
+    pub changed_at: DateTime<Local>,
+    /// Empty if the supplier was removed, the names are kept
+    pub supplier_id: Option<SupplierId>,
+    pub supplier: String,
+    pub placement_type: String,
+    pub period: String,
+    pub copy: String,
+    pub statistic_type: String,
+    /// Empty if the value wasn't filled in before
+    pub old_value: Option<i32>,
+    /// Empty if the value was cleared
+    pub new_value: Option<i32>,
+    pub user_agent: Option<String>,
+    pub forwarded_for: Option<String>,
+}
+
+/// An email of the outbox
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMessage {
+    pub id: OutboxMessageId,
+    pub kind: OutboxKind,
+    pub status: OutboxStatus,
+    pub supplier_id: SupplierId,
+    pub supplier: String,
+    pub period: Option<String>,
+    pub recipient: String,
+    pub attempts: i32,
+    /// When a pending message is sent next
+    #[schema(value_type = String, format = DateTime)]
+    pub next_attempt_at: DateTime<Local>,
+    pub last_error: Option<String>,
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime<Local>,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub sent_at: Option<DateTime<Local>>,
+}
+
+/// Result of `POST /statistics_collector/{id}/send_emails/{reminder_type}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedReminders {
+    /// Period the reminders are about
+    pub period_id: PeriodId,
+    pub queued: usize,
+    /// Suppliers who were already reminded about the period
+    pub skipped: usize,
+}
+
+/// An attempt to send a reminder, see `Mailer::send_reminder`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderDelivery {
+    pub id: ReminderDeliveryId,
+    pub supplier_id: SupplierId,
+    pub supplier: String,
+    pub period: Option<String>,
+    pub reminder_type: ReminderType,
+    #[schema(value_type = String, format = DateTime)]
+    pub attempted_at: DateTime<Local>,
+    /// Empty if the reminder was sent
+    pub error: Option<String>,
+}
+
+/// Result of `GET /statistics_collector/{id}/summary`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub group_by: Vec<Dimension>,
+    pub rows: Vec<SummaryRow>,
+}
+
+/// Totals of a group of statistics. Ids and names of the dimensions
+/// the summary isn't grouped by are empty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SummaryRow {
+    pub period_id: Option<PeriodId>,
+    pub period: Option<String>,
+    pub placement_type_id: Option<PlacementTypeId>,
+    pub placement_type: Option<String>,
+    pub supplier_id: Option<SupplierId>,
+    pub supplier: Option<String>,
+    pub copy_id: Option<CopyId>,
+    pub copy: Option<String>,
+    /// Sums by statistic type name, empty if no value of the statistic type is filled in
+    pub totals: BTreeMap<String, Option<i64>>,
+    /// Values of the derived statistic types computed from `totals`,
+    /// empty if a total is empty or the formula divides by zero
+    pub derived: BTreeMap<String, Option<f64>>,
+    /// Number of cells which aren't filled in yet
+    pub missing: i64,
+}
+
+/// Body of every error response, unless the request prefers HTML
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    /// Human readable, not meant to be parsed
+    pub message: String,
+    /// Kind of the missing or conflicting resource
+    #[schema(example = "statistics collector")]
+    pub resource: Option<String>,
+    /// Id of the missing or conflicting resource
+    pub id: Option<String>,
+    /// Every problem found in the request body, for `validation_failed` errors
+    pub details: Vec<ValidationProblem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    Conflict,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    ValidationFailed,
+    Internal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ValidationProblem {
+    /// JSON pointer to the invalid part of the request body
+    #[schema(example = "/periods/1/endDate")]
+    pub path: String,
+    pub message: String,
 }
 
 #[cfg(test)]
@@ -79,7 +361,11 @@ mod test {
         id: SupplierId::new(),
         name: "test supplier".to_string(),
         mail: Address::new("user", "test.com").unwrap(),
-        stats: vec![vec![vec![0, 1, 2], vec![3, 4, 5]]],
+        locale: None,
+        stats: vec![vec![
+            vec![None, Some(1), Some(2)],
+            vec![Some(3), Some(4), None],
+        ]],
     });
 
     static PLACEMENT_TYPE: Lazy<PlacementType> = Lazy::new(|| PlacementType {
@@ -88,6 +374,8 @@ mod test {
         suppliers: vec![SUPPLIER.clone()],
         statistics: vec!["test statistic".to_string()],
         copies: vec!["test copy".to_string()],
+        statistic_type_ids: vec![StatisticTypeId::new()],
+        copy_ids: vec![CopyId::new()],
     });
 
     static STAT_COLLECTOR: Lazy<StatCollector> = Lazy::new(|| StatCollector {
@@ -96,8 +384,12 @@ mod test {
         client: "test client".to_string(),
         periods: vec![PERIOD.clone()],
         placement_types: vec![PLACEMENT_TYPE.clone()],
-        periodicity: "idk".to_string(),
-        weekday: "saturday".to_string(),
+        periodicity: Periodicity::Weekly,
+        weekday: Some(Weekday::Sat),
+        reminder_policy: ReminderPolicy::default(),
+        derived_statistic_types: vec![],
+        locale: Locale::En,
+        completion: vec![],
     });
 
     #[test]
@@ -112,5 +404,11 @@ mod test {
             received.placement_types.len(),
             STAT_COLLECTOR.placement_types.len()
         );
+        assert_eq!(
+            received.reminder_policy.as_ref(),
+            Some(&STAT_COLLECTOR.reminder_policy)
+        );
+        assert_eq!(received.periodicity, STAT_COLLECTOR.periodicity);
+        assert_eq!(received.weekday, STAT_COLLECTOR.weekday);
     }
 }
@@ -1,45 +1,108 @@
 use std::sync::{Arc, Mutex};
 
+use crate::errors::{negotiate_error_format, AppError};
+use crate::logic::api_key::{require_api_key, ApiScope};
 use crate::logic::email::Mailer;
 use axum::extract::FromRef;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::delete;
+use axum::http::Uri;
+use axum::middleware;
+use axum::routing::{delete, patch, put};
 use axum::{
     routing::{get, post},
     Router,
 };
 use deadpool_diesel::postgres;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use rust_i18n::{i18n, set_locale};
+use rust_i18n::i18n;
 
 use tower_http::normalize_path::NormalizePathLayer;
 
 use crate::logic::time::Clock;
-use utoipa::OpenApi;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::routes::email_template::__path_delete_email_image;
+use crate::routes::email_template::__path_get_email_template;
+use crate::routes::email_template::__path_list_email_images;
+use crate::routes::email_template::__path_list_email_template_versions;
+use crate::routes::email_template::__path_list_email_templates;
+use crate::routes::email_template::__path_preview_email_template;
+use crate::routes::email_template::__path_restore_email_template_version;
+use crate::routes::email_template::__path_save_email_template;
+use crate::routes::email_template::__path_upload_email_image;
+use crate::routes::email_template::{
+    delete_email_image, get_email_template, list_email_images, list_email_template_versions,
+    list_email_templates, preview_email_template, restore_email_template_version,
+    save_email_template, upload_email_image,
+};
 use crate::routes::main_page;
+use crate::routes::statistics_collector::archive::__path_archive_statistics_collector;
+use crate::routes::statistics_collector::archive::__path_unarchive_statistics_collector;
+use crate::routes::statistics_collector::archive::{
+    archive_statistics_collector, unarchive_statistics_collector,
+};
+use crate::routes::statistics_collector::clone::__path_clone_statistics_collector;
+use crate::routes::statistics_collector::clone::clone_statistics_collector;
 use crate::routes::statistics_collector::config::__path_get_collector_config;
 use crate::routes::statistics_collector::config::get_collector_config;
 use crate::routes::statistics_collector::create::__path_create_statistics_collector;
 use crate::routes::statistics_collector::create::create_statistics_collector;
 use crate::routes::statistics_collector::delete::__path_delete_statistics_collector;
-use crate::routes::statistics_collector::delete::delete_statistics_collector;
+use crate::routes::statistics_collector::delete::__path_restore_statistics_collector;
+use crate::routes::statistics_collector::delete::{
+    delete_statistics_collector, restore_statistics_collector,
+};
 use crate::routes::statistics_collector::email::__path_send_reminder_emails;
 use crate::routes::statistics_collector::email::send_reminder_emails;
+use crate::routes::statistics_collector::export::__path_export_csv;
+use crate::routes::statistics_collector::export::__path_export_xlsx;
+use crate::routes::statistics_collector::export::{export_csv, export_xlsx};
+use crate::routes::statistics_collector::history::__path_get_collector_history;
+use crate::routes::statistics_collector::history::get_collector_history;
 use crate::routes::statistics_collector::list::__path_list_statistics_collectors;
 use crate::routes::statistics_collector::list::list_statistics_collectors;
+use crate::routes::statistics_collector::outbox::__path_get_collector_outbox;
+use crate::routes::statistics_collector::outbox::get_collector_outbox;
 use crate::routes::statistics_collector::show::__path_show_statistics_collector;
 use crate::routes::statistics_collector::show::show_statistics_collector;
+use crate::routes::statistics_collector::summary::__path_get_collector_summary;
+use crate::routes::statistics_collector::summary::get_collector_summary;
+use crate::routes::statistics_collector::supplier::__path_add_collector_supplier;
+use crate::routes::statistics_collector::supplier::__path_change_collector_supplier;
+use crate::routes::statistics_collector::supplier::__path_remove_collector_supplier;
+use crate::routes::statistics_collector::supplier::{
+    add_collector_supplier, change_collector_supplier, remove_collector_supplier,
+};
+use crate::routes::statistics_collector::template::__path_delete_collector_template;
+use crate::routes::statistics_collector::template::__path_instantiate_collector_template;
+use crate::routes::statistics_collector::template::__path_list_collector_templates;
+use crate::routes::statistics_collector::template::__path_save_collector_template;
+use crate::routes::statistics_collector::template::{
+    delete_collector_template, instantiate_collector_template, list_collector_templates,
+    save_collector_template,
+};
+use crate::routes::statistics_collector::token::__path_revoke_supplier_token;
+use crate::routes::statistics_collector::token::__path_rotate_supplier_token;
+use crate::routes::statistics_collector::token::{revoke_supplier_token, rotate_supplier_token};
+use crate::routes::statistics_collector::update::__path_update_statistics_collector;
+use crate::routes::statistics_collector::update::update_statistics_collector;
+use crate::routes::supplier::history::__path_show_supplier_history;
+use crate::routes::supplier::history::show_supplier_history;
 use crate::routes::supplier::show::__path_show_input_page;
 use crate::routes::supplier::show::show_input_page;
 use crate::routes::supplier::submit::__path_submit_input;
 use crate::routes::supplier::submit::submit_input;
+use crate::routes::supplier::template::__path_download_template_csv;
+use crate::routes::supplier::template::__path_download_template_xlsx;
+use crate::routes::supplier::template::{download_template_csv, download_template_xlsx};
+use crate::routes::supplier::upload::__path_upload_input;
+use crate::routes::supplier::upload::upload_input;
 
 pub mod db;
 mod email_templates;
-mod errors;
+pub mod errors;
+mod extract;
 pub mod json;
 pub mod logic;
 mod routes;
@@ -64,37 +127,127 @@ pub struct Config {
     paths(
         create_statistics_collector,
         list_statistics_collectors,
+        update_statistics_collector,
         delete_statistics_collector,
+        restore_statistics_collector,
+        archive_statistics_collector,
+        unarchive_statistics_collector,
+        clone_statistics_collector,
+        save_collector_template,
+        list_collector_templates,
+        instantiate_collector_template,
+        delete_collector_template,
+        add_collector_supplier,
+        change_collector_supplier,
+        remove_collector_supplier,
         show_statistics_collector,
         get_collector_config,
+        export_csv,
+        export_xlsx,
+        get_collector_history,
+        get_collector_outbox,
+        get_collector_summary,
         show_input_page,
         submit_input,
+        download_template_csv,
+        download_template_xlsx,
+        upload_input,
+        show_supplier_history,
         send_reminder_emails,
+        rotate_supplier_token,
+        revoke_supplier_token,
+        list_email_templates,
+        get_email_template,
+        save_email_template,
+        list_email_template_versions,
+        restore_email_template_version,
+        preview_email_template,
+        list_email_images,
+        upload_email_image,
+        delete_email_image,
     ),
     components(
         schemas(
+            json::derived::DerivedStatisticType,
+            json::sent::CollectorListItem,
+            json::sent::CollectorPage,
+            json::sent::CollectorTemplate,
+            json::sent::EmailImage,
+            json::sent::EmailPreview,
+            json::sent::EmailTemplate,
+            json::sent::ErrorCode,
+            json::sent::ErrorResponse,
+            json::sent::OutboxMessage,
             json::sent::Period,
+            json::sent::PeriodCompletion,
             json::sent::PlacementType,
+            json::sent::QueuedReminders,
+            json::sent::ReminderDelivery,
             json::sent::StatCollector,
+            json::sent::StatisticRevision,
+            json::sent::Summary,
+            json::sent::SummaryRow,
             json::sent::Supplier,
+            json::sent::SupplierToken,
+            json::sent::ValidationProblem,
+            json::received::Campaign,
+            json::received::CloneRequest,
+            json::received::EmailPreviewRequest,
+            json::received::EmailTemplateContent,
+            json::received::NewSupplier,
+            json::received::NewTemplate,
             json::received::Period,
             json::received::PlacementType,
             json::received::StatCollector,
             json::received::Supplier,
+            json::received::SupplierChanges,
+            json::reminders::ReminderPolicy,
+            json::reminders::ReminderRecipient,
+            json::reminders::ReminderRule,
+            logic::collector_list::CollectorStatus,
+            logic::email::ReminderType,
+            logic::locale::Locale,
+            logic::outbox::OutboxKind,
+            logic::outbox::OutboxStatus,
+            logic::periods::Periodicity,
+            logic::summary::Dimension,
             routes::supplier::submit::FormKey,
             routes::supplier::submit::FormValue,
+            routes::supplier::upload::SpreadsheetUpload,
         )
     ),
+    modifiers(&SecurityAddon),
     tags(
         (name = "Stat collector")
     )
 )]
 struct ApiDoc;
 
+/// Documents the API keys required by the `/statistics_collector` routes
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "API key created with `stat-collector create-api-key`. \
+                        Scopes: read, write, send_email",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
 #[derive(Clone)]
 struct AppState {
     db_pool: postgres::Pool,
-    mailer: Arc<Mutex<dyn Mailer>>,
+    mailer: Arc<dyn Mailer>,
     clock: Arc<Mutex<dyn Clock>>,
 }
 
@@ -104,7 +257,7 @@ impl FromRef<AppState> for deadpool_diesel::postgres::Pool {
     }
 }
 
-impl FromRef<AppState> for Arc<Mutex<dyn Mailer>> {
+impl FromRef<AppState> for Arc<dyn Mailer> {
     fn from_ref(state: &AppState) -> Self {
         state.mailer.clone()
     }
@@ -116,57 +269,164 @@ impl FromRef<AppState> for Arc<Mutex<dyn Clock>> {
     }
 }
 
-async fn handler_404() -> impl IntoResponse {
-    (StatusCode::NOT_FOUND, "Wrong URL")
+async fn handler_404(uri: Uri) -> AppError {
+    AppError::not_found("URL", uri.path())
 }
 
 i18n!("locales", fallback = "pl");
 
+pub async fn run_migrations(db_pool: &postgres::Pool) {
+    let conn = db_pool.get().await.unwrap();
+    conn.interact(|conn| conn.run_pending_migrations(MIGRATIONS).map(|_| ()))
+        .await
+        .unwrap()
+        .unwrap();
+}
+
 pub async fn build_app(
     db_pool: postgres::Pool,
-    mailer: Arc<Mutex<dyn Mailer>>,
+    mailer: Arc<dyn Mailer>,
     clock: Arc<Mutex<dyn Clock>>,
 ) -> Router {
-    set_locale("pl");
-
     // run the migrations on server startup
-    {
-        let conn = db_pool.get().await.unwrap();
-        conn.interact(|conn| conn.run_pending_migrations(MIGRATIONS).map(|_| ()))
-            .await
-            .unwrap()
-            .unwrap();
-    }
+    run_migrations(&db_pool).await;
 
     let docs: Router = SwaggerUi::new("/docs")
         .url("/api.json", ApiDoc::openapi())
         .into();
 
-    // build our application with some routes
-    let collector = Router::new()
-        .route("/statistics_collector", post(create_statistics_collector))
+    // admin routes, each group requires an API key with its scope
+    let read = Router::new()
         .route("/statistics_collector", get(list_statistics_collectors))
         .route("/statistics_collector/:id", get(show_statistics_collector))
+        .route(
+            "/statistics_collector/:id/config",
+            get(get_collector_config),
+        )
+        .route("/statistics_collector/:id/export.csv", get(export_csv))
+        .route("/statistics_collector/:id/export.xlsx", get(export_xlsx))
+        .route(
+            "/statistics_collector/:id/history",
+            get(get_collector_history),
+        )
+        .route(
+            "/statistics_collector/:id/outbox",
+            get(get_collector_outbox),
+        )
+        .route(
+            "/statistics_collector/:id/summary",
+            get(get_collector_summary),
+        )
+        .route("/collector_template", get(list_collector_templates))
+        .route("/email_template", get(list_email_templates))
+        .route("/email_template/:kind/:locale", get(get_email_template))
+        .route(
+            "/email_template/:kind/:locale/versions",
+            get(list_email_template_versions),
+        )
+        .route(
+            "/email_template/:kind/:locale/preview",
+            post(preview_email_template),
+        )
+        .route("/email_image", get(list_email_images))
+        .route_layer(middleware::from_fn_with_state(
+            (db_pool.clone(), ApiScope::Read),
+            require_api_key,
+        ));
+
+    let write = Router::new()
+        .route("/statistics_collector", post(create_statistics_collector))
+        .route(
+            "/statistics_collector/:id",
+            put(update_statistics_collector),
+        )
         .route(
             "/statistics_collector/:id",
             delete(delete_statistics_collector),
         )
         .route(
-            "/statistics_collector/:id/config",
-            get(get_collector_config),
+            "/statistics_collector/:id/restore",
+            post(restore_statistics_collector),
+        )
+        .route(
+            "/statistics_collector/:id/archive",
+            post(archive_statistics_collector),
+        )
+        .route(
+            "/statistics_collector/:id/unarchive",
+            post(unarchive_statistics_collector),
+        )
+        .route(
+            "/statistics_collector/:id/clone",
+            post(clone_statistics_collector),
+        )
+        .route(
+            "/statistics_collector/:id/template",
+            post(save_collector_template),
+        )
+        .route(
+            "/collector_template/:id/instantiate",
+            post(instantiate_collector_template),
         )
+        .route("/collector_template/:id", delete(delete_collector_template))
+        .route(
+            "/statistics_collector/:id/supplier",
+            post(add_collector_supplier),
+        )
+        .route(
+            "/statistics_collector/:id/supplier/:supplier_id",
+            patch(change_collector_supplier).delete(remove_collector_supplier),
+        )
+        .route(
+            "/statistics_collector/:id/supplier/:supplier_id/token",
+            post(rotate_supplier_token),
+        )
+        .route(
+            "/statistics_collector/:id/supplier/:supplier_id/token",
+            delete(revoke_supplier_token),
+        )
+        .route("/email_template/:kind/:locale", put(save_email_template))
+        .route(
+            "/email_template/:kind/:locale/versions/:version/restore",
+            post(restore_email_template_version),
+        )
+        .route(
+            "/email_image/:name",
+            put(upload_email_image).delete(delete_email_image),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            (db_pool.clone(), ApiScope::Write),
+            require_api_key,
+        ));
+
+    let send_email = Router::new()
         .route(
             "/statistics_collector/:id/send_emails/:reminder_type",
             post(send_reminder_emails),
         )
+        .route_layer(middleware::from_fn_with_state(
+            (db_pool.clone(), ApiScope::SendEmail),
+            require_api_key,
+        ));
+
+    // supplier routes are authorized by the token from the link instead
+    let collector = Router::new()
+        .merge(read)
+        .merge(write)
+        .merge(send_email)
         .route("/supplier/:id", get(show_input_page))
         .route("/supplier/:id", post(submit_input))
+        .route("/supplier/:id/template.csv", get(download_template_csv))
+        .route("/supplier/:id/template.xlsx", get(download_template_xlsx))
+        .route("/supplier/:id/upload", post(upload_input))
+        .route("/supplier/:id/history", get(show_supplier_history))
         .with_state(AppState {
             db_pool,
             mailer,
             clock,
         })
-        .fallback(handler_404);
+        .fallback(handler_404)
+        .layer(middleware::from_fn(negotiate_error_format));
 
     let collector = collector.layer(NormalizePathLayer::trim_trailing_slash());
 
@@ -1,4 +1,26 @@
+pub mod api_key;
+pub mod archive;
+pub mod collector_list;
 pub mod email;
+pub mod email_templates;
+pub mod export;
+pub mod formula;
+pub mod history;
+pub mod locale;
+pub mod outbox;
+pub mod periods;
+pub mod plain_text;
+pub mod reminder_log;
+pub mod reminders;
 pub mod render_html;
 pub mod scheduler;
+pub mod spreadsheet;
+pub mod statistics;
+pub mod submissions;
+pub mod summary;
+pub mod suppliers;
+pub mod templates;
 pub mod time;
+pub mod token;
+pub mod transport;
+pub mod validation;
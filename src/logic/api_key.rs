@@ -0,0 +1,120 @@
+use crate::db::{ApiKey, ApiKeyId};
+use crate::errors::AppError;
+use crate::logic::token::{generate_token, hash_token};
+use crate::schema;
+use axum::extract::{Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{DateTime, Local};
+use deadpool_diesel::postgres;
+use derive_more::Display;
+use diesel::prelude::*;
+use std::str::FromStr;
+
+/// What an API key is allowed to do
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+pub enum ApiScope {
+    /// Listing and viewing collectors, exports
+    #[display(fmt = "read")]
+    Read,
+    /// Creating, updating and deleting collectors, managing supplier tokens
+    #[display(fmt = "write")]
+    Write,
+    /// Sending reminder emails manually
+    #[display(fmt = "send_email")]
+    SendEmail,
+}
+
+impl FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "send_email" => Ok(Self::SendEmail),
+            _ => Err(format!(
+                "unknown scope {}, expected one of: read, write, send_email",
+                s
+            )),
+        }
+    }
+}
+
+/// Creates a new API key and returns it. This is the only time it's known in plain text.
+pub fn create_api_key(
+    conn: &mut PgConnection,
+    name: &str,
+    scopes: &[ApiScope],
+    now: DateTime<Local>,
+) -> QueryResult<String> {
+    let key = generate_token();
+
+    diesel::insert_into(schema::api_keys::table)
+        .values(ApiKey {
+            id: ApiKeyId::new(),
+            name: name.to_string(),
+            key_hash: hash_token(&key),
+            scopes: scopes.iter().map(ToString::to_string).collect(),
+            created_at: now,
+            revoked_at: None,
+        })
+        .execute(conn)?;
+
+    Ok(key)
+}
+
+/// Checks that the key exists, isn't revoked and has the scope
+pub fn authenticate(conn: &mut PgConnection, key: &str, scope: ApiScope) -> Result<(), AppError> {
+    let api_key = schema::api_keys::table
+        .filter(schema::api_keys::key_hash.eq(hash_token(key)))
+        .filter(schema::api_keys::revoked_at.is_null())
+        .first::<ApiKey>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::unauthorized("missing or invalid API key"))?;
+
+    if api_key.scopes.contains(&scope.to_string()) {
+        Ok(())
+    } else {
+        Err(AppError::forbidden(format!(
+            "API key {} lacks the {} scope",
+            api_key.name, scope
+        )))
+    }
+}
+
+/// Middleware which requires an `Authorization: Bearer <key>` header with a key having the scope
+pub async fn require_api_key(
+    State((pool, scope)): State<(postgres::Pool, ApiScope)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let key = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let conn = pool.get().await?;
+    conn.interact(move |conn| authenticate(conn, &key, scope))
+        .await??;
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_round_trip() {
+        for scope in [ApiScope::Read, ApiScope::Write, ApiScope::SendEmail] {
+            assert_eq!(scope.to_string().parse::<ApiScope>(), Ok(scope));
+        }
+        assert!("admin".parse::<ApiScope>().is_err());
+    }
+}
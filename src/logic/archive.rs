@@ -0,0 +1,152 @@
+use crate::db::{StatCollectorId, StatisticsCollector};
+use crate::errors::AppError;
+use crate::logic::outbox::cancel_pending;
+use crate::logic::time::Clock;
+use crate::schema;
+use chrono::{DateTime, Duration, Local};
+use deadpool_diesel::postgres;
+use diesel::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// How long deleted collectors can be restored if `DELETED_RETENTION_DAYS` isn't set
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Loads a collector which isn't deleted
+pub fn find_collector(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> Result<StatisticsCollector, AppError> {
+    schema::statistics_collectors::table
+        .find(collector_id)
+        .filter(schema::statistics_collectors::deleted_at.is_null())
+        .first::<StatisticsCollector>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("statistics collector", collector_id))
+}
+
+/// Id of another collector which isn't deleted and has the same name and client
+pub fn find_duplicate(
+    conn: &mut PgConnection,
+    collector_id: Option<StatCollectorId>,
+    name: &str,
+    client: &str,
+) -> QueryResult<Option<StatCollectorId>> {
+    let mut query = schema::statistics_collectors::table
+        .select(schema::statistics_collectors::id)
+        .filter(schema::statistics_collectors::name.eq(name))
+        .filter(schema::statistics_collectors::client.eq(client))
+        .filter(schema::statistics_collectors::deleted_at.is_null())
+        .into_boxed();
+    if let Some(collector_id) = collector_id {
+        query = query.filter(schema::statistics_collectors::id.ne(collector_id));
+    }
+    query.first::<StatCollectorId>(conn).optional()
+}
+
+fn duplicate_conflict(collector: &StatisticsCollector, existing: StatCollectorId) -> AppError {
+    AppError::Conflict {
+        resource: format!(
+            "statistics collector with name {} and client {}",
+            collector.name, collector.client
+        ),
+        id: existing.to_string(),
+    }
+}
+
+/// Hides the collector and cancels its unsent emails, it can be restored until it's purged
+pub fn delete_collector(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    now: DateTime<Local>,
+) -> Result<(), AppError> {
+    find_collector(conn, collector_id)?;
+
+    diesel::update(schema::statistics_collectors::table.find(collector_id))
+        .set(schema::statistics_collectors::deleted_at.eq(now))
+        .execute(conn)?;
+    cancel_pending(conn, collector_id)?;
+
+    Ok(())
+}
+
+/// Undoes `delete_collector`, unless another collector with the same name and client was created meanwhile
+pub fn restore_collector(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> Result<(), AppError> {
+    let collector = schema::statistics_collectors::table
+        .find(collector_id)
+        .first::<StatisticsCollector>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("statistics collector", collector_id))?;
+
+    if collector.deleted_at.is_none() {
+        return Ok(());
+    }
+    if let Some(existing) =
+        find_duplicate(conn, Some(collector_id), &collector.name, &collector.client)?
+    {
+        return Err(duplicate_conflict(&collector, existing));
+    }
+
+    diesel::update(schema::statistics_collectors::table.find(collector_id))
+        .set(schema::statistics_collectors::deleted_at.eq(None::<DateTime<Local>>))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Archives the collector, or brings it back if `archived_at` is empty.
+/// Archiving cancels the unsent emails of the collector.
+pub fn set_archived(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    archived_at: Option<DateTime<Local>>,
+) -> Result<(), AppError> {
+    let collector = find_collector(conn, collector_id)?;
+    if collector.archived_at.is_some() == archived_at.is_some() {
+        return Ok(());
+    }
+
+    diesel::update(schema::statistics_collectors::table.find(collector_id))
+        .set(schema::statistics_collectors::archived_at.eq(archived_at))
+        .execute(conn)?;
+    if archived_at.is_some() {
+        cancel_pending(conn, collector_id)?;
+    }
+
+    Ok(())
+}
+
+/// Fails if reminders can't be sent for the collector
+pub fn ensure_not_archived(collector: &StatisticsCollector) -> Result<(), AppError> {
+    match collector.archived_at {
+        Some(_) => Err(AppError::conflict(
+            "archived statistics collector",
+            collector.id,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Permanently removes the collectors deleted longer than `retention` ago, with all their data
+pub async fn purge_deleted_collectors(
+    db_pool: postgres::Pool,
+    clock: Arc<Mutex<dyn Clock>>,
+    retention: Duration,
+) -> Result<usize, AppError> {
+    let deleted_before = clock.lock().unwrap().now() - retention;
+
+    let conn = db_pool.get().await?;
+    let purged = conn
+        .interact(move |conn| {
+            diesel::delete(
+                schema::statistics_collectors::table
+                    .filter(schema::statistics_collectors::deleted_at.lt(deleted_before)),
+            )
+            .execute(conn)
+        })
+        .await??;
+
+    Ok(purged)
+}
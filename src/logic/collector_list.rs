@@ -0,0 +1,452 @@
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::logic::periods::Periodicity;
+use crate::{db, json, schema};
+use anyhow::anyhow;
+use chrono::{DateTime, Local, NaiveDate, Weekday};
+use diesel::dsl::{count_star, exists, not};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 200;
+
+/// Derived from the dates of the collector's periods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectorStatus {
+    /// No period has started yet, or there are no periods
+    Upcoming,
+    /// Some period has started and some hasn't ended yet
+    Active,
+    /// Every period has ended
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    Name,
+    Client,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortField {
+    /// The newest collectors come first, names are sorted alphabetically
+    fn default_order(self) -> SortOrder {
+        match self {
+            Self::CreatedAt => SortOrder::Desc,
+            Self::Name | Self::Client => SortOrder::Asc,
+        }
+    }
+}
+
+/// Query parameters of `GET /statistics_collector`
+#[derive(Debug, Default, Deserialize)]
+pub struct ListOptions {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    /// Exact name of the client
+    pub client: Option<String>,
+    /// Part of the name, case insensitive
+    pub name: Option<String>,
+    pub status: Option<CollectorStatus>,
+    pub created_after: Option<DateTime<Local>>,
+    pub created_before: Option<DateTime<Local>>,
+    /// List the archived collectors instead of the others
+    #[serde(default)]
+    pub archived: bool,
+    /// List the deleted collectors which can still be restored instead of the others
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub sort: SortField,
+    pub order: Option<SortOrder>,
+}
+
+/// Position after the last collector of a page, for keyset pagination
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    sort: SortField,
+    order: SortOrder,
+    /// Value of the sorted column, RFC 3339 for `created_at`
+    value: String,
+    id: StatCollectorId,
+}
+
+impl Cursor {
+    fn after(collector: &db::StatisticsCollector, sort: SortField, order: SortOrder) -> Self {
+        let value = match sort {
+            SortField::CreatedAt => collector.created_at.to_rfc3339(),
+            SortField::Name => collector.name.clone(),
+            SortField::Client => collector.client.clone(),
+        };
+        Self {
+            sort,
+            order,
+            value,
+            id: collector.id,
+        }
+    }
+
+    fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).expect("cursors are serializable"))
+    }
+
+    fn decode(cursor: &str) -> Result<Self, AppError> {
+        hex::decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| AppError::bad_request("invalid cursor"))
+    }
+}
+
+/// Escapes the wildcards of a `LIKE` pattern
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+type CollectorQuery = schema::statistics_collectors::BoxedQuery<'static, Pg>;
+
+/// Collectors matching the filters of the options, unsorted
+fn filtered(options: &ListOptions, today: NaiveDate) -> CollectorQuery {
+    use schema::periods as p;
+    use schema::statistics_collectors as c;
+
+    let mut query = c::table.into_boxed();
+    query = match options.deleted {
+        true => query.filter(c::deleted_at.is_not_null()),
+        false => query.filter(c::deleted_at.is_null()),
+    };
+    query = match options.archived {
+        true => query.filter(c::archived_at.is_not_null()),
+        false if options.deleted => query,
+        false => query.filter(c::archived_at.is_null()),
+    };
+    if let Some(client) = &options.client {
+        query = query.filter(c::client.eq(client.clone()));
+    }
+    if let Some(name) = &options.name {
+        query = query.filter(c::name.ilike(format!("%{}%", escape_like(name))));
+    }
+    if let Some(created_after) = options.created_after {
+        query = query.filter(c::created_at.ge(created_after));
+    }
+    if let Some(created_before) = options.created_before {
+        query = query.filter(c::created_at.lt(created_before));
+    }
+
+    let started = || {
+        exists(
+            p::table
+                .filter(p::statistics_collector_id.eq(c::id))
+                .filter(p::start.le(today)),
+        )
+    };
+    let running = || {
+        exists(
+            p::table
+                .filter(p::statistics_collector_id.eq(c::id))
+                .filter(p::end.ge(today)),
+        )
+    };
+    match options.status {
+        None => query,
+        Some(CollectorStatus::Upcoming) => query.filter(not(started())),
+        Some(CollectorStatus::Active) => query.filter(started().and(running())),
+        Some(CollectorStatus::Finished) => query.filter(started().and(not(running()))),
+    }
+}
+
+/// Sorts the query by the column and the id, starting after the cursor's position
+macro_rules! keyset {
+    ($query:expr, $column:expr, $order:expr, $after:expr) => {{
+        use schema::statistics_collectors::id;
+        match ($order, $after) {
+            (SortOrder::Asc, Some((value, after))) => $query
+                .filter(
+                    $column
+                        .gt(value.clone())
+                        .or($column.eq(value).and(id.gt(after))),
+                )
+                .order_by(($column.asc(), id.asc())),
+            (SortOrder::Desc, Some((value, after))) => $query
+                .filter(
+                    $column
+                        .lt(value.clone())
+                        .or($column.eq(value).and(id.lt(after))),
+                )
+                .order_by(($column.desc(), id.desc())),
+            (SortOrder::Asc, None) => $query.order_by(($column.asc(), id.asc())),
+            (SortOrder::Desc, None) => $query.order_by(($column.desc(), id.desc())),
+        }
+    }};
+}
+
+fn sorted(
+    query: CollectorQuery,
+    sort: SortField,
+    order: SortOrder,
+    cursor: Option<Cursor>,
+) -> Result<CollectorQuery, AppError> {
+    use schema::statistics_collectors as c;
+
+    let cursor = cursor.map(|cursor| (cursor.value, cursor.id));
+    Ok(match sort {
+        SortField::CreatedAt => {
+            let after = cursor
+                .map(|(value, id)| {
+                    DateTime::parse_from_rfc3339(&value)
+                        .map(|value| (value.with_timezone(&Local), id))
+                        .map_err(|_| AppError::bad_request("invalid cursor"))
+                })
+                .transpose()?;
+            keyset!(query, c::created_at, order, after)
+        }
+        SortField::Name => keyset!(query, c::name, order, cursor),
+        SortField::Client => keyset!(query, c::client, order, cursor),
+    })
+}
+
+/// Periods, suppliers and complete submissions of each collector of a page
+#[derive(Debug, Default)]
+struct Counts {
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    started: bool,
+    running: bool,
+    ended_periods: i64,
+    suppliers: i64,
+    complete: i64,
+}
+
+fn load_counts(
+    conn: &mut PgConnection,
+    ids: &[StatCollectorId],
+    today: NaiveDate,
+) -> QueryResult<BTreeMap<StatCollectorId, Counts>> {
+    use schema::period_submissions as ps;
+    use schema::periods as p;
+    use schema::placement_types as pt;
+    use schema::suppliers as s;
+
+    let mut counts = BTreeMap::<StatCollectorId, Counts>::new();
+
+    let dates = p::table
+        .filter(p::statistics_collector_id.eq_any(ids))
+        .group_by(p::statistics_collector_id)
+        .select((
+            p::statistics_collector_id,
+            diesel::dsl::min(p::start),
+            diesel::dsl::max(p::end),
+        ))
+        .load::<(StatCollectorId, Option<NaiveDate>, Option<NaiveDate>)>(conn)?;
+    for (id, start_date, end_date) in dates {
+        let counts = counts.entry(id).or_default();
+        counts.start_date = start_date;
+        counts.end_date = end_date;
+        counts.started = start_date.is_some_and(|start| start <= today);
+        counts.running = end_date.is_some_and(|end| end >= today);
+    }
+
+    let ended = p::table
+        .filter(p::statistics_collector_id.eq_any(ids))
+        .filter(p::end.lt(today))
+        .group_by(p::statistics_collector_id)
+        .select((p::statistics_collector_id, count_star()))
+        .load::<(StatCollectorId, i64)>(conn)?;
+    for (id, ended_periods) in ended {
+        counts.entry(id).or_default().ended_periods = ended_periods;
+    }
+
+    let suppliers = s::table
+        .inner_join(pt::table)
+        .filter(pt::statistics_collector_id.eq_any(ids))
+        .group_by(pt::statistics_collector_id)
+        .select((pt::statistics_collector_id, count_star()))
+        .load::<(StatCollectorId, i64)>(conn)?;
+    for (id, suppliers) in suppliers {
+        counts.entry(id).or_default().suppliers = suppliers;
+    }
+
+    let complete = ps::table
+        .inner_join(p::table)
+        .filter(p::statistics_collector_id.eq_any(ids))
+        .filter(p::end.lt(today))
+        .filter(ps::complete)
+        .group_by(p::statistics_collector_id)
+        .select((p::statistics_collector_id, count_star()))
+        .load::<(StatCollectorId, i64)>(conn)?;
+    for (id, complete) in complete {
+        counts.entry(id).or_default().complete = complete;
+    }
+
+    Ok(counts)
+}
+
+fn list_item(
+    collector: db::StatisticsCollector,
+    counts: Counts,
+) -> Result<json::sent::CollectorListItem, AppError> {
+    let status = match (counts.started, counts.running) {
+        (false, _) => CollectorStatus::Upcoming,
+        (true, true) => CollectorStatus::Active,
+        (true, false) => CollectorStatus::Finished,
+    };
+    let expected = counts.ended_periods * counts.suppliers;
+    let completion = (counts.ended_periods > 0).then(|| {
+        if expected == 0 {
+            100.0
+        } else {
+            100.0 * counts.complete as f64 / expected as f64
+        }
+    });
+
+    Ok(json::sent::CollectorListItem {
+        id: collector.id,
+        periodicity: Periodicity::from_str(&collector.periodicity)
+            .map_err(|e| AppError::other(anyhow!(e)))?,
+        weekday: collector
+            .weekday
+            .map(|weekday| Weekday::from_str(&weekday))
+            .transpose()
+            .map_err(AppError::other)?,
+        name: collector.name,
+        client: collector.client,
+        created_at: collector.created_at,
+        archived_at: collector.archived_at,
+        deleted_at: collector.deleted_at,
+        start_date: counts.start_date,
+        end_date: counts.end_date,
+        status,
+        suppliers: counts.suppliers,
+        completion,
+    })
+}
+
+/// A page of the collectors matching the options, `today` decides which of them are finished
+pub fn list_collectors(
+    conn: &mut PgConnection,
+    options: &ListOptions,
+    today: NaiveDate,
+) -> Result<json::sent::CollectorPage, AppError> {
+    let limit = options.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(AppError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    let sort = options.sort;
+    let order = options.order.unwrap_or(sort.default_order());
+    let cursor = options.cursor.as_deref().map(Cursor::decode).transpose()?;
+    if let Some(cursor) = &cursor {
+        if cursor.sort != sort || cursor.order != order {
+            return Err(AppError::bad_request(
+                "the cursor belongs to a differently sorted list",
+            ));
+        }
+    }
+
+    let total = filtered(options, today).count().get_result::<i64>(conn)?;
+
+    let mut collectors = sorted(filtered(options, today), sort, order, cursor)?
+        .limit(limit + 1)
+        .load::<db::StatisticsCollector>(conn)?;
+    let next_cursor = if collectors.len() as i64 > limit {
+        collectors.truncate(limit as usize);
+        collectors
+            .last()
+            .map(|last| Cursor::after(last, sort, order).encode())
+    } else {
+        None
+    };
+
+    let ids = collectors.iter().map(|c| c.id).collect::<Vec<_>>();
+    let mut counts = load_counts(conn, &ids, today)?;
+    let items = collectors
+        .into_iter()
+        .map(|collector| {
+            let counts = counts.remove(&collector.id).unwrap_or_default();
+            list_item(collector, counts)
+        })
+        .collect::<Result<_, AppError>>()?;
+
+    Ok(json::sent::CollectorPage {
+        items,
+        next_cursor,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor {
+            sort: SortField::Name,
+            order: SortOrder::Asc,
+            value: "kolektor".to_string(),
+            id: StatCollectorId::new(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode(&hex::encode("{}")).is_err());
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("50%_off\\"), "50\\%\\_off\\\\");
+    }
+
+    #[test]
+    fn completion_counts_ended_periods() {
+        let collector = db::StatisticsCollector {
+            id: StatCollectorId::new(),
+            name: "kolektor".to_string(),
+            client: "pepsi".to_string(),
+            periodicity: "custom".to_string(),
+            weekday: None,
+            account_manager_mail: None,
+            created_at: Local::now(),
+            archived_at: None,
+            deleted_at: None,
+            locale: "pl".to_string(),
+        };
+        let item = list_item(
+            collector.clone(),
+            Counts {
+                started: true,
+                running: true,
+                ended_periods: 2,
+                suppliers: 4,
+                complete: 6,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(item.status, CollectorStatus::Active);
+        assert_eq!(item.completion, Some(75.0));
+
+        let item = list_item(collector, Counts::default()).unwrap();
+        assert_eq!(item.status, CollectorStatus::Upcoming);
+        assert_eq!(item.completion, None);
+    }
+}
@@ -1,167 +1,324 @@
-use crate::db::{StatisticsCollector, SupplierId};
-use crate::email_templates::reminder;
+use crate::db::{Period, StatisticsCollector, Supplier, SupplierId};
 use crate::errors::AppError;
+use crate::logic::email_templates::{EmailImage, RenderedEmail, TemplatedEmail};
+use crate::logic::token::supplier_path;
+use crate::logic::transport::MailTransport;
+use axum::async_trait;
 use derive_more::Display;
-use lettre::message::header::ContentType;
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+    DkimSigningKey,
+};
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
 use lettre::message::{Attachment, Body, Mailbox, MultiPart, SinglePart};
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Address, Message, SmtpTransport, Transport};
-use maud::PreEscaped;
+use lettre::{Address, Message};
 use mockall::*;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use std::time::Duration;
+use std::str::FromStr;
+use std::sync::Arc;
 use tracing::info;
 
-#[derive(Debug, Clone, Copy, Display, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Display, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
 pub enum ReminderType {
     FirstReminder,
     SecondReminder,
 }
 
+impl FromStr for ReminderType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "FirstReminder" => Ok(Self::FirstReminder),
+            "SecondReminder" => Ok(Self::SecondReminder),
+            _ => Err(format!("unknown reminder type {}", s)),
+        }
+    }
+}
+
+/// Sending is asynchronous, so that several emails can be sent at once without blocking the server
 #[automock]
+#[async_trait]
 pub trait Mailer: Send + Sync + 'static {
-    fn send_reminder(
+    /// `token` grants the supplier access to their page, the link in the email contains it.
+    /// `email` is the template in the supplier's language, the mailer fills in the link.
+    async fn send_reminder(
         &self,
         stat_collector: StatisticsCollector,
         to_email: Address,
         supplier_id: SupplierId,
+        token: String,
         reminder_type: ReminderType,
+        email: TemplatedEmail,
+    ) -> Result<(), AppError>;
+
+    /// Tells the account manager that the supplier hasn't submitted the statistics of the period.
+    /// `email` is the template in the collector's language.
+    async fn send_escalation(
+        &self,
+        stat_collector: StatisticsCollector,
+        to_email: Address,
+        supplier: Supplier,
+        period: Period,
+        email: TemplatedEmail,
     ) -> Result<(), AppError>;
 }
 
+/// `List-Unsubscribe`, the address suppliers can ask to stop getting reminders at.
+/// Suppliers owe the statistics, so there is no one-click unsubscribe (RFC 8058):
+/// requests land in the sender's mailbox and account managers answer them by hand.
+#[derive(Debug, Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// `Auto-Submitted: auto-generated`, so that mail servers don't send automatic replies
+#[derive(Debug, Clone)]
+struct AutoSubmitted;
+
+impl Header for AutoSubmitted {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Auto-Submitted")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "auto-generated".to_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppMailer {
-    transport: SmtpTransport,
+    transport: MailTransport,
     from_email: Mailbox,
     base_url: String,
+    dkim: Option<Arc<DkimConfig>>,
 }
 
 impl AppMailer {
-    pub fn new(
-        from_email: Mailbox,
-        smtp_server: &str,
-        port: u16,
-        timeout: Duration,
-        creds: Credentials,
-        base_url: &str,
-    ) -> Self {
-        let transport = SmtpTransport::builder_dangerous(smtp_server)
-            .port(port)
-            .timeout(Some(timeout))
-            .credentials(creds)
-            .build();
-
+    pub fn new(from_email: Mailbox, transport: MailTransport, base_url: &str) -> Self {
         Self {
             from_email,
             transport,
             base_url: base_url.to_string(),
+            dkim: None,
         }
     }
+
+    /// Signs every message with the key, whose public part is published at
+    /// `<selector>._domainkey.<domain>`
+    pub fn with_dkim(
+        self,
+        selector: &str,
+        domain: &str,
+        private_key: &str,
+    ) -> Result<Self, String> {
+        let key = DkimSigningKey::new(private_key, DkimSigningAlgorithm::Rsa)
+            .map_err(|e| format!("invalid DKIM key: {}", e))?;
+        let headers = [
+            "From",
+            "To",
+            "Subject",
+            "Date",
+            "List-Unsubscribe",
+            "Auto-Submitted",
+        ]
+        .into_iter()
+        .map(HeaderName::new_from_ascii_str)
+        .collect();
+        let dkim = DkimConfig::new(
+            selector.to_string(),
+            domain.to_string(),
+            key,
+            headers,
+            DkimCanonicalization {
+                header: DkimCanonicalizationType::Relaxed,
+                body: DkimCanonicalizationType::Relaxed,
+            },
+        );
+        Ok(Self {
+            dkim: Some(Arc::new(dkim)),
+            ..self
+        })
+    }
 }
 
-static BODY_IMG: &[u8] = include_bytes!("../../assets/body.jpg");
-static FOOTER_IMG: &[u8] = include_bytes!("../../assets/footer.jpg");
-static HEADER_IMG: &[u8] = include_bytes!("../../assets/header.jpg");
-static HEADER_EXCLAMATION_IMG: &[u8] = include_bytes!("../../assets/header_exclamation.jpg");
-static DONT_PRINT_IMG: &[u8] = include_bytes!("../../assets/dont_print.jpg");
+/// An image shown in the HTML of an email, referenced by its name as the content id
+fn inline_image(image: &EmailImage) -> Result<SinglePart, AppError> {
+    let content_type = ContentType::parse(&image.content_type).map_err(AppError::other)?;
+    Ok(
+        Attachment::new_inline(image.name.clone())
+            .body(Body::new(image.data.clone()), content_type),
+    )
+}
 
-pub struct EmailAttachment {
-    name: String,
-    data: SinglePart,
+/// The plain text and the HTML with its images, mail clients show one of them
+fn message_body(rendered: RenderedEmail, images: Vec<SinglePart>) -> MultiPart {
+    let text = SinglePart::plain(rendered.text);
+    let html = SinglePart::html(rendered.html);
+    if images.is_empty() {
+        MultiPart::alternative().singlepart(text).singlepart(html)
+    } else {
+        let related = images
+            .into_iter()
+            .fold(MultiPart::related().singlepart(html), |related, image| {
+                related.singlepart(image)
+            });
+        MultiPart::alternative().singlepart(text).multipart(related)
+    }
 }
 
-impl EmailAttachment {
-    fn new(name: &str, data: &[u8], mime_type: ContentType) -> Self {
-        let data =
-            Attachment::new_inline(name.to_string()).body(Body::new(data.to_vec()), mime_type);
+impl AppMailer {
+    /// The message with the images attached inline, signed if DKIM is configured
+    fn message(&self, to_email: Address, email: &TemplatedEmail) -> Result<Message, AppError> {
+        let rendered = email.render(|image| format!("cid:{}", image.name))?;
+        let images = email
+            .images
+            .iter()
+            .map(inline_image)
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Self {
-            name: name.to_string(),
-            data,
+        let mut message = Message::builder()
+            .from(self.from_email.clone())
+            .reply_to(self.from_email.clone())
+            .to(to_email.into())
+            .subject(rendered.subject.clone())
+            .header(ListUnsubscribe(format!(
+                "<mailto:{}?subject=unsubscribe>",
+                self.from_email.email
+            )))
+            .header(AutoSubmitted)
+            .multipart(message_body(rendered, images))?;
+        if let Some(dkim) = &self.dkim {
+            message.sign(dkim);
         }
-    }
 
-    pub fn as_src(&self) -> String {
-        format!("cid:{}", self.name)
+        Ok(message)
     }
 
-    fn into_single_part(self) -> SinglePart {
-        self.data
+    /// The transports block, so they run on a thread of their own
+    async fn send(&self, to_email: Address, email: &TemplatedEmail) -> Result<(), AppError> {
+        let message = self.message(to_email, email)?;
+        let transport = self.transport.clone();
+        tokio::task::spawn_blocking(move || transport.send(&message))
+            .await
+            .map_err(AppError::other)?
     }
 }
 
+#[async_trait]
 impl Mailer for AppMailer {
-    fn send_reminder(
+    async fn send_reminder(
         &self,
-        stat_collector: StatisticsCollector,
+        _stat_collector: StatisticsCollector,
         to_email: Address,
         supplier_id: SupplierId,
+        token: String,
         reminder_type: ReminderType,
+        mut email: TemplatedEmail,
     ) -> Result<(), AppError> {
         info!(
-            "Sending {} about supplier {} to {}",
-            reminder_type, supplier_id, to_email
+            "Sending {} about supplier {} to {} in {}, template version {}",
+            reminder_type, supplier_id, to_email, email.template.locale, email.template.version
         );
-        let to_email = to_email.into();
-        let subject = match reminder_type {
-            ReminderType::FirstReminder => format!(
-                "Prośba o statystyki do kampanii {} dla klienta {}",
-                stat_collector.name, stat_collector.client
-            ),
-            ReminderType::SecondReminder => format!(
-                "Przypomnienie: Prośba o statystyki do kampanii {} dla klienta {}",
-                stat_collector.name, stat_collector.client
-            ),
-        };
 
-        let url = format!("{}/supplier/{}", self.base_url, supplier_id);
-
-        let reminder_text = match reminder_type {
-            ReminderType::FirstReminder => PreEscaped(
-                "wypełnij proszę pilnie statystyki do kampanii.<br>
-                                        Poniżej link do tabelki.<br>
-                                        Dzięki!<br>
-                                        ",
-            ),
-            ReminderType::SecondReminder => PreEscaped(
-                "widzimy, że jeszcze nie wypełniłeś statystyk.<br>
-            Zrób to proszę pilnie, bo potrzebujemy<br>
-            tego na teraz! Poniżej link do tabelki.<br>
-            Dzięki z góry!
-            ",
-            ),
-        };
-        let jpeg: ContentType = "image/jpeg".parse().unwrap();
+        // the supplier page is shown in the language of the email
+        email.values.link = Some(format!(
+            "{}{}&lang={}",
+            self.base_url,
+            supplier_path(supplier_id, &token),
+            email.template.locale
+        ));
 
-        let header_image = match reminder_type {
-            ReminderType::FirstReminder => HEADER_IMG,
-            ReminderType::SecondReminder => HEADER_EXCLAMATION_IMG,
-        };
+        self.send(to_email, &email).await
+    }
 
-        let header = EmailAttachment::new("header", header_image, jpeg.clone());
-        let body = EmailAttachment::new("body", BODY_IMG, jpeg.clone());
-        let footer = EmailAttachment::new("footer", FOOTER_IMG, jpeg.clone());
-        let dont_print = EmailAttachment::new("dont_print", DONT_PRINT_IMG, jpeg.clone());
+    async fn send_escalation(
+        &self,
+        _stat_collector: StatisticsCollector,
+        to_email: Address,
+        supplier: Supplier,
+        period: Period,
+        email: TemplatedEmail,
+    ) -> Result<(), AppError> {
+        info!(
+            "Sending escalation about supplier {} and period {} to {} in {}, template version {}",
+            supplier.id, period.id, to_email, email.template.locale, email.template.version
+        );
 
-        let html = reminder(reminder_text, &header, &body, &footer, &dont_print, &url);
+        self.send(to_email, &email).await
+    }
+}
 
-        let body = MultiPart::related()
-            .singlepart(SinglePart::html(html.into_string()))
-            .singlepart(header.into_single_part())
-            .singlepart(body.into_single_part())
-            .singlepart(footer.into_single_part())
-            .singlepart(dont_print.into_single_part());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::logic::email_templates::Placeholders;
+    use crate::logic::locale::Locale;
+    use crate::logic::outbox::OutboxKind;
+    use crate::logic::transport::TransportConfig;
 
-        let email = Message::builder()
-            .from(self.from_email.clone())
-            .reply_to(self.from_email.clone())
-            .to(to_email)
-            .subject(subject)
-            .multipart(body)?;
+    fn mailer() -> AppMailer {
+        AppMailer::new(
+            "Statystyki <stats@example.com>".parse().unwrap(),
+            TransportConfig::Sendmail(None).build().unwrap(),
+            "https://example.com",
+        )
+    }
 
-        self.transport.send(&email)?;
+    #[test]
+    fn messages_have_a_plain_text_alternative() {
+        let email = TemplatedEmail {
+            template: json::sent::EmailTemplate {
+                kind: OutboxKind::FirstReminder,
+                locale: Locale::Pl,
+                version: 1,
+                subject: "Statystyki {{collector}}".to_string(),
+                body: "<p>Prosimy o statystyki <img src=\"{{image:logo}}\"></p>".to_string(),
+                created_at: None,
+            },
+            values: Placeholders {
+                collector: "Lato".to_string(),
+                ..Default::default()
+            },
+            images: vec![EmailImage {
+                name: "logo".to_string(),
+                content_type: "image/png".to_string(),
+                data: vec![1, 2, 3],
+            }],
+        };
+        let message = mailer()
+            .message("supplier@example.com".parse().unwrap(), &email)
+            .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
 
-        Ok(())
+        assert!(formatted.contains("Subject: Statystyki Lato\r\n"));
+        assert!(formatted
+            .contains("List-Unsubscribe: <mailto:stats@example.com?subject=unsubscribe>\r\n"));
+        assert!(formatted.contains("Auto-Submitted: auto-generated\r\n"));
+        let alternative = formatted.find("multipart/alternative").unwrap();
+        let text = formatted.find("text/plain").unwrap();
+        let related = formatted.find("multipart/related").unwrap();
+        let image = formatted.find("Content-ID: <logo>").unwrap();
+        assert!(alternative < text && text < related && related < image);
+        assert!(formatted.contains("\r\n\r\nProsimy o statystyki\r\n"));
     }
 }
@@ -0,0 +1,690 @@
+use crate::db::{EmailTemplateId, Period, PeriodId, StatisticsCollector, Supplier};
+use crate::email_templates::{built_in_template, BUILT_IN_IMAGES};
+use crate::errors::AppError;
+use crate::json::date_serde;
+use crate::logic::locale::Locale;
+use crate::logic::outbox::OutboxKind;
+use crate::logic::plain_text::html_to_text;
+use crate::logic::token::supplier_path;
+use crate::logic::validation::Problems;
+use crate::{db, json, schema};
+use anyhow::anyhow;
+use base64::prelude::*;
+use chrono::{DateTime, Local, NaiveDate};
+use diesel::prelude::*;
+use maud::html;
+use std::str::FromStr;
+
+/// Content type of the built-in images
+const BUILT_IN_CONTENT_TYPE: &str = "image/jpeg";
+
+/// Values of the placeholders of a template, each `{{name}}` is replaced with the field of the name.
+/// `{{image:name}}` is replaced with the source of the image of the name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Placeholders {
+    pub collector: String,
+    pub client: String,
+    pub supplier: String,
+    pub supplier_mail: String,
+    pub period: String,
+    /// End of the period, when the statistics are due
+    pub deadline: String,
+    /// The supplier page, only reminders have it
+    pub link: Option<String>,
+}
+
+impl Placeholders {
+    /// Values for an email to or about the supplier, `link` is left to the mailer.
+    /// The period is only missing for reminders queued before they were tied to one.
+    pub fn new(
+        collector: &StatisticsCollector,
+        supplier: &Supplier,
+        period: Option<&Period>,
+    ) -> Self {
+        Self {
+            collector: collector.name.clone(),
+            client: collector.client.clone(),
+            supplier: supplier.name.clone(),
+            supplier_mail: supplier.mail.clone(),
+            period: period.map(|period| period.name.clone()).unwrap_or_default(),
+            deadline: period
+                .map(|period| period.end.format(date_serde::FORMAT).to_string())
+                .unwrap_or_default(),
+            link: None,
+        }
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "collector" => Some(self.collector.clone()),
+            "client" => Some(self.client.clone()),
+            "supplier" => Some(self.supplier.clone()),
+            "supplier_mail" => Some(self.supplier_mail.clone()),
+            "period" => Some(self.period.clone()),
+            "deadline" => Some(self.deadline.clone()),
+            "link" => self.link.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces every `{{name}}` in the text, the error is the first name `value` doesn't know
+fn substitute(text: &str, mut value: impl FnMut(&str) -> Option<String>) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + length].trim();
+        result.push_str(&rest[..start]);
+        result.push_str(&value(name).ok_or_else(|| name.to_string())?);
+        rest = &rest[start + length + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailImage {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A template with everything needed to send it, see `Mailer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplatedEmail {
+    pub template: json::sent::EmailTemplate,
+    pub values: Placeholders,
+    /// The images the template shows
+    pub images: Vec<EmailImage>,
+}
+
+/// Subject and body of an email
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    /// Generated from the HTML
+    pub text: String,
+}
+
+impl TemplatedEmail {
+    /// Fills in the placeholders, values are escaped in the body.
+    /// `image_src` gives the URL each image is shown from.
+    pub fn render(
+        &self,
+        image_src: impl Fn(&EmailImage) -> String,
+    ) -> Result<RenderedEmail, AppError> {
+        let unknown = |name: String| {
+            AppError::other(anyhow!(
+                "unknown placeholder {} in the {} template in {}",
+                name,
+                self.template.kind,
+                self.template.locale
+            ))
+        };
+        let subject =
+            substitute(&self.template.subject, |name| self.values.value(name)).map_err(unknown)?;
+        let html = substitute(&self.template.body, |name| {
+            match name.strip_prefix("image:") {
+                Some(image) => self
+                    .images
+                    .iter()
+                    .find(|candidate| candidate.name == image)
+                    .map(&image_src),
+                None => self
+                    .values
+                    .value(name)
+                    .map(|value| html! { (value) }.into_string()),
+            }
+        })
+        .map_err(unknown)?;
+        let text = html_to_text(&html);
+        Ok(RenderedEmail {
+            subject,
+            html,
+            text,
+        })
+    }
+}
+
+fn built_in(kind: OutboxKind, locale: Locale) -> json::sent::EmailTemplate {
+    let (subject, body) = built_in_template(kind, locale.as_str());
+    json::sent::EmailTemplate {
+        kind,
+        locale,
+        version: 0,
+        subject,
+        body,
+        created_at: None,
+    }
+}
+
+fn template_from_db(template: db::EmailTemplate) -> Result<json::sent::EmailTemplate, AppError> {
+    Ok(json::sent::EmailTemplate {
+        kind: OutboxKind::from_str(&template.kind).map_err(|e| AppError::other(anyhow!(e)))?,
+        locale: Locale::from_str(&template.locale).map_err(|e| AppError::other(anyhow!(e)))?,
+        version: template.version,
+        subject: template.subject,
+        body: template.body,
+        created_at: Some(template.created_at),
+    })
+}
+
+/// Every saved version of the template, newest first
+pub fn load_versions(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    locale: Locale,
+) -> Result<Vec<json::sent::EmailTemplate>, AppError> {
+    schema::email_templates::table
+        .filter(schema::email_templates::kind.eq(kind.to_string()))
+        .filter(schema::email_templates::locale.eq(locale.to_string()))
+        .order_by(schema::email_templates::version.desc())
+        .select(db::EmailTemplate::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(template_from_db)
+        .collect()
+}
+
+/// The version which is sent, the built-in template if none was saved
+pub fn current_template(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    locale: Locale,
+) -> Result<json::sent::EmailTemplate, AppError> {
+    let latest = schema::email_templates::table
+        .filter(schema::email_templates::kind.eq(kind.to_string()))
+        .filter(schema::email_templates::locale.eq(locale.to_string()))
+        .order_by(schema::email_templates::version.desc())
+        .select(db::EmailTemplate::as_select())
+        .first(conn)
+        .optional()?;
+    match latest {
+        Some(template) => template_from_db(template),
+        None => Ok(built_in(kind, locale)),
+    }
+}
+
+/// The current templates of every kind and language
+pub fn load_current_templates(
+    conn: &mut PgConnection,
+) -> Result<Vec<json::sent::EmailTemplate>, AppError> {
+    let kinds = [
+        OutboxKind::FirstReminder,
+        OutboxKind::SecondReminder,
+        OutboxKind::Escalation,
+    ];
+    let mut templates = vec![];
+    for kind in kinds {
+        for locale in Locale::ALL {
+            templates.push(current_template(conn, kind, locale)?);
+        }
+    }
+    Ok(templates)
+}
+
+/// A saved version, or the built-in template for version 0
+pub fn load_version(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    locale: Locale,
+    version: i32,
+) -> Result<json::sent::EmailTemplate, AppError> {
+    if version == 0 {
+        return Ok(built_in(kind, locale));
+    }
+    let template = schema::email_templates::table
+        .filter(schema::email_templates::kind.eq(kind.to_string()))
+        .filter(schema::email_templates::locale.eq(locale.to_string()))
+        .filter(schema::email_templates::version.eq(version))
+        .select(db::EmailTemplate::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found(format!("version of the {} template", kind), version))?;
+    template_from_db(template)
+}
+
+/// The uploaded image of the name, or the built-in one
+fn find_image(conn: &mut PgConnection, name: &str) -> Result<Option<EmailImage>, AppError> {
+    let uploaded = schema::email_images::table
+        .find(name)
+        .select(db::EmailImage::as_select())
+        .first(conn)
+        .optional()?;
+    Ok(match uploaded {
+        Some(image) => Some(EmailImage {
+            name: image.name,
+            content_type: image.content_type,
+            data: image.data,
+        }),
+        None => BUILT_IN_IMAGES
+            .iter()
+            .find(|(built_in, _)| *built_in == name)
+            .map(|(name, data)| EmailImage {
+                name: name.to_string(),
+                content_type: BUILT_IN_CONTENT_TYPE.to_string(),
+                data: data.to_vec(),
+            }),
+    })
+}
+
+/// Names of the images the body shows, each once
+fn image_names(body: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let _ = substitute(body, |name| {
+        if let Some(image) = name.strip_prefix("image:") {
+            if !names.iter().any(|known| known == image) {
+                names.push(image.to_string());
+            }
+        }
+        Some(String::new())
+    });
+    names
+}
+
+/// The template with the values and images to send it with
+pub fn templated_email(
+    conn: &mut PgConnection,
+    template: json::sent::EmailTemplate,
+    values: Placeholders,
+) -> Result<TemplatedEmail, AppError> {
+    let images = image_names(&template.body)
+        .into_iter()
+        .map(|name| {
+            find_image(conn, &name)?.ok_or_else(|| {
+                AppError::other(anyhow!(
+                    "the {} template in {} shows the missing image {}",
+                    template.kind,
+                    template.locale,
+                    name
+                ))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(TemplatedEmail {
+        template,
+        values,
+        images,
+    })
+}
+
+/// The first address mail clients would download something from, which spam filters dislike.
+/// Links are fine, images have to be embedded with `{{image:name}}`.
+fn remote_resource(html: &str) -> Option<String> {
+    let html = &html.to_ascii_lowercase();
+    if let Some(tag) = ["<link", "@import"].iter().find(|tag| html.contains(*tag)) {
+        return Some(tag.to_string());
+    }
+    ["src=", "background=", "url("]
+        .iter()
+        .flat_map(|prefix| {
+            html.match_indices(prefix)
+                .map(move |(start, _)| &html[start + prefix.len()..])
+        })
+        .map(|value| value.trim_start_matches([' ', '"', '\'']))
+        .find(|value| {
+            ["http:", "https:", "//"]
+                .iter()
+                .any(|scheme| value.starts_with(scheme))
+        })
+        .and_then(|value| value.split(['"', '\'', ')', ' ', '>']).next())
+        .map(ToString::to_string)
+}
+
+/// Checks that the placeholders are known, the images exist and nothing is downloaded.
+/// `{{link}}` is only known in reminders.
+fn validate_template(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    content: &json::received::EmailTemplateContent,
+) -> Result<(), AppError> {
+    let mut problems = Problems::default();
+    let values = Placeholders {
+        link: kind.reminder_type().map(|_| String::new()),
+        ..Default::default()
+    };
+
+    if content.subject.trim().is_empty() {
+        problems.add("/subject", "subject must not be empty");
+    }
+    if let Err(name) = substitute(&content.subject, |name| values.value(name)) {
+        problems.add("/subject", format!("unknown placeholder {}", name));
+    }
+    let body = substitute(&content.body, |name| match name.strip_prefix("image:") {
+        Some(_) => Some(String::new()),
+        None => values.value(name),
+    });
+    if let Err(name) = body {
+        problems.add("/body", format!("unknown placeholder {}", name));
+    }
+    for name in image_names(&content.body) {
+        if find_image(conn, &name)?.is_none() {
+            problems.add("/body", format!("no image named {}", name));
+        }
+    }
+    if let Some(resource) = remote_resource(&content.body) {
+        problems.add(
+            "/body",
+            format!(
+                "remote resources aren't allowed, embed images with {{{{image:name}}}}: {}",
+                resource
+            ),
+        );
+    }
+
+    problems.into_result()
+}
+
+/// Saves the content as the next version of the template, which is sent from now on
+pub fn save_version(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    locale: Locale,
+    content: &json::received::EmailTemplateContent,
+    now: DateTime<Local>,
+) -> Result<i32, AppError> {
+    validate_template(conn, kind, content)?;
+
+    let version = current_template(conn, kind, locale)?.version + 1;
+    diesel::insert_into(schema::email_templates::table)
+        .values(db::EmailTemplate {
+            id: EmailTemplateId::new(),
+            kind: kind.to_string(),
+            locale: locale.to_string(),
+            version,
+            subject: content.subject.clone(),
+            body: content.body.clone(),
+            created_at: now,
+        })
+        .execute(conn)?;
+
+    Ok(version)
+}
+
+/// The period the request names, or the last one which has ended, or the first one
+fn preview_period(
+    conn: &mut PgConnection,
+    collector: &StatisticsCollector,
+    period_id: Option<PeriodId>,
+    today: NaiveDate,
+) -> Result<Period, AppError> {
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector.id))
+        .order_by(schema::periods::start)
+        .load::<Period>(conn)?;
+    let period = match period_id {
+        Some(period_id) => periods.into_iter().find(|period| period.id == period_id),
+        None => {
+            let first = periods.first().cloned();
+            periods
+                .into_iter()
+                .rev()
+                .find(|period| period.end < today)
+                .or(first)
+        }
+    };
+    period.ok_or_else(|| match period_id {
+        Some(period_id) => AppError::not_found("period", period_id),
+        None => AppError::not_found("period of statistics collector", collector.id),
+    })
+}
+
+/// Renders the template, or the content of the request, for the supplier without sending it.
+/// The link in reminders has no valid token.
+pub fn preview(
+    conn: &mut PgConnection,
+    kind: OutboxKind,
+    locale: Locale,
+    request: &json::received::EmailPreviewRequest,
+    today: NaiveDate,
+) -> Result<json::sent::EmailPreview, AppError> {
+    let (supplier, collector) = schema::suppliers::table
+        .inner_join(schema::placement_types::table.inner_join(schema::statistics_collectors::table))
+        .filter(schema::suppliers::id.eq(request.supplier_id))
+        .filter(schema::statistics_collectors::deleted_at.is_null())
+        .select((Supplier::as_select(), StatisticsCollector::as_select()))
+        .first::<(Supplier, StatisticsCollector)>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("supplier", request.supplier_id))?;
+    let period = preview_period(conn, &collector, request.period_id, today)?;
+
+    let template = match &request.content {
+        Some(content) => {
+            validate_template(conn, kind, content)?;
+            json::sent::EmailTemplate {
+                subject: content.subject.clone(),
+                body: content.body.clone(),
+                created_at: None,
+                ..current_template(conn, kind, locale)?
+            }
+        }
+        None => current_template(conn, kind, locale)?,
+    };
+    let mut values = Placeholders::new(&collector, &supplier, Some(&period));
+    if kind.reminder_type().is_some() {
+        values.link = Some(format!(
+            "{}&lang={}",
+            supplier_path(supplier.id, "preview"),
+            locale
+        ));
+    }
+    let email = templated_email(conn, template, values)?;
+    let rendered = email.render(|image| {
+        format!(
+            "data:{};base64,{}",
+            image.content_type,
+            BASE64_STANDARD.encode(&image.data)
+        )
+    })?;
+
+    Ok(json::sent::EmailPreview {
+        to: match kind {
+            OutboxKind::Escalation => collector.account_manager_mail,
+            _ => Some(supplier.mail),
+        },
+        subject: rendered.subject,
+        html: rendered.html,
+        text: rendered.text,
+    })
+}
+
+/// Uploaded images, then the built-in ones which weren't replaced
+pub fn load_images(conn: &mut PgConnection) -> Result<Vec<json::sent::EmailImage>, AppError> {
+    let uploaded = schema::email_images::table
+        .order_by(schema::email_images::name)
+        .select(db::EmailImage::as_select())
+        .load(conn)?;
+    let built_in = BUILT_IN_IMAGES
+        .iter()
+        .filter(|(name, _)| !uploaded.iter().any(|image| image.name == *name))
+        .map(|(name, data)| json::sent::EmailImage {
+            name: name.to_string(),
+            content_type: BUILT_IN_CONTENT_TYPE.to_string(),
+            size: data.len(),
+            built_in: true,
+        })
+        .collect::<Vec<_>>();
+    Ok(uploaded
+        .into_iter()
+        .map(|image| json::sent::EmailImage {
+            name: image.name,
+            content_type: image.content_type,
+            size: image.data.len(),
+            built_in: false,
+        })
+        .chain(built_in)
+        .collect())
+}
+
+/// Adds or replaces an image
+pub fn save_image(
+    conn: &mut PgConnection,
+    name: &str,
+    content_type: &str,
+    data: Vec<u8>,
+    now: DateTime<Local>,
+) -> Result<(), AppError> {
+    let mut problems = Problems::default();
+    if name.is_empty() || name.contains(['{', '}']) {
+        problems.add("/name", "name must not be empty or contain braces");
+    }
+    if !content_type.starts_with("image/") {
+        problems.add("/contentType", "only images can be uploaded");
+    }
+    problems.into_result()?;
+
+    let image = db::EmailImage {
+        name: name.to_string(),
+        content_type: content_type.to_string(),
+        data,
+        updated_at: now,
+    };
+    diesel::insert_into(schema::email_images::table)
+        .values(&image)
+        .on_conflict(schema::email_images::name)
+        .do_update()
+        .set((
+            schema::email_images::content_type.eq(&image.content_type),
+            schema::email_images::data.eq(&image.data),
+            schema::email_images::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Deletes an uploaded image, a built-in one of the same name is shown again.
+/// Images which a current template would be left without can't be deleted.
+pub fn delete_image(conn: &mut PgConnection, name: &str) -> Result<(), AppError> {
+    let deleted = diesel::delete(schema::email_images::table.find(name)).execute(conn)?;
+    if deleted == 0 {
+        return Err(AppError::not_found("email image", name));
+    }
+
+    if find_image(conn, name)?.is_none() {
+        if let Some(template) = load_current_templates(conn)?
+            .into_iter()
+            .find(|template| image_names(&template.body).iter().any(|used| used == name))
+        {
+            return Err(AppError::bad_request(format!(
+                "the image is shown by the {} template in {}",
+                template.kind, template.locale
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(subject: &str, body: &str) -> TemplatedEmail {
+        TemplatedEmail {
+            template: json::sent::EmailTemplate {
+                kind: OutboxKind::FirstReminder,
+                locale: Locale::Pl,
+                version: 1,
+                subject: subject.to_string(),
+                body: body.to_string(),
+                created_at: None,
+            },
+            values: Placeholders {
+                collector: "Kampania <lato>".to_string(),
+                client: "Klient".to_string(),
+                supplier: "Dostawca".to_string(),
+                supplier_mail: "dostawca@example.com".to_string(),
+                period: "Tydzień 1".to_string(),
+                deadline: "2024.05.19".to_string(),
+                link: Some("https://example.com/supplier/1?token=abc&lang=pl".to_string()),
+            },
+            images: vec![EmailImage {
+                name: "logo".to_string(),
+                content_type: "image/png".to_string(),
+                data: vec![1, 2, 3],
+            }],
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_and_escaped_in_the_body() {
+        let rendered = email(
+            "{{collector}} dla {{ client }} do {{deadline}}",
+            "<img src=\"{{image:logo}}\"><a href=\"{{link}}\">{{collector}}</a> {{ unclosed",
+        )
+        .render(|image| format!("cid:{}", image.name))
+        .unwrap();
+        assert_eq!(rendered.subject, "Kampania <lato> dla Klient do 2024.05.19");
+        assert_eq!(
+            rendered.html,
+            "<img src=\"cid:logo\"><a href=\"https://example.com/supplier/1?token=abc&amp;lang=pl\">\
+            Kampania &lt;lato&gt;</a> {{ unclosed"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_errors() {
+        assert!(email("{{nope}}", "").render(|_| String::new()).is_err());
+        assert!(email("", "{{image:missing}}")
+            .render(|_| String::new())
+            .is_err());
+        assert_eq!(
+            image_names("{{image:a}} {{image:b}} {{ image:a }} {{link}}"),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn remote_resources_are_found() {
+        assert_eq!(
+            remote_resource("<a href=\"https://example.com\"><IMG SRC=\"cid:x\"></a>"),
+            None
+        );
+        assert_eq!(
+            remote_resource("<img src=\"{{image:logo}}\"><img src='https://example.com/a.png'>"),
+            Some("https://example.com/a.png".to_string())
+        );
+        assert_eq!(
+            remote_resource("<td style=\"background: url(//cdn.example.com/b.jpg)\">"),
+            Some("//cdn.example.com/b.jpg".to_string())
+        );
+        assert_eq!(
+            remote_resource("<link rel=\"stylesheet\">"),
+            Some("<link".to_string())
+        );
+    }
+
+    #[test]
+    fn built_in_templates_only_use_known_placeholders_and_images() {
+        for kind in [
+            OutboxKind::FirstReminder,
+            OutboxKind::SecondReminder,
+            OutboxKind::Escalation,
+        ] {
+            for locale in Locale::ALL {
+                let template = built_in(kind, locale);
+                assert_eq!(remote_resource(&template.body), None);
+                let images = image_names(&template.body);
+                assert!(images
+                    .iter()
+                    .all(|name| BUILT_IN_IMAGES.iter().any(|(image, _)| image == name)));
+                let mut email = email("", "");
+                email.template = template;
+                email.images = images
+                    .into_iter()
+                    .map(|name| EmailImage {
+                        name,
+                        content_type: BUILT_IN_CONTENT_TYPE.to_string(),
+                        data: vec![],
+                    })
+                    .collect();
+                assert!(email.render(|image| image.name.clone()).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use crate::errors::AppError;
+use crate::json;
+use crate::json::date_serde::FORMAT as DATE_FORMAT;
+use crate::logic::locale::Locale;
+use rust_i18n::t;
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use std::collections::BTreeSet;
+
+/// A single value of a collector with everything needed to identify it
+pub struct StatisticRow<'a> {
+    pub placement_type: &'a str,
+    pub supplier: &'a json::sent::Supplier,
+    pub copy: &'a str,
+    pub statistic_type: &'a str,
+    pub period: &'a json::sent::Period,
+    /// Empty if the supplier hasn't filled it in
+    pub value: Option<i32>,
+}
+
+/// Flattens the nested `stats` of every supplier,
+/// ordered by placement type, supplier, copy, statistic type and period
+pub fn statistic_rows(
+    collector: &json::sent::StatCollector,
+) -> impl Iterator<Item = StatisticRow<'_>> {
+    collector
+        .placement_types
+        .iter()
+        .flat_map(move |placement_type| {
+            placement_type.suppliers.iter().flat_map(move |supplier| {
+                placement_type
+                    .copies
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(copy_index, copy)| {
+                        placement_type.statistics.iter().enumerate().flat_map(
+                            move |(statistic_index, statistic_type)| {
+                                collector.periods.iter().enumerate().filter_map(
+                                    move |(period_index, period)| {
+                                        let value = *supplier
+                                            .stats
+                                            .get(statistic_index)?
+                                            .get(copy_index)?
+                                            .get(period_index)?;
+                                        Some(StatisticRow {
+                                            placement_type: &placement_type.name,
+                                            supplier,
+                                            copy,
+                                            statistic_type,
+                                            period,
+                                            value,
+                                        })
+                                    },
+                                )
+                            },
+                        )
+                    })
+            })
+        })
+}
+
+pub fn to_csv(collector: &json::sent::StatCollector, locale: Locale) -> Result<Vec<u8>, AppError> {
+    let locale = locale.as_str();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer
+        .write_record(
+            [
+                t!("placement_type", locale = locale),
+                t!("supplier", locale = locale),
+                t!("copy", locale = locale),
+                t!("statistic_type", locale = locale),
+                t!("period", locale = locale),
+                t!("start_date", locale = locale),
+                t!("end_date", locale = locale),
+                t!("value", locale = locale),
+            ]
+            .map(|header| header.into_owned()),
+        )
+        .map_err(AppError::other)?;
+
+    for row in statistic_rows(collector) {
+        writer
+            .write_record([
+                row.placement_type,
+                &row.supplier.name,
+                row.copy,
+                row.statistic_type,
+                &row.period.name,
+                &row.period.start_date.format(DATE_FORMAT).to_string(),
+                &row.period.end_date.format(DATE_FORMAT).to_string(),
+                &row.value.map(|value| value.to_string()).unwrap_or_default(),
+            ])
+            .map_err(AppError::other)?;
+    }
+
+    writer.into_inner().map_err(AppError::other)
+}
+
+/// One sheet per placement type, with a table per supplier laid out like the supplier input page
+pub fn to_xlsx(collector: &json::sent::StatCollector, locale: Locale) -> Result<Vec<u8>, AppError> {
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let mut sheet_names = BTreeSet::new();
+
+    for placement_type in &collector.placement_types {
+        let worksheet = workbook.add_worksheet();
+        worksheet
+            .set_name(unique_sheet_name(&placement_type.name, &mut sheet_names))
+            .map_err(AppError::other)?;
+
+        let mut row = 0;
+        for supplier in &placement_type.suppliers {
+            worksheet
+                .write_string_with_format(row, 0, &supplier.name, &bold)
+                .map_err(AppError::other)?;
+            worksheet
+                .write_string(row, 1, supplier.mail.to_string())
+                .map_err(AppError::other)?;
+
+            row = write_grid(
+                worksheet,
+                row + 1,
+                &collector.periods,
+                &placement_type.copies,
+                &placement_type.statistics,
+                locale,
+                |statistic_index, copy_index, period_index| {
+                    supplier
+                        .stats
+                        .get(statistic_index)?
+                        .get(copy_index)?
+                        .get(period_index)
+                        .copied()
+                        .flatten()
+                },
+            )?;
+            // leave an empty row between suppliers
+            row += 1;
+        }
+    }
+
+    workbook.save_to_buffer().map_err(AppError::other)
+}
+
+/// Writes a grid starting at `first_row` which looks like this:
+/// | (empty)    | copy 1 | copy 1 | copy 2 | copy 2 |
+/// | (empty)    | stat 1 | stat 2 | stat 1 | stat 2 |
+/// | period 1   | value  | value  | value  | value  |
+/// | period 2   | value  | value  | value  | value  |
+/// `value` is given indices of statistic type, copy and period, in this order.
+/// Returns the first row after the grid.
+pub fn write_grid(
+    worksheet: &mut Worksheet,
+    first_row: u32,
+    periods: &[json::sent::Period],
+    copies: &[String],
+    statistic_types: &[String],
+    locale: Locale,
+    value: impl Fn(usize, usize, usize) -> Option<i32>,
+) -> Result<u32, AppError> {
+    let bold = Format::new().set_bold();
+    let width = statistic_types.len() as u16;
+
+    for (copy_index, copy) in copies.iter().enumerate() {
+        let first_col = 1 + copy_index as u16 * width;
+        let header = copy_header(copy, locale);
+        if width > 1 {
+            worksheet
+                .merge_range(
+                    first_row,
+                    first_col,
+                    first_row,
+                    first_col + width - 1,
+                    &header,
+                    &bold,
+                )
+                .map_err(AppError::other)?;
+        } else {
+            worksheet
+                .write_string_with_format(first_row, first_col, &header, &bold)
+                .map_err(AppError::other)?;
+        }
+
+        for (statistic_index, statistic_type) in statistic_types.iter().enumerate() {
+            worksheet
+                .write_string_with_format(
+                    first_row + 1,
+                    first_col + statistic_index as u16,
+                    statistic_type,
+                    &bold,
+                )
+                .map_err(AppError::other)?;
+        }
+    }
+
+    for (period_index, period) in periods.iter().enumerate() {
+        let row = first_row + 2 + period_index as u32;
+        worksheet
+            .write_string_with_format(row, 0, &period.name, &bold)
+            .map_err(AppError::other)?;
+
+        for copy_index in 0..copies.len() {
+            for statistic_index in 0..statistic_types.len() {
+                if let Some(value) = value(statistic_index, copy_index, period_index) {
+                    let col = 1 + (copy_index * statistic_types.len() + statistic_index) as u16;
+                    worksheet
+                        .write_number(row, col, value)
+                        .map_err(AppError::other)?;
+                }
+            }
+        }
+    }
+
+    Ok(first_row + 2 + periods.len() as u32)
+}
+
+/// Header of a copy's columns in the spreadsheets
+pub fn copy_header(copy: &str, locale: Locale) -> String {
+    format!("{}: {}", t!("copy", locale = locale.as_str()), copy)
+}
+
+/// Excel sheet names are limited to 31 characters, can't contain some characters and must be unique
+fn unique_sheet_name(name: &str, taken: &mut BTreeSet<String>) -> String {
+    const MAX_LEN: usize = 31;
+
+    let base = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .take(MAX_LEN)
+        .collect::<String>();
+    let base = if base.trim().is_empty() {
+        "Sheet".to_string()
+    } else {
+        base
+    };
+
+    let mut candidate = base.clone();
+    let mut counter = 2;
+    while taken.contains(&candidate.to_lowercase()) {
+        let suffix = format!(" ({})", counter);
+        candidate = base
+            .chars()
+            .take(MAX_LEN - suffix.chars().count())
+            .chain(suffix.chars())
+            .collect();
+        counter += 1;
+    }
+    taken.insert(candidate.to_lowercase());
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        CopyId, PeriodId, PlacementTypeId, StatCollectorId, StatisticTypeId, SupplierId,
+    };
+    use crate::logic::periods::Periodicity;
+    use chrono::{NaiveDate, Weekday};
+
+    fn collector() -> json::sent::StatCollector {
+        json::sent::StatCollector {
+            id: StatCollectorId::new(),
+            name: "collector".to_string(),
+            client: "client".to_string(),
+            periods: vec![
+                json::sent::Period {
+                    id: PeriodId::new(),
+                    name: "first".to_string(),
+                    start_date: NaiveDate::from_ymd_opt(2021, 4, 1).unwrap(),
+                    end_date: NaiveDate::from_ymd_opt(2021, 4, 7).unwrap(),
+                },
+                json::sent::Period {
+                    id: PeriodId::new(),
+                    name: "second".to_string(),
+                    start_date: NaiveDate::from_ymd_opt(2021, 4, 8).unwrap(),
+                    end_date: NaiveDate::from_ymd_opt(2021, 4, 14).unwrap(),
+                },
+            ],
+            placement_types: vec![json::sent::PlacementType {
+                id: PlacementTypeId::new(),
+                name: "Display".to_string(),
+                suppliers: vec![json::sent::Supplier {
+                    id: SupplierId::new(),
+                    name: "supplier".to_string(),
+                    mail: "supplier@test.com".parse().unwrap(),
+                    locale: None,
+                    stats: vec![
+                        vec![vec![Some(1), Some(2)], vec![Some(3), Some(4)]],
+                        vec![vec![Some(5), Some(6)], vec![Some(7), None]],
+                    ],
+                }],
+                statistics: vec!["Displays".to_string(), "Clicks".to_string()],
+                copies: vec!["A".to_string(), "B".to_string()],
+                statistic_type_ids: vec![StatisticTypeId::new(), StatisticTypeId::new()],
+                copy_ids: vec![CopyId::new(), CopyId::new()],
+            }],
+            periodicity: Periodicity::Weekly,
+            weekday: Some(Weekday::Mon),
+            reminder_policy: Default::default(),
+            derived_statistic_types: vec![],
+            locale: Locale::Pl,
+            completion: vec![],
+        }
+    }
+
+    #[test]
+    fn csv_has_a_row_per_value() {
+        let csv = String::from_utf8(to_csv(&collector(), Locale::Pl).unwrap()).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 1 + 2 * 2 * 2);
+        assert!(lines[0].starts_with("Typ umiejscowienia,Dostawca,Kreacja"));
+        assert_eq!(
+            lines[1],
+            "Display,supplier,A,Displays,first,2021.04.01,2021.04.07,1"
+        );
+        assert_eq!(
+            lines[2],
+            "Display,supplier,A,Displays,second,2021.04.08,2021.04.14,2"
+        );
+        assert_eq!(
+            lines[3],
+            "Display,supplier,A,Clicks,first,2021.04.01,2021.04.07,5"
+        );
+        assert_eq!(
+            lines[8],
+            "Display,supplier,B,Clicks,second,2021.04.08,2021.04.14,"
+        );
+    }
+
+    #[test]
+    fn csv_headers_are_translated() {
+        let csv = String::from_utf8(to_csv(&collector(), Locale::En).unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().next().unwrap(),
+            "Placement type,Supplier,Copy,Statistic,Period,Start date,End date,Value"
+        );
+    }
+
+    #[test]
+    fn xlsx_can_be_written() {
+        let mut collector = collector();
+        let mut duplicate = collector.placement_types[0].clone();
+        duplicate.name = "display".to_string();
+        collector.placement_types.push(duplicate);
+
+        assert!(!to_xlsx(&collector, Locale::Pl).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sheet_names_are_sanitized_and_unique() {
+        let mut taken = BTreeSet::new();
+        assert_eq!(unique_sheet_name("a/b", &mut taken), "a_b");
+        assert_eq!(unique_sheet_name("A/B", &mut taken), "A_B (2)");
+        assert_eq!(unique_sheet_name("", &mut taken), "Sheet");
+        assert_eq!(
+            unique_sheet_name(&"x".repeat(40), &mut taken)
+                .chars()
+                .count(),
+            31
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// Formula of a derived statistic type, like `Clicks / Displays`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Number(f64),
+    /// Total of the statistic type with this name
+    Statistic(String),
+    Negate(Box<Formula>),
+    Binary(Box<Formula>, Operator, Box<Formula>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+fn is_special(c: char) -> bool {
+    matches!(c, '+' | '-' | '*' | '/' | '(' | ')' | '[' | ']')
+}
+
+fn tokenize(formula: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = formula.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                chars.next();
+                tokens.push(Token::Operator(match c {
+                    '+' => Operator::Add,
+                    '-' => Operator::Subtract,
+                    '*' => Operator::Multiply,
+                    _ => Operator::Divide,
+                }));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '[' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("missing ]".to_string()),
+                    }
+                }
+                if name.trim().is_empty() {
+                    return Err("empty []".to_string());
+                }
+                tokens.push(Token::Name(name.trim().to_string()));
+            }
+            ']' => return Err("unexpected ]".to_string()),
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if is_special(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let word = word.trim();
+                if word.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                    let number = f64::from_str(word)
+                        .map_err(|_| format!("{} is not a valid number", word))?;
+                    tokens.push(Token::Number(number));
+                } else {
+                    tokens.push(Token::Name(word.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn expression(&mut self) -> Result<Formula, String> {
+        let mut left = self.term()?;
+        while let Some(Token::Operator(operator @ (Operator::Add | Operator::Subtract))) =
+            self.tokens.peek().cloned()
+        {
+            self.tokens.next();
+            left = Formula::Binary(Box::new(left), operator, Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Formula, String> {
+        let mut left = self.factor()?;
+        while let Some(Token::Operator(operator @ (Operator::Multiply | Operator::Divide))) =
+            self.tokens.peek().cloned()
+        {
+            self.tokens.next();
+            left = Formula::Binary(Box::new(left), operator, Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<Formula, String> {
+        match self.tokens.next() {
+            Some(Token::Number(number)) => Ok(Formula::Number(number)),
+            Some(Token::Name(name)) => Ok(Formula::Statistic(name)),
+            Some(Token::Operator(Operator::Subtract)) => {
+                Ok(Formula::Negate(Box::new(self.factor()?)))
+            }
+            Some(Token::Open) => {
+                let inner = self.expression()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some(_) => Err("expected a number, a statistic type or (".to_string()),
+            None => Err("unexpected end of the formula".to_string()),
+        }
+    }
+}
+
+impl FromStr for Formula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let formula = parser.expression()?;
+        match parser.tokens.next() {
+            None => Ok(formula),
+            Some(Token::Close) => Err("unexpected )".to_string()),
+            Some(_) => Err("expected an operator".to_string()),
+        }
+    }
+}
+
+impl Formula {
+    /// Names of the statistic types the formula uses
+    pub fn statistics(&self) -> BTreeSet<&str> {
+        match self {
+            Self::Number(_) => BTreeSet::new(),
+            Self::Statistic(name) => BTreeSet::from([name.as_str()]),
+            Self::Negate(inner) => inner.statistics(),
+            Self::Binary(left, _, right) => {
+                let mut names = left.statistics();
+                names.extend(right.statistics());
+                names
+            }
+        }
+    }
+
+    /// Empty if a statistic has no value or the formula divides by zero
+    pub fn evaluate(&self, statistic: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        let value = match self {
+            Self::Number(number) => *number,
+            Self::Statistic(name) => statistic(name)?,
+            Self::Negate(inner) => -inner.evaluate(statistic)?,
+            Self::Binary(left, operator, right) => {
+                let left = left.evaluate(statistic)?;
+                let right = right.evaluate(statistic)?;
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right == 0.0 => return None,
+                    Operator::Divide => left / right,
+                }
+            }
+        };
+        Some(value).filter(|value| value.is_finite())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(formula: &str) -> Option<f64> {
+        let statistic = |name: &str| match name {
+            "Clicks" => Some(30.0),
+            "Displays" => Some(1200.0),
+            "Unique users" => Some(100.0),
+            "Click-through" => Some(2.0),
+            "Zero" => Some(0.0),
+            _ => None,
+        };
+        formula.parse::<Formula>().unwrap().evaluate(&statistic)
+    }
+
+    #[test]
+    fn formulas_are_evaluated() {
+        assert_eq!(evaluate("Clicks / Displays"), Some(0.025));
+        assert_eq!(evaluate("100 * Clicks / Displays"), Some(2.5));
+        assert_eq!(evaluate("Clicks + Displays * 2"), Some(2430.0));
+        assert_eq!(evaluate("(Clicks + 10) / -Unique users"), Some(-0.4));
+        assert_eq!(evaluate("[Click-through] - 0.5"), Some(1.5));
+    }
+
+    #[test]
+    fn missing_values_and_division_by_zero_give_nothing() {
+        assert_eq!(evaluate("Clicks / Zero"), None);
+        assert_eq!(evaluate("Clicks + Conversions"), None);
+    }
+
+    #[test]
+    fn invalid_formulas_are_rejected() {
+        for formula in [
+            "",
+            "Clicks /",
+            "(Clicks",
+            "Clicks)",
+            "Clicks Displays (",
+            "[Clicks",
+        ] {
+            assert!(formula.parse::<Formula>().is_err(), "{}", formula);
+        }
+    }
+
+    #[test]
+    fn used_statistics_are_listed() {
+        let formula = "([Unique users] + Clicks) / Clicks"
+            .parse::<Formula>()
+            .unwrap();
+        assert_eq!(
+            formula.statistics(),
+            BTreeSet::from(["Clicks", "Unique users"])
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::db::{
+    PeriodId, StatCollectorId, Statistic, StatisticRevision, StatisticRevisionId, SupplierId,
+    SupplierTokenId,
+};
+use crate::json;
+use crate::{db, schema};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Local};
+use diesel::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Information about the request which changed the values
+#[derive(Debug, Clone, Default)]
+pub struct RequestMetadata {
+    pub user_agent: Option<String>,
+    pub forwarded_for: Option<String>,
+}
+
+impl RequestMetadata {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            user_agent: header("user-agent"),
+            forwarded_for: header("x-forwarded-for"),
+        }
+    }
+}
+
+fn name<Id: Ord>(names: &BTreeMap<Id, String>, id: Id) -> String {
+    names.get(&id).cloned().unwrap_or_default()
+}
+
+/// Records a revision for every statistic whose value is about to change
+/// and returns the periods of those statistics.
+/// Must be called before the new values are saved.
+pub fn record_changes(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    statistics: &[Statistic],
+    supplier_token_id: Option<SupplierTokenId>,
+    metadata: &RequestMetadata,
+    now: DateTime<Local>,
+) -> QueryResult<BTreeSet<PeriodId>> {
+    let current = schema::statistics::table
+        .filter(schema::statistics::supplier_id.eq(supplier_id))
+        .select(Statistic::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|statistic| {
+            (
+                (
+                    statistic.period_id,
+                    statistic.statistic_type_id,
+                    statistic.copy_id,
+                ),
+                statistic.value,
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // names are kept with the revisions, which outlive what they refer to
+    let (supplier, placement_type) = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::suppliers::id.eq(supplier_id))
+        .select((db::Supplier::as_select(), db::PlacementType::as_select()))
+        .first(conn)?;
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(placement_type.statistics_collector_id))
+        .select((schema::periods::id, schema::periods::name))
+        .load::<(db::PeriodId, String)>(conn)?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let statistic_types = schema::statistic_types::table
+        .filter(schema::statistic_types::placement_type_id.eq(placement_type.id))
+        .select((schema::statistic_types::id, schema::statistic_types::name))
+        .load::<(db::StatisticTypeId, String)>(conn)?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let copies = schema::copies::table
+        .filter(schema::copies::placement_type_id.eq(placement_type.id))
+        .select((schema::copies::id, schema::copies::name))
+        .load::<(db::CopyId, String)>(conn)?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+    let revisions = statistics
+        .iter()
+        .filter_map(|statistic| {
+            let old_value = current
+                .get(&(
+                    statistic.period_id,
+                    statistic.statistic_type_id,
+                    statistic.copy_id,
+                ))
+                .copied()
+                .flatten();
+            (old_value != statistic.value).then(|| StatisticRevision {
+                id: StatisticRevisionId::new(),
+                supplier_id: Some(supplier_id),
+                period_id: Some(statistic.period_id),
+                statistic_type_id: Some(statistic.statistic_type_id),
+                copy_id: Some(statistic.copy_id),
+                old_value,
+                new_value: statistic.value,
+                changed_at: now,
+                supplier_token_id,
+                user_agent: metadata.user_agent.clone(),
+                forwarded_for: metadata.forwarded_for.clone(),
+                statistics_collector_id: placement_type.statistics_collector_id,
+                supplier_name: supplier.name.clone(),
+                placement_type_name: placement_type.name.clone(),
+                period_name: name(&periods, statistic.period_id),
+                statistic_type_name: name(&statistic_types, statistic.statistic_type_id),
+                copy_name: name(&copies, statistic.copy_id),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(schema::statistic_revisions::table)
+        .values(&revisions)
+        .execute(conn)?;
+
+    Ok(revisions
+        .iter()
+        .filter_map(|revision| revision.period_id)
+        .collect())
+}
+
+/// Revisions of a collector, optionally only of one supplier, newest first.
+/// Revisions of removed suppliers, periods, statistic types and copies are kept.
+pub fn load_history(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier_id: Option<SupplierId>,
+) -> QueryResult<Vec<json::sent::StatisticRevision>> {
+    let mut query = schema::statistic_revisions::table
+        .filter(schema::statistic_revisions::statistics_collector_id.eq(collector_id))
+        .order_by((
+            schema::statistic_revisions::changed_at.desc(),
+            schema::statistic_revisions::period_name,
+            schema::statistic_revisions::copy_name,
+            schema::statistic_revisions::statistic_type_name,
+        ))
+        .select(StatisticRevision::as_select())
+        .into_boxed();
+
+    if let Some(supplier_id) = supplier_id {
+        query = query.filter(schema::statistic_revisions::supplier_id.eq(supplier_id));
+    }
+
+    let revisions = query
+        .load(conn)?
+        .into_iter()
+        .map(|revision| json::sent::StatisticRevision {
+            id: revision.id,
+            changed_at: revision.changed_at,
+            supplier_id: revision.supplier_id,
+            supplier: revision.supplier_name,
+            placement_type: revision.placement_type_name,
+            period: revision.period_name,
+            copy: revision.copy_name,
+            statistic_type: revision.statistic_type_name,
+            old_value: revision.old_value,
+            new_value: revision.new_value,
+            user_agent: revision.user_agent,
+            forwarded_for: revision.forwarded_for,
+        })
+        .collect();
+
+    Ok(revisions)
+}
@@ -0,0 +1,154 @@
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Query};
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Uri};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Language of the pages and emails, every string has a translation for each of them in `locales/app.yaml`
+#[derive(Debug, Clone, Copy, Default, Display, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    #[display(fmt = "pl")]
+    Pl,
+    #[display(fmt = "en")]
+    En,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Self::Pl, Self::En];
+
+    /// The name `t!` takes
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pl => "pl",
+            Self::En => "en",
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pl" => Ok(Self::Pl),
+            "en" => Ok(Self::En),
+            _ => Err(format!("unknown locale {}", s)),
+        }
+    }
+}
+
+/// The most preferred supported language of the `Accept-Language` header, the first one of equal preference.
+/// Regional variants such as `en-GB` count as their language.
+fn from_accept_language(headers: &HeaderMap) -> Option<Locale> {
+    headers
+        .get_all(ACCEPT_LANGUAGE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let locale = parts.next()?.trim().split('-').next()?.parse().ok()?;
+            let quality = match parts.find_map(|param| param.trim().strip_prefix("q=")) {
+                Some(quality) => quality.trim().parse::<f32>().ok()?,
+                None => 1.0,
+            };
+            Some((locale, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .fold(None, |best, (locale, quality)| match best {
+            Some((_, best_quality)) if best_quality >= quality => best,
+            _ => Some((locale, quality)),
+        })
+        .map(|(locale, _)| locale)
+}
+
+#[derive(Debug, Deserialize)]
+struct LangQuery {
+    lang: Option<String>,
+}
+
+/// The languages a request asks for with the `lang` query parameter and the `Accept-Language` header.
+/// Unsupported languages are ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestedLocale {
+    pub query: Option<Locale>,
+    pub header: Option<Locale>,
+}
+
+impl RequestedLocale {
+    pub fn from_request(uri: &Uri, headers: &HeaderMap) -> Self {
+        let query = Query::<LangQuery>::try_from_uri(uri)
+            .ok()
+            .and_then(|query| query.0.lang)
+            .and_then(|lang| lang.parse().ok());
+        Self {
+            query,
+            header: from_accept_language(headers),
+        }
+    }
+
+    /// `?lang=` overrides the language chosen for the supplier,
+    /// which overrides the browser's language, which overrides the collector's one
+    pub fn resolve(self, configured: Option<Locale>, default: Locale) -> Locale {
+        self.query.or(configured).or(self.header).unwrap_or(default)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestedLocale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_request(&parts.uri, &parts.headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn requested(uri: &'static str, accept_language: &'static str) -> RequestedLocale {
+        let headers =
+            HeaderMap::from_iter([(ACCEPT_LANGUAGE, HeaderValue::from_static(accept_language))]);
+        RequestedLocale::from_request(&Uri::from_static(uri), &headers)
+    }
+
+    #[test]
+    fn browsers_languages_are_matched_by_preference() {
+        assert_eq!(
+            requested("/", "en-GB,en;q=0.9,pl;q=0.8").header,
+            Some(Locale::En)
+        );
+        assert_eq!(
+            requested("/", "de-DE,de;q=0.9,pl;q=0.8,en;q=0.7").header,
+            Some(Locale::Pl)
+        );
+        assert_eq!(requested("/", "en;q=0.5, PL").header, Some(Locale::Pl));
+        assert_eq!(requested("/", "en;q=0, fr").header, None);
+        assert_eq!(requested("/", "").header, None);
+    }
+
+    #[test]
+    fn the_query_wins_over_everything() {
+        let request = requested("/supplier/1?token=abc&lang=en", "pl");
+        assert_eq!(request.query, Some(Locale::En));
+        assert_eq!(request.resolve(Some(Locale::Pl), Locale::Pl), Locale::En);
+
+        let request = requested("/supplier/1?lang=xx", "en");
+        assert_eq!(request.query, None);
+        assert_eq!(request.resolve(Some(Locale::Pl), Locale::Pl), Locale::Pl);
+        assert_eq!(request.resolve(None, Locale::Pl), Locale::En);
+        assert_eq!(
+            RequestedLocale::default().resolve(None, Locale::En),
+            Locale::En
+        );
+    }
+}
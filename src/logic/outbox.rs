@@ -0,0 +1,612 @@
+use crate::db::{
+    OutboxMessage, OutboxMessageId, Period, PeriodId, StatCollectorId, StatisticsCollector,
+    Supplier, SupplierId,
+};
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::email::{Mailer, ReminderType};
+use crate::logic::email_templates::{
+    current_template, templated_email, Placeholders, TemplatedEmail,
+};
+use crate::logic::reminder_log::record_delivery;
+use crate::logic::time::Clock;
+use crate::logic::token::issue_token;
+use crate::schema;
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local};
+use deadpool_diesel::postgres;
+use derive_more::Display;
+use diesel::prelude::*;
+use futures::stream::{self, StreamExt};
+use lettre::Address;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing::log;
+use utoipa::ToSchema;
+
+/// Messages are given up on after this many failed attempts
+pub const MAX_ATTEMPTS: i32 = 6;
+
+/// How many messages a single run of the worker sends at most
+const BATCH_SIZE: i64 = 100;
+
+/// How many emails are sent at once unless configured otherwise
+pub const DEFAULT_SEND_CONCURRENCY: usize = 8;
+
+/// Claimed messages aren't picked up by another worker for this long,
+/// so a crashed worker doesn't lose them for good
+const CLAIM_TIMEOUT_MINUTES: i64 = 10;
+
+#[derive(Debug, Clone, Copy, Display, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    /// Waiting for its first or next attempt
+    #[display(fmt = "pending")]
+    Pending,
+    #[display(fmt = "sent")]
+    Sent,
+    /// All attempts failed
+    #[display(fmt = "failed")]
+    Failed,
+    /// The collector was archived or deleted before the message was sent
+    #[display(fmt = "cancelled")]
+    Cancelled,
+}
+
+impl FromStr for OutboxStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(format!("unknown outbox status {}", s)),
+        }
+    }
+}
+
+/// Which email the message is
+#[derive(Debug, Clone, Copy, Display, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum OutboxKind {
+    FirstReminder,
+    SecondReminder,
+    /// Sent to the account manager, see `Mailer::send_escalation`
+    Escalation,
+}
+
+impl From<ReminderType> for OutboxKind {
+    fn from(reminder_type: ReminderType) -> Self {
+        match reminder_type {
+            ReminderType::FirstReminder => Self::FirstReminder,
+            ReminderType::SecondReminder => Self::SecondReminder,
+        }
+    }
+}
+
+impl OutboxKind {
+    /// Empty for messages which aren't reminders
+    pub fn reminder_type(self) -> Option<ReminderType> {
+        match self {
+            Self::FirstReminder => Some(ReminderType::FirstReminder),
+            Self::SecondReminder => Some(ReminderType::SecondReminder),
+            Self::Escalation => None,
+        }
+    }
+}
+
+impl FromStr for OutboxKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Escalation" => Ok(Self::Escalation),
+            _ => ReminderType::from_str(s).map(Self::from),
+        }
+    }
+}
+
+/// Delay before the next attempt after `attempts` failed ones: 1, 2, 4, 8... minutes
+pub fn backoff(attempts: i32) -> Duration {
+    Duration::minutes(1 << (attempts - 1).clamp(0, 16))
+}
+
+fn new_message(
+    collector_id: StatCollectorId,
+    supplier_id: SupplierId,
+    period_id: Option<PeriodId>,
+    kind: OutboxKind,
+    recipient: &str,
+    forced: bool,
+    now: DateTime<Local>,
+) -> OutboxMessage {
+    OutboxMessage {
+        id: OutboxMessageId::new(),
+        statistics_collector_id: collector_id,
+        supplier_id,
+        period_id,
+        kind: kind.to_string(),
+        recipient: recipient.to_string(),
+        status: OutboxStatus::Pending.to_string(),
+        attempts: 0,
+        next_attempt_at: now,
+        last_error: None,
+        created_at: now,
+        sent_at: None,
+        forced,
+        supplier_token_id: None,
+        token: None,
+    }
+}
+
+/// Queues a reminder to the supplier about the period.
+/// The token for the link is issued by the first attempt to send it.
+/// A reminder of the same type about the same period is only queued again if `force` is set,
+/// otherwise nothing is done and `None` is returned. A unique index makes sure of that
+/// even if the scheduler and a manual request queue the reminder at the same time,
+/// forced reminders are marked so the index leaves them out, as it does failed and cancelled ones.
+pub fn enqueue_reminder(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier: &Supplier,
+    period_id: PeriodId,
+    reminder_type: ReminderType,
+    force: bool,
+    now: DateTime<Local>,
+) -> QueryResult<Option<OutboxMessageId>> {
+    let mut insert = |forced| {
+        diesel::insert_into(schema::outbox::table)
+            .values(new_message(
+                collector_id,
+                supplier.id,
+                Some(period_id),
+                reminder_type.into(),
+                &supplier.mail,
+                forced,
+                now,
+            ))
+            .on_conflict_do_nothing()
+            .returning(schema::outbox::id)
+            .get_result(conn)
+            .optional()
+    };
+
+    // a forced reminder which wasn't queued before still counts as the first one
+    match insert(false)? {
+        None if force => insert(true),
+        queued => Ok(queued),
+    }
+}
+
+/// Queues an escalation about the supplier's missing submission to the account manager.
+/// Like reminders, it's queued at most once per period, otherwise `None` is returned,
+/// so several rules escalating the same submission send one email.
+pub fn enqueue_escalation(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier_id: SupplierId,
+    period_id: PeriodId,
+    account_manager_mail: &str,
+    now: DateTime<Local>,
+) -> QueryResult<Option<OutboxMessageId>> {
+    diesel::insert_into(schema::outbox::table)
+        .values(new_message(
+            collector_id,
+            supplier_id,
+            Some(period_id),
+            OutboxKind::Escalation,
+            account_manager_mail,
+            false,
+            now,
+        ))
+        .on_conflict_do_nothing()
+        .returning(schema::outbox::id)
+        .get_result(conn)
+        .optional()
+}
+
+/// Cancels the messages of the collector which weren't sent yet
+pub fn cancel_pending(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<usize> {
+    diesel::update(
+        schema::outbox::table
+            .filter(schema::outbox::statistics_collector_id.eq(collector_id))
+            .filter(schema::outbox::status.eq(OutboxStatus::Pending.to_string())),
+    )
+    .set((
+        schema::outbox::status.eq(OutboxStatus::Cancelled.to_string()),
+        schema::outbox::token.eq(None::<String>),
+    ))
+    .execute(conn)
+}
+
+/// Takes the due pending messages, postponing them so that nobody else sends them meanwhile
+fn claim_due(conn: &mut PgConnection, now: DateTime<Local>) -> QueryResult<Vec<OutboxMessage>> {
+    conn.transaction(|conn| {
+        let due = schema::outbox::table
+            .filter(
+                schema::outbox::status
+                    .eq(OutboxStatus::Pending.to_string())
+                    .and(schema::outbox::next_attempt_at.le(now)),
+            )
+            .order_by(schema::outbox::next_attempt_at)
+            .limit(BATCH_SIZE)
+            .select(OutboxMessage::as_select())
+            .for_update()
+            .skip_locked()
+            .load(conn)?;
+
+        diesel::update(
+            schema::outbox::table
+                .filter(schema::outbox::id.eq_any(due.iter().map(|message| message.id))),
+        )
+        .set(schema::outbox::next_attempt_at.eq(now + Duration::minutes(CLAIM_TIMEOUT_MINUTES)))
+        .execute(conn)?;
+
+        Ok(due)
+    })
+}
+
+/// A message with everything needed to send it, loaded before sending
+/// so that no database connection is held meanwhile
+enum Delivery {
+    Reminder {
+        collector: StatisticsCollector,
+        to_email: Address,
+        supplier_id: SupplierId,
+        token: String,
+        reminder_type: ReminderType,
+        email: TemplatedEmail,
+    },
+    Escalation {
+        collector: StatisticsCollector,
+        to_email: Address,
+        supplier: Supplier,
+        period: Period,
+        email: TemplatedEmail,
+    },
+}
+
+impl Delivery {
+    fn reminder_type(&self) -> Option<ReminderType> {
+        match self {
+            Self::Reminder { reminder_type, .. } => Some(*reminder_type),
+            Self::Escalation { .. } => None,
+        }
+    }
+
+    async fn send(self, mailer: &dyn Mailer) -> Result<(), AppError> {
+        match self {
+            Self::Reminder {
+                collector,
+                to_email,
+                supplier_id,
+                token,
+                reminder_type,
+                email,
+            } => {
+                mailer
+                    .send_reminder(
+                        collector,
+                        to_email,
+                        supplier_id,
+                        token,
+                        reminder_type,
+                        email,
+                    )
+                    .await
+            }
+            Self::Escalation {
+                collector,
+                to_email,
+                supplier,
+                period,
+                email,
+            } => {
+                mailer
+                    .send_escalation(collector, to_email, supplier, period, email)
+                    .await
+            }
+        }
+    }
+}
+
+fn prepare(
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+    now: DateTime<Local>,
+) -> Result<Delivery, AppError> {
+    let kind = OutboxKind::from_str(&message.kind).map_err(|e| AppError::other(anyhow!(e)))?;
+    let to_email = message.recipient.parse().map_err(AppError::other)?;
+
+    let collector = schema::statistics_collectors::table
+        .find(message.statistics_collector_id)
+        .first::<StatisticsCollector>(conn)?;
+    let supplier = schema::suppliers::table
+        .find(message.supplier_id)
+        .first::<Supplier>(conn)?;
+
+    let period = match message.period_id {
+        Some(period_id) => Some(
+            schema::periods::table
+                .find(period_id)
+                .first::<Period>(conn)?,
+        ),
+        None => None,
+    };
+    // reminders are in the supplier's language, escalations go to the account manager
+    // in the collector's one
+    let locale = match kind {
+        OutboxKind::Escalation => collector.locale(),
+        _ => supplier.locale().unwrap_or(collector.locale()),
+    };
+    let template = current_template(conn, kind, locale)?;
+    let values = Placeholders::new(&collector, &supplier, period.as_ref());
+    let email = templated_email(conn, template, values)?;
+
+    match kind.reminder_type() {
+        Some(reminder_type) => {
+            let token = reminder_token(conn, message, now)?;
+            Ok(Delivery::Reminder {
+                collector,
+                to_email,
+                supplier_id: supplier.id,
+                token,
+                reminder_type,
+                email,
+            })
+        }
+        None => {
+            let period =
+                period.ok_or_else(|| AppError::other(anyhow!("escalation without a period")))?;
+            Ok(Delivery::Escalation {
+                collector,
+                to_email,
+                supplier,
+                period,
+                email,
+            })
+        }
+    }
+}
+
+/// The token for the link in a reminder. It's issued by the first attempt and kept
+/// on the message, so retries send the same link, unless it was revoked or has expired meanwhile.
+fn reminder_token(
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+    now: DateTime<Local>,
+) -> QueryResult<String> {
+    if let (Some(token_id), Some(token)) = (message.supplier_token_id, &message.token) {
+        let valid = schema::supplier_tokens::table
+            .find(token_id)
+            .filter(schema::supplier_tokens::revoked_at.is_null())
+            .filter(schema::supplier_tokens::expires_at.gt(now))
+            .count()
+            .get_result::<i64>(conn)?;
+        if valid > 0 {
+            return Ok(token.clone());
+        }
+    }
+
+    conn.transaction(|conn| {
+        let issued = issue_token(conn, message.supplier_id, now)?;
+        diesel::update(schema::outbox::table.find(message.id))
+            .set((
+                schema::outbox::supplier_token_id.eq(issued.id),
+                schema::outbox::token.eq(&issued.token),
+            ))
+            .execute(conn)?;
+        Ok(issued.token)
+    })
+}
+
+/// Records the outcome of an attempt to send the message, `now` is when the attempt ended.
+/// The token of a reminder isn't needed anymore once it's sent or has failed.
+fn finish_attempt(
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+    result: Result<(), AppError>,
+    now: DateTime<Local>,
+) -> QueryResult<()> {
+    let attempts = message.attempts + 1;
+    let target = schema::outbox::table.find(message.id);
+
+    match result {
+        Ok(()) => {
+            diesel::update(target)
+                .set((
+                    schema::outbox::status.eq(OutboxStatus::Sent.to_string()),
+                    schema::outbox::attempts.eq(attempts),
+                    schema::outbox::sent_at.eq(now),
+                    schema::outbox::last_error.eq(None::<String>),
+                    schema::outbox::token.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
+        Err(e) => {
+            log::warn!(
+                "Attempt {} to send outbox message {} failed: {}",
+                attempts,
+                message.id,
+                e
+            );
+            let status = if attempts >= MAX_ATTEMPTS {
+                OutboxStatus::Failed
+            } else {
+                OutboxStatus::Pending
+            };
+            diesel::update(target)
+                .set((
+                    schema::outbox::status.eq(status.to_string()),
+                    schema::outbox::attempts.eq(attempts),
+                    schema::outbox::next_attempt_at.eq(now + backoff(attempts)),
+                    schema::outbox::last_error.eq(e.to_string()),
+                ))
+                .execute(conn)?;
+            // retries keep the token
+            if status == OutboxStatus::Failed {
+                diesel::update(target)
+                    .set(schema::outbox::token.eq(None::<String>))
+                    .execute(conn)?;
+            }
+        }
+    };
+
+    Ok(())
+}
+
+/// Sends the due messages of the outbox, at most `concurrency` at once,
+/// returns how many were sent successfully.
+/// The database is only used before and after sending, so slow mail servers don't hold connections.
+pub async fn drain_outbox(
+    db_pool: postgres::Pool,
+    clock: Arc<Mutex<dyn Clock>>,
+    mailer: Arc<dyn Mailer>,
+    concurrency: usize,
+) -> Result<usize, AppError> {
+    let now = clock.lock().unwrap().now();
+
+    let conn = db_pool.get().await?;
+    let deliveries = conn
+        .interact(move |conn| {
+            let deliveries = claim_due(conn, now)?
+                .into_iter()
+                .map(|message| {
+                    let delivery = prepare(conn, &message, now);
+                    (message, delivery)
+                })
+                .collect::<Vec<_>>();
+            Ok::<_, AppError>(deliveries)
+        })
+        .await??;
+    drop(conn);
+
+    // attempts are finished at the time the mail server answered, not when they were claimed
+    let results = stream::iter(deliveries)
+        .map(|(message, delivery)| {
+            let mailer = mailer.clone();
+            let clock = clock.clone();
+            async move {
+                match delivery {
+                    Ok(delivery) => {
+                        let reminder_type = delivery.reminder_type();
+                        let result = delivery.send(mailer.as_ref()).await;
+                        let finished_at = clock.lock().unwrap().now();
+                        (message, reminder_type, result, finished_at)
+                    }
+                    Err(e) => (message, None, Err(e), now),
+                }
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let conn = db_pool.get().await?;
+    let sent = conn
+        .interact(move |conn| {
+            let mut sent = 0;
+            for (message, reminder_type, result, finished_at) in results {
+                if result.is_ok() {
+                    sent += 1;
+                }
+                // the email is gone either way, so a failure must not keep the others
+                // from being finished, or they would be sent again
+                let finished = conn.transaction(|conn| {
+                    if let Some(reminder_type) = reminder_type {
+                        let recorded = conn.transaction(|conn| {
+                            record_delivery(conn, &message, reminder_type, &result, finished_at)
+                        });
+                        if let Err(e) = recorded {
+                            log::error!(
+                                "Failed to record the delivery of outbox message {}: {}",
+                                message.id,
+                                e
+                            );
+                        }
+                    }
+                    finish_attempt(conn, &message, result, finished_at)
+                });
+                if let Err(e) = finished {
+                    log::error!("Failed to finish outbox message {}: {}", message.id, e);
+                }
+            }
+            Ok::<_, AppError>(sent)
+        })
+        .await??;
+
+    Ok(sent)
+}
+
+/// Messages of a collector, optionally only with the given status, newest first
+pub fn load_outbox(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    status: Option<OutboxStatus>,
+) -> QueryResult<Vec<json::sent::OutboxMessage>> {
+    let mut query = schema::outbox::table
+        .inner_join(schema::suppliers::table)
+        .left_join(schema::periods::table)
+        .filter(schema::outbox::statistics_collector_id.eq(collector_id))
+        .order_by(schema::outbox::created_at.desc())
+        .select((
+            OutboxMessage::as_select(),
+            schema::suppliers::name,
+            schema::periods::name.nullable(),
+        ))
+        .into_boxed();
+
+    if let Some(status) = status {
+        query = query.filter(schema::outbox::status.eq(status.to_string()));
+    }
+
+    query
+        .load::<(OutboxMessage, String, Option<String>)>(conn)?
+        .into_iter()
+        .map(|(message, supplier, period)| {
+            let parse_error = |e: String| diesel::result::Error::DeserializationError(e.into());
+            Ok(json::sent::OutboxMessage {
+                id: message.id,
+                kind: OutboxKind::from_str(&message.kind).map_err(parse_error)?,
+                status: OutboxStatus::from_str(&message.status).map_err(parse_error)?,
+                supplier_id: message.supplier_id,
+                supplier,
+                period,
+                recipient: message.recipient,
+                attempts: message.attempts,
+                next_attempt_at: message.next_attempt_at,
+                last_error: message.last_error,
+                created_at: message.created_at,
+                sent_at: message.sent_at,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(backoff(1), Duration::minutes(1));
+        assert_eq!(backoff(2), Duration::minutes(2));
+        assert_eq!(backoff(5), Duration::minutes(16));
+    }
+
+    #[test]
+    fn kind_round_trips() {
+        for kind in [
+            OutboxKind::FirstReminder,
+            OutboxKind::SecondReminder,
+            OutboxKind::Escalation,
+        ] {
+            assert_eq!(OutboxKind::from_str(&kind.to_string()), Ok(kind));
+        }
+    }
+}
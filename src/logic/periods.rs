@@ -0,0 +1,469 @@
+use crate::errors::AppError;
+use crate::json;
+use crate::json::weekday_serde;
+use crate::logic::validation::Problems;
+use chrono::{Datelike, Days, Duration, Months, NaiveDate, Weekday};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// How the campaign of a collector is divided into periods
+#[derive(Debug, Clone, Copy, Default, Display, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Periodicity {
+    #[display(fmt = "daily")]
+    Daily,
+    /// Periods of 7 days starting on the weekday of the collector
+    #[display(fmt = "weekly")]
+    Weekly,
+    /// Periods of 14 days starting on the weekday of the collector
+    #[display(fmt = "biweekly")]
+    Biweekly,
+    /// Calendar months
+    #[display(fmt = "monthly")]
+    Monthly,
+    /// Periods are given by hand and only must not overlap
+    #[default]
+    #[display(fmt = "custom")]
+    Custom,
+}
+
+impl FromStr for Periodicity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            "biweekly" => Ok(Self::Biweekly),
+            "monthly" => Ok(Self::Monthly),
+            "custom" => Ok(Self::Custom),
+            _ => Err(format!("unknown periodicity {}", s)),
+        }
+    }
+}
+
+/// Last day of the period starting on `start`, if the periodicity determines it
+fn period_end(periodicity: Periodicity, start: NaiveDate) -> Option<NaiveDate> {
+    match periodicity {
+        Periodicity::Daily => Some(start),
+        Periodicity::Weekly => start.checked_add_days(Days::new(6)),
+        Periodicity::Biweekly => start.checked_add_days(Days::new(13)),
+        Periodicity::Monthly => start
+            .checked_add_months(Months::new(1))?
+            .checked_sub_days(Days::new(1)),
+        Periodicity::Custom => None,
+    }
+}
+
+/// Names like "2023.11.08 - 11.14", or "2023.11.08" for a single day
+pub fn period_name(start: NaiveDate, end: NaiveDate) -> String {
+    if start == end {
+        start.format("%Y.%m.%d").to_string()
+    } else {
+        format!("{} - {}", start.format("%Y.%m.%d"), end.format("%m.%d"))
+    }
+}
+
+fn required_weekday(periodicity: Periodicity, weekday: Option<Weekday>) -> Result<Weekday, String> {
+    weekday.ok_or_else(|| format!("{} periods require a weekday", periodicity))
+}
+
+/// Divides the campaign into periods. The first period is the one containing `start`,
+/// it begins on the weekday for weekly periods and on the first day of the month for monthly ones.
+/// The last period is the one containing `end`.
+pub fn generate_periods(
+    periodicity: Periodicity,
+    weekday: Option<Weekday>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<json::received::Period>, String> {
+    if start > end {
+        return Err("the campaign ends before it starts".to_string());
+    }
+
+    let mut period_start = match periodicity {
+        Periodicity::Daily => start,
+        Periodicity::Weekly | Periodicity::Biweekly => {
+            let weekday = required_weekday(periodicity, weekday)?;
+            let days_since =
+                (7 + start.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+            start - Days::new(days_since as u64)
+        }
+        Periodicity::Monthly => start.with_day(1).unwrap(),
+        Periodicity::Custom => {
+            return Err("periods of a custom periodicity must be given by hand".to_string())
+        }
+    };
+
+    let mut periods = vec![];
+    while period_start <= end {
+        let period_end = period_end(periodicity, period_start)
+            .ok_or_else(|| "the campaign is out of range".to_string())?;
+        periods.push(json::received::Period {
+            id: None,
+            name: period_name(period_start, period_end),
+            start_date: period_start,
+            end_date: period_end,
+        });
+        period_start = period_end + Days::new(1);
+    }
+
+    Ok(periods)
+}
+
+/// Checks that the campaign can be divided into periods
+pub fn validate_campaign(
+    periodicity: Periodicity,
+    campaign: &json::received::Campaign,
+    problems: &mut Problems,
+) {
+    if periodicity == Periodicity::Custom {
+        problems.add(
+            "/campaign",
+            "periods of a custom periodicity must be given by hand",
+        );
+    }
+    if campaign.start_date > campaign.end_date {
+        problems.add("/campaign/endDate", "the campaign ends before it starts");
+    }
+}
+
+/// Checks that periods given by hand don't overlap and follow the periodicity
+pub fn validate_periods(
+    periodicity: Periodicity,
+    weekday: Option<Weekday>,
+    periods: &[json::received::Period],
+    problems: &mut Problems,
+) {
+    for (i, period) in periods.iter().enumerate() {
+        if period.start_date > period.end_date {
+            problems.add(
+                format!("/periods/{}/endDate", i),
+                "the period ends before it starts",
+            );
+            continue;
+        }
+
+        let aligned = match periodicity {
+            Periodicity::Weekly | Periodicity::Biweekly => match weekday {
+                Some(weekday) if period.start_date.weekday() != weekday => {
+                    problems.add(
+                        format!("/periods/{}/startDate", i),
+                        format!(
+                            "the period doesn't start on {}",
+                            weekday_serde::name(weekday)
+                        ),
+                    );
+                    false
+                }
+                _ => true,
+            },
+            Periodicity::Monthly if period.start_date.day() != 1 => {
+                problems.add(
+                    format!("/periods/{}/startDate", i),
+                    "the period doesn't start on the first day of a month",
+                );
+                false
+            }
+            _ => true,
+        };
+
+        if let Some(end) = period_end(periodicity, period.start_date).filter(|_| aligned) {
+            if period.end_date != end {
+                problems.add(
+                    format!("/periods/{}/endDate", i),
+                    format!(
+                        "the period isn't {}, it should end on {}",
+                        periodicity,
+                        end.format("%Y.%m.%d")
+                    ),
+                );
+            }
+        }
+    }
+
+    let mut sorted = periods.iter().enumerate().collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, period)| period.start_date);
+    for pair in sorted.windows(2) {
+        let (_, earlier) = pair[0];
+        let (i, later) = pair[1];
+        if later.start_date <= earlier.end_date {
+            problems.add(
+                format!("/periods/{}", i),
+                format!("the period overlaps period {}", earlier.name),
+            );
+        }
+    }
+}
+
+/// Periods of a validated create or update request, generated from the campaign if it's given
+pub fn resolve_periods(
+    collector: &json::received::StatCollector,
+) -> Result<Vec<json::received::Period>, AppError> {
+    match &collector.campaign {
+        Some(campaign) => generate_periods(
+            collector.periodicity,
+            collector.weekday,
+            campaign.start_date,
+            campaign.end_date,
+        )
+        .map_err(|e| AppError::validation("/campaign", e)),
+        None => Ok(collector.periods.clone()),
+    }
+}
+
+/// Moves the periods by `days`. Periods with generated names are renamed after their new dates.
+pub fn shift_periods(
+    periods: &[json::received::Period],
+    days: i64,
+) -> Result<Vec<json::received::Period>, AppError> {
+    let out_of_range = || {
+        AppError::validation(
+            "/periodOffsetDays",
+            "the periods would be moved out of range",
+        )
+    };
+    let offset = Duration::try_days(days).ok_or_else(out_of_range)?;
+    move_periods(periods, |date| date.checked_add_signed(offset)).ok_or_else(out_of_range)
+}
+
+/// Moves the periods by calendar `months`, so monthly periods stay whole months.
+/// Periods with generated names are renamed after their new dates.
+pub fn shift_periods_by_months(
+    periods: &[json::received::Period],
+    months: i32,
+) -> Result<Vec<json::received::Period>, AppError> {
+    move_periods(periods, |date| shift_months(date, months)).ok_or_else(|| {
+        AppError::validation(
+            "/periodOffsetMonths",
+            "the periods would be moved out of range",
+        )
+    })
+}
+
+/// The last day of a month stays the last day, other days are kept if the month has them.
+/// `None` if the date would be out of range.
+fn shift_months(date: NaiveDate, months: i32) -> Option<NaiveDate> {
+    let offset = Months::new(months.unsigned_abs());
+    let shift = |date: NaiveDate| {
+        if months < 0 {
+            date.checked_sub_months(offset)
+        } else {
+            date.checked_add_months(offset)
+        }
+    };
+
+    match date.checked_add_days(Days::new(1)) {
+        Some(next_day) if next_day.day() == 1 => shift(next_day)?.checked_sub_days(Days::new(1)),
+        _ => shift(date),
+    }
+}
+
+/// `None` if a date would be moved out of range
+fn move_periods(
+    periods: &[json::received::Period],
+    shift: impl Fn(NaiveDate) -> Option<NaiveDate>,
+) -> Option<Vec<json::received::Period>> {
+    periods
+        .iter()
+        .map(|period| {
+            let start_date = shift(period.start_date)?;
+            let end_date = shift(period.end_date)?;
+            let name = if period.name == period_name(period.start_date, period.end_date) {
+                period_name(start_date, end_date)
+            } else {
+                period.name.clone()
+            };
+            Some(json::received::Period {
+                id: None,
+                name,
+                start_date,
+                end_date,
+            })
+        })
+        .collect()
+}
+
+/// The weekday periods start on after they're moved by `days`
+pub fn shift_weekday(weekday: Weekday, days: i64) -> Weekday {
+    (0..days.rem_euclid(7)).fold(weekday, |weekday, _| weekday.succ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, month, day).unwrap()
+    }
+
+    fn problems(
+        periodicity: Periodicity,
+        weekday: Option<Weekday>,
+        periods: &[json::received::Period],
+    ) -> Vec<String> {
+        let mut problems = Problems::default();
+        validate_periods(periodicity, weekday, periods, &mut problems);
+        match problems.into_result() {
+            Ok(()) => vec![],
+            Err(AppError::Validation(problems)) => {
+                problems.into_iter().map(|problem| problem.path).collect()
+            }
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    fn dates(periods: &[json::received::Period]) -> Vec<(NaiveDate, NaiveDate)> {
+        periods
+            .iter()
+            .map(|period| (period.start_date, period.end_date))
+            .collect()
+    }
+
+    #[test]
+    fn weekly_periods_start_on_the_weekday() {
+        let periods = generate_periods(
+            Periodicity::Weekly,
+            Some(Weekday::Wed),
+            date(11, 10),
+            date(11, 22),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dates(&periods),
+            vec![
+                (date(11, 8), date(11, 14)),
+                (date(11, 15), date(11, 21)),
+                (date(11, 22), date(11, 28)),
+            ]
+        );
+        assert_eq!(periods[0].name, "2023.11.08 - 11.14");
+        assert!(problems(Periodicity::Weekly, Some(Weekday::Wed), &periods).is_empty());
+        assert_eq!(
+            problems(Periodicity::Weekly, Some(Weekday::Thu), &periods),
+            vec![
+                "/periods/0/startDate",
+                "/periods/1/startDate",
+                "/periods/2/startDate"
+            ]
+        );
+        assert_eq!(
+            problems(Periodicity::Biweekly, Some(Weekday::Wed), &periods),
+            vec![
+                "/periods/0/endDate",
+                "/periods/1/endDate",
+                "/periods/2/endDate"
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_periods_are_calendar_months() {
+        let periods =
+            generate_periods(Periodicity::Monthly, None, date(1, 15), date(2, 1)).unwrap();
+
+        assert_eq!(
+            dates(&periods),
+            vec![(date(1, 1), date(1, 31)), (date(2, 1), date(2, 28))]
+        );
+        assert!(problems(Periodicity::Monthly, None, &periods).is_empty());
+    }
+
+    #[test]
+    fn daily_periods_are_named_after_the_day() {
+        let periods = generate_periods(Periodicity::Daily, None, date(3, 1), date(3, 3)).unwrap();
+
+        assert_eq!(periods.len(), 3);
+        assert_eq!(periods[2].name, "2023.03.03");
+    }
+
+    #[test]
+    fn invalid_campaigns_are_rejected() {
+        assert!(generate_periods(Periodicity::Weekly, None, date(1, 1), date(2, 1)).is_err());
+        assert!(generate_periods(Periodicity::Custom, None, date(1, 1), date(2, 1)).is_err());
+        assert!(generate_periods(Periodicity::Daily, None, date(2, 1), date(1, 1)).is_err());
+    }
+
+    #[test]
+    fn custom_periods_must_not_overlap() {
+        let period = |name: &str, start, end| json::received::Period {
+            id: None,
+            name: name.to_string(),
+            start_date: start,
+            end_date: end,
+        };
+
+        let periods = vec![
+            period("b", date(1, 10), date(1, 20)),
+            period("a", date(1, 1), date(1, 9)),
+        ];
+        assert!(problems(Periodicity::Custom, None, &periods).is_empty());
+
+        let periods = vec![
+            period("a", date(1, 1), date(1, 10)),
+            period("b", date(1, 10), date(1, 20)),
+        ];
+        assert_eq!(
+            problems(Periodicity::Custom, None, &periods),
+            vec!["/periods/1"]
+        );
+    }
+
+    #[test]
+    fn shifted_periods_are_renamed() {
+        let periods = vec![
+            json::received::Period {
+                id: None,
+                name: "2023.01.04 - 01.10".to_string(),
+                start_date: date(1, 4),
+                end_date: date(1, 10),
+            },
+            json::received::Period {
+                id: None,
+                name: "launch".to_string(),
+                start_date: date(1, 11),
+                end_date: date(1, 11),
+            },
+        ];
+        let shifted = shift_periods(&periods, 364).unwrap();
+        assert_eq!(shifted[0].name, "2024.01.03 - 01.09");
+        assert_eq!(shifted[1].name, "launch");
+        assert_eq!(
+            shifted[1].start_date,
+            NaiveDate::from_ymd_opt(2024, 1, 10).unwrap()
+        );
+
+        assert_eq!(shift_weekday(Weekday::Wed, 364), Weekday::Wed);
+        assert_eq!(shift_weekday(Weekday::Wed, 1), Weekday::Thu);
+        assert_eq!(shift_weekday(Weekday::Mon, -1), Weekday::Sun);
+    }
+
+    #[test]
+    fn periods_are_not_shifted_out_of_range() {
+        let periods = vec![json::received::Period {
+            id: None,
+            name: "2023.01.01 - 01.31".to_string(),
+            start_date: date(1, 1),
+            end_date: date(1, 31),
+        }];
+        for days in [i64::MAX, i64::MIN, 10_000_000_000] {
+            assert!(matches!(
+                shift_periods(&periods, days),
+                Err(AppError::Validation(_))
+            ));
+        }
+        for months in [i32::MAX, i32::MIN] {
+            assert!(matches!(
+                shift_periods_by_months(&periods, months),
+                Err(AppError::Validation(_))
+            ));
+        }
+        assert_eq!(
+            shift_periods_by_months(&periods, -1).unwrap()[0].end_date,
+            NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()
+        );
+    }
+}
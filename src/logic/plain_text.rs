@@ -0,0 +1,206 @@
+/// Elements which start a new line
+const LINES: [&str; 4] = ["br", "div", "li", "tr"];
+
+/// Elements which are separated from the rest by an empty line
+const PARAGRAPHS: [&str; 10] = ["p", "table", "ul", "ol", "h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Elements separated from the previous one by a space
+const CELLS: [&str; 2] = ["td", "th"];
+
+/// Elements whose content isn't text of the email
+const HIDDEN: [&str; 4] = ["head", "style", "script", "title"];
+
+/// Value of an attribute of a tag, without decoding entities
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lowercase[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        let preceded_by_space = lowercase[..start].ends_with(char::is_whitespace);
+        let rest = lowercase[from..].trim_start();
+        if !preceded_by_space || !rest.starts_with('=') {
+            continue;
+        }
+        let value = tag[tag.len() - rest.len() + 1..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split(char::is_whitespace).next().unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 8)
+            .map(|end| &rest[1..end + 1]);
+        let decoded = match entity {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some("nbsp") => Some(' '),
+            Some(entity) => entity
+                .strip_prefix('#')
+                .and_then(|code| match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                })
+                .and_then(char::from_u32),
+            None => None,
+        };
+        match (decoded, entity) {
+            (Some(decoded), Some(entity)) => {
+                result.push(decoded);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Whitespace of the HTML, including line breaks, is only a space in the text
+fn push_text(text: &mut String, html: &str) {
+    text.extend(
+        decode_entities(html)
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c }),
+    );
+}
+
+/// The text of an HTML email, for the plain text part. Block elements start new lines,
+/// links are followed by their address and images are replaced with their alt text.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut hidden: Option<&str> = None;
+    // address of the open link and where its text starts
+    let mut link: Option<(String, usize)> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if hidden.is_none() {
+            push_text(&mut text, &rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if let Some(hidden_name) = hidden {
+            if closing && name == hidden_name {
+                hidden = None;
+            }
+            continue;
+        }
+        if let Some(hidden_name) = HIDDEN.iter().find(|hidden| **hidden == name) {
+            if !closing {
+                hidden = Some(hidden_name);
+            }
+            continue;
+        }
+
+        if PARAGRAPHS.contains(&name.as_str()) {
+            text.push_str("\n\n");
+        } else if LINES.contains(&name.as_str()) && !closing {
+            text.push('\n');
+        } else if CELLS.contains(&name.as_str()) && !closing {
+            text.push(' ');
+        }
+        match (name.as_str(), closing) {
+            ("a", false) => {
+                link = attribute(tag, "href").map(|href| (href, text.len()));
+            }
+            ("a", true) => {
+                if let Some((href, start)) = link.take() {
+                    let shown = text[start..].trim();
+                    if !href.is_empty() && !href.starts_with('#') && shown != href {
+                        if shown.is_empty() {
+                            text.push_str(&href);
+                        } else {
+                            text.push_str(&format!(" ({})", href));
+                        }
+                    }
+                }
+            }
+            ("img", _) => {
+                if let Some(alt) = attribute(tag, "alt").filter(|alt| !alt.trim().is_empty()) {
+                    text.push_str(&alt);
+                }
+            }
+            _ => {}
+        }
+    }
+    if hidden.is_none() {
+        push_text(&mut text, rest);
+    }
+
+    // at most one empty line separates paragraphs
+    let mut result = String::new();
+    let mut after_empty_line = false;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            after_empty_line = true;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if after_empty_line { "\n\n" } else { "\n" });
+        }
+        result.push_str(&line);
+        after_empty_line = false;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emails_are_converted_to_text() {
+        let html = r#"<!DOCTYPE html><head><title>Ignored</title><style>td { color: red; }</style></head>
+            <body><table><tr><td><a href="https://example.com/s?token=a&amp;lang=pl"><img src="cid:header" alt=""></a></td></tr>
+            <tr><td><span>Dzień dobry,</span></td><td><span><br>prosimy   o statystyki &lt;Lato&gt;.</span></td></tr><tr><th>Razem:</th><th>5</th></tr></table>
+            <p><a href='https://example.com'>https://example.com</a> &amp; <img alt="Nie drukuj"></p></body>"#;
+        assert_eq!(
+            html_to_text(html),
+            "https://example.com/s?token=a&lang=pl\n\
+            Dzień dobry,\n\
+            prosimy o statystyki <Lato>.\n\
+            Razem: 5\n\n\
+            https://example.com & Nie drukuj"
+        );
+    }
+
+    #[test]
+    fn attributes_are_found_by_whole_name() {
+        let tag = r#"a data-href="no" HREF = 'yes&amp;1' class=x"#;
+        assert_eq!(attribute(tag, "href"), Some("yes&1".to_string()));
+        assert_eq!(attribute(tag, "class"), Some("x".to_string()));
+        assert_eq!(attribute(tag, "alt"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::db::{OutboxMessage, ReminderDelivery, ReminderDeliveryId, StatCollectorId};
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::email::ReminderType;
+use crate::schema;
+use chrono::{DateTime, Local};
+use diesel::prelude::*;
+use std::str::FromStr;
+
+/// Records the outcome of a `Mailer::send_reminder` call
+pub fn record_delivery(
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+    reminder_type: ReminderType,
+    result: &Result<(), AppError>,
+    now: DateTime<Local>,
+) -> QueryResult<()> {
+    diesel::insert_into(schema::reminder_deliveries::table)
+        .values(ReminderDelivery {
+            id: ReminderDeliveryId::new(),
+            supplier_id: message.supplier_id,
+            period_id: message.period_id,
+            reminder_type: reminder_type.to_string(),
+            attempted_at: now,
+            error: result.as_ref().err().map(ToString::to_string),
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Reminders sent to the suppliers of a collector, newest first
+pub fn load_deliveries(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<Vec<json::sent::ReminderDelivery>> {
+    schema::reminder_deliveries::table
+        .inner_join(schema::suppliers::table.inner_join(schema::placement_types::table))
+        .left_join(schema::periods::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .order_by(schema::reminder_deliveries::attempted_at.desc())
+        .select((
+            ReminderDelivery::as_select(),
+            schema::suppliers::name,
+            schema::periods::name.nullable(),
+        ))
+        .load::<(ReminderDelivery, String, Option<String>)>(conn)?
+        .into_iter()
+        .map(|(delivery, supplier, period)| {
+            Ok(json::sent::ReminderDelivery {
+                id: delivery.id,
+                supplier_id: delivery.supplier_id,
+                supplier,
+                period,
+                reminder_type: ReminderType::from_str(&delivery.reminder_type)
+                    .map_err(|e| diesel::result::Error::DeserializationError(e.into()))?,
+                attempted_at: delivery.attempted_at,
+                error: delivery.error,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,204 @@
+use crate::db::{ReminderRuleId, StatCollectorId};
+use crate::errors::AppError;
+use crate::json::reminders::{ReminderPolicy, ReminderRecipient, ReminderRule};
+use crate::logic::email::ReminderType;
+use crate::logic::validation::Problems;
+use crate::{db, schema};
+use anyhow::anyhow;
+use chrono::{DateTime, Days, Local, NaiveDate, Timelike};
+use diesel::prelude::*;
+
+/// Checks the policy of a create or update request, reporting problems under `/reminderPolicy`
+pub fn validate_policy(policy: &ReminderPolicy, problems: &mut Problems) {
+    for (i, rule) in policy.rules.iter().enumerate() {
+        let path = format!("/reminderPolicy/rules/{}", i);
+        if rule.hour > 23 {
+            problems.add(
+                format!("{}/hour", path),
+                format!("reminder hour must be between 0 and 23, got {}", rule.hour),
+            );
+        }
+        if rule.recipients.is_empty() {
+            problems.add(format!("{}/recipients", path), "reminder has no recipients");
+        }
+        if rule.recipients.contains(&ReminderRecipient::AccountManager)
+            && policy.account_manager.is_none()
+        {
+            problems.add(
+                format!("{}/recipients", path),
+                "reminder is sent to the account manager, but none is set",
+            );
+        }
+    }
+}
+
+/// Replaces the reminder policy of the collector, which must have been validated
+pub fn save_policy(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    policy: &ReminderPolicy,
+) -> Result<(), AppError> {
+    diesel::update(schema::statistics_collectors::table.find(collector_id))
+        .set(
+            schema::statistics_collectors::account_manager_mail
+                .eq(policy.account_manager.as_ref().map(ToString::to_string)),
+        )
+        .execute(conn)?;
+
+    diesel::delete(
+        schema::reminder_rules::table
+            .filter(schema::reminder_rules::statistics_collector_id.eq(collector_id)),
+    )
+    .execute(conn)?;
+
+    let rules = policy
+        .rules
+        .iter()
+        .map(|rule| db::ReminderRule {
+            id: ReminderRuleId::new(),
+            statistics_collector_id: collector_id,
+            days_after_end: rule.days_after_end,
+            hour: rule.hour as i32,
+            reminder_type: rule.reminder_type.to_string(),
+            recipients: rule.recipients.iter().map(ToString::to_string).collect(),
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(schema::reminder_rules::table)
+        .values(&rules)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+pub fn load_policy(
+    conn: &mut PgConnection,
+    collector: &db::StatisticsCollector,
+) -> Result<ReminderPolicy, AppError> {
+    let rules = schema::reminder_rules::table
+        .filter(schema::reminder_rules::statistics_collector_id.eq(collector.id))
+        .order_by((
+            schema::reminder_rules::days_after_end,
+            schema::reminder_rules::hour,
+        ))
+        .select(db::ReminderRule::as_select())
+        .load(conn)?
+        .iter()
+        .map(rule_from_db)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let account_manager = collector
+        .account_manager_mail
+        .as_ref()
+        .map(|mail| mail.parse())
+        .transpose()
+        .map_err(AppError::other)?;
+
+    Ok(ReminderPolicy {
+        account_manager,
+        rules,
+    })
+}
+
+pub fn rule_from_db(rule: &db::ReminderRule) -> Result<ReminderRule, AppError> {
+    let invalid = |error: String| AppError::other(anyhow!("invalid reminder rule: {}", error));
+
+    Ok(ReminderRule {
+        days_after_end: rule.days_after_end,
+        hour: rule.hour as u32,
+        reminder_type: rule
+            .reminder_type
+            .parse::<ReminderType>()
+            .map_err(invalid)?,
+        recipients: rule
+            .recipients
+            .iter()
+            .map(|recipient| recipient.parse::<ReminderRecipient>())
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?,
+    })
+}
+
+/// End date of the periods the rule sends reminders about, if it's due in the current hour
+pub fn due_period_end(rule: &ReminderRule, now: DateTime<Local>) -> Option<NaiveDate> {
+    if rule.hour != now.hour() {
+        return None;
+    }
+    let today = now.date_naive();
+    if rule.days_after_end >= 0 {
+        today.checked_sub_days(Days::new(rule.days_after_end as u64))
+    } else {
+        today.checked_add_days(Days::new(rule.days_after_end.unsigned_abs() as u64))
+    }
+}
+
+/// Whether the rule skips suppliers who have already submitted their statistics
+pub fn requires_missing_submission(rule: &ReminderRule, recipient: ReminderRecipient) -> bool {
+    rule.reminder_type == ReminderType::SecondReminder
+        || recipient == ReminderRecipient::AccountManager
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn rule(days_after_end: i32, hour: u32) -> ReminderRule {
+        ReminderRule {
+            days_after_end,
+            hour,
+            reminder_type: ReminderType::FirstReminder,
+            recipients: vec![ReminderRecipient::Supplier],
+        }
+    }
+
+    #[test]
+    fn rules_are_due_at_their_hour() {
+        let now = Local.with_ymd_and_hms(2024, 3, 10, 8, 0, 0).unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day);
+
+        assert_eq!(due_period_end(&rule(0, 8), now), date(10));
+        assert_eq!(due_period_end(&rule(2, 8), now), date(8));
+        assert_eq!(due_period_end(&rule(-1, 8), now), date(11));
+        assert_eq!(due_period_end(&rule(0, 15), now), None);
+    }
+
+    #[test]
+    fn policies_are_validated() {
+        let is_valid = |policy: &ReminderPolicy| {
+            let mut problems = Problems::default();
+            validate_policy(policy, &mut problems);
+            problems.into_result().is_ok()
+        };
+
+        let mut policy = ReminderPolicy::default();
+        assert!(is_valid(&policy));
+
+        policy.rules[0].hour = 24;
+        assert!(!is_valid(&policy));
+
+        policy.rules[0].hour = 8;
+        policy.rules[0]
+            .recipients
+            .push(ReminderRecipient::AccountManager);
+        assert!(!is_valid(&policy));
+
+        policy.account_manager = Some("manager@example.com".parse().unwrap());
+        assert!(is_valid(&policy));
+    }
+
+    #[test]
+    fn stored_rules_can_be_read() {
+        let stored = db::ReminderRule {
+            id: ReminderRuleId::new(),
+            statistics_collector_id: StatCollectorId::new(),
+            days_after_end: 7,
+            hour: 9,
+            reminder_type: ReminderType::SecondReminder.to_string(),
+            recipients: vec![ReminderRecipient::AccountManager.to_string()],
+        };
+        let rule = rule_from_db(&stored).unwrap();
+        assert_eq!(rule.reminder_type, ReminderType::SecondReminder);
+        assert_eq!(rule.recipients, vec![ReminderRecipient::AccountManager]);
+    }
+}
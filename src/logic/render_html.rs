@@ -1,9 +1,10 @@
 use maud::{html, Markup, DOCTYPE};
 
-pub fn template(title: &str, body: Markup) -> Markup {
+/// `locale` is the name of the page's language, as `t!` takes it
+pub fn template(locale: &str, title: &str, body: Markup) -> Markup {
     html! {
         (DOCTYPE)
-        html lang="en" {
+        html lang=(locale) {
             head {
                 meta charset="UTF-8";
                 meta name="viewport" content="width=device-width, initial-scale=1.0";
@@ -17,3 +18,97 @@ pub fn template(title: &str, body: Markup) -> Markup {
         }
     }
 }
+
+const CHART_WIDTH: f64 = 480.0;
+const CHART_HEIGHT: f64 = 160.0;
+const CHART_PADDING: f64 = 24.0;
+const CHART_COLOR: &str = "#36c";
+
+/// Inline SVG line chart of labelled values, the line has gaps where values are missing
+pub fn line_chart(points: &[(String, Option<i64>)]) -> Markup {
+    let max = points
+        .iter()
+        .filter_map(|(_, value)| *value)
+        .max()
+        .unwrap_or_default()
+        .max(1) as f64;
+    let min = points
+        .iter()
+        .filter_map(|(_, value)| *value)
+        .min()
+        .unwrap_or_default()
+        .min(0) as f64;
+
+    let x = |i: usize| {
+        let width = CHART_WIDTH - 2.0 * CHART_PADDING;
+        match points.len() {
+            0 | 1 => CHART_PADDING + width / 2.0,
+            n => CHART_PADDING + i as f64 * width / (n - 1) as f64,
+        }
+    };
+    let y = |value: i64| {
+        let height = CHART_HEIGHT - 2.0 * CHART_PADDING;
+        CHART_HEIGHT - CHART_PADDING - (value as f64 - min) * height / (max - min)
+    };
+
+    let segments = points
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .split(|(_, (_, value))| value.is_none())
+        .filter(|segment| segment.len() > 1)
+        .map(|segment| {
+            segment
+                .iter()
+                .filter_map(|(i, (_, value))| {
+                    value.map(|value| format!("{:.1},{:.1}", x(*i), y(value)))
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>();
+
+    html! {
+        svg xmlns="http://www.w3.org/2000/svg" width=(CHART_WIDTH) height=(CHART_HEIGHT)
+            viewBox=(format!("0 0 {} {}", CHART_WIDTH, CHART_HEIGHT)) role="img" {
+            line x1=(CHART_PADDING) y1=(CHART_HEIGHT - CHART_PADDING)
+                x2=(CHART_WIDTH - CHART_PADDING) y2=(CHART_HEIGHT - CHART_PADDING) stroke="#999" {}
+            text x="2" y=(CHART_PADDING - 8.0) font-size="10" { (max) }
+            @for segment in &segments {
+                polyline points=(segment) fill="none" stroke=(CHART_COLOR) stroke-width="2" {}
+            }
+            @for (i, (label, value)) in points.iter().enumerate() {
+                @if let Some(value) = value {
+                    circle cx=(format!("{:.1}", x(i))) cy=(format!("{:.1}", y(*value))) r="3" fill=(CHART_COLOR) {
+                        title { (label) ": " (value) }
+                    }
+                }
+            }
+            @if let (Some((first, _)), Some((last, _))) = (points.first(), points.last()) {
+                text x=(CHART_PADDING) y=(CHART_HEIGHT - 8.0) font-size="10" { (first) }
+                @if points.len() > 1 {
+                    text x=(CHART_WIDTH - CHART_PADDING) y=(CHART_HEIGHT - 8.0) font-size="10" text-anchor="end" { (last) }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts_have_gaps_for_missing_values() {
+        let points = [Some(1), Some(2), None, Some(3), Some(4), None, Some(5)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| (format!("period {}", i), value))
+            .collect::<Vec<_>>();
+        let chart = line_chart(&points).into_string();
+
+        assert_eq!(chart.matches("<polyline").count(), 2);
+        assert_eq!(chart.matches("<circle").count(), 5);
+        assert!(chart.contains("<title>period 6: 5</title>"));
+    }
+}
@@ -1,9 +1,14 @@
-use crate::db::{StatisticsCollector, Supplier};
+use crate::db::{Period, StatisticsCollector, Supplier};
 use crate::errors::AppError;
-use crate::logic::email::{Mailer, ReminderType};
+use crate::json::reminders::ReminderRecipient;
+use crate::logic::archive::purge_deleted_collectors;
+use crate::logic::email::Mailer;
+use crate::logic::outbox::{drain_outbox, enqueue_escalation, enqueue_reminder};
+use crate::logic::reminders::{due_period_end, requires_missing_submission, rule_from_db};
+use crate::logic::submissions::is_complete;
 use crate::logic::time::Clock;
-use crate::schema;
-use chrono::Timelike;
+use crate::{db, schema};
+use chrono::{Duration, Timelike};
 use deadpool_diesel::postgres;
 use diesel::prelude::*;
 use diesel::{Connection, ExpressionMethods, QueryDsl};
@@ -11,36 +16,81 @@ use std::sync::{Arc, Mutex};
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 use tracing::log;
 
-async fn first_reminder(
+/// Queues the reminders of all collectors' reminder policies which are due in the current hour
+pub async fn enqueue_due_reminders(
     db_pool: postgres::Pool,
     clock: Arc<Mutex<dyn Clock>>,
-    mailer: Arc<Mutex<dyn Mailer>>,
 ) -> Result<(), AppError> {
-    // find all collectors that have a period which is due today
-    let today = clock.lock().unwrap().now().date_naive();
+    let now = clock.lock().unwrap().now();
 
     let conn = db_pool.get().await?;
     conn.interact(move |conn| {
         conn.transaction(move |conn| {
-            let collectors_suppliers = schema::statistics_collectors::table
-                .inner_join(schema::periods::table)
-                .inner_join(schema::placement_types::table.inner_join(schema::suppliers::table))
-                .filter(schema::periods::end.eq(today))
-                .select((
-                    schema::statistics_collectors::all_columns,
-                    schema::suppliers::all_columns,
-                ))
-                .load::<(StatisticsCollector, Supplier)>(conn)?;
-
-            for (collector, supplier) in collectors_suppliers {
-                let address = supplier.mail.parse().unwrap();
-                let supplier_id = supplier.id;
-                mailer.lock().unwrap().send_reminder(
-                    collector,
-                    address,
-                    supplier_id,
-                    ReminderType::FirstReminder,
-                )?;
+            let rules = schema::reminder_rules::table
+                .filter(schema::reminder_rules::hour.eq(now.hour() as i32))
+                .select(db::ReminderRule::as_select())
+                .load(conn)?;
+
+            for stored_rule in rules {
+                let rule = rule_from_db(&stored_rule)?;
+                let Some(period_end) = due_period_end(&rule, now) else {
+                    continue;
+                };
+
+                let due = schema::statistics_collectors::table
+                    .inner_join(schema::periods::table)
+                    .inner_join(schema::placement_types::table.inner_join(schema::suppliers::table))
+                    .filter(
+                        schema::statistics_collectors::id
+                            .eq(stored_rule.statistics_collector_id)
+                            .and(schema::periods::end.eq(period_end)),
+                    )
+                    .filter(schema::statistics_collectors::archived_at.is_null())
+                    .filter(schema::statistics_collectors::deleted_at.is_null())
+                    .select((
+                        StatisticsCollector::as_select(),
+                        Period::as_select(),
+                        Supplier::as_select(),
+                    ))
+                    .load::<(StatisticsCollector, Period, Supplier)>(conn)?;
+
+                for (collector, period, supplier) in due {
+                    for &recipient in &rule.recipients {
+                        if requires_missing_submission(&rule, recipient)
+                            && is_complete(conn, supplier.id, period.id)?
+                        {
+                            continue;
+                        }
+
+                        match recipient {
+                            ReminderRecipient::Supplier => {
+                                enqueue_reminder(
+                                    conn,
+                                    collector.id,
+                                    &supplier,
+                                    period.id,
+                                    rule.reminder_type,
+                                    false,
+                                    now,
+                                )?;
+                            }
+                            ReminderRecipient::AccountManager => {
+                                // the policy can't be saved without it
+                                let Some(mail) = &collector.account_manager_mail else {
+                                    continue;
+                                };
+                                enqueue_escalation(
+                                    conn,
+                                    collector.id,
+                                    supplier.id,
+                                    period.id,
+                                    mail,
+                                    now,
+                                )?;
+                            }
+                        }
+                    }
+                }
             }
 
             Ok::<_, AppError>(())
@@ -51,114 +101,75 @@ async fn first_reminder(
     Ok(())
 }
 
-async fn second_reminder(
-    db_pool: postgres::Pool,
-    clock: Arc<Mutex<dyn Clock>>,
-    mailer: Arc<Mutex<dyn Mailer>>,
-) -> Result<(), AppError> {
-    // find all collectors that have a period which is due today
-    // and the last filled date is earlier than today
-    let now = clock.lock().unwrap().now();
-    let today = now.date_naive();
-    let today_9 = now
-        .with_hour(9)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap();
+/// Reminder policies are evaluated at the start of every hour
+const REMINDER_SCHEDULE: &str = "0 0 * * * *";
 
-    let conn = db_pool.get().await?;
-    conn.interact(move |conn| {
-        conn.transaction(move |conn| {
-            let collectors_suppliers = schema::statistics_collectors::table
-                .inner_join(schema::periods::table)
-                .inner_join(schema::placement_types::table.inner_join(schema::suppliers::table))
-                .filter(
-                    schema::periods::end
-                        .eq(today)
-                        .and(schema::suppliers::submitted_date.lt(today_9)),
-                )
-                .select((
-                    schema::statistics_collectors::all_columns,
-                    schema::suppliers::all_columns,
-                ))
-                .load::<(StatisticsCollector, Supplier)>(conn)?;
-
-            for (collector, supplier) in collectors_suppliers {
-                let address = supplier.mail.parse().unwrap();
-                let supplier_id = supplier.id;
-                mailer.lock().unwrap().send_reminder(
-                    collector,
-                    address,
-                    supplier_id,
-                    ReminderType::SecondReminder,
-                )?;
-            }
+/// The outbox is drained every minute, retries are delayed by `outbox::backoff`
+const OUTBOX_SCHEDULE: &str = "30 * * * * *";
 
-            Ok::<_, AppError>(())
-        })
-    })
-    .await??;
-
-    Ok(())
-}
-
-const FIRST_REMINDER_SCHEDULE: &str = "0 0 8 * * *";
-const SECOND_REMINDER_SCHEDULE: &str = "0 0 15 * * *";
+/// Collectors deleted longer than the retention period ago are purged every night
+const PURGE_SCHEDULE: &str = "0 30 3 * * *";
 
+/// `retention` is how long deleted collectors can still be restored,
+/// `send_concurrency` how many emails are sent at once
 pub async fn start_scheduler(
     db_pool: postgres::Pool,
     clock: Arc<Mutex<dyn Clock>>,
-    mailer: Arc<Mutex<dyn Mailer>>,
+    mailer: Arc<dyn Mailer>,
+    retention: Duration,
+    send_concurrency: usize,
 ) -> Result<(), JobSchedulerError> {
     let sched = JobScheduler::new().await?;
 
     {
         let db_pool = db_pool.clone();
         let clock = clock.clone();
-        let mailer = mailer.clone();
         sched
-            .add(Job::new_async(
-                FIRST_REMINDER_SCHEDULE,
-                move |_uuid, _l| {
-                    let db_pool = db_pool.clone();
-                    let clock = clock.clone();
-                    let mailer = mailer.clone();
-                    Box::pin(async move {
-                        first_reminder(db_pool, clock, mailer)
-                            .await
-                            .unwrap_or_else(|e| {
-                                log::error!("Failed to send first reminder: {}", e);
-                            });
-                    })
-                },
-            )?)
+            .add(Job::new_async(PURGE_SCHEDULE, move |_uuid, _l| {
+                let db_pool = db_pool.clone();
+                let clock = clock.clone();
+                Box::pin(async move {
+                    match purge_deleted_collectors(db_pool, clock, retention).await {
+                        Ok(0) => {}
+                        Ok(purged) => log::info!("Purged {} deleted collectors", purged),
+                        Err(e) => log::error!("Failed to purge deleted collectors: {}", e),
+                    }
+                })
+            })?)
             .await?;
     }
+
     {
         let db_pool = db_pool.clone();
         let clock = clock.clone();
-        let mailer = mailer.clone();
         sched
-            .add(Job::new_async(
-                SECOND_REMINDER_SCHEDULE,
-                move |_uuid, _l| {
-                    let db_pool = db_pool.clone();
-                    let clock = clock.clone();
-                    let mailer = mailer.clone();
-                    Box::pin(async move {
-                        second_reminder(db_pool, clock, mailer)
-                            .await
-                            .unwrap_or_else(|e| {
-                                log::error!("Failed to send second reminder: {}", e);
-                            });
-                    })
-                },
-            )?)
+            .add(Job::new_async(REMINDER_SCHEDULE, move |_uuid, _l| {
+                let db_pool = db_pool.clone();
+                let clock = clock.clone();
+                Box::pin(async move {
+                    enqueue_due_reminders(db_pool, clock)
+                        .await
+                        .unwrap_or_else(|e| {
+                            log::error!("Failed to queue reminders: {}", e);
+                        });
+                })
+            })?)
             .await?;
     }
 
+    sched
+        .add(Job::new_async(OUTBOX_SCHEDULE, move |_uuid, _l| {
+            let db_pool = db_pool.clone();
+            let clock = clock.clone();
+            let mailer = mailer.clone();
+            Box::pin(async move {
+                if let Err(e) = drain_outbox(db_pool, clock, mailer, send_concurrency).await {
+                    log::error!("Failed to drain the outbox: {}", e);
+                }
+            })
+        })?)
+        .await?;
+
     sched.shutdown_on_ctrl_c();
     sched.start().await?;
 
@@ -167,15 +178,14 @@ pub async fn start_scheduler(
 
 #[cfg(test)]
 mod tests {
-    use crate::logic::scheduler::{FIRST_REMINDER_SCHEDULE, SECOND_REMINDER_SCHEDULE};
+    use crate::logic::scheduler::{OUTBOX_SCHEDULE, PURGE_SCHEDULE, REMINDER_SCHEDULE};
 
     #[test]
-    fn schedules_can_be_parsed() {
-        let _ = tokio_cron_scheduler::JobBuilder::new()
-            .with_schedule(FIRST_REMINDER_SCHEDULE)
-            .unwrap();
-        let _ = tokio_cron_scheduler::JobBuilder::new()
-            .with_schedule(SECOND_REMINDER_SCHEDULE)
-            .unwrap();
+    fn schedule_can_be_parsed() {
+        for schedule in [REMINDER_SCHEDULE, OUTBOX_SCHEDULE, PURGE_SCHEDULE] {
+            let _ = tokio_cron_scheduler::JobBuilder::new()
+                .with_schedule(schedule)
+                .unwrap();
+        }
     }
 }
@@ -0,0 +1,515 @@
+use crate::db;
+use crate::errors::AppError;
+use crate::logic::export::{self, write_grid};
+use crate::logic::locale::Locale;
+use crate::routes::supplier::submit::FormKey;
+use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+use chrono::NaiveDate;
+use rust_i18n::t;
+use rust_xlsxwriter::Workbook;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+/// The grid of a single supplier, as shown on the supplier page
+pub struct SupplierGrid<'a> {
+    pub periods: &'a [db::Period],
+    pub copies: &'a [db::Copy],
+    pub statistic_types: &'a [db::StatisticType],
+    /// Language of the headers and of the upload errors
+    pub locale: Locale,
+}
+
+/// Result of matching an uploaded spreadsheet against a [`SupplierGrid`]
+#[derive(Debug, Default)]
+pub struct ParsedGrid {
+    pub values: BTreeMap<FormKey, i32>,
+    /// Cells which can't be submitted: the text found in them and what's wrong with it
+    pub cell_errors: BTreeMap<FormKey, (String, String)>,
+    /// Problems which don't belong to a single cell, e.g. unknown periods
+    pub errors: Vec<String>,
+}
+
+impl ParsedGrid {
+    pub fn is_valid(&self) -> bool {
+        self.cell_errors.is_empty() && self.errors.is_empty()
+    }
+}
+
+impl SupplierGrid<'_> {
+    /// Same layout as [`write_grid`], with a cell per row for every column
+    pub fn to_csv(&self, values: &BTreeMap<FormKey, i32>) -> Result<Vec<u8>, AppError> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+
+        let mut copies_row = vec![String::new()];
+        let mut statistic_types_row = vec![String::new()];
+        for copy in self.copies {
+            for (i, statistic_type) in self.statistic_types.iter().enumerate() {
+                copies_row.push(if i == 0 {
+                    export::copy_header(&copy.name, self.locale)
+                } else {
+                    String::new()
+                });
+                statistic_types_row.push(statistic_type.name.clone());
+            }
+        }
+        writer.write_record(&copies_row).map_err(AppError::other)?;
+        writer
+            .write_record(&statistic_types_row)
+            .map_err(AppError::other)?;
+
+        for period in self.periods {
+            let mut row = vec![period.name.clone()];
+            for copy in self.copies {
+                for statistic_type in self.statistic_types {
+                    let key = FormKey {
+                        copy_id: copy.id,
+                        statistic_type_id: statistic_type.id,
+                        period_id: period.id,
+                    };
+                    row.push(values.get(&key).map(i32::to_string).unwrap_or_default());
+                }
+            }
+            writer.write_record(&row).map_err(AppError::other)?;
+        }
+
+        writer.into_inner().map_err(AppError::other)
+    }
+
+    pub fn to_xlsx(&self, values: &BTreeMap<FormKey, i32>) -> Result<Vec<u8>, AppError> {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+
+        let periods = self
+            .periods
+            .iter()
+            .map(db::Period::as_json)
+            .collect::<Vec<_>>();
+        let copies = self
+            .copies
+            .iter()
+            .map(db::Copy::as_json)
+            .collect::<Vec<_>>();
+        let statistic_types = self
+            .statistic_types
+            .iter()
+            .map(db::StatisticType::as_json)
+            .collect::<Vec<_>>();
+
+        write_grid(
+            worksheet,
+            0,
+            &periods,
+            &copies,
+            &statistic_types,
+            self.locale,
+            |statistic_index, copy_index, period_index| {
+                let key = FormKey {
+                    copy_id: self.copies[copy_index].id,
+                    statistic_type_id: self.statistic_types[statistic_index].id,
+                    period_id: self.periods[period_index].id,
+                };
+                values.get(&key).copied()
+            },
+        )?;
+
+        workbook.save_to_buffer().map_err(AppError::other)
+    }
+
+    /// Why a value can't be submitted for the key: it isn't a cell of this grid,
+    /// or its period starts after `today`
+    pub fn check_key(&self, key: &FormKey, today: NaiveDate) -> Result<(), &'static str> {
+        if !self.copies.iter().any(|copy| copy.id == key.copy_id) {
+            return Err("unknown copy");
+        }
+        if !self
+            .statistic_types
+            .iter()
+            .any(|statistic_type| statistic_type.id == key.statistic_type_id)
+        {
+            return Err("unknown statistic type");
+        }
+        match self
+            .periods
+            .iter()
+            .find(|period| period.id == key.period_id)
+        {
+            None => Err("unknown period"),
+            Some(period) if period.start > today => Err("the period hasn't started yet"),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Matches cells laid out like the templates against this grid.
+    /// Columns are recognized by their copy and statistic headers and rows by period names,
+    /// so their order doesn't matter. Empty cells are skipped.
+    /// Values for periods which start after `today` are rejected, just like the supplier page disables them.
+    /// Copy headers are recognized in every language, the template may have been downloaded in another one.
+    pub fn parse(&self, cells: &[Vec<String>], today: NaiveDate) -> ParsedGrid {
+        let locale = self.locale.as_str();
+        let mut parsed = ParsedGrid::default();
+
+        let mut rows = cells
+            .iter()
+            .enumerate()
+            .skip_while(|(_, row)| row.iter().all(|cell| cell.trim().is_empty()));
+        let (Some((_, copies_row)), Some((_, statistic_types_row))) = (rows.next(), rows.next())
+        else {
+            parsed
+                .errors
+                .push(t!("upload_missing_headers", locale = locale).to_string());
+            return parsed;
+        };
+
+        // Copy headers span all statistics of the copy, but only the first cell of the span is filled
+        let mut columns = BTreeMap::new();
+        let mut copy_header = "";
+        for (column, statistic_type_header) in statistic_types_row.iter().enumerate().skip(1) {
+            if let Some(header) = copies_row.get(column).filter(|h| !h.trim().is_empty()) {
+                copy_header = header.trim();
+            }
+            let statistic_type_header = statistic_type_header.trim();
+            if statistic_type_header.is_empty() {
+                continue;
+            }
+
+            let copy = self.copies.iter().find(|copy| {
+                copy_header == copy.name
+                    || Locale::ALL
+                        .iter()
+                        .any(|locale| copy_header == export::copy_header(&copy.name, *locale))
+            });
+            let statistic_type = self
+                .statistic_types
+                .iter()
+                .find(|statistic_type| statistic_type_header == statistic_type.name);
+
+            match (copy, statistic_type) {
+                (Some(copy), Some(statistic_type)) => {
+                    columns.insert(column, (copy.id, statistic_type.id));
+                }
+                _ => parsed.errors.push(
+                    t!(
+                        "upload_unknown_column",
+                        locale = locale,
+                        column = column_name(column),
+                        copy = copy_header,
+                        statistic = statistic_type_header
+                    )
+                    .to_string(),
+                ),
+            }
+        }
+
+        let mut seen_periods = Vec::new();
+        for (row_index, row) in rows {
+            let Some(period_name) = row.first().map(|name| name.trim()) else {
+                continue;
+            };
+            if period_name.is_empty() {
+                continue;
+            }
+
+            let Some(period) = self.periods.iter().find(|p| p.name == period_name) else {
+                parsed.errors.push(
+                    t!(
+                        "upload_unknown_period",
+                        locale = locale,
+                        row = row_index + 1,
+                        period = period_name
+                    )
+                    .to_string(),
+                );
+                continue;
+            };
+            if seen_periods.contains(&period.id) {
+                parsed.errors.push(
+                    t!(
+                        "upload_duplicate_period",
+                        locale = locale,
+                        row = row_index + 1,
+                        period = period_name
+                    )
+                    .to_string(),
+                );
+                continue;
+            }
+            seen_periods.push(period.id);
+
+            for (column, (copy_id, statistic_type_id)) in &columns {
+                let text = row.get(*column).map(|cell| cell.trim()).unwrap_or_default();
+                if text.is_empty() {
+                    continue;
+                }
+
+                let key = FormKey {
+                    copy_id: *copy_id,
+                    statistic_type_id: *statistic_type_id,
+                    period_id: period.id,
+                };
+                if period.start > today {
+                    let error = t!("upload_period_not_started", locale = locale).to_string();
+                    parsed.cell_errors.insert(key, (text.to_string(), error));
+                    continue;
+                }
+                match text.parse::<i32>() {
+                    Ok(value) => {
+                        parsed.values.insert(key, value);
+                    }
+                    Err(_) => {
+                        let error = t!("upload_not_a_number", locale = locale).to_string();
+                        parsed.cell_errors.insert(key, (text.to_string(), error));
+                    }
+                }
+            }
+        }
+
+        parsed
+    }
+}
+
+/// Reads a CSV file or the first sheet of an XLSX workbook into rows of cells
+pub fn read_cells(bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    if bytes.starts_with(b"PK\x03\x04") {
+        read_xlsx(bytes)
+    } else {
+        read_csv(bytes)
+    }
+}
+
+fn read_xlsx(bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(bytes))
+        .map_err(|e: calamine::XlsxError| e.to_string())?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| "no sheets".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    // The range starts at the first non-empty cell, so it has to be shifted back into place
+    let Some((first_row, first_column)) = range.start() else {
+        return Ok(Vec::new());
+    };
+    let mut cells = vec![Vec::new(); first_row as usize];
+    for row in range.rows() {
+        let mut cells_row = vec![String::new(); first_column as usize];
+        cells_row.extend(row.iter().map(|cell| match cell {
+            Data::Float(f) if f.fract() == 0.0 => format!("{}", *f as i64),
+            cell => cell.to_string(),
+        }));
+        cells.push(cells_row);
+    }
+
+    Ok(cells)
+}
+
+fn read_csv(bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+    let text = text.trim_start_matches('\u{feff}');
+
+    // Spreadsheets with a comma as the decimal separator save CSV files with semicolons
+    let first_line = text.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains(';') && !first_line.contains(',') {
+        b';'
+    } else {
+        b','
+    };
+
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes())
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(str::to_string).collect())
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Spreadsheet-style column name, e.g. `A` for 0 and `AA` for 26
+fn column_name(column: usize) -> String {
+    let mut name = Vec::new();
+    let mut column = column + 1;
+    while column > 0 {
+        column -= 1;
+        name.push(b'A' + (column % 26) as u8);
+        column /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{CopyId, PeriodId, PlacementTypeId, StatCollectorId, StatisticTypeId};
+
+    struct Fixture {
+        periods: Vec<db::Period>,
+        copies: Vec<db::Copy>,
+        statistic_types: Vec<db::StatisticType>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let placement_type_id = PlacementTypeId::new();
+            let statistics_collector_id = StatCollectorId::new();
+            let period = |name: &str, day| db::Period {
+                id: PeriodId::new(),
+                name: name.to_string(),
+                start: NaiveDate::from_ymd_opt(2021, 4, day).unwrap(),
+                end: NaiveDate::from_ymd_opt(2021, 4, day + 6).unwrap(),
+                statistics_collector_id,
+            };
+            let copy = |name: &str| db::Copy {
+                id: CopyId::new(),
+                name: name.to_string(),
+                placement_type_id,
+            };
+            let statistic_type = |name: &str| db::StatisticType {
+                id: StatisticTypeId::new(),
+                name: name.to_string(),
+                placement_type_id,
+            };
+
+            Self {
+                periods: vec![period("first", 1), period("second", 8)],
+                copies: vec![copy("A"), copy("B")],
+                statistic_types: vec![statistic_type("Displays"), statistic_type("Clicks")],
+            }
+        }
+
+        fn grid(&self) -> SupplierGrid<'_> {
+            SupplierGrid {
+                periods: &self.periods,
+                copies: &self.copies,
+                statistic_types: &self.statistic_types,
+                locale: Locale::Pl,
+            }
+        }
+
+        fn key(&self, copy: usize, statistic_type: usize, period: usize) -> FormKey {
+            FormKey {
+                copy_id: self.copies[copy].id,
+                statistic_type_id: self.statistic_types[statistic_type].id,
+                period_id: self.periods[period].id,
+            }
+        }
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 5, 1).unwrap()
+    }
+
+    #[test]
+    fn templates_can_be_read_back() {
+        let fixture = Fixture::new();
+        let grid = fixture.grid();
+        let values = BTreeMap::from([(fixture.key(0, 1, 0), 5), (fixture.key(1, 0, 1), 12)]);
+
+        // the supplier may switch the language between downloading and uploading
+        let english = SupplierGrid {
+            locale: Locale::En,
+            ..fixture.grid()
+        };
+
+        for template in [
+            grid.to_csv(&values).unwrap(),
+            grid.to_xlsx(&values).unwrap(),
+            english.to_csv(&values).unwrap(),
+            english.to_xlsx(&values).unwrap(),
+        ] {
+            let parsed = grid.parse(&read_cells(&template).unwrap(), today());
+            assert!(parsed.is_valid(), "{:?}", parsed);
+            assert_eq!(parsed.values, values);
+        }
+    }
+
+    #[test]
+    fn columns_and_rows_are_matched_by_name() {
+        let fixture = Fixture::new();
+        let csv = "\
+            ;B;;A\n\
+            ;Clicks;Displays;Clicks\n\
+            second;1;2;3\n\
+            first;;;4\n";
+
+        let parsed = fixture
+            .grid()
+            .parse(&read_cells(csv.as_bytes()).unwrap(), today());
+
+        assert!(parsed.is_valid(), "{:?}", parsed);
+        assert_eq!(
+            parsed.values,
+            BTreeMap::from([
+                (fixture.key(1, 1, 1), 1),
+                (fixture.key(1, 0, 1), 2),
+                (fixture.key(0, 1, 1), 3),
+                (fixture.key(0, 1, 0), 4),
+            ])
+        );
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        let fixture = Fixture::new();
+        let csv = "\
+            ,A,A,C\n\
+            ,Displays,Clicks,Clicks\n\
+            first,x,1,1\n\
+            third,1,1,1\n\
+            second,2,,\n";
+
+        let parsed = fixture.grid().parse(
+            &read_cells(csv.as_bytes()).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 4, 5).unwrap(),
+        );
+
+        assert_eq!(parsed.errors.len(), 2, "{:?}", parsed.errors);
+        assert_eq!(parsed.values, BTreeMap::from([(fixture.key(0, 1, 0), 1)]));
+        let mut expected = vec![fixture.key(0, 0, 0), fixture.key(0, 0, 1)];
+        expected.sort();
+        assert_eq!(
+            parsed.cell_errors.keys().copied().collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn only_started_cells_of_the_grid_can_be_submitted() {
+        let fixture = Fixture::new();
+        let grid = fixture.grid();
+        let started = NaiveDate::from_ymd_opt(2021, 4, 5).unwrap();
+
+        assert_eq!(grid.check_key(&fixture.key(1, 1, 0), started), Ok(()));
+        assert!(grid.check_key(&fixture.key(1, 1, 1), started).is_err());
+        assert_eq!(grid.check_key(&fixture.key(1, 1, 1), today()), Ok(()));
+
+        let other = Fixture::new();
+        for key in [
+            FormKey {
+                copy_id: other.copies[0].id,
+                ..fixture.key(0, 0, 0)
+            },
+            FormKey {
+                statistic_type_id: other.statistic_types[0].id,
+                ..fixture.key(0, 0, 0)
+            },
+            FormKey {
+                period_id: other.periods[0].id,
+                ..fixture.key(0, 0, 0)
+            },
+        ] {
+            assert!(grid.check_key(&key, today()).is_err());
+        }
+    }
+
+    #[test]
+    fn column_names() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(27), "AB");
+    }
+}
@@ -0,0 +1,160 @@
+use crate::db::StatCollectorId;
+use crate::{db, schema};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use std::collections::BTreeMap;
+
+/// Postgres limits a single statement to 65535 bind parameters
+const INSERT_CHUNK_SIZE: usize = 10_000;
+
+/// Inserts an empty `statistics` row for every (period, supplier, statistic type, copy)
+/// combination of the collector that does not have one yet.
+/// Existing values are left untouched.
+pub fn insert_missing_statistics(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<usize> {
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector_id))
+        .select(db::Period::as_select())
+        .load(conn)?;
+
+    let suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(db::Supplier::as_select())
+        .load(conn)?;
+
+    let statistic_types = schema::statistic_types::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(db::StatisticType::as_select())
+        .load(conn)?;
+
+    let copies = schema::copies::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(db::Copy::as_select())
+        .load(conn)?;
+
+    // for each period, for supplier, for each of supplier's statistic types, for each of supplier's copies
+    let db_statistics = periods
+        .iter()
+        .flat_map(|period| {
+            suppliers.iter().flat_map(|supplier| {
+                statistic_types
+                    .iter()
+                    .filter(|statistic_type| {
+                        statistic_type.placement_type_id == supplier.placement_type_id
+                    })
+                    .flat_map(|statistic_type| {
+                        copies
+                            .iter()
+                            .filter(|copy| copy.placement_type_id == supplier.placement_type_id)
+                            .map(|copy| db::Statistic {
+                                value: None,
+                                period_id: period.id,
+                                supplier_id: supplier.id,
+                                statistic_type_id: statistic_type.id,
+                                copy_id: copy.id,
+                            })
+                    })
+            })
+        })
+        .collect::<Vec<db::Statistic>>();
+
+    let mut inserted = 0;
+    for chunk in db_statistics.chunks(INSERT_CHUNK_SIZE) {
+        inserted += diesel::insert_into(schema::statistics::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+    }
+
+    Ok(inserted)
+}
+
+/// Moves the values of a supplier which was moved to another placement type
+/// to the statistic types and copies of the same names there.
+/// Values the new placement type has no cell for are removed.
+pub fn move_statistics(conn: &mut PgConnection, supplier: &db::Supplier) -> QueryResult<usize> {
+    let statistic_types = schema::statistic_types::table
+        .filter(schema::statistic_types::placement_type_id.eq(supplier.placement_type_id))
+        .select((schema::statistic_types::name, schema::statistic_types::id))
+        .load::<(String, db::StatisticTypeId)>(conn)?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let copies = schema::copies::table
+        .filter(schema::copies::placement_type_id.eq(supplier.placement_type_id))
+        .select((schema::copies::name, schema::copies::id))
+        .load::<(String, db::CopyId)>(conn)?
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+    let moved = schema::statistics::table
+        .inner_join(schema::statistic_types::table)
+        .inner_join(schema::copies::table)
+        .filter(schema::statistics::supplier_id.eq(supplier.id))
+        .filter(schema::statistics::value.is_not_null())
+        .filter(
+            schema::statistic_types::placement_type_id
+                .ne(supplier.placement_type_id)
+                .or(schema::copies::placement_type_id.ne(supplier.placement_type_id)),
+        )
+        .select((
+            db::Statistic::as_select(),
+            schema::statistic_types::name,
+            schema::copies::name,
+        ))
+        .load::<(db::Statistic, String, String)>(conn)?
+        .into_iter()
+        .filter_map(|(statistic, statistic_type, copy)| {
+            Some(db::Statistic {
+                statistic_type_id: *statistic_types.get(&statistic_type)?,
+                copy_id: *copies.get(&copy)?,
+                ..statistic
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut changed = 0;
+    for chunk in moved.chunks(INSERT_CHUNK_SIZE) {
+        changed += diesel::insert_into(schema::statistics::table)
+            .values(chunk)
+            .on_conflict((
+                schema::statistics::period_id,
+                schema::statistics::supplier_id,
+                schema::statistics::statistic_type_id,
+                schema::statistics::copy_id,
+            ))
+            .do_update()
+            .set(schema::statistics::value.eq(excluded(schema::statistics::value)))
+            .execute(conn)?;
+    }
+    changed += delete_orphaned_statistics(conn, supplier)?;
+
+    Ok(changed)
+}
+
+/// Removes values a supplier no longer has a cell for,
+/// i.e. ones whose statistic type or copy belongs to a different placement type.
+fn delete_orphaned_statistics(
+    conn: &mut PgConnection,
+    supplier: &db::Supplier,
+) -> QueryResult<usize> {
+    let statistic_types = schema::statistic_types::table
+        .filter(schema::statistic_types::placement_type_id.eq(supplier.placement_type_id))
+        .select(schema::statistic_types::id);
+    let copies = schema::copies::table
+        .filter(schema::copies::placement_type_id.eq(supplier.placement_type_id))
+        .select(schema::copies::id);
+
+    diesel::delete(schema::statistics::table)
+        .filter(schema::statistics::supplier_id.eq(supplier.id))
+        .filter(
+            schema::statistics::statistic_type_id
+                .ne_all(statistic_types.load::<db::StatisticTypeId>(conn)?)
+                .or(schema::statistics::copy_id.ne_all(copies.load::<db::CopyId>(conn)?)),
+        )
+        .execute(conn)
+}
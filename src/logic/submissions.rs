@@ -0,0 +1,211 @@
+use crate::db::{PeriodId, PeriodSubmission, StatCollectorId, SupplierId};
+use crate::json;
+use crate::schema;
+use chrono::{DateTime, Local};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Cells are empty until they're filled in, a filled in 0 counts
+pub fn is_filled(value: Option<i32>) -> bool {
+    value.is_some()
+}
+
+/// Records that the supplier submitted the statistics of the periods.
+/// Must be called after the new values are saved.
+pub fn record_submission(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    period_ids: &BTreeSet<PeriodId>,
+    now: DateTime<Local>,
+) -> QueryResult<usize> {
+    if period_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let values = schema::statistics::table
+        .filter(
+            schema::statistics::supplier_id
+                .eq(supplier_id)
+                .and(schema::statistics::period_id.eq_any(period_ids)),
+        )
+        .select((schema::statistics::period_id, schema::statistics::value))
+        .load::<(PeriodId, Option<i32>)>(conn)?;
+
+    let submissions = period_ids
+        .iter()
+        .map(|&period_id| PeriodSubmission {
+            supplier_id,
+            period_id,
+            submitted_at: now,
+            complete: values
+                .iter()
+                .filter(|(id, _)| *id == period_id)
+                .all(|&(_, value)| is_filled(value)),
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(schema::period_submissions::table)
+        .values(&submissions)
+        .on_conflict((
+            schema::period_submissions::supplier_id,
+            schema::period_submissions::period_id,
+        ))
+        .do_update()
+        .set((
+            schema::period_submissions::submitted_at
+                .eq(excluded(schema::period_submissions::submitted_at)),
+            schema::period_submissions::complete.eq(excluded(schema::period_submissions::complete)),
+        ))
+        .execute(conn)
+}
+
+/// Updates whether the submissions of the collector are complete,
+/// after values were added or removed by changing the collector
+pub fn refresh_completion(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<()> {
+    let suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(schema::suppliers::id);
+
+    let mut complete = BTreeMap::<(SupplierId, PeriodId), bool>::new();
+    for (supplier_id, period_id, value) in schema::statistics::table
+        .filter(schema::statistics::supplier_id.eq_any(suppliers))
+        .select((
+            schema::statistics::supplier_id,
+            schema::statistics::period_id,
+            schema::statistics::value,
+        ))
+        .load::<(SupplierId, PeriodId, Option<i32>)>(conn)?
+    {
+        *complete.entry((supplier_id, period_id)).or_insert(true) &= is_filled(value);
+    }
+
+    let submissions = schema::period_submissions::table
+        .filter(schema::period_submissions::supplier_id.eq_any(suppliers))
+        .select(PeriodSubmission::as_select())
+        .load(conn)?;
+
+    for submission in submissions {
+        let is_complete = complete
+            .get(&(submission.supplier_id, submission.period_id))
+            .copied()
+            .unwrap_or(true);
+        if is_complete != submission.complete {
+            diesel::update(
+                schema::period_submissions::table
+                    .find((submission.supplier_id, submission.period_id)),
+            )
+            .set(schema::period_submissions::complete.eq(is_complete))
+            .execute(conn)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Submissions of the supplier by period
+pub fn load_submissions(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+) -> QueryResult<BTreeMap<PeriodId, PeriodSubmission>> {
+    Ok(schema::period_submissions::table
+        .filter(schema::period_submissions::supplier_id.eq(supplier_id))
+        .select(PeriodSubmission::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|submission| (submission.period_id, submission))
+        .collect())
+}
+
+/// Submissions of all suppliers of the collector by supplier and period
+pub fn load_collector_submissions(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<BTreeMap<SupplierId, BTreeMap<PeriodId, PeriodSubmission>>> {
+    let suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(schema::suppliers::id);
+
+    let mut submissions = BTreeMap::<_, BTreeMap<_, _>>::new();
+    for submission in schema::period_submissions::table
+        .filter(schema::period_submissions::supplier_id.eq_any(suppliers))
+        .select(PeriodSubmission::as_select())
+        .load(conn)?
+    {
+        submissions
+            .entry(submission.supplier_id)
+            .or_default()
+            .insert(submission.period_id, submission);
+    }
+    Ok(submissions)
+}
+
+/// Whether the supplier has filled in all statistics of the period
+pub fn is_complete(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    period_id: PeriodId,
+) -> QueryResult<bool> {
+    Ok(schema::period_submissions::table
+        .find((supplier_id, period_id))
+        .select(schema::period_submissions::complete)
+        .first(conn)
+        .optional()?
+        .unwrap_or(false))
+}
+
+/// How many suppliers of the collector submitted each period, sorted by the start of the period
+pub fn load_completion(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<Vec<json::sent::PeriodCompletion>> {
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector_id))
+        .order_by(schema::periods::start)
+        .select(schema::periods::id)
+        .load::<PeriodId>(conn)?;
+
+    let suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .order_by(schema::suppliers::name)
+        .select(schema::suppliers::id)
+        .load::<SupplierId>(conn)?;
+
+    let submissions = schema::period_submissions::table
+        .filter(schema::period_submissions::supplier_id.eq_any(&suppliers))
+        .select(PeriodSubmission::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|submission| ((submission.period_id, submission.supplier_id), submission))
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(periods
+        .into_iter()
+        .map(|period_id| {
+            let submission = |supplier_id: &SupplierId| submissions.get(&(period_id, *supplier_id));
+            json::sent::PeriodCompletion {
+                period_id,
+                suppliers: suppliers.len(),
+                submitted: suppliers.iter().filter_map(submission).count(),
+                complete: suppliers
+                    .iter()
+                    .filter_map(submission)
+                    .filter(|submission| submission.complete)
+                    .count(),
+                incomplete_suppliers: suppliers
+                    .iter()
+                    .filter(|supplier_id| {
+                        !submission(supplier_id).is_some_and(|submission| submission.complete)
+                    })
+                    .copied()
+                    .collect(),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,234 @@
+use crate::db::{
+    CopyId, DerivedStatisticTypeId, PeriodId, PlacementTypeId, StatCollectorId, SupplierId,
+};
+use crate::errors::AppError;
+use crate::json::derived::DerivedStatisticType;
+use crate::logic::formula::Formula;
+use crate::{db, json, schema};
+use anyhow::anyhow;
+use derive_more::Display;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Nullable, Text, Uuid};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// What the statistics of a summary can be grouped by.
+/// They are always grouped by the name of the statistic type.
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    #[display(fmt = "period")]
+    Period,
+    #[display(fmt = "placement_type")]
+    PlacementType,
+    #[display(fmt = "supplier")]
+    Supplier,
+    #[display(fmt = "copy")]
+    Copy,
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "period" => Ok(Self::Period),
+            "placement_type" => Ok(Self::PlacementType),
+            "supplier" => Ok(Self::Supplier),
+            "copy" => Ok(Self::Copy),
+            _ => Err(format!("unknown dimension {}", s)),
+        }
+    }
+}
+
+impl Dimension {
+    const ALL: [Dimension; 4] = [
+        Self::Period,
+        Self::PlacementType,
+        Self::Supplier,
+        Self::Copy,
+    ];
+
+    /// Columns of the id and the name, and the expression the groups are sorted by
+    fn columns(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::Period => ("p.id", "p.name", "p.start, p.id"),
+            Self::PlacementType => ("pt.id", "pt.name", "pt.name, pt.id"),
+            Self::Supplier => ("su.id", "su.name", "su.name, su.id"),
+            Self::Copy => ("c.id", "c.name", "c.name, c.id"),
+        }
+    }
+}
+
+/// Replaces the derived statistic types of the collector, which must have been validated
+pub fn save_derived_statistic_types(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    derived: &[DerivedStatisticType],
+) -> QueryResult<()> {
+    diesel::delete(
+        schema::derived_statistic_types::table
+            .filter(schema::derived_statistic_types::statistics_collector_id.eq(collector_id)),
+    )
+    .execute(conn)?;
+
+    let rows = derived
+        .iter()
+        .map(|derived| db::DerivedStatisticType {
+            id: DerivedStatisticTypeId::new(),
+            statistics_collector_id: collector_id,
+            name: derived.name.clone(),
+            formula: derived.formula.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(schema::derived_statistic_types::table)
+        .values(&rows)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+pub fn load_derived_statistic_types(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> QueryResult<Vec<DerivedStatisticType>> {
+    Ok(schema::derived_statistic_types::table
+        .filter(schema::derived_statistic_types::statistics_collector_id.eq(collector_id))
+        .order_by(schema::derived_statistic_types::name)
+        .select(db::DerivedStatisticType::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|derived| DerivedStatisticType {
+            name: derived.name,
+            formula: derived.formula,
+        })
+        .collect())
+}
+
+/// Total of a statistic type in a group of the summary
+#[derive(Debug, QueryableByName)]
+struct SummaryCell {
+    #[diesel(sql_type = Nullable<Uuid>)]
+    period_id: Option<PeriodId>,
+    #[diesel(sql_type = Nullable<Text>)]
+    period: Option<String>,
+    #[diesel(sql_type = Nullable<Uuid>)]
+    placement_type_id: Option<PlacementTypeId>,
+    #[diesel(sql_type = Nullable<Text>)]
+    placement_type: Option<String>,
+    #[diesel(sql_type = Nullable<Uuid>)]
+    supplier_id: Option<SupplierId>,
+    #[diesel(sql_type = Nullable<Text>)]
+    supplier: Option<String>,
+    #[diesel(sql_type = Nullable<Uuid>)]
+    copy_id: Option<CopyId>,
+    #[diesel(sql_type = Nullable<Text>)]
+    copy: Option<String>,
+    #[diesel(sql_type = Text)]
+    statistic: String,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    total: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    missing: i64,
+}
+
+/// Sums the statistics of the collector grouped by the dimensions,
+/// and computes its derived statistic types from the sums
+pub fn load_summary(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    group_by: &[Dimension],
+) -> Result<Vec<json::sent::SummaryRow>, AppError> {
+    let derived = load_derived_statistic_types(conn, collector_id)?
+        .into_iter()
+        .map(|derived| {
+            let formula = derived
+                .formula
+                .parse::<Formula>()
+                .map_err(|e| AppError::other(anyhow!(e)))?;
+            Ok((derived.name, formula))
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut select = vec![];
+    let mut groups = vec![];
+    let mut order = vec![];
+    for dimension in Dimension::ALL {
+        let (id, name, sort) = dimension.columns();
+        let column = dimension.to_string();
+        if group_by.contains(&dimension) {
+            select.push(format!("{id} AS {column}_id, {name} AS {column}"));
+            groups.push(id);
+            order.push(sort);
+        } else {
+            select.push(format!("NULL::uuid AS {column}_id, NULL::text AS {column}"));
+        }
+    }
+    groups.push("st.name");
+    order.push("st.name");
+
+    let query = format!(
+        "SELECT {}, st.name AS statistic, SUM(s.value) AS total, COUNT(*) - COUNT(s.value) AS missing \
+        FROM statistics s \
+        JOIN statistic_types st ON st.id = s.statistic_type_id \
+        JOIN suppliers su ON su.id = s.supplier_id \
+        JOIN placement_types pt ON pt.id = su.placement_type_id \
+        JOIN periods p ON p.id = s.period_id \
+        JOIN copies c ON c.id = s.copy_id \
+        WHERE pt.statistics_collector_id = $1 \
+        GROUP BY {} \
+        ORDER BY {}",
+        select.join(", "),
+        groups.join(", "),
+        order.join(", "),
+    );
+
+    let cells = diesel::sql_query(query)
+        .bind::<Uuid, _>(collector_id)
+        .load::<SummaryCell>(conn)?;
+
+    let rows = cells
+        .into_iter()
+        .group_by(|cell| {
+            (
+                cell.period_id,
+                cell.placement_type_id,
+                cell.supplier_id,
+                cell.copy_id,
+            )
+        })
+        .into_iter()
+        .map(|(_, group)| {
+            let cells = group.collect_vec();
+            let first = &cells[0];
+            let totals = cells
+                .iter()
+                .map(|cell| (cell.statistic.clone(), cell.total))
+                .collect::<BTreeMap<_, _>>();
+            let statistic = |name: &str| totals.get(name).copied().flatten().map(|t| t as f64);
+
+            json::sent::SummaryRow {
+                period_id: first.period_id,
+                period: first.period.clone(),
+                placement_type_id: first.placement_type_id,
+                placement_type: first.placement_type.clone(),
+                supplier_id: first.supplier_id,
+                supplier: first.supplier.clone(),
+                copy_id: first.copy_id,
+                copy: first.copy.clone(),
+                missing: cells.iter().map(|cell| cell.missing).sum(),
+                derived: derived
+                    .iter()
+                    .map(|(name, formula)| (name.clone(), formula.evaluate(&statistic)))
+                    .collect(),
+                totals,
+            }
+        })
+        .collect();
+
+    Ok(rows)
+}
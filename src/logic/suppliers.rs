@@ -0,0 +1,168 @@
+use crate::db::{PlacementTypeId, StatCollectorId, SupplierId};
+use crate::errors::AppError;
+use crate::logic::archive::find_collector;
+use crate::logic::outbox::OutboxStatus;
+use crate::logic::statistics::{insert_missing_statistics, move_statistics};
+use crate::logic::submissions::refresh_completion;
+use crate::logic::validation::Problems;
+use crate::{db, json, schema};
+use diesel::prelude::*;
+use lettre::Address;
+
+fn find_placement_type(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    placement_type_id: PlacementTypeId,
+) -> Result<db::PlacementType, AppError> {
+    schema::placement_types::table
+        .find(placement_type_id)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(db::PlacementType::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("placement type", placement_type_id))
+}
+
+fn find_supplier(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier_id: SupplierId,
+) -> Result<db::Supplier, AppError> {
+    schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .filter(schema::suppliers::id.eq(supplier_id))
+        .select(db::Supplier::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("supplier", supplier_id))
+}
+
+/// Checks the name and that no other supplier of the placement type has the mail,
+/// like `validate_collector` does for whole collectors
+fn validate_supplier(
+    conn: &mut PgConnection,
+    supplier_id: Option<SupplierId>,
+    placement_type_id: PlacementTypeId,
+    name: &str,
+    mail: &Address,
+) -> Result<(), AppError> {
+    let mut problems = Problems::default();
+
+    if name.trim().is_empty() {
+        problems.add("/name", "name must not be empty");
+    }
+
+    let mail = mail.to_string().to_lowercase();
+    let taken = schema::suppliers::table
+        .filter(schema::suppliers::placement_type_id.eq(placement_type_id))
+        .select(db::Supplier::as_select())
+        .load(conn)?
+        .into_iter()
+        .any(|other| Some(other.id) != supplier_id && other.mail.to_lowercase() == mail);
+    if taken {
+        problems.add("/mail", "duplicate supplier mail in the placement type");
+    }
+
+    problems.into_result()
+}
+
+/// Adds a supplier with empty statistics for every period, statistic type and copy
+pub fn add_supplier(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier: &json::received::NewSupplier,
+) -> Result<SupplierId, AppError> {
+    find_collector(conn, collector_id)?;
+    find_placement_type(conn, collector_id, supplier.placement_type_id)?;
+    validate_supplier(
+        conn,
+        None,
+        supplier.placement_type_id,
+        &supplier.name,
+        &supplier.mail,
+    )?;
+
+    let id = SupplierId::new();
+    diesel::insert_into(schema::suppliers::table)
+        .values(db::Supplier {
+            id,
+            name: supplier.name.clone(),
+            mail: supplier.mail.to_string(),
+            placement_type_id: supplier.placement_type_id,
+            locale: supplier.locale.map(|locale| locale.to_string()),
+        })
+        .execute(conn)?;
+    insert_missing_statistics(conn, collector_id)?;
+
+    Ok(id)
+}
+
+/// Renames the supplier, changes its mail or language or moves it to another placement type.
+/// Reminders which weren't sent yet go to the new mail.
+pub fn change_supplier(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier_id: SupplierId,
+    changes: &json::received::SupplierChanges,
+) -> Result<(), AppError> {
+    find_collector(conn, collector_id)?;
+    let stored = find_supplier(conn, collector_id, supplier_id)?;
+
+    let placement_type_id = match changes.placement_type_id {
+        Some(placement_type_id) => find_placement_type(conn, collector_id, placement_type_id)?.id,
+        None => stored.placement_type_id,
+    };
+    let name = changes.name.clone().unwrap_or_else(|| stored.name.clone());
+    let mail = match &changes.mail {
+        Some(mail) => mail.clone(),
+        None => stored.mail.parse().map_err(AppError::other)?,
+    };
+    validate_supplier(conn, Some(supplier_id), placement_type_id, &name, &mail)?;
+
+    let updated = diesel::update(schema::suppliers::table.find(supplier_id))
+        .set((
+            schema::suppliers::name.eq(&name),
+            schema::suppliers::mail.eq(mail.to_string()),
+            schema::suppliers::placement_type_id.eq(placement_type_id),
+            schema::suppliers::locale.eq(changes
+                .locale
+                .map(|locale| locale.to_string())
+                .or(stored.locale.clone())),
+        ))
+        .get_result::<db::Supplier>(conn)?;
+
+    if updated.mail != stored.mail {
+        diesel::update(
+            schema::outbox::table
+                .filter(schema::outbox::supplier_id.eq(supplier_id))
+                .filter(schema::outbox::recipient.eq(&stored.mail))
+                .filter(schema::outbox::status.eq(OutboxStatus::Pending.to_string())),
+        )
+        .set(schema::outbox::recipient.eq(&updated.mail))
+        .execute(conn)?;
+    }
+
+    if updated.placement_type_id != stored.placement_type_id {
+        move_statistics(conn, &updated)?;
+        insert_missing_statistics(conn, collector_id)?;
+        refresh_completion(conn, collector_id)?;
+    }
+
+    Ok(())
+}
+
+/// Removes the supplier with its statistics, links and emails
+pub fn remove_supplier(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    supplier_id: SupplierId,
+) -> Result<(), AppError> {
+    find_collector(conn, collector_id)?;
+    find_supplier(conn, collector_id, supplier_id)?;
+
+    diesel::delete(schema::suppliers::table.find(supplier_id)).execute(conn)?;
+    refresh_completion(conn, collector_id)?;
+
+    Ok(())
+}
@@ -0,0 +1,309 @@
+use crate::db::CollectorTemplateId;
+use crate::errors::AppError;
+use crate::logic::periods::{shift_periods, shift_periods_by_months, shift_weekday, Periodicity};
+use crate::{db, json, schema};
+use chrono::{DateTime, Local};
+use diesel::prelude::*;
+
+/// The parts of a collector's configuration which are copied to new collectors
+pub fn collector_structure(config: json::sent::StatCollector) -> json::received::StatCollector {
+    json::received::StatCollector {
+        name: config.name,
+        client: config.client,
+        periods: config
+            .periods
+            .into_iter()
+            .map(|period| json::received::Period {
+                id: None,
+                name: period.name,
+                start_date: period.start_date,
+                end_date: period.end_date,
+            })
+            .collect(),
+        placement_types: config
+            .placement_types
+            .into_iter()
+            .map(|placement_type| json::received::PlacementType {
+                id: None,
+                name: placement_type.name,
+                suppliers: placement_type
+                    .suppliers
+                    .into_iter()
+                    .map(|supplier| json::received::Supplier {
+                        id: None,
+                        name: supplier.name,
+                        mail: supplier.mail,
+                        locale: supplier.locale,
+                    })
+                    .collect(),
+                statistics: placement_type.statistics,
+                copies: placement_type.copies,
+                statistic_type_ids: vec![],
+                copy_ids: vec![],
+            })
+            .collect(),
+        periodicity: config.periodicity,
+        weekday: config.weekday,
+        campaign: None,
+        reminder_policy: Some(config.reminder_policy),
+        derived_statistic_types: Some(config.derived_statistic_types),
+        locale: Some(config.locale),
+    }
+}
+
+/// A create request for a new collector with the given structure, which still has to be validated.
+/// Days don't fit months, so the periods of monthly and custom periodicities are only moved
+/// by months and the others only by days.
+pub fn instantiate(
+    structure: json::received::StatCollector,
+    request: json::received::CloneRequest,
+) -> Result<json::received::StatCollector, AppError> {
+    let mut collector = json::received::StatCollector {
+        name: request.name,
+        client: request.client,
+        ..structure
+    };
+
+    if !request.periods.is_empty() || request.campaign.is_some() {
+        collector.periods = request.periods;
+        collector.campaign = request.campaign;
+    } else {
+        match collector.periodicity {
+            Periodicity::Monthly | Periodicity::Custom => {
+                if request.period_offset_days != 0 {
+                    return Err(AppError::validation(
+                        "/periodOffsetDays",
+                        "monthly and custom periods are moved by periodOffsetMonths",
+                    ));
+                }
+                collector.periods =
+                    shift_periods_by_months(&collector.periods, request.period_offset_months)?;
+            }
+            Periodicity::Daily | Periodicity::Weekly | Periodicity::Biweekly => {
+                if request.period_offset_months != 0 {
+                    return Err(AppError::validation(
+                        "/periodOffsetMonths",
+                        "daily and weekly periods are moved by periodOffsetDays",
+                    ));
+                }
+                collector.periods = shift_periods(&collector.periods, request.period_offset_days)?;
+                collector.weekday = collector
+                    .weekday
+                    .map(|weekday| shift_weekday(weekday, request.period_offset_days));
+            }
+        }
+    }
+
+    Ok(collector)
+}
+
+fn template_from_db(
+    template: db::CollectorTemplate,
+) -> Result<json::sent::CollectorTemplate, AppError> {
+    Ok(json::sent::CollectorTemplate {
+        id: template.id,
+        name: template.name,
+        created_at: template.created_at,
+        collector: serde_json::from_value(template.collector).map_err(AppError::other)?,
+    })
+}
+
+pub fn save_template(
+    conn: &mut PgConnection,
+    name: &str,
+    structure: &json::received::StatCollector,
+    now: DateTime<Local>,
+) -> Result<CollectorTemplateId, AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::validation("/name", "name must not be empty"));
+    }
+
+    let existing = schema::collector_templates::table
+        .filter(schema::collector_templates::name.eq(name))
+        .select(schema::collector_templates::id)
+        .first::<CollectorTemplateId>(conn)
+        .optional()?;
+    if let Some(existing) = existing {
+        return Err(AppError::conflict(
+            format!("collector template with name {}", name),
+            existing,
+        ));
+    }
+
+    let id = CollectorTemplateId::new();
+    diesel::insert_into(schema::collector_templates::table)
+        .values(db::CollectorTemplate {
+            id,
+            name: name.to_string(),
+            collector: serde_json::to_value(structure).map_err(AppError::other)?,
+            created_at: now,
+        })
+        .execute(conn)?;
+
+    Ok(id)
+}
+
+/// All templates, sorted by name
+pub fn load_templates(
+    conn: &mut PgConnection,
+) -> Result<Vec<json::sent::CollectorTemplate>, AppError> {
+    schema::collector_templates::table
+        .order_by(schema::collector_templates::name)
+        .select(db::CollectorTemplate::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(template_from_db)
+        .collect()
+}
+
+pub fn load_template(
+    conn: &mut PgConnection,
+    id: CollectorTemplateId,
+) -> Result<json::sent::CollectorTemplate, AppError> {
+    let template = schema::collector_templates::table
+        .find(id)
+        .select(db::CollectorTemplate::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("collector template", id))?;
+    template_from_db(template)
+}
+
+pub fn delete_template(conn: &mut PgConnection, id: CollectorTemplateId) -> Result<(), AppError> {
+    let deleted = diesel::delete(schema::collector_templates::table.find(id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(AppError::not_found("collector template", id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Weekday};
+
+    fn structure() -> json::received::StatCollector {
+        json::received::StatCollector {
+            name: "kolektor".to_string(),
+            client: "pepsi".to_string(),
+            periods: vec![json::received::Period {
+                id: None,
+                name: "2023.11.08 - 11.14".to_string(),
+                start_date: NaiveDate::from_ymd_opt(2023, 11, 8).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+            }],
+            periodicity: Periodicity::Weekly,
+            weekday: Some(Weekday::Wed),
+            ..Default::default()
+        }
+    }
+
+    fn request() -> json::received::CloneRequest {
+        json::received::CloneRequest {
+            name: "kolektor 2024".to_string(),
+            client: "pepsi".to_string(),
+            periods: vec![],
+            campaign: None,
+            period_offset_days: 0,
+            period_offset_months: 0,
+        }
+    }
+
+    #[test]
+    fn periods_are_moved_by_the_offset() {
+        let collector = instantiate(
+            structure(),
+            json::received::CloneRequest {
+                period_offset_days: 366,
+                ..request()
+            },
+        )
+        .unwrap();
+        assert_eq!(collector.name, "kolektor 2024");
+        assert_eq!(collector.periods[0].name, "2024.11.08 - 11.14");
+        assert_eq!(collector.weekday, Some(Weekday::Fri));
+    }
+
+    #[test]
+    fn a_campaign_replaces_the_periods() {
+        let campaign = json::received::Campaign {
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+        };
+        let collector = instantiate(
+            structure(),
+            json::received::CloneRequest {
+                campaign: Some(campaign.clone()),
+                period_offset_days: 7,
+                ..request()
+            },
+        )
+        .unwrap();
+        assert!(collector.periods.is_empty());
+        assert_eq!(collector.campaign, Some(campaign));
+        assert_eq!(collector.weekday, Some(Weekday::Wed));
+    }
+
+    #[test]
+    fn monthly_periods_are_moved_by_months() {
+        let month = |month: u32, last_day: u32| json::received::Period {
+            id: None,
+            name: format!("2023.{:02}.01 - {:02}.{}", month, month, last_day),
+            start_date: NaiveDate::from_ymd_opt(2023, month, 1).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2023, month, last_day).unwrap(),
+        };
+        let monthly = json::received::StatCollector {
+            periods: vec![month(2, 28), month(3, 31)],
+            periodicity: Periodicity::Monthly,
+            weekday: None,
+            ..structure()
+        };
+
+        let collector = instantiate(
+            monthly.clone(),
+            json::received::CloneRequest {
+                period_offset_months: 1,
+                ..request()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            collector
+                .periods
+                .iter()
+                .map(|period| period.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["2023.03.01 - 03.31", "2023.04.01 - 04.30"]
+        );
+
+        let collector = instantiate(
+            monthly.clone(),
+            json::received::CloneRequest {
+                period_offset_months: -1,
+                ..request()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            collector.periods[1].end_date,
+            NaiveDate::from_ymd_opt(2023, 2, 28).unwrap()
+        );
+
+        assert!(instantiate(
+            monthly,
+            json::received::CloneRequest {
+                period_offset_days: 365,
+                ..request()
+            },
+        )
+        .is_err());
+        assert!(instantiate(
+            structure(),
+            json::received::CloneRequest {
+                period_offset_months: 12,
+                ..request()
+            },
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::db::{SupplierId, SupplierToken, SupplierTokenId};
+use crate::errors::AppError;
+use crate::schema;
+use chrono::{DateTime, Duration, Local};
+use diesel::prelude::*;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// How long a link sent to a supplier stays valid
+pub fn token_validity() -> Duration {
+    Duration::days(60)
+}
+
+/// A token which was just issued. This is the only time it's known in plain text.
+#[derive(Debug, Clone)]
+pub struct IssuedToken {
+    pub id: SupplierTokenId,
+    pub token: String,
+    pub expires_at: DateTime<Local>,
+}
+
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Path of the supplier page which the token grants access to
+pub fn supplier_path(supplier_id: SupplierId, token: &str) -> String {
+    format!("/supplier/{}?token={}", supplier_id, token)
+}
+
+/// Creates a new token for the supplier. Tokens issued earlier stay valid.
+pub fn issue_token(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    now: DateTime<Local>,
+) -> QueryResult<IssuedToken> {
+    let id = SupplierTokenId::new();
+    let token = generate_token();
+    let expires_at = now + token_validity();
+
+    diesel::insert_into(schema::supplier_tokens::table)
+        .values(SupplierToken {
+            id,
+            supplier_id,
+            token_hash: hash_token(&token),
+            created_at: now,
+            expires_at,
+            revoked_at: None,
+        })
+        .execute(conn)?;
+
+    Ok(IssuedToken {
+        id,
+        token,
+        expires_at,
+    })
+}
+
+/// Revokes all tokens of the supplier, so none of the links sent so far work anymore
+pub fn revoke_tokens(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    now: DateTime<Local>,
+) -> QueryResult<usize> {
+    diesel::update(
+        schema::supplier_tokens::table
+            .filter(schema::supplier_tokens::supplier_id.eq(supplier_id))
+            .filter(schema::supplier_tokens::revoked_at.is_null()),
+    )
+    .set(schema::supplier_tokens::revoked_at.eq(now))
+    .execute(conn)
+}
+
+/// Checks that the token is a valid one for the supplier and returns its id.
+/// Links of deleted collectors don't work anymore.
+pub fn authorize_supplier(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+    token: &str,
+    now: DateTime<Local>,
+) -> Result<SupplierTokenId, AppError> {
+    let live_suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table.inner_join(schema::statistics_collectors::table))
+        .filter(schema::statistics_collectors::deleted_at.is_null())
+        .select(schema::suppliers::id);
+
+    schema::supplier_tokens::table
+        .filter(schema::supplier_tokens::supplier_id.eq_any(live_suppliers))
+        .filter(schema::supplier_tokens::token_hash.eq(hash_token(token)))
+        .filter(schema::supplier_tokens::supplier_id.eq(supplier_id))
+        .filter(schema::supplier_tokens::revoked_at.is_null())
+        .filter(schema::supplier_tokens::expires_at.gt(now))
+        .select(schema::supplier_tokens::id)
+        .first::<SupplierTokenId>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::forbidden("the link is invalid or has expired, ask for a new one"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_hashed() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+
+        let hash = hash_token(&token);
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, token);
+        assert_eq!(hash, hash_token(&token));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::errors::AppError;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{FileTransport, Message, SendmailTransport, SmtpTransport, Transport};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// The connection is upgraded with `STARTTLS`, which the server has to support
+    #[default]
+    StartTls,
+    /// TLS from the start, also called SMTPS
+    Tls,
+    /// Plain text, only for servers on a trusted network
+    None,
+}
+
+impl SmtpTls {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::StartTls => 587,
+            Self::Tls => 465,
+            Self::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            _ => Err(format!("unknown SMTP TLS mode {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// The default port of the TLS mode if not given
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    /// Username and password, no authentication if not given
+    pub credentials: Option<(String, String)>,
+    pub timeout: Duration,
+}
+
+/// Where emails are sent. Staging environments write them to files instead of sending them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportConfig {
+    Smtp(SmtpConfig),
+    /// Every email is written to an `.eml` file in the directory
+    File(PathBuf),
+    /// Every email is delivered to the `new` folder of the maildir, which is created if needed
+    Maildir(PathBuf),
+    /// Emails are piped to the local `sendmail`, or to the given command which works like it
+    Sendmail(Option<String>),
+}
+
+impl TransportConfig {
+    /// Reads the configuration from environment variables, `var` gives the value of one:
+    /// - `MAIL_TRANSPORT`: `smtp` (the default), `file`, `maildir` or `sendmail`
+    /// - for `smtp`: `SMTP_HOST`, `SMTP_TLS` (`starttls`, the default, `tls` or `none`), `SMTP_PORT`,
+    ///   `SMTP_USERNAME` and `SMTP_PASSWORD` if the server requires authentication,
+    ///   `SMTP_TIMEOUT_SECS` (15 by default)
+    /// - for `file` and `maildir`: `MAIL_DIR`
+    /// - for `sendmail`: `SENDMAIL_COMMAND`, `sendmail` by default
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let required = |name: &str| var(name).ok_or_else(|| format!("{} must be set", name));
+
+        match var("MAIL_TRANSPORT").as_deref().unwrap_or("smtp") {
+            "smtp" => {
+                let tls = var("SMTP_TLS")
+                    .map(|tls| tls.parse())
+                    .transpose()?
+                    .unwrap_or_default();
+                let port = var("SMTP_PORT")
+                    .map(|port| port.parse().map_err(|_| "SMTP_PORT must be a port"))
+                    .transpose()?;
+                let credentials = match var("SMTP_USERNAME") {
+                    Some(username) => Some((username, required("SMTP_PASSWORD")?)),
+                    None => None,
+                };
+                let timeout = var("SMTP_TIMEOUT_SECS")
+                    .map(|secs| {
+                        secs.parse()
+                            .map_err(|_| "SMTP_TIMEOUT_SECS must be a number of seconds")
+                    })
+                    .transpose()?
+                    .unwrap_or(15);
+                Ok(Self::Smtp(SmtpConfig {
+                    host: required("SMTP_HOST")?,
+                    port,
+                    tls,
+                    credentials,
+                    timeout: Duration::from_secs(timeout),
+                }))
+            }
+            "file" => Ok(Self::File(required("MAIL_DIR")?.into())),
+            "maildir" => Ok(Self::Maildir(required("MAIL_DIR")?.into())),
+            "sendmail" => Ok(Self::Sendmail(var("SENDMAIL_COMMAND"))),
+            other => Err(format!("unknown MAIL_TRANSPORT {}", other)),
+        }
+    }
+
+    pub fn build(&self) -> Result<MailTransport, String> {
+        Ok(match self {
+            Self::Smtp(config) => {
+                let builder = match config.tls {
+                    SmtpTls::StartTls => SmtpTransport::starttls_relay(&config.host),
+                    SmtpTls::Tls => SmtpTransport::relay(&config.host),
+                    SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&config.host)),
+                }
+                .map_err(|e| format!("invalid SMTP configuration: {}", e))?
+                .port(config.port.unwrap_or(config.tls.default_port()))
+                .timeout(Some(config.timeout));
+                let builder = match &config.credentials {
+                    Some((username, password)) => {
+                        builder.credentials(Credentials::new(username.clone(), password.clone()))
+                    }
+                    None => builder,
+                };
+                MailTransport::Smtp(builder.build())
+            }
+            Self::File(dir) => {
+                fs::create_dir_all(dir)
+                    .map_err(|e| format!("can't create {}: {}", dir.display(), e))?;
+                MailTransport::File(FileTransport::new(dir))
+            }
+            Self::Maildir(dir) => MailTransport::Maildir(dir.clone()),
+            Self::Sendmail(command) => MailTransport::Sendmail(match command {
+                Some(command) => SendmailTransport::new_with_command(command),
+                None => SendmailTransport::new(),
+            }),
+        })
+    }
+}
+
+/// A built `TransportConfig`
+#[derive(Debug, Clone)]
+pub enum MailTransport {
+    Smtp(SmtpTransport),
+    File(FileTransport),
+    Maildir(PathBuf),
+    Sendmail(SendmailTransport),
+}
+
+/// Writes the message to `tmp` and moves it to `new` once it's complete, as maildirs require
+fn deliver_to_maildir(dir: &Path, message: &Message) -> std::io::Result<()> {
+    for folder in ["tmp", "new", "cur"] {
+        fs::create_dir_all(dir.join(folder))?;
+    }
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = format!("{}.{}.stat-collector", seconds, Uuid::new_v4().simple());
+    let tmp = dir.join("tmp").join(&name);
+    fs::write(&tmp, message.formatted())?;
+    fs::rename(tmp, dir.join("new").join(name))
+}
+
+impl MailTransport {
+    pub fn send(&self, message: &Message) -> Result<(), AppError> {
+        match self {
+            Self::Smtp(transport) => transport.send(message).map(|_| ())?,
+            Self::File(transport) => transport.send(message).map(|_| ())?,
+            Self::Maildir(dir) => deliver_to_maildir(dir, message).map_err(AppError::other)?,
+            Self::Sendmail(transport) => transport.send(message)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_env(vars: &[(&str, &str)]) -> Result<TransportConfig, String> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        TransportConfig::from_env(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn smtp_is_the_default() {
+        assert_eq!(
+            from_env(&[
+                ("SMTP_HOST", "smtp.example.com"),
+                ("SMTP_USERNAME", "user"),
+                ("SMTP_PASSWORD", "password"),
+            ]),
+            Ok(TransportConfig::Smtp(SmtpConfig {
+                host: "smtp.example.com".to_string(),
+                port: None,
+                tls: SmtpTls::StartTls,
+                credentials: Some(("user".to_string(), "password".to_string())),
+                timeout: Duration::from_secs(15),
+            }))
+        );
+        assert_eq!(
+            from_env(&[
+                ("MAIL_TRANSPORT", "smtp"),
+                ("SMTP_HOST", "localhost"),
+                ("SMTP_TLS", "none"),
+                ("SMTP_PORT", "1025"),
+            ]),
+            Ok(TransportConfig::Smtp(SmtpConfig {
+                host: "localhost".to_string(),
+                port: Some(1025),
+                tls: SmtpTls::None,
+                credentials: None,
+                timeout: Duration::from_secs(15),
+            }))
+        );
+        assert!(from_env(&[("SMTP_HOST", "localhost"), ("SMTP_USERNAME", "user")]).is_err());
+        assert!(from_env(&[("SMTP_HOST", "localhost"), ("SMTP_TLS", "ssl")]).is_err());
+    }
+
+    #[test]
+    fn other_transports_are_selected_by_name() {
+        assert_eq!(
+            from_env(&[
+                ("MAIL_TRANSPORT", "maildir"),
+                ("MAIL_DIR", "/var/mail/stats")
+            ]),
+            Ok(TransportConfig::Maildir("/var/mail/stats".into()))
+        );
+        assert_eq!(
+            from_env(&[("MAIL_TRANSPORT", "sendmail")]),
+            Ok(TransportConfig::Sendmail(None))
+        );
+        assert!(from_env(&[("MAIL_TRANSPORT", "file")]).is_err());
+        assert!(from_env(&[("MAIL_TRANSPORT", "pigeon")]).is_err());
+    }
+
+    #[test]
+    fn maildir_messages_are_moved_to_new() {
+        let dir = std::env::temp_dir().join(format!("stat-collector-{}", Uuid::new_v4()));
+        let message = Message::builder()
+            .from("stats@example.com".parse().unwrap())
+            .to("supplier@example.com".parse().unwrap())
+            .subject("Statystyki")
+            .body("Prosimy o statystyki".to_string())
+            .unwrap();
+        let transport = TransportConfig::Maildir(dir.clone()).build().unwrap();
+        transport.send(&message).unwrap();
+
+        let delivered = fs::read_dir(dir.join("new"))
+            .unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(delivered.len(), 1);
+        assert!(delivered[0].contains("Subject: Statystyki"));
+        assert_eq!(fs::read_dir(dir.join("tmp")).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
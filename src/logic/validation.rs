@@ -0,0 +1,348 @@
+use crate::errors::AppError;
+use crate::json;
+use crate::json::sent::ValidationProblem;
+use crate::logic::formula::Formula;
+use crate::logic::periods::{validate_campaign, validate_periods, Periodicity};
+use crate::logic::reminders::validate_policy;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Problems found in a request body, which are reported all at once
+#[derive(Debug, Default)]
+pub struct Problems(Vec<ValidationProblem>);
+
+impl Problems {
+    /// `path` is a JSON pointer into the request body, like `/periods/1/endDate`
+    pub fn add(&mut self, path: impl ToString, message: impl ToString) {
+        self.0.push(ValidationProblem {
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(self.0))
+        }
+    }
+}
+
+/// Reports every item whose key was already used by an earlier item of the list
+fn check_unique<'a, T: 'a, K: Ord>(
+    problems: &mut Problems,
+    items: impl IntoIterator<Item = &'a T>,
+    key: impl Fn(&T) -> K,
+    path: impl Fn(usize) -> String,
+    what: &str,
+) {
+    let mut seen = BTreeMap::new();
+    for (i, item) in items.into_iter().enumerate() {
+        match seen.entry(key(item)) {
+            Entry::Occupied(first) => problems.add(
+                path(i),
+                format!("duplicate {}, already used at index {}", what, first.get()),
+            ),
+            Entry::Vacant(entry) => {
+                entry.insert(i);
+            }
+        }
+    }
+}
+
+/// Checks a create or update request before anything is stored
+pub fn validate_collector(collector: &json::received::StatCollector) -> Result<(), AppError> {
+    let mut problems = Problems::default();
+
+    if collector.name.trim().is_empty() {
+        problems.add("/name", "name must not be empty");
+    }
+    if collector.client.trim().is_empty() {
+        problems.add("/client", "client must not be empty");
+    }
+
+    if matches!(
+        collector.periodicity,
+        Periodicity::Weekly | Periodicity::Biweekly
+    ) && collector.weekday.is_none()
+    {
+        problems.add(
+            "/weekday",
+            format!("{} periods require a weekday", collector.periodicity),
+        );
+    }
+
+    match &collector.campaign {
+        Some(_) if !collector.periods.is_empty() => problems.add(
+            "/campaign",
+            "either the campaign or the periods can be given, not both",
+        ),
+        Some(campaign) => validate_campaign(collector.periodicity, campaign, &mut problems),
+        None => {
+            validate_periods(
+                collector.periodicity,
+                collector.weekday,
+                &collector.periods,
+                &mut problems,
+            );
+            check_unique(
+                &mut problems,
+                &collector.periods,
+                |period| period.name.clone(),
+                |i| format!("/periods/{}/name", i),
+                "period name",
+            );
+        }
+    }
+
+    check_unique(
+        &mut problems,
+        &collector.placement_types,
+        |placement_type| placement_type.name.clone(),
+        |i| format!("/placementTypes/{}/name", i),
+        "placement type name",
+    );
+
+    for (i, placement_type) in collector.placement_types.iter().enumerate() {
+        let path = format!("/placementTypes/{}", i);
+
+        if placement_type.name.trim().is_empty() {
+            problems.add(format!("{}/name", path), "name must not be empty");
+        }
+
+        for (list, items, ids, id_count) in [
+            (
+                "statistics",
+                &placement_type.statistics,
+                "statisticTypeIds",
+                placement_type.statistic_type_ids.len(),
+            ),
+            (
+                "copies",
+                &placement_type.copies,
+                "copyIds",
+                placement_type.copy_ids.len(),
+            ),
+        ] {
+            if items.is_empty() {
+                problems.add(format!("{}/{}", path, list), format!("no {} given", list));
+            }
+            if id_count != 0 && id_count != items.len() {
+                problems.add(
+                    format!("{}/{}", path, ids),
+                    format!("must have an id or null for each of the {}", list),
+                );
+            }
+            for (j, item) in items.iter().enumerate() {
+                if item.trim().is_empty() {
+                    problems.add(format!("{}/{}/{}", path, list, j), "name must not be empty");
+                }
+            }
+            check_unique(
+                &mut problems,
+                items,
+                |item| item.clone(),
+                |j| format!("{}/{}/{}", path, list, j),
+                "name",
+            );
+        }
+
+        for (j, supplier) in placement_type.suppliers.iter().enumerate() {
+            if supplier.name.trim().is_empty() {
+                problems.add(
+                    format!("{}/suppliers/{}/name", path, j),
+                    "name must not be empty",
+                );
+            }
+        }
+        check_unique(
+            &mut problems,
+            &placement_type.suppliers,
+            |supplier| supplier.mail.to_string().to_lowercase(),
+            |j| format!("{}/suppliers/{}/mail", path, j),
+            "supplier mail",
+        );
+    }
+
+    if let Some(policy) = &collector.reminder_policy {
+        validate_policy(policy, &mut problems);
+    }
+
+    let statistics = collector
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| &placement_type.statistics)
+        .map(String::as_str)
+        .collect::<BTreeSet<_>>();
+    let derived_statistic_types = collector
+        .derived_statistic_types
+        .as_deref()
+        .unwrap_or_default();
+    check_unique(
+        &mut problems,
+        derived_statistic_types,
+        |derived| derived.name.clone(),
+        |i| format!("/derivedStatisticTypes/{}/name", i),
+        "statistic type name",
+    );
+    for (i, derived) in derived_statistic_types.iter().enumerate() {
+        let path = format!("/derivedStatisticTypes/{}", i);
+
+        if derived.name.trim().is_empty() {
+            problems.add(format!("{}/name", path), "name must not be empty");
+        } else if statistics.contains(derived.name.as_str()) {
+            problems.add(
+                format!("{}/name", path),
+                "a statistic type with this name is already collected",
+            );
+        }
+
+        match derived.formula.parse::<Formula>() {
+            Ok(formula) => {
+                for name in formula.statistics() {
+                    if !statistics.contains(name) {
+                        problems.add(
+                            format!("{}/formula", path),
+                            format!("unknown statistic type {}", name),
+                        );
+                    }
+                }
+            }
+            Err(e) => problems.add(format!("{}/formula", path), e),
+        }
+    }
+
+    problems.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::derived::DerivedStatisticType;
+    use chrono::{NaiveDate, Weekday};
+
+    fn paths(collector: &json::received::StatCollector) -> Vec<String> {
+        match validate_collector(collector) {
+            Ok(()) => vec![],
+            Err(AppError::Validation(problems)) => {
+                problems.into_iter().map(|problem| problem.path).collect()
+            }
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    fn collector() -> json::received::StatCollector {
+        let date = |day| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        json::received::StatCollector {
+            name: "kolektor".to_string(),
+            client: "pepsi".to_string(),
+            periods: vec![
+                json::received::Period {
+                    id: None,
+                    name: "first".to_string(),
+                    start_date: date(1),
+                    end_date: date(7),
+                },
+                json::received::Period {
+                    id: None,
+                    name: "second".to_string(),
+                    start_date: date(8),
+                    end_date: date(14),
+                },
+            ],
+            placement_types: vec![json::received::PlacementType {
+                id: None,
+                name: "Display".to_string(),
+                suppliers: vec![json::received::Supplier {
+                    id: None,
+                    name: "Google".to_string(),
+                    mail: "google@google.com".parse().unwrap(),
+                    locale: None,
+                }],
+                statistics: vec!["Clicks".to_string()],
+                copies: vec!["kopia a".to_string()],
+                ..Default::default()
+            }],
+            periodicity: Periodicity::Weekly,
+            weekday: Some(Weekday::Wed),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_collectors_pass() {
+        assert_eq!(paths(&collector()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut collector = collector();
+        collector.name = " ".to_string();
+        collector.periods[1].start_date = collector.periods[0].end_date;
+        collector.periods[1].name = "first".to_string();
+        let mut duplicate = collector.placement_types[0].clone();
+        duplicate.copies.clear();
+        duplicate.statistic_type_ids = vec![None, None];
+        duplicate.suppliers.push(duplicate.suppliers[0].clone());
+        collector.placement_types.push(duplicate);
+
+        assert_eq!(
+            paths(&collector),
+            vec![
+                "/name",
+                "/periods/1/startDate",
+                "/periods/1",
+                "/periods/1/name",
+                "/placementTypes/1/name",
+                "/placementTypes/1/statisticTypeIds",
+                "/placementTypes/1/copies",
+                "/placementTypes/1/suppliers/1/mail",
+            ]
+        );
+    }
+
+    #[test]
+    fn derived_statistic_types_use_collected_ones() {
+        let mut collector = collector();
+        let derived = |name: &str, formula: &str| DerivedStatisticType {
+            name: name.to_string(),
+            formula: formula.to_string(),
+        };
+        collector.derived_statistic_types = Some(vec![
+            derived("CTR", "Clicks / Displays"),
+            derived("Clicks", "Clicks * 2"),
+            derived("Double", "Clicks *"),
+        ]);
+        collector.placement_types[0]
+            .statistics
+            .push("Displays".to_string());
+        assert_eq!(
+            paths(&collector),
+            vec![
+                "/derivedStatisticTypes/1/name",
+                "/derivedStatisticTypes/2/formula"
+            ]
+        );
+
+        collector.placement_types[0].statistics.pop();
+        assert_eq!(
+            paths(&collector),
+            vec![
+                "/derivedStatisticTypes/0/formula",
+                "/derivedStatisticTypes/1/name",
+                "/derivedStatisticTypes/2/formula"
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_collectors_need_a_weekday() {
+        let mut collector = collector();
+        collector.weekday = None;
+        assert_eq!(paths(&collector), vec!["/weekday"]);
+
+        collector.periodicity = Periodicity::Custom;
+        assert_eq!(paths(&collector), Vec::<String>::new());
+    }
+}
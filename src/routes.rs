@@ -1,5 +1,6 @@
 use maud::{html, Markup};
 
+pub mod email_template;
 pub mod statistics_collector;
 pub mod supplier;
 
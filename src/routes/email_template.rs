@@ -0,0 +1,275 @@
+use crate::extract::{Json, Path};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use diesel::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::email_templates::{
+    current_template, delete_image, load_current_templates, load_images, load_version,
+    load_versions, preview, save_image, save_version,
+};
+use crate::logic::locale::Locale;
+use crate::logic::outbox::OutboxKind;
+use crate::logic::time::Clock;
+
+/// Lists the templates which are sent for every kind of email and language
+#[utoipa::path(
+    get,
+    path = "/email_template",
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = [EmailTemplate]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
+    )
+)]
+pub async fn list_email_templates(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+) -> Result<Json<Vec<json::sent::EmailTemplate>>, AppError> {
+    let conn = pool.get().await?;
+    let templates = conn.interact(load_current_templates).await??;
+    Ok(Json(templates))
+}
+
+/// The template which is sent, version 0 is the built-in one
+#[utoipa::path(
+    get,
+    path = "/email_template/{kind}/{locale}",
+    params(
+        ("kind" = OutboxKind, Path, description = "Kind of email"),
+        ("locale" = Locale, Path, description = "Language of the email")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = EmailTemplate),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
+    )
+)]
+pub async fn get_email_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path((kind, locale)): Path<(OutboxKind, Locale)>,
+) -> Result<Json<json::sent::EmailTemplate>, AppError> {
+    let conn = pool.get().await?;
+    let template = conn
+        .interact(move |conn| current_template(conn, kind, locale))
+        .await??;
+    Ok(Json(template))
+}
+
+/// Saves a new version of the template, which is sent from now on. Earlier versions are kept.
+#[utoipa::path(
+    put,
+    path = "/email_template/{kind}/{locale}",
+    params(
+        ("kind" = OutboxKind, Path, description = "Kind of email"),
+        ("locale" = Locale, Path, description = "Language of the email")
+    ),
+    request_body = EmailTemplateContent,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Number of the new version"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 422, description = "Empty subject, unknown placeholders or images", body = ErrorResponse)
+    )
+)]
+pub async fn save_email_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path((kind, locale)): Path<(OutboxKind, Locale)>,
+    Json(content): Json<json::received::EmailTemplateContent>,
+) -> Result<Json<i32>, AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    let version = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| save_version(conn, kind, locale, &content, now))
+        })
+        .await??;
+    Ok(Json(version))
+}
+
+/// Lists the saved versions of the template, newest first
+#[utoipa::path(
+    get,
+    path = "/email_template/{kind}/{locale}/versions",
+    params(
+        ("kind" = OutboxKind, Path, description = "Kind of email"),
+        ("locale" = Locale, Path, description = "Language of the email")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = [EmailTemplate]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
+    )
+)]
+pub async fn list_email_template_versions(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path((kind, locale)): Path<(OutboxKind, Locale)>,
+) -> Result<Json<Vec<json::sent::EmailTemplate>>, AppError> {
+    let conn = pool.get().await?;
+    let versions = conn
+        .interact(move |conn| load_versions(conn, kind, locale))
+        .await??;
+    Ok(Json(versions))
+}
+
+/// Saves an earlier version, or the built-in template for version 0, as a new version
+#[utoipa::path(
+    post,
+    path = "/email_template/{kind}/{locale}/versions/{version}/restore",
+    params(
+        ("kind" = OutboxKind, Path, description = "Kind of email"),
+        ("locale" = Locale, Path, description = "Language of the email"),
+        ("version" = i32, Path, description = "Version to restore")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Number of the new version"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such version", body = ErrorResponse),
+        (status = 422, description = "The version shows an image which was deleted", body = ErrorResponse)
+    )
+)]
+pub async fn restore_email_template_version(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path((kind, locale, version)): Path<(OutboxKind, Locale, i32)>,
+) -> Result<Json<i32>, AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    let version = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let restored = load_version(conn, kind, locale, version)?;
+                let content = json::received::EmailTemplateContent {
+                    subject: restored.subject,
+                    body: restored.body,
+                };
+                save_version(conn, kind, locale, &content, now)
+            })
+        })
+        .await??;
+    Ok(Json(version))
+}
+
+/// Renders the template for a supplier of a collector without sending it.
+/// Unsaved content can be previewed too, the link in reminders has no valid token.
+#[utoipa::path(
+    post,
+    path = "/email_template/{kind}/{locale}/preview",
+    params(
+        ("kind" = OutboxKind, Path, description = "Kind of email"),
+        ("locale" = Locale, Path, description = "Language of the email")
+    ),
+    request_body = EmailPreviewRequest,
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = EmailPreview),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such supplier or period", body = ErrorResponse),
+        (status = 422, description = "The content is invalid, every problem is listed", body = ErrorResponse)
+    )
+)]
+pub async fn preview_email_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path((kind, locale)): Path<(OutboxKind, Locale)>,
+    Json(request): Json<json::received::EmailPreviewRequest>,
+) -> Result<Json<json::sent::EmailPreview>, AppError> {
+    let today = clock.lock().unwrap().now().date_naive();
+    let conn = pool.get().await?;
+    let preview = conn
+        .interact(move |conn| preview(conn, kind, locale, &request, today))
+        .await??;
+    Ok(Json(preview))
+}
+
+/// Lists the images templates can show, uploaded ones first
+#[utoipa::path(
+    get,
+    path = "/email_image",
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = [EmailImage]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
+    )
+)]
+pub async fn list_email_images(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+) -> Result<Json<Vec<json::sent::EmailImage>>, AppError> {
+    let conn = pool.get().await?;
+    let images = conn.interact(load_images).await??;
+    Ok(Json(images))
+}
+
+/// Uploads an image for templates to show with `{{image:name}}`, replacing one with the same name.
+/// The request body is the image, with its `Content-Type`.
+#[utoipa::path(
+    put,
+    path = "/email_image/{name}",
+    params(
+        ("name" = String, Path, description = "Name of the image")
+    ),
+    request_body(content = Vec<u8>, content_type = "image/*", description = "The image"),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 422, description = "Not an image or an invalid name", body = ErrorResponse)
+    )
+)]
+pub async fn upload_email_image(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    data: Bytes,
+) -> Result<(), AppError> {
+    let now = clock.lock().unwrap().now();
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let conn = pool.get().await?;
+    conn.interact(move |conn| save_image(conn, &name, &content_type, data.to_vec(), now))
+        .await??;
+    Ok(())
+}
+
+/// Deletes an uploaded image, a built-in image with the same name is shown again
+#[utoipa::path(
+    delete,
+    path = "/email_image/{name}",
+    params(
+        ("name" = String, Path, description = "Name of the image")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 400, description = "A current template shows the image", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such uploaded image", body = ErrorResponse)
+    )
+)]
+pub async fn delete_email_image(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(name): Path<String>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| conn.transaction(|conn| delete_image(conn, &name)))
+        .await??;
+    Ok(())
+}
@@ -1,6 +1,16 @@
+pub mod archive;
+pub mod clone;
 pub mod config;
 pub mod create;
 pub mod delete;
 pub mod email;
+pub mod export;
+pub mod history;
 pub mod list;
+pub mod outbox;
 pub mod show;
+pub mod summary;
+pub mod supplier;
+pub mod template;
+pub mod token;
+pub mod update;
@@ -0,0 +1,65 @@
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::extract::Path;
+use axum::extract::State;
+use std::sync::{Arc, Mutex};
+
+use diesel::prelude::*;
+
+use crate::logic::archive::set_archived;
+use crate::logic::time::Clock;
+
+/// Archives a statistics collector: it's hidden from the list, sends no reminders anymore
+/// and its unsent emails are cancelled. Its statistics can still be read.
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/archive",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn archive_statistics_collector(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(id): Path<StatCollectorId>,
+) -> Result<(), AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    conn.interact(move |conn| conn.transaction(|conn| set_archived(conn, id, Some(now))))
+        .await??;
+
+    Ok(())
+}
+
+/// Brings an archived statistics collector back, its reminders are sent again
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/unarchive",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn unarchive_statistics_collector(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| conn.transaction(|conn| set_archived(conn, id, None)))
+        .await??;
+
+    Ok(())
+}
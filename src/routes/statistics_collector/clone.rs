@@ -0,0 +1,56 @@
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use diesel::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::periods::resolve_periods;
+use crate::logic::templates::{collector_structure, instantiate};
+use crate::logic::time::Clock;
+use crate::logic::validation::validate_collector;
+use crate::routes::statistics_collector::config::load_collector_config;
+use crate::routes::statistics_collector::create::insert_collector;
+
+/// Creates a new collector with the placement types, suppliers, statistic types, copies
+/// and reminder policy of an existing one. No statistics are copied.
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/clone",
+    params(
+        ("id" = Uuid, Path, description = "Id of the statistics collector to copy")
+    ),
+    request_body = CloneRequest,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Id of the new collector"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse),
+        (status = 409, description = "A collector with this name and client exists, its id is given", body = ErrorResponse),
+        (status = 422, description = "Invalid collector, every problem is listed", body = ErrorResponse)
+    )
+)]
+pub async fn clone_statistics_collector(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(id): Path<StatCollectorId>,
+    Json(request): Json<json::received::CloneRequest>,
+) -> Result<Json<StatCollectorId>, AppError> {
+    let now = clock.lock().unwrap().now();
+
+    let conn = pool.get().await?;
+    let id = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let structure = collector_structure(load_collector_config(conn, id)?);
+                let collector = instantiate(structure, request)?;
+                validate_collector(&collector)?;
+                let periods = resolve_periods(&collector)?;
+                insert_collector(conn, &collector, &periods, now)
+            })
+        })
+        .await??;
+    Ok(Json(id))
+}
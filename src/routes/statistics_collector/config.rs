@@ -1,23 +1,35 @@
 use crate::db::StatCollectorId;
 use crate::errors::AppError;
+use crate::extract::{Json, Path};
+use crate::logic::archive::find_collector;
+use crate::logic::periods::Periodicity;
+use crate::logic::reminders::load_policy;
+use crate::logic::submissions::load_completion;
+use crate::logic::summary::load_derived_statistic_types;
 use crate::{db, json, schema};
-use axum::extract::{Path, State};
-use axum::Json;
+use anyhow::anyhow;
+use axum::extract::State;
+use chrono::Weekday;
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
 use diesel::prelude::*;
 use itertools::Itertools;
 
-/// Returns the same json as the one used to create the statistics collector
+/// Returns the same json as the one used to create the statistics collector,
+/// along with the statistics and how complete they are
 #[utoipa::path(
     get,
     path = "/statistics_collector/{collector_id}/config",
     params(
         ("collector_id" = Uuid, Path, description = "Statistics collector id")
     ),
+    security(("api_key" = ["read"])),
     responses(
         (status = 200, response = json::sent::StatCollector),
-        (status = 404, description = "No such id", content_type = "text/html")
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
     )
 )]
 pub async fn get_collector_config(
@@ -26,113 +38,133 @@ pub async fn get_collector_config(
 ) -> Result<Json<json::sent::StatCollector>, AppError> {
     let conn = pool.get().await?;
     let map = conn
-        .interact(move |conn| {
-            let collector = schema::statistics_collectors::table
-                .find(collector_id)
-                .first::<db::StatisticsCollector>(conn)
-                .map_err(|_| AppError::not_found("collector", collector_id))?;
-
-            let periods = schema::periods::table
-                .filter(schema::periods::statistics_collector_id.eq(collector_id))
-                .load::<db::Period>(conn)?
-                .into_iter()
-                .sorted_by_key(|period| period.start)
-                .map(|period| period.as_json())
-                .collect_vec();
-
-            let periods_sort_keys = periods
-                .iter()
-                .enumerate()
-                .map(|(i, period)| (period.id, i))
-                .collect::<BTreeMap<_, _>>();
-
-            let placement_types = schema::placement_types::table
-                .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
-                .load::<db::PlacementType>(conn)?;
+        .interact(move |conn| load_collector_config(conn, collector_id))
+        .await??;
 
-            let mut json_placement_types = Vec::new();
+    Ok(Json(map))
+}
 
-            for placement_type in placement_types {
-                let suppliers = schema::suppliers::table
-                    .filter(schema::suppliers::placement_type_id.eq(placement_type.id))
-                    .load::<db::Supplier>(conn)?;
+/// Loads the collector with all of its statistics in the shape returned by [`get_collector_config`]
+pub fn load_collector_config(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+) -> Result<json::sent::StatCollector, AppError> {
+    let collector = find_collector(conn, collector_id)?;
+
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector_id))
+        .load::<db::Period>(conn)?
+        .into_iter()
+        .sorted_by_key(|period| period.start)
+        .map(|period| period.as_json())
+        .collect_vec();
+
+    let periods_sort_keys = periods
+        .iter()
+        .enumerate()
+        .map(|(i, period)| (period.id, i))
+        .collect::<BTreeMap<_, _>>();
+
+    let placement_types = schema::placement_types::table
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .load::<db::PlacementType>(conn)?;
+
+    let mut json_placement_types = Vec::new();
+
+    for placement_type in placement_types {
+        let suppliers = schema::suppliers::table
+            .filter(schema::suppliers::placement_type_id.eq(placement_type.id))
+            .load::<db::Supplier>(conn)?;
+
+        let copies = schema::copies::table
+            .filter(schema::copies::placement_type_id.eq(placement_type.id))
+            .load::<db::Copy>(conn)?
+            .into_iter()
+            .sorted_by_key(|copy| copy.id)
+            .collect_vec();
+
+        let stat_types = schema::statistic_types::table
+            .filter(schema::statistic_types::placement_type_id.eq(placement_type.id))
+            .load::<db::StatisticType>(conn)?
+            .into_iter()
+            .sorted_by_key(|statistic_type| statistic_type.id)
+            .collect_vec();
+
+        let mut suppliers_json = Vec::new();
+
+        for supplier in suppliers {
+            let stats = schema::statistics::table
+                .filter(schema::statistics::supplier_id.eq(supplier.id))
+                .select(db::Statistic::as_select())
+                .load(conn)?;
+
+            let mut stat_types_json = Vec::new();
+
+            for (_, grouped_by_stat_type) in stats
+                .into_iter()
+                .sorted_by_key(|s| s.statistic_type_id)
+                .group_by(|s| s.statistic_type_id)
+                .into_iter()
+            {
+                let mut copies_json = Vec::new();
 
-                let copies = schema::copies::table
-                    .filter(schema::copies::placement_type_id.eq(placement_type.id))
-                    .load::<db::Copy>(conn)?
+                for (_, grouped_by_copy) in grouped_by_stat_type
                     .into_iter()
-                    .sorted_by_key(|copy| copy.id)
-                    .map(|copy| copy.as_json())
-                    .collect_vec();
-
-                let stat_types = schema::statistic_types::table
-                    .filter(schema::statistic_types::placement_type_id.eq(placement_type.id))
-                    .load::<db::StatisticType>(conn)?
+                    .sorted_by_key(|s| s.copy_id)
+                    .group_by(|s| s.copy_id)
                     .into_iter()
-                    .sorted_by_key(|statistic_type| statistic_type.id)
-                    .map(|statistic_type| statistic_type.as_json())
-                    .collect_vec();
-
-                let mut suppliers_json = Vec::new();
-
-                for supplier in suppliers {
-                    let stats = schema::statistics::table
-                        .filter(schema::statistics::supplier_id.eq(supplier.id))
-                        .select(db::Statistic::as_select())
-                        .load(conn)?;
-
-                    let mut stat_types_json = Vec::new();
-
-                    for (_, grouped_by_stat_type) in stats
+                {
+                    let stats = grouped_by_copy
                         .into_iter()
-                        .sorted_by_key(|s| s.statistic_type_id)
-                        .group_by(|s| s.statistic_type_id)
-                        .into_iter()
-                    {
-                        let mut copies_json = Vec::new();
-
-                        for (_, grouped_by_copy) in grouped_by_stat_type
-                            .into_iter()
-                            .sorted_by_key(|s| s.copy_id)
-                            .group_by(|s| s.copy_id)
-                            .into_iter()
-                        {
-                            let stats = grouped_by_copy
-                                .into_iter()
-                                .sorted_by_key(|s| periods_sort_keys[&s.period_id])
-                                .map(|s| s.value)
-                                .collect::<Vec<_>>();
-
-                            copies_json.push(stats);
-                        }
-                        stat_types_json.push(copies_json);
-                    }
-
-                    suppliers_json.push(supplier.as_json(stat_types_json))
-                }
-
-                let placement_type = json::sent::PlacementType {
-                    id: placement_type.id,
-                    name: placement_type.name.clone(),
-                    suppliers: suppliers_json,
-                    copies,
-                    statistics: stat_types,
-                };
+                        .sorted_by_key(|s| periods_sort_keys[&s.period_id])
+                        .map(|s| s.value)
+                        .collect::<Vec<_>>();
 
-                json_placement_types.push(placement_type);
+                    copies_json.push(stats);
+                }
+                stat_types_json.push(copies_json);
             }
 
-            Ok::<_, AppError>(json::sent::StatCollector {
-                id: collector.id,
-                name: collector.name,
-                client: collector.client,
-                periodicity: collector.periodicity,
-                weekday: collector.weekday,
-                periods,
-                placement_types: json_placement_types,
-            })
-        })
-        .await??;
-
-    Ok(Json(map))
+            suppliers_json.push(supplier.as_json(stat_types_json))
+        }
+
+        let placement_type = json::sent::PlacementType {
+            id: placement_type.id,
+            name: placement_type.name.clone(),
+            suppliers: suppliers_json,
+            copies: copies.iter().map(db::Copy::as_json).collect(),
+            statistics: stat_types.iter().map(db::StatisticType::as_json).collect(),
+            copy_ids: copies.iter().map(|copy| copy.id).collect(),
+            statistic_type_ids: stat_types
+                .iter()
+                .map(|statistic_type| statistic_type.id)
+                .collect(),
+        };
+
+        json_placement_types.push(placement_type);
+    }
+
+    let reminder_policy = load_policy(conn, &collector)?;
+    let derived_statistic_types = load_derived_statistic_types(conn, collector_id)?;
+    let completion = load_completion(conn, collector_id)?;
+    let locale = collector.locale();
+
+    Ok(json::sent::StatCollector {
+        id: collector.id,
+        name: collector.name,
+        client: collector.client,
+        periodicity: Periodicity::from_str(&collector.periodicity)
+            .map_err(|e| AppError::other(anyhow!(e)))?,
+        weekday: collector
+            .weekday
+            .map(|weekday| Weekday::from_str(&weekday))
+            .transpose()
+            .map_err(AppError::other)?,
+        periods,
+        placement_types: json_placement_types,
+        reminder_policy,
+        derived_statistic_types,
+        locale,
+        completion,
+    })
 }
@@ -1,21 +1,34 @@
-use axum::{extract::State, response::Json};
+use crate::extract::Json;
+use axum::extract::State;
+use chrono::{DateTime, Local};
 use diesel::prelude::*;
 use std::sync::{Arc, Mutex};
 
 use crate::db::{CopyId, PeriodId, PlacementTypeId, StatCollectorId, StatisticTypeId, SupplierId};
 
 use crate::errors::AppError;
+use crate::json::weekday_serde;
+use crate::logic::archive::find_duplicate;
+use crate::logic::periods::resolve_periods;
+use crate::logic::reminders::save_policy;
+use crate::logic::summary::save_derived_statistic_types;
+use crate::logic::validation::validate_collector;
 use crate::{db, json, schema};
 
 use crate::logic::time::Clock;
 
-/// Creates a new statistics collector
+/// Creates a new statistics collector. The periods are generated if a campaign is given instead of them.
 #[utoipa::path(
     post,
     path = "/statistics_collector",
     request_body = StatisticsCollector,
+    security(("api_key" = ["write"])),
     responses(
         (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 409, description = "A collector with this name and client exists, its id is given", body = ErrorResponse),
+        (status = 422, description = "Invalid collector, every problem is listed", body = ErrorResponse)
     )
 )]
 pub async fn create_statistics_collector(
@@ -23,208 +36,228 @@ pub async fn create_statistics_collector(
     State(clock): State<Arc<Mutex<dyn Clock>>>,
     Json(statistics_collector): Json<json::received::StatCollector>,
 ) -> Result<Json<StatCollectorId>, AppError> {
+    validate_collector(&statistics_collector)?;
+    let periods = resolve_periods(&statistics_collector)?;
+    let now = clock.lock().unwrap().now();
+
     let conn = pool.get().await?;
     let id = conn
         .interact(move |conn| {
-            conn.transaction(|conn| {
-                let collector_id = StatCollectorId::new();
-
-                let db_statistics_collector = db::StatisticsCollector {
-                    id: collector_id,
-                    periodicity: statistics_collector.periodicity.clone(),
-                    weekday: statistics_collector.weekday.clone(),
-                    name: statistics_collector.name.clone(),
-                    client: statistics_collector.client.clone(),
-                };
-
-                // Ensure that (name, client) tuple is unique
-                let existing = schema::statistics_collectors::table
-                    .select(schema::statistics_collectors::id)
-                    .filter(
-                        schema::statistics_collectors::name
-                            .eq(&db_statistics_collector.name)
-                            .and(
-                                schema::statistics_collectors::client
-                                    .eq(&db_statistics_collector.client),
-                            ),
-                    )
-                    .first::<StatCollectorId>(conn)
-                    .optional()?;
-
-                if let Some(existing) = existing {
-                    return Err(AppError::Conflict {
-                        resource: format!(
-                            "statistics collector with name {} and client {}",
-                            db_statistics_collector.name, db_statistics_collector.client
-                        ),
-                        id: existing.to_string(),
-                    });
-                }
-
-                diesel::insert_into(schema::statistics_collectors::table)
-                    .values(&db_statistics_collector)
-                    .execute(conn)?;
-
-                let db_periods = statistics_collector
-                    .periods
-                    .iter()
-                    .map(|period| db::Period {
-                        id: PeriodId::new(),
-                        name: period.name.clone(),
-                        start: period.start_date,
-                        end: period.end_date,
-                        statistics_collector_id: collector_id,
-                    })
-                    .collect::<Vec<db::Period>>();
-
-                diesel::insert_into(schema::periods::table)
-                    .values(&db_periods)
-                    .execute(conn)?;
-
-                let db_placement_types = statistics_collector
-                    .placement_types
-                    .iter()
-                    .map(|placement_type| db::PlacementType {
-                        id: PlacementTypeId::new(),
-                        name: placement_type.name.clone(),
-                        statistics_collector_id: collector_id,
-                    })
-                    .collect::<Vec<db::PlacementType>>();
-
-                let db_placement_types = diesel::insert_into(schema::placement_types::table)
-                    .values(&db_placement_types)
-                    .get_results::<db::PlacementType>(conn)?;
-
-                let db_suppliers = statistics_collector
-                    .placement_types
-                    .iter()
-                    .flat_map(|placement_type| {
-                        placement_type
-                            .suppliers
-                            .iter()
-                            .map(|supplier| {
-                                let placement_type_id = db_placement_types
-                                    .iter()
-                                    .find(|db_placement_type| {
-                                        db_placement_type.name == placement_type.name
-                                    })
-                                    .unwrap()
-                                    .id;
-                                db::Supplier {
-                                    id: SupplierId::new(),
-                                    name: supplier.name.clone(),
-                                    mail: supplier.mail.to_string(),
-                                    placement_type_id,
-                                    submitted_date: clock.lock().unwrap().now(),
-                                }
-                            })
-                            .collect::<Vec<db::Supplier>>()
-                    })
-                    .collect::<Vec<db::Supplier>>();
-
-                let _db_suppliers = diesel::insert_into(schema::suppliers::table)
-                    .values(&db_suppliers)
-                    .get_results::<db::Supplier>(conn)?;
-
-                let db_statistic_types = statistics_collector
-                    .placement_types
-                    .iter()
-                    .flat_map(|placement_type| {
-                        placement_type
-                            .statistics
-                            .iter()
-                            .map(|statistic| {
-                                let placement_type_id = db_placement_types
-                                    .iter()
-                                    .find(|db_placement_type| {
-                                        db_placement_type.name == placement_type.name
-                                    })
-                                    .unwrap()
-                                    .id;
-                                db::StatisticType {
-                                    id: StatisticTypeId::new(),
-                                    name: statistic.clone(),
-                                    placement_type_id,
-                                }
-                            })
-                            .collect::<Vec<db::StatisticType>>()
-                    })
-                    .collect::<Vec<db::StatisticType>>();
-
-                diesel::insert_into(schema::statistic_types::table)
-                    .values(&db_statistic_types)
-                    .execute(conn)?;
-
-                let db_copies = statistics_collector
-                    .placement_types
-                    .iter()
-                    .flat_map(|placement_type| {
-                        placement_type
-                            .copies
-                            .iter()
-                            .map(|copy| {
-                                let placement_type_id = db_placement_types
-                                    .iter()
-                                    .find(|db_placement_type| {
-                                        db_placement_type.name == placement_type.name
-                                    })
-                                    .unwrap()
-                                    .id;
-                                db::Copy {
-                                    id: CopyId::new(),
-                                    name: copy.clone(),
-                                    placement_type_id,
-                                }
-                            })
-                            .collect::<Vec<db::Copy>>()
-                    })
-                    .collect::<Vec<db::Copy>>();
-
-                diesel::insert_into(schema::copies::table)
-                    .values(&db_copies)
-                    .execute(conn)?;
-
-                // for each period, for supplier, for each of supplier's statistic types, for each of supplier's copies
-                let db_statistics = db_periods
-                    .iter()
-                    .flat_map(|period| {
-                        db_suppliers
-                            .iter()
-                            .flat_map(|supplier| {
-                                db_statistic_types
-                                    .iter()
-                                    .filter(|statistic_type| {
-                                        statistic_type.placement_type_id
-                                            == supplier.placement_type_id
-                                    })
-                                    .flat_map(|statistic_type| {
-                                        db_copies
-                                            .iter()
-                                            .filter(|copy| {
-                                                copy.placement_type_id == supplier.placement_type_id
-                                            })
-                                            .map(|copy| db::Statistic {
-                                                value: 0,
-                                                period_id: period.id,
-                                                supplier_id: supplier.id,
-                                                statistic_type_id: statistic_type.id,
-                                                copy_id: copy.id,
-                                            })
-                                            .collect::<Vec<db::Statistic>>()
-                                    })
-                                    .collect::<Vec<db::Statistic>>()
-                            })
-                            .collect::<Vec<db::Statistic>>()
-                    })
-                    .collect::<Vec<db::Statistic>>();
-
-                diesel::insert_into(schema::statistics::table)
-                    .values(&db_statistics)
-                    .execute(conn)?;
-
-                Ok(collector_id)
-            })
+            conn.transaction(|conn| insert_collector(conn, &statistics_collector, &periods, now))
         })
         .await??;
     Ok(Json(id))
 }
+
+/// Stores a validated collector with empty statistics, `periods` are its resolved periods
+pub fn insert_collector(
+    conn: &mut PgConnection,
+    statistics_collector: &json::received::StatCollector,
+    periods: &[json::received::Period],
+    now: DateTime<Local>,
+) -> Result<StatCollectorId, AppError> {
+    let collector_id = StatCollectorId::new();
+
+    let db_statistics_collector = db::StatisticsCollector {
+        id: collector_id,
+        periodicity: statistics_collector.periodicity.to_string(),
+        weekday: statistics_collector
+            .weekday
+            .map(|weekday| weekday_serde::name(weekday).to_string()),
+        name: statistics_collector.name.clone(),
+        client: statistics_collector.client.clone(),
+        account_manager_mail: None,
+        created_at: now,
+        archived_at: None,
+        deleted_at: None,
+        locale: statistics_collector.locale.unwrap_or_default().to_string(),
+    };
+
+    // Ensure that (name, client) tuple is unique
+    let existing = find_duplicate(
+        conn,
+        None,
+        &db_statistics_collector.name,
+        &db_statistics_collector.client,
+    )?;
+
+    if let Some(existing) = existing {
+        return Err(AppError::Conflict {
+            resource: format!(
+                "statistics collector with name {} and client {}",
+                db_statistics_collector.name, db_statistics_collector.client
+            ),
+            id: existing.to_string(),
+        });
+    }
+
+    diesel::insert_into(schema::statistics_collectors::table)
+        .values(&db_statistics_collector)
+        .execute(conn)?;
+
+    save_policy(
+        conn,
+        collector_id,
+        &statistics_collector
+            .reminder_policy
+            .clone()
+            .unwrap_or_default(),
+    )?;
+    save_derived_statistic_types(
+        conn,
+        collector_id,
+        statistics_collector
+            .derived_statistic_types
+            .as_deref()
+            .unwrap_or_default(),
+    )?;
+
+    let db_periods = periods
+        .iter()
+        .map(|period| db::Period {
+            id: PeriodId::new(),
+            name: period.name.clone(),
+            start: period.start_date,
+            end: period.end_date,
+            statistics_collector_id: collector_id,
+        })
+        .collect::<Vec<db::Period>>();
+
+    diesel::insert_into(schema::periods::table)
+        .values(&db_periods)
+        .execute(conn)?;
+
+    let db_placement_types = statistics_collector
+        .placement_types
+        .iter()
+        .map(|placement_type| db::PlacementType {
+            id: PlacementTypeId::new(),
+            name: placement_type.name.clone(),
+            statistics_collector_id: collector_id,
+        })
+        .collect::<Vec<db::PlacementType>>();
+
+    let db_placement_types = diesel::insert_into(schema::placement_types::table)
+        .values(&db_placement_types)
+        .get_results::<db::PlacementType>(conn)?;
+
+    let db_suppliers = statistics_collector
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| {
+            placement_type
+                .suppliers
+                .iter()
+                .map(|supplier| {
+                    let placement_type_id = db_placement_types
+                        .iter()
+                        .find(|db_placement_type| db_placement_type.name == placement_type.name)
+                        .unwrap()
+                        .id;
+                    db::Supplier {
+                        id: SupplierId::new(),
+                        name: supplier.name.clone(),
+                        mail: supplier.mail.to_string(),
+                        placement_type_id,
+                        locale: supplier.locale.map(|locale| locale.to_string()),
+                    }
+                })
+                .collect::<Vec<db::Supplier>>()
+        })
+        .collect::<Vec<db::Supplier>>();
+
+    let _db_suppliers = diesel::insert_into(schema::suppliers::table)
+        .values(&db_suppliers)
+        .get_results::<db::Supplier>(conn)?;
+
+    let db_statistic_types = statistics_collector
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| {
+            placement_type
+                .statistics
+                .iter()
+                .map(|statistic| {
+                    let placement_type_id = db_placement_types
+                        .iter()
+                        .find(|db_placement_type| db_placement_type.name == placement_type.name)
+                        .unwrap()
+                        .id;
+                    db::StatisticType {
+                        id: StatisticTypeId::new(),
+                        name: statistic.clone(),
+                        placement_type_id,
+                    }
+                })
+                .collect::<Vec<db::StatisticType>>()
+        })
+        .collect::<Vec<db::StatisticType>>();
+
+    diesel::insert_into(schema::statistic_types::table)
+        .values(&db_statistic_types)
+        .execute(conn)?;
+
+    let db_copies = statistics_collector
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| {
+            placement_type
+                .copies
+                .iter()
+                .map(|copy| {
+                    let placement_type_id = db_placement_types
+                        .iter()
+                        .find(|db_placement_type| db_placement_type.name == placement_type.name)
+                        .unwrap()
+                        .id;
+                    db::Copy {
+                        id: CopyId::new(),
+                        name: copy.clone(),
+                        placement_type_id,
+                    }
+                })
+                .collect::<Vec<db::Copy>>()
+        })
+        .collect::<Vec<db::Copy>>();
+
+    diesel::insert_into(schema::copies::table)
+        .values(&db_copies)
+        .execute(conn)?;
+
+    // for each period, for supplier, for each of supplier's statistic types, for each of supplier's copies
+    let db_statistics = db_periods
+        .iter()
+        .flat_map(|period| {
+            db_suppliers
+                .iter()
+                .flat_map(|supplier| {
+                    db_statistic_types
+                        .iter()
+                        .filter(|statistic_type| {
+                            statistic_type.placement_type_id == supplier.placement_type_id
+                        })
+                        .flat_map(|statistic_type| {
+                            db_copies
+                                .iter()
+                                .filter(|copy| copy.placement_type_id == supplier.placement_type_id)
+                                .map(|copy| db::Statistic {
+                                    value: None,
+                                    period_id: period.id,
+                                    supplier_id: supplier.id,
+                                    statistic_type_id: statistic_type.id,
+                                    copy_id: copy.id,
+                                })
+                                .collect::<Vec<db::Statistic>>()
+                        })
+                        .collect::<Vec<db::Statistic>>()
+                })
+                .collect::<Vec<db::Statistic>>()
+        })
+        .collect::<Vec<db::Statistic>>();
+
+    diesel::insert_into(schema::statistics::table)
+        .values(&db_statistics)
+        .execute(conn)?;
+
+    Ok(collector_id)
+}
@@ -1,35 +1,65 @@
 use crate::db::StatCollectorId;
 use crate::errors::AppError;
-use axum::extract::{Path, State};
+use crate::extract::Path;
+use axum::extract::State;
+use std::sync::{Arc, Mutex};
 
 use diesel::prelude::*;
 
-use crate::schema;
+use crate::logic::archive::{delete_collector, restore_collector};
+use crate::logic::time::Clock;
 
-/// Deletes a statistics collector
+/// Deletes a statistics collector. It can be restored until it's purged after the retention period.
 #[utoipa::path(
     delete,
     path = "/statistics_collector/{id}",
     params(
         ("id" = Uuid, Path, description = "Statistics collector id")
     ),
+    security(("api_key" = ["write"])),
     responses(
         (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id, or the collector is already deleted", body = ErrorResponse)
     )
 )]
 pub async fn delete_statistics_collector(
     State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
     Path(id): Path<StatCollectorId>,
 ) -> Result<(), AppError> {
+    let now = clock.lock().unwrap().now();
     let conn = pool.get().await?;
-    conn.interact(move |conn| {
-        diesel::delete(schema::statistics_collectors::table)
-            .filter(schema::statistics_collectors::id.eq(id))
-            .execute(conn)?;
+    conn.interact(move |conn| conn.transaction(|conn| delete_collector(conn, id, now)))
+        .await??;
 
-        Ok::<_, diesel::result::Error>(())
-    })
-    .await??;
+    Ok(())
+}
+
+/// Restores a deleted statistics collector which wasn't purged yet
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/restore",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id, or the collector was purged", body = ErrorResponse),
+        (status = 409, description = "Another collector with the same name and client exists", body = ErrorResponse)
+    )
+)]
+pub async fn restore_statistics_collector(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| conn.transaction(|conn| restore_collector(conn, id)))
+        .await??;
 
     Ok(())
 }
@@ -1,56 +1,115 @@
-use axum::extract::Path;
+use crate::extract::{Json, Path, Query};
 use axum::extract::State;
 use diesel::prelude::*;
+use serde::Deserialize;
 use std::sync::{Arc, Mutex};
 
-use crate::db::StatCollectorId;
+use crate::db::{PeriodId, StatCollectorId};
 
 use crate::errors::AppError;
-use crate::logic::email::{Mailer, ReminderType};
-use crate::{db, schema};
+use crate::logic::archive::{ensure_not_archived, find_collector};
+use crate::logic::email::ReminderType;
+use crate::logic::outbox::enqueue_reminder;
+use crate::logic::time::Clock;
+use crate::{db, json, schema};
 
-/// Sends reminder emails to all suppliers of a statistics collector
+#[derive(Debug, Deserialize)]
+pub struct SendOptions {
+    /// The most recently ended period if not given
+    pub period_id: Option<PeriodId>,
+    /// Send the reminder even to suppliers who already got it
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Queues reminder emails about a period to all suppliers of a statistics collector, see `/outbox`.
+/// Suppliers who were already reminded about the period are skipped unless `force` is set.
 #[utoipa::path(
     post,
-    path = "/statistics_collector/{id}/send_emails",
+    path = "/statistics_collector/{id}/send_emails/{reminder_type}",
     params(
-        ("id" = Uuid, Path, description = "Statistics collector id")
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("reminder_type" = ReminderType, Path, description = "Reminder to send"),
+        ("period_id" = Option<Uuid>, Query, description = "Period the reminder is about, the most recently ended one by default"),
+        ("force" = Option<bool>, Query, description = "Remind suppliers who were already reminded")
     ),
+    security(("api_key" = ["send_email"])),
     responses(
-    (status = 200, description = "Ok"),
+        (status = 200, description = "Ok", body = QueuedReminders),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 400, description = "The collector has no periods", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
     )
 )]
 pub async fn send_reminder_emails(
-    State(mailer): State<Arc<Mutex<dyn Mailer>>>,
     State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
     Path((id, reminder_type)): Path<(StatCollectorId, ReminderType)>,
-) -> Result<(), AppError> {
+    Query(options): Query<SendOptions>,
+) -> Result<Json<json::sent::QueuedReminders>, AppError> {
+    let now = clock.lock().unwrap().now();
     let conn = pool.get().await?;
-    conn.interact(move |conn| {
-        conn.transaction(move |conn| {
-            let stat_collector = schema::statistics_collectors::table
-                .find(id)
-                .first::<db::StatisticsCollector>(conn)
-                .map_err(|_| AppError::not_found("statistics collector", id))?;
-
-            let suppliers: Vec<db::Supplier> = schema::placement_types::table
-                .filter(schema::placement_types::statistics_collector_id.eq(id))
-                .inner_join(schema::suppliers::table)
-                .select(db::Supplier::as_select())
-                .load(conn)?;
-
-            for supplier in suppliers {
-                mailer.lock().unwrap().send_reminder(
-                    stat_collector.clone(),
-                    supplier.mail.parse().unwrap(),
-                    supplier.id,
-                    reminder_type,
-                )?;
-            }
-
-            Ok::<(), AppError>(())
+    let queued = conn
+        .interact(move |conn| {
+            conn.transaction(move |conn| {
+                let collector = find_collector(conn, id)?;
+                ensure_not_archived(&collector)?;
+
+                let periods = schema::periods::table
+                    .filter(schema::periods::statistics_collector_id.eq(id))
+                    .order_by(schema::periods::end)
+                    .select(db::Period::as_select())
+                    .load(conn)?;
+
+                let period = match options.period_id {
+                    Some(period_id) => periods
+                        .into_iter()
+                        .find(|period| period.id == period_id)
+                        .ok_or_else(|| AppError::not_found("period", period_id))?,
+                    None => {
+                        let today = now.date_naive();
+                        let first = periods.first().cloned();
+                        periods
+                            .into_iter()
+                            .rev()
+                            .find(|period| period.end <= today)
+                            .or(first)
+                            .ok_or_else(|| {
+                                AppError::bad_request("the statistics collector has no periods")
+                            })?
+                    }
+                };
+
+                let suppliers: Vec<db::Supplier> = schema::placement_types::table
+                    .filter(schema::placement_types::statistics_collector_id.eq(id))
+                    .inner_join(schema::suppliers::table)
+                    .select(db::Supplier::as_select())
+                    .load(conn)?;
+
+                let mut queued = json::sent::QueuedReminders {
+                    period_id: period.id,
+                    queued: 0,
+                    skipped: 0,
+                };
+                for supplier in suppliers {
+                    match enqueue_reminder(
+                        conn,
+                        id,
+                        &supplier,
+                        period.id,
+                        reminder_type,
+                        options.force,
+                        now,
+                    )? {
+                        Some(_) => queued.queued += 1,
+                        None => queued.skipped += 1,
+                    }
+                }
+
+                Ok::<_, AppError>(queued)
+            })
         })
-    })
-    .await??;
-    Ok(())
+        .await??;
+    Ok(Json(queued))
 }
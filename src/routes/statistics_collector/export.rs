@@ -0,0 +1,108 @@
+use crate::extract::Path;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::logic::export;
+use crate::logic::locale::RequestedLocale;
+use crate::routes::statistics_collector::config::load_collector_config;
+
+/// Exports all statistics of a collector as CSV, one row per value
+#[utoipa::path(
+    get,
+    path = "/statistics_collector/{id}/export.csv",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("lang" = Option<Locale>, Query, description = "Language of the headers, else `Accept-Language` or the collector's language")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", content_type = "text/csv"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn export_csv(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    requested: RequestedLocale,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.get().await?;
+    let collector = conn
+        .interact(move |conn| load_collector_config(conn, id))
+        .await??;
+
+    let csv = export::to_csv(&collector, requested.resolve(None, collector.locale))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment(&collector.name, "csv"),
+            ),
+        ],
+        csv,
+    ))
+}
+
+/// Exports all statistics of a collector as an XLSX workbook with a sheet per placement type
+#[utoipa::path(
+    get,
+    path = "/statistics_collector/{id}/export.xlsx",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("lang" = Option<Locale>, Query, description = "Language of the headers, else `Accept-Language` or the collector's language")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn export_xlsx(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    requested: RequestedLocale,
+) -> Result<impl IntoResponse, AppError> {
+    let conn = pool.get().await?;
+    let collector = conn
+        .interact(move |conn| load_collector_config(conn, id))
+        .await??;
+
+    let xlsx = export::to_xlsx(&collector, requested.resolve(None, collector.locale))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, XLSX_CONTENT_TYPE.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                attachment(&collector.name, "xlsx"),
+            ),
+        ],
+        xlsx,
+    ))
+}
+
+pub const XLSX_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+/// `Content-Disposition` value with a file name safe to put in a header
+pub fn attachment(name: &str, extension: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("attachment; filename=\"{}.{}\"", name, extension)
+}
@@ -0,0 +1,46 @@
+use crate::db::{StatCollectorId, SupplierId};
+use crate::errors::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::json;
+use crate::logic::archive::find_collector;
+use crate::logic::history::load_history;
+use axum::extract::State;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryFilter {
+    pub supplier_id: Option<SupplierId>,
+}
+
+/// Returns all changes of the collector's statistics made by suppliers, newest first
+#[utoipa::path(
+    get,
+    path = "/statistics_collector/{id}/history",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("supplier_id" = Option<Uuid>, Query, description = "Only changes of this supplier")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = Vec<StatisticRevision>),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn get_collector_history(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<Vec<json::sent::StatisticRevision>>, AppError> {
+    let conn = pool.get().await?;
+    let history = conn
+        .interact(move |conn| {
+            find_collector(conn, id)?;
+
+            Ok::<_, AppError>(load_history(conn, id, filter.supplier_id)?)
+        })
+        .await??;
+
+    Ok(Json(history))
+}
@@ -1,23 +1,46 @@
-use axum::{extract::State, response::Json};
-use diesel::prelude::*;
+use crate::extract::{Json, Query};
+use axum::extract::State;
+use std::sync::{Arc, Mutex};
 
 use crate::errors::AppError;
-use crate::{db, schema};
+use crate::json;
+use crate::logic::collector_list::{list_collectors, ListOptions};
+use crate::logic::time::Clock;
 
-/// Lists all statistics collectors
+/// Lists the statistics collectors page by page, newest first unless sorted otherwise
 #[utoipa::path(
     get,
     path = "/statistics_collector",
+    params(
+        ("cursor" = Option<String>, Query, description = "`nextCursor` of the previous page, the first page if not given"),
+        ("limit" = Option<i64>, Query, description = "Collectors per page, 50 by default, at most 200"),
+        ("client" = Option<String>, Query, description = "Only collectors of this client"),
+        ("name" = Option<String>, Query, description = "Only collectors whose name contains this, case insensitive"),
+        ("status" = Option<CollectorStatus>, Query, description = "Only collectors in this state, derived from the dates of their periods"),
+        ("created_after" = Option<String>, Query, description = "Only collectors created at this RFC 3339 time or later"),
+        ("created_before" = Option<String>, Query, description = "Only collectors created before this RFC 3339 time"),
+        ("archived" = Option<bool>, Query, description = "List the archived collectors, which are hidden otherwise"),
+        ("deleted" = Option<bool>, Query, description = "List the deleted collectors which can still be restored"),
+        ("sort" = Option<String>, Query, description = "`created_at` (default), `name` or `client`"),
+        ("order" = Option<String>, Query, description = "`asc` or `desc`, descending for `created_at` and ascending otherwise by default")
+    ),
+    security(("api_key" = ["read"])),
     responses(
-        (status = 200, description = "Ok"),
+        (status = 200, description = "Ok", body = CollectorPage),
+        (status = 400, description = "Invalid query parameters or cursor", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
     )
 )]
 pub async fn list_statistics_collectors(
     State(pool): State<deadpool_diesel::postgres::Pool>,
-) -> Result<Json<Vec<db::StatisticsCollector>>, AppError> {
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Query(options): Query<ListOptions>,
+) -> Result<Json<json::sent::CollectorPage>, AppError> {
+    let today = clock.lock().unwrap().now().date_naive();
     let conn = pool.get().await?;
-    let statistics_collectors = conn
-        .interact(|conn| schema::statistics_collectors::table.load::<db::StatisticsCollector>(conn))
+    let page = conn
+        .interact(move |conn| list_collectors(conn, &options, today))
         .await??;
-    Ok(Json(statistics_collectors))
+    Ok(Json(page))
 }
@@ -0,0 +1,46 @@
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::json;
+use crate::logic::archive::find_collector;
+use crate::logic::outbox::{load_outbox, OutboxStatus};
+use axum::extract::State;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct OutboxFilter {
+    pub status: Option<OutboxStatus>,
+}
+
+/// Returns the emails queued for the collector's suppliers, newest first
+#[utoipa::path(
+    get,
+    path = "/statistics_collector/{id}/outbox",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("status" = Option<OutboxStatus>, Query, description = "Only messages with this status")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = Vec<OutboxMessage>),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn get_collector_outbox(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    Query(filter): Query<OutboxFilter>,
+) -> Result<Json<Vec<json::sent::OutboxMessage>>, AppError> {
+    let conn = pool.get().await?;
+    let messages = conn
+        .interact(move |conn| {
+            find_collector(conn, id)?;
+
+            Ok::<_, AppError>(load_outbox(conn, id, filter.status)?)
+        })
+        .await??;
+
+    Ok(Json(messages))
+}
@@ -1,77 +1,372 @@
-use axum::extract::Path;
+use crate::extract::{Path, Query};
 use axum::extract::State;
-use diesel::prelude::*;
 
 use maud::{html, Markup};
+use rust_i18n::t;
+use serde::Deserialize;
 use std::collections::BTreeMap;
 
-use crate::db::StatCollectorId;
+use crate::db::{PeriodId, StatCollectorId, SupplierId};
 
 use crate::errors::AppError;
-use crate::{db, schema};
+use crate::logic::locale::RequestedLocale;
+use crate::logic::reminder_log::load_deliveries;
+use crate::logic::render_html;
+use crate::logic::submissions::load_collector_submissions;
+use crate::routes::statistics_collector::config::load_collector_config;
+use crate::{db, json};
+
+static DATETIME_FORMAT: &str = "%H:%M:%S %d-%m-%Y";
+
+/// Style of cells missing values of some suppliers
+const MISSING_STYLE: &str = "background-color: #fdd";
+const INCOMPLETE_STYLE: &str = "background-color: #ffd";
+const COMPLETE_STYLE: &str = "background-color: #dfd";
+
+/// Ids of the placement type and the period to show, everything is shown if they're empty
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportFilter {
+    pub placement_type_id: Option<String>,
+    pub period_id: Option<String>,
+}
+
+fn is_selected(filter: &Option<String>, id: impl ToString) -> bool {
+    match filter.as_deref() {
+        None | Some("") => true,
+        Some(selected) => selected == id.to_string(),
+    }
+}
 
 struct ShowCollectorData {
-    collector: db::StatisticsCollector,
-    types_suppliers: BTreeMap<db::PlacementType, Vec<db::Supplier>>,
+    collector: json::sent::StatCollector,
+    submissions: BTreeMap<SupplierId, BTreeMap<PeriodId, db::PeriodSubmission>>,
+    deliveries: Vec<json::sent::ReminderDelivery>,
+}
+
+/// Sum of the filled in values of a pivot table cell, and how many are missing
+#[derive(Debug, Default, Clone, Copy)]
+struct Total {
+    sum: Option<i64>,
+    missing: usize,
+}
+
+impl Total {
+    fn add(&mut self, value: Option<i32>) {
+        match value {
+            Some(value) => *self.sum.get_or_insert(0) += value as i64,
+            None => self.missing += 1,
+        }
+    }
+
+    fn merge(&mut self, other: Total) {
+        if let Some(sum) = other.sum {
+            *self.sum.get_or_insert(0) += sum;
+        }
+        self.missing += other.missing;
+    }
+}
+
+/// Value of a cell of the supplier, `None` if there's no such cell
+fn cell(
+    supplier: &json::sent::Supplier,
+    statistic: usize,
+    copy: usize,
+    period: usize,
+) -> Option<Option<i32>> {
+    supplier
+        .stats
+        .get(statistic)?
+        .get(copy)?
+        .get(period)
+        .copied()
+}
+
+fn total_cell(total: Total, locale: &str) -> Markup {
+    let sum = total.sum.map(|sum| sum.to_string()).unwrap_or_default();
+    html! {
+        @if total.missing > 0 {
+            td style=(MISSING_STYLE) title=(t!("report_missing_values", locale = locale, count = total.missing)) { (sum) }
+        } @else {
+            td { (sum) }
+        }
+    }
+}
+
+/// Pivot table of a statistic type of the placement type, summed over its suppliers
+fn pivot_table(
+    placement_type: &json::sent::PlacementType,
+    statistic: usize,
+    periods: &[(usize, &json::sent::Period)],
+    locale: &str,
+) -> Markup {
+    let cells = placement_type
+        .copies
+        .iter()
+        .enumerate()
+        .map(|(copy, _)| {
+            periods
+                .iter()
+                .map(|&(period, _)| {
+                    let mut total = Total::default();
+                    for supplier in &placement_type.suppliers {
+                        if let Some(value) = cell(supplier, statistic, copy, period) {
+                            total.add(value);
+                        }
+                    }
+                    total
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let row_total = |row: &Vec<Total>| {
+        let mut total = Total::default();
+        row.iter().for_each(|cell| total.merge(*cell));
+        total
+    };
+    let column_total = |column: usize| {
+        let mut total = Total::default();
+        cells.iter().for_each(|row| total.merge(row[column]));
+        total
+    };
+    let mut grand_total = Total::default();
+    cells
+        .iter()
+        .for_each(|row| grand_total.merge(row_total(row)));
+
+    html! {
+        table {
+            tr {
+                th { (t!("copy", locale = locale)) }
+                @for (_, period) in periods {
+                    th { (period.name) }
+                }
+                th { (t!("total", locale = locale)) }
+            }
+            @for (copy, row) in placement_type.copies.iter().zip(&cells) {
+                tr {
+                    th { (copy) }
+                    @for cell in row {
+                        (total_cell(*cell, locale))
+                    }
+                    (total_cell(row_total(row), locale))
+                }
+            }
+            tr {
+                th { (t!("total", locale = locale)) }
+                @for column in 0..periods.len() {
+                    (total_cell(column_total(column), locale))
+                }
+                (total_cell(grand_total, locale))
+            }
+        }
+    }
+}
+
+/// Totals of a statistic type of the placement type in every period, for the chart
+fn statistic_over_time(
+    collector: &json::sent::StatCollector,
+    placement_type: &json::sent::PlacementType,
+    statistic: usize,
+) -> Vec<(String, Option<i64>)> {
+    collector
+        .periods
+        .iter()
+        .enumerate()
+        .map(|(period_index, period)| {
+            let mut total = Total::default();
+            for supplier in &placement_type.suppliers {
+                for copy in 0..placement_type.copies.len() {
+                    if let Some(value) = cell(supplier, statistic, copy, period_index) {
+                        total.add(value);
+                    }
+                }
+            }
+            (period.name.clone(), total.sum)
+        })
+        .collect()
 }
 
-/// Displays a page with all suppliers for a statistics collector
+/// Displays a report of a statistics collector: which suppliers submitted their statistics,
+/// the totals of each statistic type per copy and period, charts of them over time
+/// and the reminders sent to the suppliers
 #[utoipa::path(
     get,
     path = "/statistics_collector/{id}",
     params(
-        ("id" = Uuid, Path, description = "Statistics collector id")
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("placement_type_id" = Option<Uuid>, Query, description = "Only show this placement type"),
+        ("period_id" = Option<Uuid>, Query, description = "Only show this period in the tables, charts show all of them"),
+        ("lang" = Option<Locale>, Query, description = "Language of the page, else `Accept-Language` or the collector's language")
     ),
+    security(("api_key" = ["read"])),
     responses(
         (status = 200, description = "Ok", content_type = "text/html"),
-        (status = 404, description = "No such id", content_type = "text/html")
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
     )
 )]
 pub async fn show_statistics_collector(
     State(pool): State<deadpool_diesel::postgres::Pool>,
     Path(id): Path<StatCollectorId>,
+    Query(filter): Query<ReportFilter>,
+    requested: RequestedLocale,
 ) -> Result<Markup, AppError> {
     let conn = pool.get().await?;
     let data = conn
         .interact(move |conn| {
-            let collector = schema::statistics_collectors::table
-                .find(id)
-                .first::<db::StatisticsCollector>(conn)?;
-
-            let placement_types: Vec<(db::PlacementType, db::Supplier)> =
-                schema::placement_types::table
-                    .filter(schema::placement_types::statistics_collector_id.eq(id))
-                    .inner_join(schema::suppliers::table)
-                    .select((db::PlacementType::as_select(), db::Supplier::as_select()))
-                    .load(conn)?;
-
-            let mut types_suppliers = BTreeMap::new();
-            for (placement_type, supplier) in placement_types {
-                let suppliers = types_suppliers
-                    .entry(placement_type)
-                    .or_insert_with(std::vec::Vec::new);
-                suppliers.push(supplier);
-            }
+            let collector = load_collector_config(conn, id)?;
+
+            let submissions = load_collector_submissions(conn, id)?;
 
-            Ok::<_, diesel::result::Error>(ShowCollectorData {
+            let deliveries = load_deliveries(conn, id)?;
+
+            Ok::<_, AppError>(ShowCollectorData {
                 collector,
-                types_suppliers,
+                submissions,
+                deliveries,
             })
         })
         .await??;
 
-    let ok = html! {
-        h1 { (data.collector.name) }
+    let collector = &data.collector;
+    let locale = requested.resolve(None, collector.locale).as_str();
+    let placement_types = collector
+        .placement_types
+        .iter()
+        .filter(|placement_type| is_selected(&filter.placement_type_id, placement_type.id))
+        .collect::<Vec<_>>();
+    let periods = collector
+        .periods
+        .iter()
+        .enumerate()
+        .filter(|(_, period)| is_selected(&filter.period_id, period.id))
+        .collect::<Vec<_>>();
+
+    let ok = render_html::template(
+        locale,
+        &collector.name,
+        html! {
+            h1 { (collector.name) }
+            p { (t!("client", locale = locale)) ": " (collector.client) }
+
+            form method="get" {
+                label {
+                    (t!("placement_type", locale = locale)) " "
+                    select name="placement_type_id" {
+                        option value="" { (t!("report_all", locale = locale)) }
+                        @for placement_type in &collector.placement_types {
+                            @let id = placement_type.id.to_string();
+                            option value=(id) selected[filter.placement_type_id.as_deref() == Some(id.as_str())] {
+                                (placement_type.name)
+                            }
+                        }
+                    }
+                }
+                " "
+                label {
+                    (t!("period", locale = locale)) " "
+                    select name="period_id" {
+                        option value="" { (t!("report_all", locale = locale)) }
+                        @for period in &collector.periods {
+                            @let id = period.id.to_string();
+                            option value=(id) selected[filter.period_id.as_deref() == Some(id.as_str())] {
+                                (period.name)
+                            }
+                        }
+                    }
+                }
+                " "
+                @if let Some(lang) = requested.query {
+                    input type="hidden" name="lang" value=(lang);
+                }
+                button type="submit" { (t!("report_filter", locale = locale)) }
+            }
 
-        @for (placement_type, suppliers) in data.types_suppliers {
-            h2 { (placement_type.name) }
-            ul {
-                @for supplier in suppliers {
-                    li { a href=(format!("/supplier/{}", supplier.id)) { (supplier.name) } }
+            h2 { (t!("report_completion", locale = locale)) }
+            table {
+                tr {
+                    th { (t!("placement_type", locale = locale)) }
+                    th { (t!("supplier", locale = locale)) }
+                    @for (_, period) in &periods {
+                        th { (period.name) }
+                    }
+                }
+                @for placement_type in &placement_types {
+                    @for supplier in &placement_type.suppliers {
+                        tr {
+                            td { (placement_type.name) }
+                            td { (supplier.name) " <" (supplier.mail) ">" }
+                            @for (_, period) in &periods {
+                                @match data.submissions.get(&supplier.id).and_then(|submissions| submissions.get(&period.id)) {
+                                    Some(submission) if submission.complete => {
+                                        td style=(COMPLETE_STYLE) title=(t!("period_complete", locale = locale, date = submission.submitted_at.format(DATETIME_FORMAT))) {
+                                            (t!("report_complete", locale = locale))
+                                        }
+                                    }
+                                    Some(submission) => {
+                                        td style=(INCOMPLETE_STYLE) title=(t!("period_incomplete", locale = locale, date = submission.submitted_at.format(DATETIME_FORMAT))) {
+                                            (t!("report_incomplete", locale = locale))
+                                        }
+                                    }
+                                    None => {
+                                        td style=(MISSING_STYLE) { (t!("period_not_submitted", locale = locale)) }
+                                    }
+                                }
+                            }
+                        }
+                    }
                 }
             }
-        }
-    };
+
+            @for placement_type in &placement_types {
+                h2 { (placement_type.name) }
+                @for (statistic, name) in placement_type.statistics.iter().enumerate() {
+                    h3 { (name) }
+                    (pivot_table(placement_type, statistic, &periods, locale))
+                    @let points = statistic_over_time(collector, placement_type, statistic);
+                    @if points.iter().any(|(_, value)| value.is_some()) {
+                        p { (render_html::line_chart(&points)) }
+                    } @else {
+                        p { (t!("report_no_data", locale = locale)) }
+                    }
+                }
+            }
+            @if !placement_types.is_empty() {
+                p { span style=(MISSING_STYLE) { (t!("report_missing_legend", locale = locale)) } }
+            }
+
+            h2 { (t!("reminder_log", locale = locale)) }
+            @if data.deliveries.is_empty() {
+                p { (t!("reminder_log_empty", locale = locale)) }
+            } @else {
+                table {
+                    tr {
+                        th { (t!("sent_at", locale = locale)) }
+                        th { (t!("supplier", locale = locale)) }
+                        th { (t!("period", locale = locale)) }
+                        th { (t!("reminder_type", locale = locale)) }
+                        th { (t!("outcome", locale = locale)) }
+                    }
+                    @for delivery in &data.deliveries {
+                        tr {
+                            td { (delivery.attempted_at.format(DATETIME_FORMAT)) }
+                            td { (delivery.supplier) }
+                            td { (delivery.period.as_deref().unwrap_or_default()) }
+                            td { (delivery.reminder_type) }
+                            td {
+                                @match &delivery.error {
+                                    Some(error) => { (t!("delivery_failed", locale = locale, error = error)) }
+                                    None => { (t!("delivery_sent", locale = locale)) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    );
 
     Ok(ok)
 }
@@ -0,0 +1,64 @@
+use crate::db::StatCollectorId;
+use crate::errors::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::json;
+use crate::logic::archive::find_collector;
+use crate::logic::summary::{load_summary, Dimension};
+use axum::extract::State;
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct SummaryOptions {
+    pub group_by: Option<String>,
+}
+
+/// Returns the totals of the collector's statistics and its derived statistic types,
+/// grouped by statistic type and any combination of `period`, `placement_type`, `supplier` and `copy`
+#[utoipa::path(
+    get,
+    path = "/statistics_collector/{id}/summary",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("group_by" = Option<String>, Query, description = "Comma separated dimensions, like `period,supplier`. Totals of the whole collector if not given.")
+    ),
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = Summary),
+        (status = 400, description = "Unknown dimension", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn get_collector_summary(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    Query(options): Query<SummaryOptions>,
+) -> Result<Json<json::sent::Summary>, AppError> {
+    let mut group_by = vec![];
+    for dimension in options
+        .group_by
+        .iter()
+        .flat_map(|group_by| group_by.split(','))
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+    {
+        let dimension = Dimension::from_str(dimension).map_err(AppError::bad_request)?;
+        if !group_by.contains(&dimension) {
+            group_by.push(dimension);
+        }
+    }
+
+    let conn = pool.get().await?;
+    let summary = conn
+        .interact(move |conn| {
+            find_collector(conn, id)?;
+
+            let rows = load_summary(conn, id, &group_by)?;
+            Ok::<_, AppError>(json::sent::Summary { group_by, rows })
+        })
+        .await??;
+
+    Ok(Json(summary))
+}
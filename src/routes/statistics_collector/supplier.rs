@@ -0,0 +1,96 @@
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use diesel::prelude::*;
+
+use crate::db::{StatCollectorId, SupplierId};
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::suppliers::{add_supplier, change_supplier, remove_supplier};
+
+/// Adds a supplier to a placement type of the collector, with empty statistics for every
+/// period, statistic type and copy
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/supplier",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    request_body = NewSupplier,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Id of the new supplier"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such collector or placement type in it", body = ErrorResponse),
+        (status = 422, description = "Empty name or the mail is taken in the placement type", body = ErrorResponse)
+    )
+)]
+pub async fn add_collector_supplier(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<StatCollectorId>,
+    Json(supplier): Json<json::received::NewSupplier>,
+) -> Result<Json<SupplierId>, AppError> {
+    let conn = pool.get().await?;
+    let supplier_id = conn
+        .interact(move |conn| conn.transaction(|conn| add_supplier(conn, id, &supplier)))
+        .await??;
+    Ok(Json(supplier_id))
+}
+
+/// Renames a supplier, changes its mail or moves it to another placement type of the collector.
+/// Statistics the new placement type lacks are deleted and the missing ones are created empty.
+#[utoipa::path(
+    patch,
+    path = "/statistics_collector/{id}/supplier/{supplier_id}",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("supplier_id" = Uuid, Path, description = "Supplier id")
+    ),
+    request_body = SupplierChanges,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such collector, supplier or placement type in it", body = ErrorResponse),
+        (status = 422, description = "Empty name or the mail is taken in the placement type", body = ErrorResponse)
+    )
+)]
+pub async fn change_collector_supplier(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path((id, supplier_id)): Path<(StatCollectorId, SupplierId)>,
+    Json(changes): Json<json::received::SupplierChanges>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| {
+        conn.transaction(|conn| change_supplier(conn, id, supplier_id, &changes))
+    })
+    .await??;
+    Ok(())
+}
+
+/// Removes a supplier with its statistics, links and queued emails
+#[utoipa::path(
+    delete,
+    path = "/statistics_collector/{id}/supplier/{supplier_id}",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("supplier_id" = Uuid, Path, description = "Supplier id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such supplier in the collector", body = ErrorResponse)
+    )
+)]
+pub async fn remove_collector_supplier(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path((id, supplier_id)): Path<(StatCollectorId, SupplierId)>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| conn.transaction(|conn| remove_supplier(conn, id, supplier_id)))
+        .await??;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use diesel::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::db::{CollectorTemplateId, StatCollectorId};
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::periods::resolve_periods;
+use crate::logic::templates::{
+    collector_structure, delete_template, instantiate, load_template, load_templates, save_template,
+};
+use crate::logic::time::Clock;
+use crate::logic::validation::validate_collector;
+use crate::routes::statistics_collector::config::load_collector_config;
+use crate::routes::statistics_collector::create::insert_collector;
+
+/// Saves the structure of a collector as a template for new collectors.
+/// Later changes of the collector don't change the template.
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/template",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    request_body = NewTemplate,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Id of the template"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse),
+        (status = 409, description = "A template with this name exists, its id is given", body = ErrorResponse),
+        (status = 422, description = "The name is empty", body = ErrorResponse)
+    )
+)]
+pub async fn save_collector_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(id): Path<StatCollectorId>,
+    Json(template): Json<json::received::NewTemplate>,
+) -> Result<Json<CollectorTemplateId>, AppError> {
+    let now = clock.lock().unwrap().now();
+
+    let conn = pool.get().await?;
+    let id = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let structure = collector_structure(load_collector_config(conn, id)?);
+                save_template(conn, &template.name, &structure, now)
+            })
+        })
+        .await??;
+    Ok(Json(id))
+}
+
+/// Lists the saved collector templates
+#[utoipa::path(
+    get,
+    path = "/collector_template",
+    security(("api_key" = ["read"])),
+    responses(
+        (status = 200, description = "Ok", body = [CollectorTemplate]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse)
+    )
+)]
+pub async fn list_collector_templates(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+) -> Result<Json<Vec<json::sent::CollectorTemplate>>, AppError> {
+    let conn = pool.get().await?;
+    let templates = conn.interact(load_templates).await??;
+    Ok(Json(templates))
+}
+
+/// Creates a new collector from a template, see `POST /statistics_collector/{id}/clone`
+#[utoipa::path(
+    post,
+    path = "/collector_template/{id}/instantiate",
+    params(
+        ("id" = Uuid, Path, description = "Collector template id")
+    ),
+    request_body = CloneRequest,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Id of the new collector"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such template", body = ErrorResponse),
+        (status = 409, description = "A collector with this name and client exists, its id is given", body = ErrorResponse),
+        (status = 422, description = "Invalid collector, every problem is listed", body = ErrorResponse)
+    )
+)]
+pub async fn instantiate_collector_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(id): Path<CollectorTemplateId>,
+    Json(request): Json<json::received::CloneRequest>,
+) -> Result<Json<StatCollectorId>, AppError> {
+    let now = clock.lock().unwrap().now();
+
+    let conn = pool.get().await?;
+    let id = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let template = load_template(conn, id)?;
+                let collector = instantiate(template.collector, request)?;
+                validate_collector(&collector)?;
+                let periods = resolve_periods(&collector)?;
+                insert_collector(conn, &collector, &periods, now)
+            })
+        })
+        .await??;
+    Ok(Json(id))
+}
+
+/// Deletes a collector template, collectors created from it stay
+#[utoipa::path(
+    delete,
+    path = "/collector_template/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Collector template id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such template", body = ErrorResponse)
+    )
+)]
+pub async fn delete_collector_template(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(id): Path<CollectorTemplateId>,
+) -> Result<(), AppError> {
+    let conn = pool.get().await?;
+    conn.interact(move |conn| delete_template(conn, id))
+        .await??;
+    Ok(())
+}
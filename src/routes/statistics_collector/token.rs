@@ -0,0 +1,102 @@
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use diesel::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::db::{StatCollectorId, SupplierId};
+use crate::errors::AppError;
+use crate::json;
+use crate::logic::archive::find_collector;
+use crate::logic::time::Clock;
+use crate::logic::token::{issue_token, revoke_tokens, supplier_path};
+use crate::schema;
+
+fn check_supplier(
+    conn: &mut PgConnection,
+    id: StatCollectorId,
+    supplier_id: SupplierId,
+) -> Result<(), AppError> {
+    find_collector(conn, id)?;
+    schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(id))
+        .filter(schema::suppliers::id.eq(supplier_id))
+        .select(schema::suppliers::id)
+        .first::<SupplierId>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::not_found("supplier", supplier_id))?;
+    Ok(())
+}
+
+/// Revokes all links sent to a supplier so far and issues a new one
+#[utoipa::path(
+    post,
+    path = "/statistics_collector/{id}/supplier/{supplier_id}/token",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("supplier_id" = Uuid, Path, description = "Supplier id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok", body = SupplierToken),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such supplier in the collector", body = ErrorResponse)
+    )
+)]
+pub async fn rotate_supplier_token(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path((id, supplier_id)): Path<(StatCollectorId, SupplierId)>,
+) -> Result<Json<json::sent::SupplierToken>, AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    let issued = conn
+        .interact(move |conn| {
+            conn.transaction(move |conn| {
+                check_supplier(conn, id, supplier_id)?;
+                revoke_tokens(conn, supplier_id, now)?;
+                Ok::<_, AppError>(issue_token(conn, supplier_id, now)?)
+            })
+        })
+        .await??;
+
+    Ok(Json(json::sent::SupplierToken {
+        path: supplier_path(supplier_id, &issued.token),
+        token: issued.token,
+        expires_at: issued.expires_at,
+    }))
+}
+
+/// Revokes all links sent to a supplier so far
+#[utoipa::path(
+    delete,
+    path = "/statistics_collector/{id}/supplier/{supplier_id}/token",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id"),
+        ("supplier_id" = Uuid, Path, description = "Supplier id")
+    ),
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 404, description = "No such supplier in the collector", body = ErrorResponse)
+    )
+)]
+pub async fn revoke_supplier_token(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path((id, supplier_id)): Path<(StatCollectorId, SupplierId)>,
+) -> Result<(), AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    conn.interact(move |conn| {
+        check_supplier(conn, id, supplier_id)?;
+        revoke_tokens(conn, supplier_id, now)?;
+        Ok::<_, AppError>(())
+    })
+    .await??;
+
+    Ok(())
+}
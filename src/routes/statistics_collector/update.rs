@@ -0,0 +1,565 @@
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use diesel::prelude::*;
+
+use crate::db::{CopyId, PeriodId, PlacementTypeId, StatCollectorId, StatisticTypeId, SupplierId};
+
+use crate::errors::AppError;
+use crate::json::weekday_serde;
+use crate::logic::archive::{find_collector, find_duplicate};
+use crate::logic::periods::resolve_periods;
+use crate::logic::reminders::save_policy;
+use crate::logic::statistics::{insert_missing_statistics, move_statistics};
+use crate::logic::submissions::refresh_completion;
+use crate::logic::summary::{load_derived_statistic_types, save_derived_statistic_types};
+use crate::logic::validation::validate_collector;
+use crate::{db, json, schema};
+
+/// Updates a statistics collector in place, keeping already collected statistics.
+/// Periods, placement types and suppliers are matched with the stored ones by `id` if it is given
+/// and by name otherwise (suppliers also by mail, within their placement type).
+/// Statistic types and copies are matched by their ids in `statisticTypeIds` and `copyIds`
+/// if they are given and by name otherwise, renaming them keeps their statistics.
+/// Stored items which were not matched are removed together with their statistics.
+#[utoipa::path(
+    put,
+    path = "/statistics_collector/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Statistics collector id")
+    ),
+    request_body = StatCollector,
+    security(("api_key" = ["write"])),
+    responses(
+        (status = 200, description = "Ok"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The API key lacks the scope", body = ErrorResponse),
+        (status = 422, description = "Invalid collector, every problem is listed", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse),
+        (status = 409, description = "Another collector with this name and client exists", body = ErrorResponse)
+    )
+)]
+pub async fn update_statistics_collector(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    Path(collector_id): Path<StatCollectorId>,
+    Json(statistics_collector): Json<json::received::StatCollector>,
+) -> Result<Json<StatCollectorId>, AppError> {
+    validate_collector(&statistics_collector)?;
+    let periods = resolve_periods(&statistics_collector)?;
+
+    let conn = pool.get().await?;
+    conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            find_collector(conn, collector_id)?;
+
+            // Ensure that (name, client) tuple stays unique
+            let existing = find_duplicate(
+                conn,
+                Some(collector_id),
+                &statistics_collector.name,
+                &statistics_collector.client,
+            )?;
+
+            if let Some(existing) = existing {
+                return Err(AppError::Conflict {
+                    resource: format!(
+                        "statistics collector with name {} and client {}",
+                        statistics_collector.name, statistics_collector.client
+                    ),
+                    id: existing.to_string(),
+                });
+            }
+
+            diesel::update(schema::statistics_collectors::table.find(collector_id))
+                .set((
+                    schema::statistics_collectors::name.eq(&statistics_collector.name),
+                    schema::statistics_collectors::client.eq(&statistics_collector.client),
+                    schema::statistics_collectors::periodicity
+                        .eq(statistics_collector.periodicity.to_string()),
+                    schema::statistics_collectors::weekday.eq(statistics_collector
+                        .weekday
+                        .map(|weekday| weekday_serde::name(weekday).to_string())),
+                ))
+                .execute(conn)?;
+            if let Some(locale) = statistics_collector.locale {
+                diesel::update(schema::statistics_collectors::table.find(collector_id))
+                    .set(schema::statistics_collectors::locale.eq(locale.to_string()))
+                    .execute(conn)?;
+            }
+
+            // the Django app doesn't send the policy, it is only changed if given
+            if let Some(policy) = &statistics_collector.reminder_policy {
+                save_policy(conn, collector_id, policy)?;
+            }
+            // neither are the derived statistic types, the stored ones must still fit the statistic types
+            match &statistics_collector.derived_statistic_types {
+                Some(derived) => save_derived_statistic_types(conn, collector_id, derived)?,
+                None => validate_collector(&json::received::StatCollector {
+                    derived_statistic_types: Some(load_derived_statistic_types(
+                        conn,
+                        collector_id,
+                    )?),
+                    ..statistics_collector.clone()
+                })?,
+            }
+
+            update_periods(conn, collector_id, &periods)?;
+
+            let stored_placement_types = schema::placement_types::table
+                .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+                .select(db::PlacementType::as_select())
+                .load(conn)?;
+
+            let placement_types = pair_up(
+                &statistics_collector.placement_types,
+                stored_placement_types,
+                |placement_type| placement_type.id,
+                |placement_type| placement_type.id,
+                |placement_type, stored| placement_type.name == stored.name,
+            )
+            .map_err(|id| AppError::not_found("placement type", id))?;
+
+            let mut placement_type_ids = Vec::new();
+            for (placement_type, stored) in placement_types.pairs {
+                let placement_type_id = match stored {
+                    Some(stored) => {
+                        if stored.name != placement_type.name {
+                            diesel::update(schema::placement_types::table.find(stored.id))
+                                .set(schema::placement_types::name.eq(&placement_type.name))
+                                .execute(conn)?;
+                        }
+                        stored.id
+                    }
+                    None => {
+                        let db_placement_type = db::PlacementType {
+                            id: PlacementTypeId::new(),
+                            name: placement_type.name.clone(),
+                            statistics_collector_id: collector_id,
+                        };
+                        diesel::insert_into(schema::placement_types::table)
+                            .values(&db_placement_type)
+                            .execute(conn)?;
+                        db_placement_type.id
+                    }
+                };
+
+                update_statistic_types(conn, placement_type_id, placement_type)?;
+                update_copies(conn, placement_type_id, placement_type)?;
+                placement_type_ids.push(placement_type_id);
+            }
+
+            let suppliers = statistics_collector
+                .placement_types
+                .iter()
+                .zip(placement_type_ids)
+                .flat_map(|(placement_type, placement_type_id)| {
+                    placement_type
+                        .suppliers
+                        .iter()
+                        .map(move |supplier| (placement_type_id, supplier))
+                })
+                .collect::<Vec<_>>();
+
+            update_suppliers(conn, collector_id, &suppliers)?;
+
+            // Suppliers could have been moved out of these, so they go last
+            for placement_type in placement_types.removed {
+                diesel::delete(schema::placement_types::table.find(placement_type.id))
+                    .execute(conn)?;
+            }
+
+            insert_missing_statistics(conn, collector_id)?;
+            refresh_completion(conn, collector_id)?;
+
+            Ok(())
+        })
+    })
+    .await??;
+
+    Ok(Json(collector_id))
+}
+
+fn update_periods(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    periods: &[json::received::Period],
+) -> Result<(), AppError> {
+    let stored_periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector_id))
+        .select(db::Period::as_select())
+        .load(conn)?;
+
+    let periods = pair_up(
+        periods,
+        stored_periods,
+        |period| period.id,
+        |period| period.id,
+        |period, stored| period.name == stored.name,
+    )
+    .map_err(|id| AppError::not_found("period", id))?;
+
+    for (period, stored) in periods.pairs {
+        match stored {
+            Some(stored) => {
+                if stored.name != period.name
+                    || stored.start != period.start_date
+                    || stored.end != period.end_date
+                {
+                    diesel::update(schema::periods::table.find(stored.id))
+                        .set((
+                            schema::periods::name.eq(&period.name),
+                            schema::periods::start.eq(period.start_date),
+                            schema::periods::end.eq(period.end_date),
+                        ))
+                        .execute(conn)?;
+                }
+            }
+            None => {
+                diesel::insert_into(schema::periods::table)
+                    .values(db::Period {
+                        id: PeriodId::new(),
+                        name: period.name.clone(),
+                        start: period.start_date,
+                        end: period.end_date,
+                        statistics_collector_id: collector_id,
+                    })
+                    .execute(conn)?;
+            }
+        }
+    }
+
+    for period in periods.removed {
+        diesel::delete(schema::periods::table.find(period.id)).execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Names of a placement type's statistic types or copies with the ids they were given, if any
+fn with_ids<'a, Id: Copy>(
+    names: &'a [String],
+    ids: &[Option<Id>],
+) -> Vec<(&'a String, Option<Id>)> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name, ids.get(i).copied().flatten()))
+        .collect()
+}
+
+fn update_statistic_types(
+    conn: &mut PgConnection,
+    placement_type_id: PlacementTypeId,
+    placement_type: &json::received::PlacementType,
+) -> Result<(), AppError> {
+    let stored_statistic_types = schema::statistic_types::table
+        .filter(schema::statistic_types::placement_type_id.eq(placement_type_id))
+        .select(db::StatisticType::as_select())
+        .load(conn)?;
+
+    let incoming = with_ids(
+        &placement_type.statistics,
+        &placement_type.statistic_type_ids,
+    );
+    let statistic_types = pair_up(
+        &incoming,
+        stored_statistic_types,
+        |(_, id)| *id,
+        |statistic_type| statistic_type.id,
+        |(name, _), stored| **name == stored.name,
+    )
+    .map_err(|id| AppError::not_found("statistic type", id))?;
+
+    let mut new_statistic_types = Vec::new();
+    for ((name, _), stored) in statistic_types.pairs {
+        match stored {
+            Some(stored) => {
+                if stored.name != **name {
+                    diesel::update(schema::statistic_types::table.find(stored.id))
+                        .set(schema::statistic_types::name.eq(name))
+                        .execute(conn)?;
+                }
+            }
+            None => new_statistic_types.push(db::StatisticType {
+                id: StatisticTypeId::new(),
+                name: (*name).clone(),
+                placement_type_id,
+            }),
+        }
+    }
+
+    diesel::insert_into(schema::statistic_types::table)
+        .values(&new_statistic_types)
+        .execute(conn)?;
+
+    for statistic_type in statistic_types.removed {
+        diesel::delete(schema::statistic_types::table.find(statistic_type.id)).execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn update_copies(
+    conn: &mut PgConnection,
+    placement_type_id: PlacementTypeId,
+    placement_type: &json::received::PlacementType,
+) -> Result<(), AppError> {
+    let stored_copies = schema::copies::table
+        .filter(schema::copies::placement_type_id.eq(placement_type_id))
+        .select(db::Copy::as_select())
+        .load(conn)?;
+
+    let incoming = with_ids(&placement_type.copies, &placement_type.copy_ids);
+    let copies = pair_up(
+        &incoming,
+        stored_copies,
+        |(_, id)| *id,
+        |copy| copy.id,
+        |(name, _), stored| **name == stored.name,
+    )
+    .map_err(|id: CopyId| AppError::not_found("copy", id))?;
+
+    let mut new_copies = Vec::new();
+    for ((name, _), stored) in copies.pairs {
+        match stored {
+            Some(stored) => {
+                if stored.name != **name {
+                    diesel::update(schema::copies::table.find(stored.id))
+                        .set(schema::copies::name.eq(name))
+                        .execute(conn)?;
+                }
+            }
+            None => new_copies.push(db::Copy {
+                id: CopyId::new(),
+                name: (*name).clone(),
+                placement_type_id,
+            }),
+        }
+    }
+
+    diesel::insert_into(schema::copies::table)
+        .values(&new_copies)
+        .execute(conn)?;
+
+    for copy in copies.removed {
+        diesel::delete(schema::copies::table.find(copy.id)).execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a supplier without an id is the stored one. A supplier can be listed under several
+/// placement types, each with its own row, so rows of other placement types never match.
+fn same_supplier(
+    placement_type_id: PlacementTypeId,
+    supplier: &json::received::Supplier,
+    stored: &db::Supplier,
+) -> bool {
+    stored.placement_type_id == placement_type_id
+        && (supplier.name == stored.name || supplier.mail.to_string() == stored.mail)
+}
+
+/// Suppliers are matched across the whole collector by id, so they can be moved between
+/// placement types, and within their placement type by name or mail
+fn update_suppliers(
+    conn: &mut PgConnection,
+    collector_id: StatCollectorId,
+    suppliers: &[(PlacementTypeId, &json::received::Supplier)],
+) -> Result<(), AppError> {
+    let stored_suppliers = schema::suppliers::table
+        .inner_join(schema::placement_types::table)
+        .filter(schema::placement_types::statistics_collector_id.eq(collector_id))
+        .select(db::Supplier::as_select())
+        .load(conn)?;
+
+    let suppliers = pair_up(
+        suppliers,
+        stored_suppliers,
+        |(_, supplier)| supplier.id,
+        |supplier| supplier.id,
+        |(placement_type_id, supplier), stored| same_supplier(*placement_type_id, supplier, stored),
+    )
+    .map_err(|id: SupplierId| AppError::not_found("supplier", id))?;
+
+    for ((placement_type_id, supplier), stored) in suppliers.pairs {
+        let mail = supplier.mail.to_string();
+        let locale = supplier.locale.map(|locale| locale.to_string());
+        match stored {
+            Some(stored) => {
+                let locale = locale.or(stored.locale.clone());
+                if stored.name != supplier.name
+                    || stored.mail != mail
+                    || stored.placement_type_id != *placement_type_id
+                    || stored.locale != locale
+                {
+                    let updated = diesel::update(schema::suppliers::table.find(stored.id))
+                        .set((
+                            schema::suppliers::name.eq(&supplier.name),
+                            schema::suppliers::mail.eq(&mail),
+                            schema::suppliers::placement_type_id.eq(placement_type_id),
+                            schema::suppliers::locale.eq(&locale),
+                        ))
+                        .get_result::<db::Supplier>(conn)?;
+
+                    if stored.placement_type_id != updated.placement_type_id {
+                        move_statistics(conn, &updated)?;
+                    }
+                }
+            }
+            None => {
+                diesel::insert_into(schema::suppliers::table)
+                    .values(db::Supplier {
+                        id: SupplierId::new(),
+                        name: supplier.name.clone(),
+                        mail,
+                        placement_type_id: *placement_type_id,
+                        locale,
+                    })
+                    .execute(conn)?;
+            }
+        }
+    }
+
+    for supplier in suppliers.removed {
+        diesel::delete(schema::suppliers::table.find(supplier.id)).execute(conn)?;
+    }
+
+    Ok(())
+}
+
+struct Pairing<'a, I, S> {
+    /// Every incoming item with the stored row it refers to, in incoming order
+    pairs: Vec<(&'a I, Option<S>)>,
+    /// Stored rows no incoming item refers to
+    removed: Vec<S>,
+}
+
+/// Matches incoming items with stored rows, first by explicit id
+/// and then by name among the rows which were not claimed yet.
+/// Fails with the offending id if an incoming item refers to a row which is not stored.
+fn pair_up<'a, I, S, Id: PartialEq>(
+    incoming: &'a [I],
+    stored: Vec<S>,
+    incoming_id: impl Fn(&I) -> Option<Id>,
+    stored_id: impl Fn(&S) -> Id,
+    same_name: impl Fn(&I, &S) -> bool,
+) -> Result<Pairing<'a, I, S>, Id> {
+    let mut claimed = vec![false; stored.len()];
+    let mut matches = vec![None; incoming.len()];
+
+    for (i, item) in incoming.iter().enumerate() {
+        if let Some(id) = incoming_id(item) {
+            let j = match stored.iter().position(|s| stored_id(s) == id) {
+                Some(j) if !claimed[j] => j,
+                _ => return Err(id),
+            };
+            claimed[j] = true;
+            matches[i] = Some(j);
+        }
+    }
+
+    for (i, item) in incoming.iter().enumerate() {
+        if matches[i].is_none() && incoming_id(item).is_none() {
+            if let Some(j) = (0..stored.len()).find(|&j| !claimed[j] && same_name(item, &stored[j]))
+            {
+                claimed[j] = true;
+                matches[i] = Some(j);
+            }
+        }
+    }
+
+    let mut stored = stored.into_iter().map(Some).collect::<Vec<_>>();
+    let pairs = incoming
+        .iter()
+        .zip(matches)
+        .map(|(item, j)| (item, j.and_then(|j| stored[j].take())))
+        .collect();
+    let removed = stored.into_iter().flatten().collect();
+
+    Ok(Pairing { pairs, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Incoming<'a> = (Option<u32>, &'a str);
+    type Stored<'a> = (u32, &'a str);
+
+    fn pair_names<'a>(
+        incoming: &'a [Incoming<'a>],
+        stored: Vec<Stored<'a>>,
+    ) -> Result<Pairing<'a, Incoming<'a>, Stored<'a>>, u32> {
+        pair_up(
+            incoming,
+            stored,
+            |(id, _)| *id,
+            |(id, _)| *id,
+            |(_, name), (_, stored_name)| name == stored_name,
+        )
+    }
+
+    #[test]
+    fn pair_up_prefers_ids_over_names() {
+        let incoming = [(None, "b"), (Some(1), "b"), (None, "c")];
+        let stored = vec![(1, "a"), (2, "b"), (3, "d")];
+
+        let pairing = pair_names(&incoming, stored).unwrap();
+
+        let pairs = pairing
+            .pairs
+            .iter()
+            .map(|(item, stored)| (item.1, stored.map(|s| s.0)))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![("b", Some(2)), ("b", Some(1)), ("c", None)]);
+        assert_eq!(pairing.removed, vec![(3, "d")]);
+    }
+
+    #[test]
+    fn suppliers_in_several_placement_types_keep_their_rows() {
+        let supplier = json::received::Supplier {
+            id: None,
+            name: "Google".to_string(),
+            mail: "google@google.com".parse().unwrap(),
+            locale: None,
+        };
+        let display = PlacementTypeId::new();
+        let mailing = PlacementTypeId::new();
+        let stored = |placement_type_id| db::Supplier {
+            id: SupplierId::new(),
+            name: supplier.name.clone(),
+            mail: supplier.mail.to_string(),
+            placement_type_id,
+            locale: None,
+        };
+        // stored rows come in no particular order
+        let stored = vec![stored(mailing), stored(display)];
+        let incoming = [(display, &supplier), (mailing, &supplier)];
+
+        let pairing = pair_up(
+            &incoming,
+            stored,
+            |(_, supplier)| supplier.id,
+            |supplier| supplier.id,
+            |(placement_type_id, supplier), stored| {
+                same_supplier(*placement_type_id, supplier, stored)
+            },
+        )
+        .unwrap();
+
+        for ((placement_type_id, _), stored) in pairing.pairs {
+            assert_eq!(stored.unwrap().placement_type_id, *placement_type_id);
+        }
+        assert!(pairing.removed.is_empty());
+    }
+
+    #[test]
+    fn pair_up_rejects_unknown_and_repeated_ids() {
+        let stored = vec![(1, "a")];
+
+        assert_eq!(pair_names(&[(Some(2), "a")], stored.clone()).err(), Some(2));
+        assert_eq!(
+            pair_names(&[(Some(1), "a"), (Some(1), "a")], stored).err(),
+            Some(1)
+        );
+    }
+}
@@ -1,2 +1,24 @@
+use crate::logic::locale::RequestedLocale;
+use serde::Deserialize;
+
+pub mod history;
 pub mod show;
 pub mod submit;
+pub mod template;
+pub mod upload;
+
+/// Every supplier route requires a token from a link sent to the supplier
+#[derive(Debug, Deserialize)]
+pub struct SupplierAccess {
+    #[serde(default)]
+    pub token: String,
+}
+
+/// Keeps the language chosen with `?lang=` on the links between the supplier pages.
+/// `path` must already have a query, as every supplier path has the token in it.
+pub fn keep_lang(path: String, requested: RequestedLocale) -> String {
+    match requested.query {
+        Some(locale) => format!("{}&lang={}", path, locale),
+        None => path,
+    }
+}
@@ -0,0 +1,108 @@
+use crate::extract::{Path, Query};
+use axum::extract::State;
+use diesel::prelude::*;
+use maud::{html, Markup};
+use rust_i18n::t;
+use std::sync::{Arc, Mutex};
+
+use crate::db::SupplierId;
+use crate::errors::AppError;
+use crate::logic::history::load_history;
+use crate::logic::locale::RequestedLocale;
+use crate::logic::render_html;
+use crate::logic::time::Clock;
+use crate::logic::token::{authorize_supplier, supplier_path};
+use crate::routes::supplier::{keep_lang, SupplierAccess};
+use crate::{db, schema};
+
+static DATETIME_FORMAT: &str = "%H:%M:%S %d-%m-%Y";
+
+/// Shows all changes the supplier made to their statistics, newest first
+#[utoipa::path(
+    get,
+    path = "/supplier/{uuid}/history",
+    params(
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier"),
+        ("lang" = Option<Locale>, Query, description = "Language of the page, else the supplier's, `Accept-Language` or the collector's language")
+    ),
+    responses(
+        (status = 200, description = "Ok", content_type = "text/html"),
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn show_supplier_history(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
+) -> Result<Markup, AppError> {
+    let now = clock.lock().unwrap().now();
+    let token = access.token.clone();
+    let conn = pool.get().await?;
+    let (supplier, collector, history) = conn
+        .interact(move |conn| {
+            authorize_supplier(conn, supplier_id, &token, now)?;
+
+            let (supplier, collector) = schema::suppliers::table
+                .inner_join(
+                    schema::placement_types::table.inner_join(schema::statistics_collectors::table),
+                )
+                .filter(schema::suppliers::id.eq(supplier_id))
+                .select((
+                    db::Supplier::as_select(),
+                    db::StatisticsCollector::as_select(),
+                ))
+                .first(conn)
+                .map_err(|_| AppError::not_found("supplier", supplier_id))?;
+
+            let history = load_history(conn, collector.id, Some(supplier_id))?;
+            Ok::<_, AppError>((supplier, collector, history))
+        })
+        .await??;
+
+    let locale = requested
+        .resolve(supplier.locale(), collector.locale())
+        .as_str();
+    let title = format!("{} - {}", supplier.name, t!("history", locale = locale));
+
+    let ok = render_html::template(
+        locale,
+        &title,
+        html! {
+            h1 { (title) }
+
+            @if history.is_empty() {
+                p { (t!("history_empty", locale = locale)) }
+            } @else {
+                table {
+                    tr {
+                        th { (t!("changed_at", locale = locale)) }
+                        th { (t!("period", locale = locale)) }
+                        th { (t!("copy", locale = locale)) }
+                        th { (t!("statistic_type", locale = locale)) }
+                        th { (t!("old_value", locale = locale)) }
+                        th { (t!("new_value", locale = locale)) }
+                    }
+                    @for revision in &history {
+                        tr {
+                            td { (revision.changed_at.format(DATETIME_FORMAT)) }
+                            td { (revision.period) }
+                            td { (revision.copy) }
+                            td { (revision.statistic_type) }
+                            td { (revision.old_value.map(|value| value.to_string()).unwrap_or_default()) }
+                            td { (revision.new_value.map(|value| value.to_string()).unwrap_or_default()) }
+                        }
+                    }
+                }
+            }
+            p {
+                a href=(keep_lang(supplier_path(supplier_id, &access.token), requested)) { (t!("back", locale = locale)) }
+            }
+        },
+    );
+
+    Ok(ok)
+}
@@ -1,4 +1,4 @@
-use axum::extract::Path;
+use crate::extract::{Path, Query};
 use axum::extract::State;
 use diesel::prelude::*;
 use maud::{html, Markup};
@@ -6,23 +6,43 @@ use rust_i18n::t;
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
-use crate::db::{StatisticsCollector, SupplierId};
+use crate::db::{PeriodId, StatisticsCollector, SupplierId};
 use crate::routes::supplier::submit::FormKey;
+use crate::routes::supplier::{keep_lang, SupplierAccess};
 
 use crate::errors::AppError;
+use crate::logic::locale::{Locale, RequestedLocale};
 use crate::logic::render_html;
+use crate::logic::submissions::load_submissions;
 use crate::logic::time::Clock;
+use crate::logic::token::{authorize_supplier, supplier_path};
 use crate::{db, schema};
 
-struct InputPageData {
-    collector_name: String,
-    client: String,
-    supplier: db::Supplier,
-    placement_type: db::PlacementType,
-    periods: Vec<db::Period>,
-    copies: Vec<db::Copy>,
-    statistic_types: Vec<db::StatisticType>,
-    values: BTreeMap<FormKey, i32>,
+pub struct InputPageData {
+    pub collector_name: String,
+    pub client: String,
+    pub supplier: db::Supplier,
+    pub placement_type: db::PlacementType,
+    /// Sorted by start date
+    pub periods: Vec<db::Period>,
+    /// Sorted by id
+    pub copies: Vec<db::Copy>,
+    /// Sorted by id
+    pub statistic_types: Vec<db::StatisticType>,
+    /// Only the values which are filled in
+    pub values: BTreeMap<FormKey, i32>,
+    pub submissions: BTreeMap<PeriodId, db::PeriodSubmission>,
+    /// Previous values of the statistics changed by the last submission
+    pub changed: BTreeMap<FormKey, Option<i32>>,
+    /// Used if the supplier has no language of their own
+    pub collector_locale: Locale,
+}
+
+impl InputPageData {
+    /// Language of the supplier's pages and spreadsheets
+    pub fn locale(&self, requested: RequestedLocale) -> Locale {
+        requested.resolve(self.supplier.locale(), self.collector_locale)
+    }
 }
 
 static DATETIME_FORMAT: &str = "%H:%M:%S %d-%m-%Y";
@@ -32,80 +52,30 @@ static DATETIME_FORMAT: &str = "%H:%M:%S %d-%m-%Y";
     get,
     path = "/supplier/{uuid}",
     params(
-        ("uuid" = Uuid, Path, description = "Supplier id")
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier"),
+        ("lang" = Option<Locale>, Query, description = "Language of the page, else the supplier's, `Accept-Language` or the collector's language")
     ),
     responses(
         (status = 200, description = "Ok", content_type = "text/html"),
-        (status = 404, description = "No such id", content_type = "text/html")
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
     )
 )]
 pub async fn show_input_page(
     State(pool): State<deadpool_diesel::postgres::Pool>,
     State(clock): State<Arc<Mutex<dyn Clock>>>,
     Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
 ) -> Result<Markup, AppError> {
+    let now = clock.lock().unwrap().now();
+    let token = access.token.clone();
     let conn = pool.get().await?;
     let input_page_data = conn
         .interact(move |conn| {
-            let (placement_type, supplier) = schema::suppliers::table
-                .filter(schema::suppliers::id.eq(supplier_id))
-                .inner_join(schema::placement_types::table)
-                .select((db::PlacementType::as_select(), db::Supplier::as_select()))
-                .first(conn)
-                .map_err(|_| AppError::not_found("supplier", supplier_id))?;
-
-            let collector = schema::placement_types::table
-                .filter(schema::placement_types::id.eq(placement_type.id))
-                .inner_join(schema::statistics_collectors::table)
-                .select(schema::statistics_collectors::all_columns)
-                .first::<StatisticsCollector>(conn)?;
-
-            let collector_id = collector.id;
-            let collector_name = collector.name;
-            let client = collector.client;
-
-            let periods = schema::periods::table
-                .filter(schema::periods::statistics_collector_id.eq(collector_id))
-                .select(db::Period::as_select())
-                .load(conn)?;
-
-            let copies = schema::copies::table
-                .filter(schema::copies::placement_type_id.eq(placement_type.id))
-                .select(db::Copy::as_select())
-                .load(conn)?;
-
-            let statistic_types = schema::statistic_types::table
-                .filter(schema::statistic_types::placement_type_id.eq(placement_type.id))
-                .select(db::StatisticType::as_select())
-                .load(conn)?;
-
-            let values = schema::statistics::table
-                .filter(schema::statistics::supplier_id.eq(supplier.id))
-                .select(db::Statistic::as_select())
-                .load(conn)?
-                .into_iter()
-                .map(|statistic| {
-                    (
-                        FormKey {
-                            period_id: statistic.period_id,
-                            statistic_type_id: statistic.statistic_type_id,
-                            copy_id: statistic.copy_id,
-                        },
-                        statistic.value,
-                    )
-                })
-                .collect();
-
-            Ok::<_, AppError>(InputPageData {
-                client,
-                collector_name,
-                supplier,
-                placement_type,
-                periods,
-                copies,
-                statistic_types,
-                values,
-            })
+            authorize_supplier(conn, supplier_id, &token, now)?;
+            load_input_page_data(conn, supplier_id)
         })
         .await??;
 
@@ -116,13 +86,25 @@ pub async fn show_input_page(
         input_page_data.collector_name
     );
 
-    let now = clock.lock().unwrap().now().date_naive();
+    let today = now.date_naive();
+    let locale = input_page_data.locale(requested).as_str();
+    let page_path = keep_lang(supplier_path(supplier_id, &access.token), requested);
+    let token_query = keep_lang(format!("?token={}", access.token), requested);
 
     let ok = render_html::template(
+        locale,
         &title,
         html! {
             h1 { (input_page_data.placement_type.name) " - " (input_page_data.supplier.name) " / " (input_page_data.collector_name)  }
-            h2 { (t!("client")) ":" (input_page_data.client) }
+            h2 { (t!("client", locale = locale)) ":" (input_page_data.client) }
+            p {
+                @for (i, other) in Locale::ALL.iter().enumerate() {
+                    @if i > 0 { " | " }
+                    a href=(format!("{}&lang={}", supplier_path(supplier_id, &access.token), other)) lang=(other) {
+                        (t!("language_name", locale = other.as_str()))
+                    }
+                }
+            }
 
             // Table should look like this:
             // | (empty)    | copy 1 | copy 1 | copy 2 | copy 2 |
@@ -130,12 +112,12 @@ pub async fn show_input_page(
             // | period 1   | input  | input  | input  | input  |
             // | period 2   | input  | input  | input  | input  |
 
-            form method="post" action=(format!("/supplier/{}", supplier_id)) {
+            form method="post" action=(page_path) {
                 table {
                     tr {
                         th { "" }
                         @for copy in &input_page_data.copies {
-                            th colspan=(input_page_data.statistic_types.len()) { (t!("copy")) ":" (copy.name) }
+                            th colspan=(input_page_data.statistic_types.len()) { (t!("copy", locale = locale)) ":" (copy.name) }
                         }
                     }
                     tr {
@@ -145,6 +127,7 @@ pub async fn show_input_page(
                                 th { (statistic_type.name) }
                             }
                         }
+                        th { (t!("status", locale = locale)) }
                     }
                     @for period in &input_page_data.periods {
                         tr {
@@ -157,23 +140,163 @@ pub async fn show_input_page(
                                         copy_id: copy.id,
                                     };
                                     @let name = format!("{}", form_key);
-                                    @let value = input_page_data.values.get(&form_key).copied().unwrap_or(0);
-                                    @let disabled = period.start > now;
-                                    td {
-                                        input type="number" name=(name) id=(name) value=(value) disabled[disabled];
+                                    @let value = input_page_data.values.get(&form_key);
+                                    @let disabled = period.start > today;
+                                    @if let Some(old_value) = input_page_data.changed.get(&form_key) {
+                                        @let old_value = old_value.map(|value| value.to_string()).unwrap_or_default();
+                                        td style="background-color: #ffd" title=(t!("changed_in_last_submission", locale = locale, value = old_value)) {
+                                            input type="number" name=(name) id=(name) value=[value] disabled[disabled];
+                                            " *"
+                                        }
+                                    } @else {
+                                        td {
+                                            input type="number" name=(name) id=(name) value=[value] disabled[disabled];
+                                        }
+                                    }
+                                }
+                            }
+                            td {
+                                @match input_page_data.submissions.get(&period.id) {
+                                    Some(submission) if submission.complete => {
+                                        (t!("period_complete", locale = locale, date = submission.submitted_at.format(DATETIME_FORMAT)))
+                                    }
+                                    Some(submission) => {
+                                        (t!("period_incomplete", locale = locale, date = submission.submitted_at.format(DATETIME_FORMAT)))
                                     }
+                                    None => { (t!("period_not_submitted", locale = locale)) }
                                 }
                             }
                         }
                     }
                 }
                 p {
-                    (t!("last_submitted")) ": " (input_page_data.supplier.submitted_date.format(DATETIME_FORMAT))
+                    (t!("last_submitted", locale = locale)) ": "
+                    @match input_page_data.submissions.values().map(|submission| submission.submitted_at).max() {
+                        Some(submitted_at) => { (submitted_at.format(DATETIME_FORMAT)) }
+                        None => { (t!("never_submitted", locale = locale)) }
+                    }
+                    " | "
+                    a href=(format!("/supplier/{}/history{}", supplier_id, token_query)) { (t!("history", locale = locale)) }
+                }
+                @if !input_page_data.changed.is_empty() {
+                    p { "* " (t!("changed_in_last_submission_legend", locale = locale)) }
                 }
-                input type="submit" value=(t!("submit"));
+                input type="submit" value=(t!("submit", locale = locale));
+            }
+
+            h3 { (t!("upload_spreadsheet", locale = locale)) }
+            p {
+                (t!("download_template", locale = locale)) ": "
+                a href=(format!("/supplier/{}/template.xlsx{}", supplier_id, token_query)) { "XLSX" }
+                " | "
+                a href=(format!("/supplier/{}/template.csv{}", supplier_id, token_query)) { "CSV" }
+            }
+            form method="post" action=(format!("/supplier/{}/upload{}", supplier_id, token_query)) enctype="multipart/form-data" {
+                input type="file" name="file" accept=".csv,.xlsx" required;
+                input type="submit" value=(t!("upload_spreadsheet", locale = locale));
             }
         },
     );
 
     Ok(ok)
 }
+
+pub fn load_input_page_data(
+    conn: &mut PgConnection,
+    supplier_id: SupplierId,
+) -> Result<InputPageData, AppError> {
+    let (placement_type, supplier) = schema::suppliers::table
+        .filter(schema::suppliers::id.eq(supplier_id))
+        .inner_join(schema::placement_types::table)
+        .select((db::PlacementType::as_select(), db::Supplier::as_select()))
+        .first(conn)
+        .map_err(|_| AppError::not_found("supplier", supplier_id))?;
+
+    let collector = schema::placement_types::table
+        .filter(schema::placement_types::id.eq(placement_type.id))
+        .inner_join(schema::statistics_collectors::table)
+        .select(schema::statistics_collectors::all_columns)
+        .first::<StatisticsCollector>(conn)?;
+
+    let collector_id = collector.id;
+    let collector_locale = collector.locale();
+    let collector_name = collector.name;
+    let client = collector.client;
+
+    let periods = schema::periods::table
+        .filter(schema::periods::statistics_collector_id.eq(collector_id))
+        .order_by(schema::periods::start)
+        .select(db::Period::as_select())
+        .load(conn)?;
+
+    let copies = schema::copies::table
+        .filter(schema::copies::placement_type_id.eq(placement_type.id))
+        .order_by(schema::copies::id)
+        .select(db::Copy::as_select())
+        .load(conn)?;
+
+    let statistic_types = schema::statistic_types::table
+        .filter(schema::statistic_types::placement_type_id.eq(placement_type.id))
+        .order_by(schema::statistic_types::id)
+        .select(db::StatisticType::as_select())
+        .load(conn)?;
+
+    let values = schema::statistics::table
+        .filter(schema::statistics::supplier_id.eq(supplier.id))
+        .select(db::Statistic::as_select())
+        .load(conn)?
+        .into_iter()
+        .filter_map(|statistic| {
+            Some((
+                FormKey {
+                    period_id: statistic.period_id,
+                    statistic_type_id: statistic.statistic_type_id,
+                    copy_id: statistic.copy_id,
+                },
+                statistic.value?,
+            ))
+        })
+        .collect();
+
+    let submissions = load_submissions(conn, supplier.id)?;
+    let last_submitted = submissions
+        .values()
+        .map(|submission| submission.submitted_at)
+        .max();
+
+    let changed = schema::statistic_revisions::table
+        .filter(schema::statistic_revisions::supplier_id.eq(supplier.id))
+        .filter(
+            schema::statistic_revisions::changed_at
+                .nullable()
+                .eq(last_submitted),
+        )
+        .select(db::StatisticRevision::as_select())
+        .load(conn)?
+        .into_iter()
+        .filter_map(|revision| {
+            Some((
+                FormKey {
+                    period_id: revision.period_id?,
+                    statistic_type_id: revision.statistic_type_id?,
+                    copy_id: revision.copy_id?,
+                },
+                revision.old_value,
+            ))
+        })
+        .collect();
+
+    Ok(InputPageData {
+        client,
+        collector_name,
+        supplier,
+        placement_type,
+        periods,
+        copies,
+        statistic_types,
+        values,
+        submissions,
+        changed,
+        collector_locale,
+    })
+}
@@ -1,17 +1,28 @@
 use crate::db::{CopyId, PeriodId, StatisticTypeId, SupplierId};
 
 use crate::errors::AppError;
+use crate::extract::{Form, Path, Query};
+use crate::logic::history::{record_changes, RequestMetadata};
+use crate::logic::locale::RequestedLocale;
+use crate::logic::submissions::record_submission;
+use crate::logic::time::Clock;
+use crate::logic::token::{authorize_supplier, supplier_path};
+use crate::logic::validation::Problems;
+use crate::routes::supplier::show::load_input_page_data;
+use crate::routes::supplier::template::grid;
+use crate::routes::supplier::{keep_lang, SupplierAccess};
 use crate::{db, schema};
-use axum::extract::{Path, State};
+use axum::extract::State;
+use axum::http::HeaderMap;
 
 use axum::response::Redirect;
-use axum::Form;
 use diesel::prelude::*;
 use diesel::upsert::excluded;
 use diesel::RunQueryDsl;
 use serde::{Deserialize, Deserializer};
 use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::sync::{Arc, Mutex};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
@@ -84,7 +95,8 @@ impl<'de> Deserialize<'de> for FormValue {
     post,
     path = "/supplier/{uuid}",
     params(
-        ("uuid" = Uuid, Path, description = "Supplier id")
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier")
     ),
     request_body(
         content = BTreeMap<FormKey, FormValue>,
@@ -94,31 +106,54 @@ impl<'de> Deserialize<'de> for FormValue {
     ),
     responses(
         (status = 200, description = "Ok", content_type = "text/html"),
-        (status = 404, description = "No such id", content_type = "text/html")
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse),
+        (status = 422, description = "A value isn't a cell of the supplier or its period hasn't started", body = ErrorResponse)
     )
 )]
-#[axum::debug_handler]
+#[axum::debug_handler(state = crate::AppState)]
 pub async fn submit_input(
     State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
     Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
+    headers: HeaderMap,
     Form(form): Form<BTreeMap<FormKey, FormValue>>,
 ) -> Result<Redirect, AppError> {
+    let now = clock.lock().unwrap().now();
+    let token = access.token.clone();
+    let metadata = RequestMetadata::from_headers(&headers);
     let conn = pool.get().await?;
     conn.interact(move |conn| {
+        let token_id = authorize_supplier(conn, supplier_id, &token, now)?;
+
+        // only the cells shown on the supplier page can be submitted
+        let page_data = load_input_page_data(conn, supplier_id)?;
+        let grid = grid(&page_data, page_data.locale(requested));
+        let mut problems = Problems::default();
+        for key in form.keys() {
+            if let Err(message) = grid.check_key(key, now.date_naive()) {
+                problems.add(format!("/{}", key), message);
+            }
+        }
+        problems.into_result()?;
+
         conn.transaction(move |conn| {
             let data: Vec<db::Statistic> = form
                 .iter()
-                .filter_map(|(k, v)| {
-                    v.0.map(|v| db::Statistic {
-                        period_id: k.period_id,
-                        supplier_id,
-                        statistic_type_id: k.statistic_type_id,
-                        copy_id: k.copy_id,
-                        value: v,
-                    })
+                .map(|(k, v)| db::Statistic {
+                    period_id: k.period_id,
+                    supplier_id,
+                    statistic_type_id: k.statistic_type_id,
+                    copy_id: k.copy_id,
+                    value: v.0,
                 })
                 .collect();
 
+            let changed_periods =
+                record_changes(conn, supplier_id, &data, Some(token_id), &metadata, now)?;
+
             // Upsert statistics
             diesel::insert_into(schema::statistics::table)
                 .values(&data)
@@ -132,15 +167,18 @@ pub async fn submit_input(
                 .set(schema::statistics::value.eq(excluded(schema::statistics::value)))
                 .execute(conn)?;
 
-            // Update "submitted_date" for the supplier
-            diesel::update(schema::suppliers::table.filter(schema::suppliers::id.eq(supplier_id)))
-                .set(schema::suppliers::submitted_date.eq(diesel::dsl::now))
-                .execute(conn)?;
+            // "submitted_at" of the periods is equal to "changed_at" of the revisions,
+            // periods whose values didn't change keep their last submission
+            record_submission(conn, supplier_id, &changed_periods, now)?;
 
             Ok::<_, diesel::result::Error>(())
-        })
+        })?;
+        Ok::<_, AppError>(())
     })
     .await??;
 
-    Ok(Redirect::to(&format!("/supplier/{}", supplier_id)))
+    Ok(Redirect::to(&keep_lang(
+        supplier_path(supplier_id, &access.token),
+        requested,
+    )))
 }
@@ -0,0 +1,121 @@
+use crate::extract::{Path, Query};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use std::sync::{Arc, Mutex};
+
+use crate::db::SupplierId;
+use crate::errors::AppError;
+use crate::logic::locale::{Locale, RequestedLocale};
+use crate::logic::spreadsheet::SupplierGrid;
+use crate::logic::time::Clock;
+use crate::logic::token::authorize_supplier;
+use crate::routes::statistics_collector::export::{attachment, XLSX_CONTENT_TYPE};
+use crate::routes::supplier::show::{load_input_page_data, InputPageData};
+use crate::routes::supplier::SupplierAccess;
+
+/// Downloads the supplier's table as CSV, filled with the current values.
+/// It can be uploaded back once filled in.
+#[utoipa::path(
+    get,
+    path = "/supplier/{uuid}/template.csv",
+    params(
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier"),
+        ("lang" = Option<Locale>, Query, description = "Language of the headers, else the supplier's, `Accept-Language` or the collector's language")
+    ),
+    responses(
+        (status = 200, description = "Ok", content_type = "text/csv"),
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn download_template_csv(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
+) -> Result<impl IntoResponse, AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    let data = conn
+        .interact(move |conn| {
+            authorize_supplier(conn, supplier_id, &access.token, now)?;
+            load_input_page_data(conn, supplier_id)
+        })
+        .await??;
+
+    let csv = grid(&data, data.locale(requested)).to_csv(&data.values)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, file_name(&data, "csv")),
+        ],
+        csv,
+    ))
+}
+
+/// Downloads the supplier's table as XLSX, filled with the current values.
+/// It can be uploaded back once filled in.
+#[utoipa::path(
+    get,
+    path = "/supplier/{uuid}/template.xlsx",
+    params(
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier"),
+        ("lang" = Option<Locale>, Query, description = "Language of the headers, else the supplier's, `Accept-Language` or the collector's language")
+    ),
+    responses(
+        (status = 200, description = "Ok", content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn download_template_xlsx(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
+) -> Result<impl IntoResponse, AppError> {
+    let now = clock.lock().unwrap().now();
+    let conn = pool.get().await?;
+    let data = conn
+        .interact(move |conn| {
+            authorize_supplier(conn, supplier_id, &access.token, now)?;
+            load_input_page_data(conn, supplier_id)
+        })
+        .await??;
+
+    let xlsx = grid(&data, data.locale(requested)).to_xlsx(&data.values)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, XLSX_CONTENT_TYPE.to_string()),
+            (header::CONTENT_DISPOSITION, file_name(&data, "xlsx")),
+        ],
+        xlsx,
+    ))
+}
+
+pub fn grid(data: &InputPageData, locale: Locale) -> SupplierGrid<'_> {
+    SupplierGrid {
+        periods: &data.periods,
+        copies: &data.copies,
+        statistic_types: &data.statistic_types,
+        locale,
+    }
+}
+
+fn file_name(data: &InputPageData, extension: &str) -> String {
+    attachment(
+        &format!(
+            "{} {} {}",
+            data.collector_name, data.placement_type.name, data.supplier.name
+        ),
+        extension,
+    )
+}
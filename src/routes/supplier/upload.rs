@@ -0,0 +1,170 @@
+use crate::extract::{Path, Query};
+use axum::extract::{Multipart, State};
+use maud::{html, Markup};
+use rust_i18n::t;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::db::SupplierId;
+use crate::errors::AppError;
+use crate::logic::locale::RequestedLocale;
+use crate::logic::render_html;
+use crate::logic::spreadsheet::{read_cells, ParsedGrid};
+use crate::logic::time::Clock;
+use crate::logic::token::{authorize_supplier, supplier_path};
+use crate::routes::supplier::show::load_input_page_data;
+use crate::routes::supplier::submit::FormKey;
+use crate::routes::supplier::template::grid;
+use crate::routes::supplier::{keep_lang, SupplierAccess};
+
+/// Form sent by the supplier page to upload a spreadsheet, only used for documentation
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct SpreadsheetUpload {
+    /// CSV or XLSX file laid out like the templates
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+/// Shows a preview of values from an uploaded spreadsheet. This is not meant to be used manually.
+/// It's used by the supplier page, the preview submits the values the same way the supplier page does.
+#[utoipa::path(
+    post,
+    path = "/supplier/{uuid}/upload",
+    params(
+        ("uuid" = Uuid, Path, description = "Supplier id"),
+        ("token" = String, Query, description = "Access token of the supplier"),
+        ("lang" = Option<Locale>, Query, description = "Language of the page, else the supplier's, `Accept-Language` or the collector's language")
+    ),
+    request_body(
+        content = SpreadsheetUpload,
+        content_type = "multipart/form-data",
+    ),
+    responses(
+        (status = 200, description = "Ok", content_type = "text/html"),
+        (status = 400, description = "No file in the form", body = ErrorResponse),
+        (status = 403, description = "Missing, invalid or expired token", body = ErrorResponse),
+        (status = 404, description = "No such id", body = ErrorResponse)
+    )
+)]
+pub async fn upload_input(
+    State(pool): State<deadpool_diesel::postgres::Pool>,
+    State(clock): State<Arc<Mutex<dyn Clock>>>,
+    Path(supplier_id): Path<SupplierId>,
+    Query(access): Query<SupplierAccess>,
+    requested: RequestedLocale,
+    mut multipart: Multipart,
+) -> Result<Markup, AppError> {
+    let mut file = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(AppError::bad_request)?
+    {
+        if field.name() == Some("file") {
+            file = Some(field.bytes().await.map_err(AppError::bad_request)?);
+        }
+    }
+    let file = file.ok_or_else(|| AppError::bad_request("no file in the form"))?;
+
+    let now = clock.lock().unwrap().now();
+    let token = access.token.clone();
+    let conn = pool.get().await?;
+    let data = conn
+        .interact(move |conn| {
+            authorize_supplier(conn, supplier_id, &token, now)?;
+            load_input_page_data(conn, supplier_id)
+        })
+        .await??;
+
+    let today = now.date_naive();
+    let locale = data.locale(requested);
+    let page_path = keep_lang(supplier_path(supplier_id, &access.token), requested);
+    let parsed = match read_cells(&file) {
+        Ok(cells) => grid(&data, locale).parse(&cells, today),
+        Err(error) => ParsedGrid {
+            errors: vec![
+                t!("upload_unreadable", locale = locale.as_str(), error = error).to_string(),
+            ],
+            ..Default::default()
+        },
+    };
+
+    let title = format!(
+        "{} - {} / {}",
+        data.placement_type.name, data.supplier.name, data.collector_name
+    );
+
+    let locale = locale.as_str();
+    let ok = render_html::template(
+        locale,
+        &title,
+        html! {
+            h1 { (data.placement_type.name) " - " (data.supplier.name) " / " (data.collector_name)  }
+            h2 { (t!("upload_preview", locale = locale)) }
+
+            @if !parsed.errors.is_empty() {
+                ul {
+                    @for error in &parsed.errors {
+                        li style="color: #b00" { (error) }
+                    }
+                }
+            }
+
+            form method="post" action=(page_path) {
+                table {
+                    tr {
+                        th { "" }
+                        @for copy in &data.copies {
+                            th colspan=(data.statistic_types.len()) { (t!("copy", locale = locale)) ":" (copy.name) }
+                        }
+                    }
+                    tr {
+                        th { "" }
+                        @for _copy in &data.copies {
+                            @for statistic_type in &data.statistic_types {
+                                th { (statistic_type.name) }
+                            }
+                        }
+                    }
+                    @for period in &data.periods {
+                        tr {
+                            th { (period.name) }
+                            @for copy in &data.copies {
+                                @for statistic_type in &data.statistic_types {
+                                    @let form_key = FormKey {
+                                        period_id: period.id,
+                                        statistic_type_id: statistic_type.id,
+                                        copy_id: copy.id,
+                                    };
+                                    @if let Some((text, error)) = parsed.cell_errors.get(&form_key) {
+                                        td style="background-color: #fdd" title=(error) {
+                                            (text) br; small { (error) }
+                                        }
+                                    } @else if let Some(value) = parsed.values.get(&form_key) {
+                                        @let name = format!("{}", form_key);
+                                        td {
+                                            input type="number" name=(name) id=(name) value=(value) readonly;
+                                        }
+                                    } @else {
+                                        td { "" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                @if parsed.is_valid() {
+                    input type="submit" value=(t!("upload_confirm", locale = locale));
+                } @else {
+                    p { (t!("upload_errors", locale = locale)) }
+                }
+            }
+            p {
+                a href=(page_path) { (t!("upload_back", locale = locale)) }
+            }
+        },
+    );
+
+    Ok(ok)
+}
@@ -1,5 +1,25 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        name -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        created_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    collector_templates (id) {
+        id -> Uuid,
+        name -> Text,
+        collector -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     copies (id) {
         id -> Uuid,
@@ -8,6 +28,65 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    derived_statistic_types (id) {
+        id -> Uuid,
+        statistics_collector_id -> Uuid,
+        name -> Text,
+        formula -> Text,
+    }
+}
+
+diesel::table! {
+    email_images (name) {
+        name -> Text,
+        content_type -> Text,
+        data -> Bytea,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_templates (id) {
+        id -> Uuid,
+        kind -> Text,
+        locale -> Text,
+        version -> Int4,
+        subject -> Text,
+        body -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    outbox (id) {
+        id -> Uuid,
+        statistics_collector_id -> Uuid,
+        supplier_id -> Uuid,
+        period_id -> Nullable<Uuid>,
+        kind -> Text,
+        recipient -> Text,
+        status -> Text,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+        forced -> Bool,
+        supplier_token_id -> Nullable<Uuid>,
+        token -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    period_submissions (supplier_id, period_id) {
+        supplier_id -> Uuid,
+        period_id -> Uuid,
+        submitted_at -> Timestamptz,
+        complete -> Bool,
+    }
+}
+
 diesel::table! {
     periods (id) {
         id -> Uuid,
@@ -26,6 +105,50 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    reminder_deliveries (id) {
+        id -> Uuid,
+        supplier_id -> Uuid,
+        period_id -> Nullable<Uuid>,
+        reminder_type -> Text,
+        attempted_at -> Timestamptz,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    reminder_rules (id) {
+        id -> Uuid,
+        statistics_collector_id -> Uuid,
+        days_after_end -> Int4,
+        hour -> Int4,
+        reminder_type -> Text,
+        recipients -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    statistic_revisions (id) {
+        id -> Uuid,
+        supplier_id -> Nullable<Uuid>,
+        period_id -> Nullable<Uuid>,
+        statistic_type_id -> Nullable<Uuid>,
+        copy_id -> Nullable<Uuid>,
+        old_value -> Nullable<Int4>,
+        new_value -> Nullable<Int4>,
+        changed_at -> Timestamptz,
+        supplier_token_id -> Nullable<Uuid>,
+        user_agent -> Nullable<Text>,
+        forwarded_for -> Nullable<Text>,
+        statistics_collector_id -> Uuid,
+        supplier_name -> Text,
+        placement_type_name -> Text,
+        period_name -> Text,
+        statistic_type_name -> Text,
+        copy_name -> Text,
+    }
+}
+
 diesel::table! {
     statistic_types (id) {
         id -> Uuid,
@@ -40,7 +163,7 @@ diesel::table! {
         supplier_id -> Uuid,
         statistic_type_id -> Uuid,
         copy_id -> Uuid,
-        value -> Int4,
+        value -> Nullable<Int4>,
     }
 }
 
@@ -50,7 +173,23 @@ diesel::table! {
         name -> Text,
         client -> Text,
         periodicity -> Text,
-        weekday -> Text,
+        weekday -> Nullable<Text>,
+        account_manager_mail -> Nullable<Text>,
+        created_at -> Timestamptz,
+        archived_at -> Nullable<Timestamptz>,
+        deleted_at -> Nullable<Timestamptz>,
+        locale -> Text,
+    }
+}
+
+diesel::table! {
+    supplier_tokens (id) {
+        id -> Uuid,
+        supplier_id -> Uuid,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -60,26 +199,54 @@ diesel::table! {
         name -> Text,
         mail -> Text,
         placement_type_id -> Uuid,
-        submitted_date -> Timestamptz,
+        locale -> Nullable<Text>,
     }
 }
 
 diesel::joinable!(copies -> placement_types (placement_type_id));
+diesel::joinable!(derived_statistic_types -> statistics_collectors (statistics_collector_id));
+diesel::joinable!(outbox -> periods (period_id));
+diesel::joinable!(outbox -> statistics_collectors (statistics_collector_id));
+diesel::joinable!(outbox -> suppliers (supplier_id));
+diesel::joinable!(period_submissions -> periods (period_id));
+diesel::joinable!(outbox -> supplier_tokens (supplier_token_id));
+diesel::joinable!(period_submissions -> suppliers (supplier_id));
 diesel::joinable!(periods -> statistics_collectors (statistics_collector_id));
 diesel::joinable!(placement_types -> statistics_collectors (statistics_collector_id));
+diesel::joinable!(reminder_deliveries -> periods (period_id));
+diesel::joinable!(reminder_deliveries -> suppliers (supplier_id));
+diesel::joinable!(reminder_rules -> statistics_collectors (statistics_collector_id));
+diesel::joinable!(statistic_revisions -> copies (copy_id));
+diesel::joinable!(statistic_revisions -> periods (period_id));
+diesel::joinable!(statistic_revisions -> statistics_collectors (statistics_collector_id));
+diesel::joinable!(statistic_revisions -> statistic_types (statistic_type_id));
+diesel::joinable!(statistic_revisions -> supplier_tokens (supplier_token_id));
+diesel::joinable!(statistic_revisions -> suppliers (supplier_id));
 diesel::joinable!(statistic_types -> placement_types (placement_type_id));
 diesel::joinable!(statistics -> copies (copy_id));
 diesel::joinable!(statistics -> periods (period_id));
 diesel::joinable!(statistics -> statistic_types (statistic_type_id));
 diesel::joinable!(statistics -> suppliers (supplier_id));
+diesel::joinable!(supplier_tokens -> suppliers (supplier_id));
 diesel::joinable!(suppliers -> placement_types (placement_type_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    collector_templates,
     copies,
+    derived_statistic_types,
+    email_images,
+    email_templates,
+    outbox,
+    period_submissions,
     periods,
     placement_types,
+    reminder_deliveries,
+    reminder_rules,
+    statistic_revisions,
     statistic_types,
     statistics,
     statistics_collectors,
+    supplier_tokens,
     suppliers,
 );
@@ -1,11 +1,32 @@
+use axum::http::header::{ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION};
+use axum::http::{HeaderValue, StatusCode};
+use axum_test::multipart::{MultipartForm, Part};
 use axum_test::TestServer;
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Weekday};
+use diesel::RunQueryDsl;
+use lettre::Address;
 use stat_collector::db::StatCollectorId;
-use stat_collector::logic::email::MockMailer;
+use stat_collector::errors::AppError;
+use stat_collector::json::derived::DerivedStatisticType;
+use stat_collector::json::reminders::{ReminderPolicy, ReminderRecipient, ReminderRule};
+use stat_collector::logic::api_key::{create_api_key, ApiScope};
+use stat_collector::logic::archive::purge_deleted_collectors;
+use stat_collector::logic::collector_list::CollectorStatus;
 use stat_collector::logic::email::ReminderType::{FirstReminder, SecondReminder};
-use stat_collector::logic::time::AppClock;
+use stat_collector::logic::email::{Mailer, MockMailer, ReminderType};
+use stat_collector::logic::email_templates::TemplatedEmail;
+use stat_collector::logic::locale::Locale;
+use stat_collector::logic::outbox::{
+    drain_outbox, OutboxKind, OutboxStatus, DEFAULT_SEND_CONCURRENCY,
+};
+use stat_collector::logic::periods::Periodicity;
+use stat_collector::logic::scheduler::enqueue_due_reminders;
+use stat_collector::logic::time::{AppClock, Clock, MockClock};
 use stat_collector::{build_app, db, json};
+use std::collections::BTreeMap;
+use std::future::IntoFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use testcontainers_modules::{postgres::Postgres, testcontainers::clients::Cli};
 use uuid::Uuid;
@@ -30,30 +51,69 @@ async fn main() {
         .build()
         .unwrap();
 
-    let mailer = Arc::new(Mutex::new(MockMailer::new()));
+    let mailer = Arc::new(SharedMailer::default());
     let clock = Arc::new(Mutex::new(AppClock));
 
-    let app = build_app(db_pool, mailer.clone(), clock.clone()).await;
+    let app = build_app(db_pool.clone(), mailer.clone(), clock.clone()).await;
 
-    let server = TestServer::new(app).unwrap();
+    let mut server = TestServer::new(app).unwrap();
+
+    let create_key = |name: &'static str, scopes: Vec<ApiScope>| {
+        let db_pool = db_pool.clone();
+        async move {
+            let conn = db_pool.get().await.unwrap();
+            conn.interact(move |conn| create_api_key(conn, name, &scopes, AppClock.now()))
+                .await
+                .unwrap()
+                .unwrap()
+        }
+    };
+    let bearer = |key: &str| HeaderValue::from_str(&format!("Bearer {}", key)).unwrap();
+
+    // The admin API requires a key with the right scope
+    let response = server.get("/statistics_collector").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let read_key = create_key("read only", vec![ApiScope::Read]).await;
+    let response = server
+        .get("/statistics_collector")
+        .add_header(AUTHORIZATION, bearer(&read_key))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .delete(&format!("/statistics_collector/{}", Uuid::new_v4()))
+        .add_header(AUTHORIZATION, bearer(&read_key))
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+
+    let admin_key = create_key(
+        "admin",
+        vec![ApiScope::Read, ApiScope::Write, ApiScope::SendEmail],
+    )
+    .await;
+    server.add_header(AUTHORIZATION, bearer(&admin_key));
 
     let new_collector = json::received::StatCollector {
         name: "kolektor testowy".to_string(),
         client: "pepsi".to_string(),
-        periodicity: "tygodniowo".to_string(),
-        weekday: "Wednesday".to_string(),
+        periodicity: Periodicity::Weekly,
+        weekday: Some(Weekday::Wed),
+        campaign: None,
         periods: vec![
             json::received::Period {
+                id: None,
                 name: "2023.11.08 - 11.14".to_string(),
                 start_date: NaiveDate::from_ymd_opt(2023, 11, 8).unwrap(),
                 end_date: NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
             },
             json::received::Period {
+                id: None,
                 name: "2023.11.15 - 11.21".to_string(),
                 start_date: NaiveDate::from_ymd_opt(2023, 11, 15).unwrap(),
                 end_date: NaiveDate::from_ymd_opt(2023, 11, 21).unwrap(),
             },
             json::received::Period {
+                id: None,
                 name: "2023.11.22 - 11.28".to_string(),
                 start_date: NaiveDate::from_ymd_opt(2023, 11, 22).unwrap(),
                 end_date: NaiveDate::from_ymd_opt(2023, 11, 28).unwrap(),
@@ -61,32 +121,129 @@ async fn main() {
         ],
         placement_types: vec![
             json::received::PlacementType {
+                id: None,
                 name: "Display".to_string(),
                 suppliers: vec![json::received::Supplier {
+                    id: None,
                     name: "Google".to_string(),
                     mail: "google@google.com".parse().unwrap(),
+                    locale: None,
                 }],
                 statistics: vec!["Conversions".to_string()],
                 copies: vec!["kopia a".to_string(), "kopia b".to_string()],
+                ..Default::default()
             },
             json::received::PlacementType {
+                id: None,
                 name: "Mailing".to_string(),
                 suppliers: vec![
                     json::received::Supplier {
+                        id: None,
                         name: "Inis".to_string(),
                         mail: "inis@inis.com".parse().unwrap(),
+                        locale: None,
                     },
                     json::received::Supplier {
+                        id: None,
                         name: "Inis2".to_string(),
                         mail: "inis2@inis.com".parse().unwrap(),
+                        locale: None,
                     },
                 ],
                 statistics: vec!["Impressions".to_string()],
                 copies: vec!["kopia c".to_string()],
+                ..Default::default()
             },
         ],
+        locale: Some(Locale::Pl),
+        derived_statistic_types: Some(vec![
+            DerivedStatisticType {
+                name: "Conversion rate".to_string(),
+                formula: "Conversions / Impressions".to_string(),
+            },
+            DerivedStatisticType {
+                name: "Doubled".to_string(),
+                formula: "2 * Conversions".to_string(),
+            },
+        ]),
+        reminder_policy: Some(ReminderPolicy {
+            account_manager: Some("manager@test.com".parse().unwrap()),
+            rules: vec![
+                ReminderRule {
+                    days_after_end: 2,
+                    hour: 8,
+                    reminder_type: FirstReminder,
+                    recipients: vec![ReminderRecipient::Supplier],
+                },
+                ReminderRule {
+                    days_after_end: 5,
+                    hour: 8,
+                    reminder_type: SecondReminder,
+                    recipients: vec![ReminderRecipient::Supplier],
+                },
+                ReminderRule {
+                    days_after_end: 7,
+                    hour: 9,
+                    reminder_type: SecondReminder,
+                    recipients: vec![ReminderRecipient::AccountManager],
+                },
+            ],
+        }),
     };
 
+    let mut invalid_collector = new_collector.clone();
+    invalid_collector
+        .reminder_policy
+        .as_mut()
+        .unwrap()
+        .account_manager = None;
+    invalid_collector.periods[1].end_date = NaiveDate::from_ymd_opt(2023, 11, 1).unwrap();
+    invalid_collector.placement_types[1].name = "Display".to_string();
+    let response = server
+        .post("/statistics_collector")
+        .json(&invalid_collector)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    let problems = response.json::<json::sent::ErrorResponse>().details;
+    assert_eq!(
+        problems
+            .iter()
+            .map(|problem| problem.path.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "/periods/1/endDate",
+            "/placementTypes/1/name",
+            "/reminderPolicy/rules/2/recipients"
+        ]
+    );
+
+    // malformed requests get structured errors too
+    let response = server
+        .post("/statistics_collector")
+        .bytes("{\"name\":".into())
+        .content_type("application/json")
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<json::sent::ErrorResponse>().code,
+        json::sent::ErrorCode::BadRequest
+    );
+    let response = server
+        .post("/statistics_collector")
+        .json(&serde_json::json!({ "name": 1 }))
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json::<json::sent::ErrorResponse>().code,
+        json::sent::ErrorCode::ValidationFailed
+    );
+    let response = server.get("/statistics_collector/not-an-id").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<json::sent::ErrorResponse>().code,
+        json::sent::ErrorCode::BadRequest
+    );
+
     let response = server
         .post("/statistics_collector")
         .json(&new_collector)
@@ -95,21 +252,36 @@ async fn main() {
     response.assert_status_ok();
     let id: Uuid = response.json();
 
+    // Conflicting collectors are reported with the id of the existing one
+    let response = server
+        .post("/statistics_collector")
+        .json(&new_collector)
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+    let error = response.json::<json::sent::ErrorResponse>();
+    assert_eq!(error.code, json::sent::ErrorCode::Conflict);
+    assert_eq!(error.id, Some(id.to_string()));
+
     let response = server.get("/statistics_collector").await;
     response.assert_status_ok();
 
     // dbg!(response.text());
 
-    let collectors = response.json::<Vec<db::StatisticsCollector>>();
-    assert_eq!(collectors.len(), 1);
+    let page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.total, 1);
+    assert_eq!(page.next_cursor, None);
 
-    let collector = &collectors[0];
+    let collector = &page.items[0];
 
     assert_eq!(collector.id, StatCollectorId::from(id));
     assert_eq!(collector.name, new_collector.name);
     assert_eq!(collector.client, new_collector.client);
-    assert_eq!(collector.periodicity, new_collector.periodicity);
-    assert_eq!(collector.weekday, new_collector.weekday);
+    assert_eq!(collector.periodicity, Periodicity::Weekly);
+    assert_eq!(collector.weekday, Some(Weekday::Wed));
+    assert_eq!(collector.status, CollectorStatus::Finished);
+    assert_eq!(collector.suppliers, 3);
+    assert_eq!(collector.completion, Some(0.0));
 
     let response = server
         .get(&format!("/statistics_collector/{}/config", id))
@@ -125,6 +297,10 @@ async fn main() {
     assert_eq!(collector.client, new_collector.client);
     assert_eq!(collector.periodicity, new_collector.periodicity);
     assert_eq!(collector.weekday, new_collector.weekday);
+    assert_eq!(
+        Some(collector.reminder_policy.clone()),
+        new_collector.reminder_policy
+    );
     assert_eq!(collector.periods.len(), new_collector.periods.len());
     assert_eq!(
         collector.placement_types.len(),
@@ -132,34 +308,129 @@ async fn main() {
     );
     assert_eq!(collector.id, StatCollectorId::from(id));
 
+    let clock_at = |now: DateTime<Local>| {
+        let mut clock = MockClock::new();
+        clock.expect_now().return_const(now);
+        Arc::new(Mutex::new(clock))
+    };
+    let outbox = |status: OutboxStatus| {
+        let server = &server;
+        async move {
+            let response = server
+                .get(&format!("/statistics_collector/{}/outbox", id))
+                .add_query_param("status", status)
+                .await;
+            response.assert_status_ok();
+            response.json::<Vec<json::sent::OutboxMessage>>()
+        }
+    };
+
+    // Test manual email sending, the emails are only queued
+    mailer.lock().await.expect_send_reminder().never();
+
+    // requests at the same time don't queue a reminder twice
+    // a test server handles one request at a time, so the other one goes to a second server
+    let mut second_server =
+        TestServer::new(build_app(db_pool.clone(), mailer.clone(), clock.clone()).await).unwrap();
+    second_server.add_header(AUTHORIZATION, bearer(&admin_key));
+    let send_emails = format!("/statistics_collector/{}/send_emails/FirstReminder", id);
+    let (response, concurrent) = futures::join!(
+        server.post(&send_emails).into_future(),
+        second_server.post(&send_emails).into_future()
+    );
+
+    response.assert_status_ok();
+    concurrent.assert_status_ok();
+    let concurrent = concurrent.json::<json::sent::QueuedReminders>();
+    let queued = response.json::<json::sent::QueuedReminders>();
+    assert_eq!(
+        (
+            queued.queued + concurrent.queued,
+            queued.skipped + concurrent.skipped
+        ),
+        (3, 3)
+    );
+    // the most recently ended period
+    assert_eq!(
+        Some(queued.period_id),
+        collector.periods.last().map(|period| period.id)
+    );
+
+    // Suppliers aren't reminded twice unless forced
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            id
+        ))
+        .await;
+
+    response.assert_status_ok();
+    let queued = response.json::<json::sent::QueuedReminders>();
+    assert_eq!((queued.queued, queued.skipped), (0, 3));
+
+    mailer.lock().await.checkpoint();
+
+    let pending = outbox(OutboxStatus::Pending).await;
+    assert_eq!(pending.len(), 3);
+    assert!(pending.iter().all(|message| message.attempts == 0));
+
     mailer
         .lock()
-        .unwrap()
+        .await
         .expect_send_reminder()
-        .withf(move |_, _, _, reminder_type| *reminder_type == FirstReminder)
+        .withf(move |_, _, _, _, reminder_type, email| {
+            *reminder_type == FirstReminder && email.template.locale == Locale::Pl
+        })
         .times(3)
-        .returning(|_, _, _, _| Ok(()));
+        .returning(|_, _, _, _, _, _| Ok(()));
+
+    let sent = drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, 3);
+
+    mailer.lock().await.checkpoint();
+
+    assert!(outbox(OutboxStatus::Pending).await.is_empty());
+    assert_eq!(outbox(OutboxStatus::Sent).await.len(), 3);
 
-    // Test manual email sending
     let response = server
         .post(&format!(
             "/statistics_collector/{}/send_emails/FirstReminder",
             id
         ))
+        .add_query_param("force", true)
         .await;
 
     response.assert_status_ok();
-
-    mailer.lock().unwrap().checkpoint();
+    let queued = response.json::<json::sent::QueuedReminders>();
+    assert_eq!((queued.queued, queued.skipped), (3, 0));
 
     mailer
         .lock()
-        .unwrap()
+        .await
         .expect_send_reminder()
-        .withf(move |_, _, _, reminder_type| *reminder_type == SecondReminder)
+        .withf(move |_, _, _, _, reminder_type, _| *reminder_type == FirstReminder)
         .times(3)
-        .returning(|_, _, _, _| Ok(()));
+        .returning(|_, _, _, _, _, _| Ok(()));
+
+    drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
 
+    mailer.lock().await.checkpoint();
+
+    // Failed emails are retried with a delay
     let response = server
         .post(&format!(
             "/statistics_collector/{}/send_emails/SecondReminder",
@@ -168,4 +439,1799 @@ async fn main() {
         .await;
 
     response.assert_status_ok();
+
+    let failed_tokens = Arc::new(Mutex::new(Vec::new()));
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, _, _, reminder_type, _| *reminder_type == SecondReminder)
+        .times(3)
+        .returning({
+            let failed_tokens = failed_tokens.clone();
+            move |_, _, _, token, _, _| {
+                failed_tokens.lock().unwrap().push(token);
+                Err(anyhow::anyhow!("connection refused").into())
+            }
+        });
+
+    let now = Local::now();
+    let sent = drain_outbox(
+        db_pool.clone(),
+        clock_at(now),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, 0);
+
+    mailer.lock().await.checkpoint();
+
+    let pending = outbox(OutboxStatus::Pending).await;
+    assert_eq!(pending.len(), 3);
+    assert!(pending.iter().all(|message| message.attempts == 1
+        && message.last_error.as_deref() == Some("connection refused")));
+
+    // not due yet
+    mailer.lock().await.expect_send_reminder().never();
+    let sent = drain_outbox(
+        db_pool.clone(),
+        clock_at(now + Duration::seconds(30)),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, 0);
+
+    mailer.lock().await.checkpoint();
+
+    // retries send the same link
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, _, token, reminder_type, _| {
+            *reminder_type == SecondReminder && failed_tokens.lock().unwrap().contains(token)
+        })
+        .times(3)
+        .returning(|_, _, _, _, _, _| Ok(()));
+
+    // they're sent when the mail server answers, which is after they were claimed
+    let claimed_at = now + Duration::minutes(2);
+    let mut retry_clock = MockClock::new();
+    retry_clock.expect_now().times(1).return_const(claimed_at);
+    retry_clock
+        .expect_now()
+        .return_const(claimed_at + Duration::seconds(5));
+    let sent = drain_outbox(
+        db_pool.clone(),
+        Arc::new(Mutex::new(retry_clock)),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, 3);
+
+    mailer.lock().await.checkpoint();
+
+    let sent = outbox(OutboxStatus::Sent).await;
+    assert_eq!(sent.len(), 9);
+    assert!(sent.iter().all(|message| message.last_error.is_none()));
+    let retried = sent
+        .iter()
+        .filter(|message| message.kind == OutboxKind::SecondReminder)
+        .collect::<Vec<_>>();
+    assert_eq!(retried.len(), 3);
+    assert!(retried
+        .iter()
+        .all(|message| message.sent_at.unwrap() > claimed_at));
+
+    // Every attempt is shown on the collector's page
+    let response = server.get(&format!("/statistics_collector/{}", id)).await;
+    response.assert_status_ok();
+    let page = response.text();
+    assert_eq!(page.matches("Wysłano").count(), 9);
+    assert_eq!(page.matches("connection refused").count(), 3);
+
+    // Test the reminder policy, nobody has submitted anything yet
+    let at = |day, hour| clock_at(Local.with_ymd_and_hms(2023, 11, day, hour, 0, 0).unwrap());
+
+    // first reminder about the period ending on 14th
+    enqueue_due_reminders(db_pool.clone(), at(16, 8))
+        .await
+        .unwrap();
+    // restarting the server doesn't send it twice
+    enqueue_due_reminders(db_pool.clone(), at(16, 8))
+        .await
+        .unwrap();
+    // nothing is due at this hour
+    enqueue_due_reminders(db_pool.clone(), at(16, 9))
+        .await
+        .unwrap();
+    // second reminder and escalation
+    enqueue_due_reminders(db_pool.clone(), at(19, 8))
+        .await
+        .unwrap();
+    enqueue_due_reminders(db_pool.clone(), at(21, 9))
+        .await
+        .unwrap();
+    // nor the escalation
+    enqueue_due_reminders(db_pool.clone(), at(21, 9))
+        .await
+        .unwrap();
+
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, _, _, reminder_type, _| *reminder_type == FirstReminder)
+        .times(3)
+        .returning(|_, _, _, _, _, _| Ok(()));
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, _, _, reminder_type, _| *reminder_type == SecondReminder)
+        .times(3)
+        .returning(|_, _, _, _, _, _| Ok(()));
+    mailer
+        .lock()
+        .await
+        .expect_send_escalation()
+        .withf(|_, to_email, _, period, email| {
+            to_email.to_string() == "manager@test.com"
+                && email.template.locale == Locale::Pl
+                && period.end == NaiveDate::from_ymd_opt(2023, 11, 14).unwrap()
+        })
+        .times(3)
+        .returning(|_, _, _, _, _| Ok(()));
+
+    drain_outbox(
+        db_pool.clone(),
+        at(21, 9),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+
+    mailer.lock().await.checkpoint();
+
+    // Test in-place update
+    let google = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap()
+        .suppliers[0]
+        .clone();
+
+    // Supplier pages can only be opened with a valid token
+    let response = server.get(&format!("/supplier/{}", google.id)).await;
+    response.assert_status(StatusCode::FORBIDDEN);
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", "invalid")
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            id, google.id
+        ))
+        .await;
+    response.assert_status_ok();
+    let token = response.json::<json::sent::SupplierToken>().token;
+
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            Uuid::new_v4(),
+            google.id
+        ))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    response.assert_status_ok();
+
+    let form = input_names(&response.text())
+        .into_iter()
+        .map(|name| (name, "7".to_string()))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(form.len(), 2 * 3);
+
+    // Only the supplier's own cells can be submitted
+    let (first_key, _) = form.first_key_value().unwrap();
+    let foreign_key = format!(
+        "{},{}",
+        first_key.rsplit_once(',').unwrap().0,
+        Uuid::new_v4()
+    );
+    let mut foreign_form = form.clone();
+    foreign_form.insert(foreign_key, "7".to_string());
+    let response = server
+        .post(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .form(&foreign_form)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = server
+        .post(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .form(&form)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    // Test history
+    let response = server
+        .get(&format!("/statistics_collector/{}/history", id))
+        .await;
+    response.assert_status_ok();
+    let history = response.json::<Vec<json::sent::StatisticRevision>>();
+    assert_eq!(history.len(), 6);
+    assert!(history
+        .iter()
+        .all(|revision| revision.supplier_id == Some(google.id)
+            && revision.old_value.is_none()
+            && revision.new_value == Some(7)));
+
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    assert_eq!(response.text().matches(CHANGED_MARKER).count(), 6);
+
+    let response = server
+        .get(&format!("/supplier/{}/history", google.id))
+        .add_query_param("token", &token)
+        .await;
+    response.assert_status_ok();
+
+    let mut updated_collector: json::received::StatCollector =
+        serde_json::from_value(serde_json::to_value(&collector).unwrap()).unwrap();
+    updated_collector.periods[0].name = "renamed period".to_string();
+    // the Django app doesn't send the reminder policy and the derived statistic types
+    updated_collector.reminder_policy = None;
+    updated_collector.derived_statistic_types = None;
+    updated_collector.periods.pop();
+    for placement_type in &mut updated_collector.placement_types {
+        match placement_type.name.as_str() {
+            "Display" => {
+                placement_type.copies.push("kopia d".to_string());
+                placement_type.copy_ids.push(None);
+            }
+            _ => placement_type
+                .suppliers
+                .retain(|supplier| supplier.name != "Inis2"),
+        }
+    }
+
+    let response = server
+        .put(&format!("/statistics_collector/{}", id))
+        .json(&updated_collector)
+        .await;
+    response.assert_status_ok();
+
+    let collector = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await
+        .json::<json::sent::StatCollector>();
+
+    assert_eq!(collector.periods.len(), 2);
+    assert_eq!(collector.periods[0].name, "renamed period");
+    assert_eq!(
+        Some(collector.reminder_policy.clone()),
+        new_collector.reminder_policy
+    );
+    assert_eq!(
+        Some(collector.derived_statistic_types.clone()),
+        new_collector.derived_statistic_types
+    );
+
+    let display = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap();
+    assert_eq!(display.suppliers.len(), 1);
+    assert_eq!(display.suppliers[0].id, google.id);
+
+    let new_copy = display
+        .copies
+        .iter()
+        .position(|copy| copy == "kopia d")
+        .unwrap();
+    let stats = &display.suppliers[0].stats[0];
+    assert_eq!(stats.len(), 3);
+    for (copy, values) in stats.iter().enumerate() {
+        let expected = if copy == new_copy { None } else { Some(7) };
+        assert_eq!(values, &vec![expected; 2]);
+    }
+
+    let mailing = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Mailing")
+        .unwrap();
+    assert_eq!(mailing.suppliers.len(), 1);
+    assert_eq!(mailing.suppliers[0].name, "Inis");
+
+    let response = server
+        .put(&format!("/statistics_collector/{}", Uuid::new_v4()))
+        .json(&updated_collector)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // Copies given with their ids keep their values when they're renamed
+    let mut renamed_collector: json::received::StatCollector =
+        serde_json::from_value(serde_json::to_value(&collector).unwrap()).unwrap();
+    renamed_collector.reminder_policy = None;
+    renamed_collector.derived_statistic_types = None;
+    let rename_copy = |collector: &mut json::received::StatCollector, from: &str, to: &str| {
+        let display = collector
+            .placement_types
+            .iter_mut()
+            .find(|placement_type| placement_type.name == "Display")
+            .unwrap();
+        let copy = display.copies.iter().position(|copy| copy == from).unwrap();
+        display.copies[copy] = to.to_string();
+    };
+    rename_copy(&mut renamed_collector, "kopia b", "kopia e");
+    let response = server
+        .put(&format!("/statistics_collector/{}", id))
+        .json(&renamed_collector)
+        .await;
+    response.assert_status_ok();
+
+    let renamed = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let renamed_display = renamed
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap();
+    assert_eq!(renamed_display.copy_ids, display.copy_ids);
+    let copy = renamed_display
+        .copies
+        .iter()
+        .position(|copy| copy == "kopia e")
+        .unwrap();
+    assert_eq!(
+        renamed_display.suppliers[0].stats[0][copy],
+        vec![Some(7); 2]
+    );
+
+    rename_copy(&mut renamed_collector, "kopia e", "kopia b");
+    let response = server
+        .put(&format!("/statistics_collector/{}", id))
+        .json(&renamed_collector)
+        .await;
+    response.assert_status_ok();
+
+    // Test exports
+    let response = server
+        .get(&format!("/statistics_collector/{}/export.csv", id))
+        .await;
+    response.assert_status_ok();
+    assert!(response
+        .header("content-type")
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let csv = response.text();
+    // header + Google: 3 copies * 1 stat * 2 periods + Inis: 1 copy * 1 stat * 2 periods
+    assert_eq!(csv.lines().count(), 1 + 6 + 2);
+    assert!(
+        csv.contains("Display,Google,kopia a,Conversions,renamed period,2023.11.08,2023.11.14,7")
+    );
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/export.xlsx", id))
+        .await;
+    response.assert_status_ok();
+    assert!(response.as_bytes().starts_with(b"PK"));
+
+    // Test spreadsheet upload
+    let response = server
+        .get(&format!("/supplier/{}/template.csv", google.id))
+        .add_query_param("token", &token)
+        .await;
+    response.assert_status_ok();
+
+    let filled_template = response
+        .text()
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i < 2 {
+                return line.to_string();
+            }
+            // keep the period name, overwrite all values
+            let mut cells = line.split(',').map(str::to_string).collect::<Vec<_>>();
+            for cell in cells.iter_mut().skip(1) {
+                *cell = "8".to_string();
+            }
+            cells.join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let response = server
+        .post(&format!("/supplier/{}/upload", google.id))
+        .add_query_param("token", &token)
+        .multipart(MultipartForm::new().add_part(
+            "file",
+            Part::bytes(filled_template.into_bytes()).file_name("filled.csv"),
+        ))
+        .await;
+    response.assert_status_ok();
+
+    let form = input_names(&response.text())
+        .into_iter()
+        .map(|name| (name, "8".to_string()))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(form.len(), 3 * 2);
+
+    let response = server
+        .post(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .form(&form)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    // Only the changed values get new revisions
+    let response = server
+        .get(&format!("/statistics_collector/{}/history", id))
+        .add_query_param("supplier_id", google.id)
+        .await;
+    response.assert_status_ok();
+    let history = response.json::<Vec<json::sent::StatisticRevision>>();
+    // revisions of the removed period are kept
+    assert_eq!(history.len(), 6 + 6);
+    assert_eq!(
+        history
+            .iter()
+            .filter(|revision| revision.period == "2023.11.22 - 11.28")
+            .count(),
+        2
+    );
+    assert!(history[..6]
+        .iter()
+        .all(|revision| revision.new_value == Some(8)));
+    // the new copy wasn't filled in before
+    assert_eq!(
+        history[..6]
+            .iter()
+            .filter(|revision| revision.old_value.is_none())
+            .count(),
+        2
+    );
+
+    let response = server
+        .post(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .form(&form)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/history", id))
+        .await;
+    assert_eq!(
+        response.json::<Vec<json::sent::StatisticRevision>>().len(),
+        6 + 6
+    );
+
+    // the values of the last submission which changed something are still highlighted
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    assert!(response.text().contains(CHANGED_MARKER));
+
+    // Periods whose values didn't change aren't submitted
+    let inis = mailing.suppliers[0].id;
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            id, inis
+        ))
+        .await;
+    let inis_token = response.json::<json::sent::SupplierToken>().token;
+    let response = server
+        .get(&format!("/supplier/{}", inis))
+        .add_query_param("token", &inis_token)
+        .await;
+    let empty_form = input_names(&response.text())
+        .into_iter()
+        .map(|name| (name, String::new()))
+        .collect::<BTreeMap<_, _>>();
+    let response = server
+        .post(&format!("/supplier/{}", inis))
+        .add_query_param("token", &inis_token)
+        .form(&empty_form)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    let collector = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let display = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap();
+    assert!(display.suppliers[0].stats[0]
+        .iter()
+        .all(|values| values == &vec![Some(8); 2]));
+
+    // Google filled in both periods, Inis nothing
+    assert_eq!(collector.completion.len(), 2);
+    for completion in &collector.completion {
+        assert_eq!(completion.suppliers, 2);
+        assert_eq!(completion.submitted, 1);
+        assert_eq!(completion.complete, 1);
+        assert_eq!(completion.incomplete_suppliers, vec![inis]);
+    }
+
+    // Summaries sum the values in SQL and compute the derived statistic types
+    let summary = |group_by: &'static str| {
+        let server = &server;
+        async move {
+            let response = server
+                .get(&format!("/statistics_collector/{}/summary", id))
+                .add_query_param("group_by", group_by)
+                .await;
+            response.assert_status_ok();
+            response.json::<json::sent::Summary>().rows
+        }
+    };
+
+    let rows = summary("").await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].totals["Conversions"], Some(8 * 6));
+    assert_eq!(rows[0].totals["Impressions"], None);
+    assert_eq!(rows[0].missing, 2);
+    assert_eq!(rows[0].derived["Conversion rate"], None);
+    assert_eq!(rows[0].derived["Doubled"], Some(96.0));
+
+    let rows = summary("placement_type,period").await;
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0].placement_type.as_deref(), Some("Display"));
+    assert_eq!(rows[0].period.as_deref(), Some("renamed period"));
+    assert_eq!(rows[0].totals["Conversions"], Some(8 * 3));
+    assert_eq!(rows[0].supplier, None);
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/summary", id))
+        .add_query_param("group_by", "client")
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // The report shows totals, missing values and charts, filtered by placement type
+    let response = server.get(&format!("/statistics_collector/{}", id)).await;
+    response.assert_status_ok();
+    let page = response.text();
+    assert!(page.contains("<h2>Mailing</h2>"));
+    assert!(page.contains(&format!("<td>{}</td>", 8 * 6)));
+    assert!(page.contains("Brakujące wartości: 2"));
+    assert_eq!(page.matches("<svg").count(), 1);
+
+    let display_id = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap()
+        .id;
+    let response = server
+        .get(&format!("/statistics_collector/{}", id))
+        .add_query_param("placement_type_id", display_id)
+        .add_query_param("period_id", collector.periods[0].id)
+        .await;
+    let page = response.text();
+    assert!(!page.contains("<h2>Mailing</h2>"));
+    assert!(page.contains(&format!("<td>{}</td>", 8 * 3)));
+    assert!(!page.contains(&format!("<td>{}</td>", 8 * 6)));
+
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    assert_eq!(response.text().matches("Uzupełnione").count(), 2);
+
+    // Only suppliers who haven't filled in the period get the second reminder
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, supplier_id, _, reminder_type, _| {
+            *supplier_id == inis && *reminder_type == SecondReminder
+        })
+        .times(1)
+        .returning(|_, _, _, _, _, _| Ok(()));
+
+    enqueue_due_reminders(db_pool.clone(), at(26, 8))
+        .await
+        .unwrap();
+    drain_outbox(
+        db_pool.clone(),
+        at(26, 8),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+
+    mailer.lock().await.checkpoint();
+
+    // Suppliers can clear values, cleared values are empty, not 0
+    let mut cleared_form = form.clone();
+    let cleared = cleared_form.keys().next().unwrap().clone();
+    cleared_form.insert(cleared.clone(), String::new());
+    let response = server
+        .post(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .form(&cleared_form)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/history", id))
+        .add_query_param("supplier_id", google.id)
+        .await;
+    let history = response.json::<Vec<json::sent::StatisticRevision>>();
+    assert_eq!(history[0].old_value, Some(8));
+    assert_eq!(history[0].new_value, None);
+
+    let collector = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let display = collector
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Display")
+        .unwrap();
+    let values = display.suppliers[0].stats[0].concat();
+    assert_eq!(values.iter().filter(|value| value.is_none()).count(), 1);
+    assert_eq!(
+        collector.completion[0].complete + collector.completion[1].complete,
+        1
+    );
+
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    assert!(response
+        .text()
+        .contains(&format!("name=\"{}\" id=\"{}\">", cleared, cleared)));
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/export.csv", id))
+        .await;
+    assert!(response.text().lines().any(|line| line.ends_with(",")));
+
+    // Revoked tokens stop working
+    let response = server
+        .delete(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            id, google.id
+        ))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<json::sent::ErrorResponse>().code,
+        json::sent::ErrorCode::Forbidden
+    );
+
+    // Browsers get a localized error page instead
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .add_header(ACCEPT, HeaderValue::from_static("text/html,*/*;q=0.8"))
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+    assert!(response.text().contains("<h1>Błąd 403</h1>"));
+    let response = server
+        .get(&format!("/supplier/{}", google.id))
+        .add_query_param("token", &token)
+        .add_header(ACCEPT, HeaderValue::from_static("text/html,*/*;q=0.8"))
+        .add_header(ACCEPT_LANGUAGE, HeaderValue::from_static("en-GB,en;q=0.9"))
+        .await;
+    assert!(response.text().contains("<h1>Error 403</h1>"));
+
+    let response = server.get("/no/such/page").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.json::<json::sent::ErrorResponse>().code,
+        json::sent::ErrorCode::NotFound
+    );
+
+    // Periods are generated from a campaign and hand-supplied ones must follow the rule
+    let mut misaligned_collector = new_collector.clone();
+    misaligned_collector.name = "kolektor kampanii".to_string();
+    misaligned_collector.weekday = Some(Weekday::Thu);
+    let response = server
+        .post("/statistics_collector")
+        .json(&misaligned_collector)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    let mut campaign_collector = misaligned_collector.clone();
+    campaign_collector.weekday = Some(Weekday::Wed);
+    campaign_collector.campaign = Some(json::received::Campaign {
+        start_date: NaiveDate::from_ymd_opt(2023, 11, 10).unwrap(),
+        end_date: NaiveDate::from_ymd_opt(2023, 11, 22).unwrap(),
+    });
+    let response = server
+        .post("/statistics_collector")
+        .json(&campaign_collector)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    campaign_collector.periods = vec![];
+    let response = server
+        .post("/statistics_collector")
+        .json(&campaign_collector)
+        .await;
+    response.assert_status_ok();
+    let campaign_id: Uuid = response.json();
+
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", campaign_id))
+        .await;
+    let collector = response.json::<json::sent::StatCollector>();
+    let names = collector
+        .periods
+        .iter()
+        .map(|period| period.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "2023.11.08 - 11.14",
+            "2023.11.15 - 11.21",
+            "2023.11.22 - 11.28"
+        ]
+    );
+
+    // The list is filtered, sorted and split into pages
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("name", "KAMPANII")
+        .add_query_param("status", "finished")
+        .await;
+    let page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].id, StatCollectorId::from(campaign_id));
+
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("status", "active")
+        .await;
+    assert_eq!(response.json::<json::sent::CollectorPage>().total, 0);
+
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("sort", "name")
+        .add_query_param("limit", 1)
+        .await;
+    let first_page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(first_page.total, 2);
+    assert_eq!(first_page.items[0].name, "kolektor kampanii");
+    let cursor = first_page.next_cursor.unwrap();
+
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("sort", "name")
+        .add_query_param("limit", 1)
+        .add_query_param("cursor", &cursor)
+        .await;
+    let second_page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(second_page.items[0].name, new_collector.name);
+    assert_eq!(second_page.next_cursor, None);
+
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("sort", "client")
+        .add_query_param("cursor", &cursor)
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("limit", 1000)
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // Archived collectors are hidden from the list and send no reminders
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            campaign_id
+        ))
+        .await;
+    let queued = response.json::<json::sent::QueuedReminders>().queued;
+    assert!(queued > 0);
+    let response = server
+        .post(&format!("/statistics_collector/{}/archive", campaign_id))
+        .await;
+    response.assert_status_ok();
+    let response = server.get("/statistics_collector").await;
+    let page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].id, StatCollectorId::from(id));
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("archived", true)
+        .await;
+    let page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(page.items[0].id, StatCollectorId::from(campaign_id));
+    assert!(page.items[0].archived_at.is_some());
+
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            campaign_id
+        ))
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+    enqueue_due_reminders(db_pool.clone(), at(16, 8))
+        .await
+        .unwrap();
+    let response = server
+        .get(&format!("/statistics_collector/{}/outbox", campaign_id))
+        .await;
+    let messages = response.json::<Vec<json::sent::OutboxMessage>>();
+    assert_eq!(messages.len(), queued);
+    assert!(messages
+        .iter()
+        .all(|message| message.status == OutboxStatus::Cancelled));
+
+    // The cancelled reminders are queued again once the collector is back
+    let response = server
+        .post(&format!("/statistics_collector/{}/unarchive", campaign_id))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            campaign_id
+        ))
+        .await;
+    let again = response.json::<json::sent::QueuedReminders>();
+    assert_eq!((again.queued, again.skipped), (queued, 0));
+
+    // Deleted collectors can be restored until they're purged
+    let response = server
+        .delete(&format!("/statistics_collector/{}", Uuid::new_v4()))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let response = server
+        .delete(&format!("/statistics_collector/{}", campaign_id))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .delete(&format!("/statistics_collector/{}", campaign_id))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", campaign_id))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let response = server
+        .get("/statistics_collector")
+        .add_query_param("deleted", true)
+        .await;
+    let page = response.json::<json::sent::CollectorPage>();
+    assert_eq!(page.items[0].id, StatCollectorId::from(campaign_id));
+
+    let response = server
+        .post(&format!("/statistics_collector/{}/restore", campaign_id))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", campaign_id))
+        .await;
+    response.assert_status_ok();
+
+    // a collector with the same name and client can be created in place of a deleted one
+    server
+        .delete(&format!("/statistics_collector/{}", campaign_id))
+        .await
+        .assert_status_ok();
+    let response = server
+        .post("/statistics_collector")
+        .json(&campaign_collector)
+        .await;
+    response.assert_status_ok();
+    let recreated_id: Uuid = response.json();
+    let response = server
+        .post(&format!("/statistics_collector/{}/restore", campaign_id))
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+
+    let retention = Duration::days(30);
+    let purged = purge_deleted_collectors(db_pool.clone(), clock.clone(), retention)
+        .await
+        .unwrap();
+    assert_eq!(purged, 0);
+    let purged = purge_deleted_collectors(
+        db_pool.clone(),
+        clock_at(Local::now() + retention + Duration::days(1)),
+        retention,
+    )
+    .await
+    .unwrap();
+    assert_eq!(purged, 1);
+    let response = server
+        .post(&format!("/statistics_collector/{}/restore", campaign_id))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", recreated_id))
+        .await;
+    response.assert_status_ok();
+
+    // Collectors are cloned without their statistics
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    let original = response.json::<json::sent::StatCollector>();
+    let clone_request = json::received::CloneRequest {
+        name: "kolektor testowy 2024".to_string(),
+        client: new_collector.client.clone(),
+        periods: vec![],
+        campaign: None,
+        period_offset_days: 364,
+        period_offset_months: 0,
+    };
+    let response = server
+        .post(&format!("/statistics_collector/{}/clone", id))
+        .json(&clone_request)
+        .await;
+    response.assert_status_ok();
+    let clone_id: Uuid = response.json();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", clone_id))
+        .await;
+    let clone = response.json::<json::sent::StatCollector>();
+    assert_eq!(clone.name, clone_request.name);
+    assert_eq!(clone.weekday, Some(Weekday::Wed));
+    assert_eq!(clone.periods.len(), original.periods.len());
+    assert_eq!(
+        clone.periods[0].start_date,
+        original.periods[0].start_date + Duration::days(364)
+    );
+    assert_eq!(clone.placement_types.len(), original.placement_types.len());
+    assert_eq!(
+        clone.placement_types[1].suppliers.len(),
+        original.placement_types[1].suppliers.len()
+    );
+    assert!(clone
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| &placement_type.suppliers)
+        .flat_map(|supplier| supplier.stats.iter().flatten().flatten())
+        .all(Option::is_none));
+
+    let response = server
+        .post(&format!("/statistics_collector/{}/clone", id))
+        .json(&clone_request)
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+    let response = server
+        .post(&format!("/statistics_collector/{}/clone", Uuid::new_v4()))
+        .json(&clone_request)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // Templates keep the structure of a collector to create new ones from
+    let new_template = json::received::NewTemplate {
+        name: "szablon".to_string(),
+    };
+    let response = server
+        .post(&format!("/statistics_collector/{}/template", id))
+        .json(&new_template)
+        .await;
+    response.assert_status_ok();
+    let template_id: Uuid = response.json();
+    let response = server
+        .post(&format!("/statistics_collector/{}/template", id))
+        .json(&new_template)
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+
+    let response = server.get("/collector_template").await;
+    let templates = response.json::<Vec<json::sent::CollectorTemplate>>();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].name, "szablon");
+    assert_eq!(templates[0].collector.name, new_collector.name);
+
+    let response = server
+        .post(&format!("/collector_template/{}/instantiate", template_id))
+        .json(&json::received::CloneRequest {
+            name: "kolektor z szablonu".to_string(),
+            campaign: Some(json::received::Campaign {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 16).unwrap(),
+            }),
+            ..clone_request.clone()
+        })
+        .await;
+    response.assert_status_ok();
+    let instance_id: Uuid = response.json();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", instance_id))
+        .await;
+    let instance = response.json::<json::sent::StatCollector>();
+    assert_eq!(instance.periods.len(), 2);
+    assert_eq!(
+        instance.placement_types.len(),
+        original.placement_types.len()
+    );
+
+    let response = server
+        .delete(&format!("/collector_template/{}", template_id))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .post(&format!("/collector_template/{}/instantiate", template_id))
+        .json(&clone_request)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // Suppliers can be added, changed and removed on a live collector
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    let before = response.json::<json::sent::StatCollector>();
+    let first_type = &before.placement_types[0];
+    let second_type = &before.placement_types[1];
+    let response = server
+        .post(&format!("/statistics_collector/{}/supplier", id))
+        .json(&json::received::NewSupplier {
+            placement_type_id: first_type.id,
+            name: "nowy dostawca".to_string(),
+            mail: "nowy@example.com".parse().unwrap(),
+            locale: None,
+        })
+        .await;
+    response.assert_status_ok();
+    let supplier_id: Uuid = response.json();
+
+    let find_supplier = |config: &json::sent::StatCollector| {
+        config
+            .placement_types
+            .iter()
+            .find_map(|placement_type| {
+                placement_type
+                    .suppliers
+                    .iter()
+                    .find(|supplier| supplier.id.to_string() == supplier_id.to_string())
+                    .map(|supplier| (placement_type.id, supplier.clone()))
+            })
+            .unwrap()
+    };
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    let (placement_type_id, added) = find_supplier(&response.json());
+    assert_eq!(placement_type_id, first_type.id);
+    assert_eq!(added.stats.len(), first_type.statistics.len());
+    assert_eq!(added.stats[0].len(), first_type.copies.len());
+    assert_eq!(added.stats[0][0].len(), before.periods.len());
+    assert!(added.stats.iter().flatten().flatten().all(Option::is_none));
+
+    let taken_mail = first_type.suppliers[0].mail.clone();
+    let response = server
+        .post(&format!("/statistics_collector/{}/supplier", id))
+        .json(&json::received::NewSupplier {
+            placement_type_id: first_type.id,
+            name: "kopia".to_string(),
+            mail: taken_mail.clone(),
+            locale: None,
+        })
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    let response = server
+        .patch(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, supplier_id
+        ))
+        .json(&json::received::SupplierChanges {
+            mail: Some(taken_mail),
+            ..Default::default()
+        })
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    let response = server
+        .patch(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, supplier_id
+        ))
+        .json(&json::received::SupplierChanges {
+            placement_type_id: Some(instance.placement_types[0].id),
+            ..Default::default()
+        })
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    let response = server
+        .patch(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, supplier_id
+        ))
+        .json(&json::received::SupplierChanges {
+            name: Some("przeniesiony dostawca".to_string()),
+            mail: Some("przeniesiony@example.com".parse().unwrap()),
+            placement_type_id: Some(second_type.id),
+            locale: None,
+        })
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    let (placement_type_id, moved) = find_supplier(&response.json());
+    assert_eq!(placement_type_id, second_type.id);
+    assert_eq!(moved.name, "przeniesiony dostawca");
+    assert_eq!(moved.mail.to_string(), "przeniesiony@example.com");
+    assert_eq!(moved.stats.len(), second_type.statistics.len());
+    assert_eq!(moved.stats[0].len(), second_type.copies.len());
+
+    let response = server
+        .delete(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, supplier_id
+        ))
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    assert_eq!(response.json::<json::sent::StatCollector>(), before);
+    let response = server
+        .delete(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, supplier_id
+        ))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // Suppliers get their pages and emails in their language, the collector's one by default
+    assert_eq!(before.locale, Locale::Pl);
+    let response = server
+        .post(&format!("/statistics_collector/{}/supplier", id))
+        .json(&json::received::NewSupplier {
+            placement_type_id: first_type.id,
+            name: "english supplier".to_string(),
+            mail: "english@example.com".parse().unwrap(),
+            locale: Some(Locale::En),
+        })
+        .await;
+    let english_id: Uuid = response.json();
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            id, english_id
+        ))
+        .await;
+    let english_token = response.json::<json::sent::SupplierToken>().token;
+
+    let response = server
+        .get(&format!("/supplier/{}", english_id))
+        .add_query_param("token", &english_token)
+        .add_header(ACCEPT_LANGUAGE, HeaderValue::from_static("pl"))
+        .await;
+    let page = response.text();
+    assert!(page.contains("<html lang=\"en\">"));
+    assert!(page.contains("value=\"Submit\""));
+    let response = server
+        .get(&format!("/supplier/{}", english_id))
+        .add_query_param("token", &english_token)
+        .add_query_param("lang", "pl")
+        .await;
+    let page = response.text();
+    assert!(page.contains("value=\"Wyślij\""));
+    // the chosen language is kept on the links to the other pages
+    assert!(page.contains("/history?token=") && page.contains("&amp;lang=pl"));
+
+    let other = &first_type.suppliers[0];
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/supplier/{}/token",
+            id, other.id
+        ))
+        .await;
+    let other_token = response.json::<json::sent::SupplierToken>().token;
+    let response = server
+        .get(&format!("/supplier/{}", other.id))
+        .add_query_param("token", &other_token)
+        .await;
+    assert!(response.text().contains("value=\"Wyślij\""));
+    let response = server
+        .get(&format!("/supplier/{}", other.id))
+        .add_query_param("token", &other_token)
+        .add_header(ACCEPT_LANGUAGE, HeaderValue::from_static("de, en;q=0.5"))
+        .await;
+    assert!(response.text().contains("value=\"Submit\""));
+
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            id
+        ))
+        .await;
+    assert_eq!(response.json::<json::sent::QueuedReminders>().queued, 3);
+    let english = StatCollectorId::from(english_id).to_string();
+    let others = english.clone();
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, supplier_id, _, _, email| {
+            supplier_id.to_string() == english && email.template.locale == Locale::En
+        })
+        .times(1)
+        .returning(|_, _, _, _, _, _| Ok(()));
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, supplier_id, _, _, email| {
+            supplier_id.to_string() != others && email.template.locale == Locale::Pl
+        })
+        .times(2)
+        .returning(|_, _, _, _, _, _| Ok(()));
+    drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    mailer.lock().await.checkpoint();
+
+    let response = server
+        .patch(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            id, english_id
+        ))
+        .json(&json::received::SupplierChanges {
+            locale: Some(Locale::Pl),
+            ..Default::default()
+        })
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    let config: json::sent::StatCollector = response.json();
+    let changed = config
+        .placement_types
+        .iter()
+        .flat_map(|placement_type| placement_type.suppliers.iter())
+        .find(|supplier| supplier.id.to_string() == english_id.to_string())
+        .unwrap();
+    assert_eq!(changed.locale, Some(Locale::Pl));
+
+    // updates without languages, like the ones of the Django app, keep the stored ones
+    let mut without_locales: json::received::StatCollector =
+        serde_json::from_value(serde_json::to_value(&config).unwrap()).unwrap();
+    without_locales.locale = None;
+    for placement_type in &mut without_locales.placement_types {
+        for supplier in &mut placement_type.suppliers {
+            supplier.locale = None;
+        }
+    }
+    let response = server
+        .put(&format!("/statistics_collector/{}", id))
+        .json(&without_locales)
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .get(&format!("/statistics_collector/{}/config", id))
+        .await;
+    assert_eq!(response.json::<json::sent::StatCollector>(), config);
+
+    // email templates
+    let response = server.get("/email_template").await;
+    let built_in: Vec<json::sent::EmailTemplate> = response.json();
+    assert_eq!(built_in.len(), 6);
+    assert!(built_in.iter().all(|template| template.version == 0));
+
+    let content = json::received::EmailTemplateContent {
+        subject: "Statystyki {{collector}} dla {{client}}".to_string(),
+        body: "<p>{{supplier}}, do {{deadline}}: <a href=\"{{link}}\">link</a></p>\
+            <img src=\"{{image:logo}}\">"
+            .to_string(),
+    };
+    let response = server
+        .put("/email_template/FirstReminder/pl")
+        .json(&content)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.text().contains("no image named logo"));
+
+    let response = server
+        .put("/email_image/logo")
+        .bytes(vec![137, 80, 78, 71].into())
+        .content_type("image/png")
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .put("/email_image/notes")
+        .bytes("text".into())
+        .content_type("text/plain")
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = server
+        .put("/email_template/FirstReminder/pl")
+        .json(&content)
+        .await;
+    assert_eq!(response.json::<i32>(), 1);
+    // escalations have no link
+    let response = server
+        .put("/email_template/Escalation/pl")
+        .json(&content)
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.text().contains("unknown placeholder link"));
+
+    let response = server
+        .post("/email_template/FirstReminder/pl/preview")
+        .json(&json::received::EmailPreviewRequest {
+            supplier_id: other.id,
+            period_id: None,
+            content: None,
+        })
+        .await;
+    let preview: json::sent::EmailPreview = response.json();
+    assert_eq!(preview.to, Some(other.mail.to_string()));
+    assert!(preview.subject.starts_with("Statystyki "));
+    assert!(preview.html.contains(&format!("<p>{}, do ", other.name)));
+    assert!(preview.html.contains("token=preview&amp;lang=pl"));
+    assert!(preview
+        .html
+        .contains("src=\"data:image/png;base64,iVBORw==\""));
+    assert!(preview.text.starts_with(&format!("{}, do ", other.name)));
+    assert!(preview.text.contains("(/supplier/"));
+
+    let response = server
+        .post("/email_template/FirstReminder/pl/preview")
+        .json(&json::received::EmailPreviewRequest {
+            supplier_id: other.id,
+            period_id: None,
+            content: Some(json::received::EmailTemplateContent {
+                subject: "{{nope}}".to_string(),
+                body: String::new(),
+            }),
+        })
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+    // the current template shows it
+    let response = server.delete("/email_image/logo").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .post("/email_template/FirstReminder/pl/versions/0/restore")
+        .await;
+    assert_eq!(response.json::<i32>(), 2);
+    let response = server.get("/email_template/FirstReminder/pl").await;
+    let current: json::sent::EmailTemplate = response.json();
+    assert_eq!(current.version, 2);
+    assert_eq!(current.body, built_in[0].body);
+    let response = server
+        .post("/email_template/FirstReminder/pl/versions/1/restore")
+        .await;
+    assert_eq!(response.json::<i32>(), 3);
+    let response = server
+        .get("/email_template/FirstReminder/pl/versions")
+        .await;
+    let versions: Vec<json::sent::EmailTemplate> = response.json();
+    assert_eq!(
+        versions
+            .iter()
+            .map(|version| version.version)
+            .collect::<Vec<_>>(),
+        vec![3, 2, 1]
+    );
+
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            id
+        ))
+        .add_query_param("force", "true")
+        .await;
+    assert_eq!(response.json::<json::sent::QueuedReminders>().queued, 3);
+    mailer
+        .lock()
+        .await
+        .expect_send_reminder()
+        .withf(move |_, _, _, _, _, email| {
+            email.template.version == 3
+                && email.images.len() == 1
+                && email.images[0].name == "logo"
+                && !email.values.deadline.is_empty()
+        })
+        .times(3)
+        .returning(|_, _, _, _, _, _| Ok(()));
+    drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        mailer.clone(),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    mailer.lock().await.checkpoint();
+
+    // templates can't make mail clients download anything
+    let response = server
+        .put("/email_template/SecondReminder/pl")
+        .json(&json::received::EmailTemplateContent {
+            subject: "Statystyki".to_string(),
+            body: "<img src=\"https://example.com/logo.png\">".to_string(),
+        })
+        .await;
+    response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.text().contains("https://example.com/logo.png"));
+
+    // A supplier in two placement types keeps both rows and their values when the collector
+    // is saved again without ids, as the Django app does
+    let supplier = json::received::Supplier {
+        id: None,
+        name: "Onet".to_string(),
+        mail: "onet@onet.pl".parse().unwrap(),
+        locale: None,
+    };
+    let shared_supplier_collector = json::received::StatCollector {
+        name: "kolektor wspólnego dostawcy".to_string(),
+        placement_types: vec![
+            json::received::PlacementType {
+                id: None,
+                name: "Display".to_string(),
+                suppliers: vec![supplier.clone()],
+                statistics: vec!["Clicks".to_string()],
+                copies: vec!["baner".to_string()],
+                ..Default::default()
+            },
+            json::received::PlacementType {
+                id: None,
+                name: "Mailing".to_string(),
+                suppliers: vec![supplier],
+                statistics: vec!["Opens".to_string()],
+                copies: vec!["newsletter".to_string()],
+                ..Default::default()
+            },
+        ],
+        derived_statistic_types: None,
+        ..new_collector.clone()
+    };
+    let response = server
+        .post("/statistics_collector")
+        .json(&shared_supplier_collector)
+        .await;
+    response.assert_status_ok();
+    let shared_id: Uuid = response.json();
+    let config = server
+        .get(&format!("/statistics_collector/{}/config", shared_id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let rows = config
+        .placement_types
+        .iter()
+        .map(|placement_type| (placement_type.name.clone(), placement_type.suppliers[0].id))
+        .collect::<BTreeMap<_, _>>();
+    assert_ne!(rows["Display"], rows["Mailing"]);
+    for supplier_id in rows.values() {
+        let response = server
+            .post(&format!(
+                "/statistics_collector/{}/supplier/{}/token",
+                shared_id, supplier_id
+            ))
+            .await;
+        let token = response.json::<json::sent::SupplierToken>().token;
+        let response = server
+            .get(&format!("/supplier/{}", supplier_id))
+            .add_query_param("token", &token)
+            .await;
+        let form = input_names(&response.text())
+            .into_iter()
+            .map(|name| (name, "5".to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let response = server
+            .post(&format!("/supplier/{}", supplier_id))
+            .add_query_param("token", &token)
+            .form(&form)
+            .await;
+        response.assert_status(StatusCode::SEE_OTHER);
+    }
+
+    // in either order of the placement types
+    let mut reordered = shared_supplier_collector.clone();
+    reordered.placement_types.reverse();
+    for collector in [&reordered, &shared_supplier_collector] {
+        let response = server
+            .put(&format!("/statistics_collector/{}", shared_id))
+            .json(collector)
+            .await;
+        response.assert_status_ok();
+    }
+    let config = server
+        .get(&format!("/statistics_collector/{}/config", shared_id))
+        .await
+        .json::<json::sent::StatCollector>();
+    for placement_type in &config.placement_types {
+        let supplier = &placement_type.suppliers[0];
+        assert_eq!(supplier.id, rows[&placement_type.name]);
+        assert!(supplier
+            .stats
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|value| *value == Some(5)));
+    }
+
+    // Moved suppliers keep the values of statistic types and copies with the same names
+    let mut with_video = shared_supplier_collector.clone();
+    with_video
+        .placement_types
+        .push(json::received::PlacementType {
+            id: None,
+            name: "Video".to_string(),
+            suppliers: vec![],
+            statistics: vec!["Clicks".to_string(), "Views".to_string()],
+            copies: vec!["baner".to_string(), "spot".to_string()],
+            ..Default::default()
+        });
+    let response = server
+        .put(&format!("/statistics_collector/{}", shared_id))
+        .json(&with_video)
+        .await;
+    response.assert_status_ok();
+    let config = server
+        .get(&format!("/statistics_collector/{}/config", shared_id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let video = config
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Video")
+        .unwrap();
+    let response = server
+        .patch(&format!(
+            "/statistics_collector/{}/supplier/{}",
+            shared_id, rows["Display"]
+        ))
+        .json(&json::received::SupplierChanges {
+            placement_type_id: Some(video.id),
+            ..Default::default()
+        })
+        .await;
+    response.assert_status_ok();
+    let config = server
+        .get(&format!("/statistics_collector/{}/config", shared_id))
+        .await
+        .json::<json::sent::StatCollector>();
+    let video = config
+        .placement_types
+        .iter()
+        .find(|placement_type| placement_type.name == "Video")
+        .unwrap();
+    let moved = &video.suppliers[0];
+    assert_eq!(moved.id, rows["Display"]);
+    for (statistic, values) in video.statistics.iter().zip(&moved.stats) {
+        for (copy, values) in video.copies.iter().zip(values) {
+            let expected = match (statistic.as_str(), copy.as_str()) {
+                ("Clicks", "baner") => Some(5),
+                _ => None,
+            };
+            assert_eq!(values, &vec![expected; config.periods.len()]);
+        }
+    }
+
+    // Emails are sent at the same time, but never more than allowed
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            id
+        ))
+        .add_query_param("force", true)
+        .await;
+    let queued = response.json::<json::sent::QueuedReminders>().queued;
+    assert!(queued > 2);
+    let slow_mailer = Arc::new(SlowMailer::default());
+    let sent = drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        slow_mailer.clone(),
+        2,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, queued);
+    assert_eq!(slow_mailer.max_in_flight.load(Ordering::SeqCst), 2);
+
+    // A message that can't be finished doesn't keep the others from being finished
+    let response = server
+        .post(&format!(
+            "/statistics_collector/{}/send_emails/FirstReminder",
+            id
+        ))
+        .add_query_param("force", true)
+        .await;
+    let queued = response.json::<json::sent::QueuedReminders>().queued;
+    assert!(queued > 1);
+    let failing = outbox(OutboxStatus::Pending).await[0].id;
+    let execute_sql = |sql: String| {
+        let db_pool = db_pool.clone();
+        async move {
+            let conn = db_pool.get().await.unwrap();
+            conn.interact(move |conn| diesel::sql_query(sql).execute(conn))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+    };
+    execute_sql(format!(
+        "CREATE FUNCTION fail_finish() RETURNS trigger AS $$ BEGIN
+            IF NEW.status = 'sent' AND OLD.id = '{}' THEN RAISE EXCEPTION 'finish failed'; END IF;
+            RETURN NEW;
+        END $$ LANGUAGE plpgsql",
+        failing
+    ))
+    .await;
+    execute_sql(
+        "CREATE TRIGGER fail_finish BEFORE UPDATE ON outbox \
+        FOR EACH ROW EXECUTE FUNCTION fail_finish()"
+            .to_string(),
+    )
+    .await;
+    let sent = drain_outbox(
+        db_pool.clone(),
+        clock_at(Local::now()),
+        Arc::new(SlowMailer::default()),
+        DEFAULT_SEND_CONCURRENCY,
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent, queued);
+    let pending = outbox(OutboxStatus::Pending).await;
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, failing);
+    execute_sql("DROP TRIGGER fail_finish ON outbox".to_string()).await;
+    execute_sql("DROP FUNCTION fail_finish".to_string()).await;
+}
+
+/// Lets the expectations of the mock be changed while the app shares it.
+/// Only one email is sent at a time.
+#[derive(Default)]
+struct SharedMailer(tokio::sync::Mutex<MockMailer>);
+
+impl SharedMailer {
+    async fn lock(&self) -> tokio::sync::MutexGuard<'_, MockMailer> {
+        self.0.lock().await
+    }
+}
+
+#[axum::async_trait]
+impl Mailer for SharedMailer {
+    async fn send_reminder(
+        &self,
+        stat_collector: db::StatisticsCollector,
+        to_email: Address,
+        supplier_id: db::SupplierId,
+        token: String,
+        reminder_type: ReminderType,
+        email: TemplatedEmail,
+    ) -> Result<(), AppError> {
+        self.lock()
+            .await
+            .send_reminder(
+                stat_collector,
+                to_email,
+                supplier_id,
+                token,
+                reminder_type,
+                email,
+            )
+            .await
+    }
+
+    async fn send_escalation(
+        &self,
+        stat_collector: db::StatisticsCollector,
+        to_email: Address,
+        supplier: db::Supplier,
+        period: db::Period,
+        email: TemplatedEmail,
+    ) -> Result<(), AppError> {
+        self.lock()
+            .await
+            .send_escalation(stat_collector, to_email, supplier, period, email)
+            .await
+    }
+}
+
+/// Takes a while to send an email and remembers how many were sent at the same time
+#[derive(Default)]
+struct SlowMailer {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl SlowMailer {
+    async fn send(&self) -> Result<(), AppError> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[axum::async_trait]
+impl Mailer for SlowMailer {
+    async fn send_reminder(
+        &self,
+        _: db::StatisticsCollector,
+        _: Address,
+        _: db::SupplierId,
+        _: String,
+        _: ReminderType,
+        _: TemplatedEmail,
+    ) -> Result<(), AppError> {
+        self.send().await
+    }
+
+    async fn send_escalation(
+        &self,
+        _: db::StatisticsCollector,
+        _: Address,
+        _: db::Supplier,
+        _: db::Period,
+        _: TemplatedEmail,
+    ) -> Result<(), AppError> {
+        self.send().await
+    }
+}
+
+/// Style of cells changed in the last submission on the supplier page
+const CHANGED_MARKER: &str = "background-color: #ffd";
+
+/// Names of all number inputs on an HTML page
+fn input_names(html: &str) -> Vec<String> {
+    html.split("<input")
+        .skip(1)
+        .filter_map(|input| input.split('>').next())
+        .filter(|input| input.contains("type=\"number\""))
+        .filter_map(|input| input.split("name=\"").nth(1))
+        .filter_map(|rest| rest.split('"').next())
+        .map(|name| name.to_string())
+        .collect()
 }